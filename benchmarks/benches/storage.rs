@@ -0,0 +1,172 @@
+use criterion::{
+    black_box, criterion_group, criterion_main, BatchSize, Criterion,
+};
+use serde::{Deserialize, Serialize};
+use squid_db::{Attributes, Builder, Instance};
+use std::{fs, sync::Arc};
+use tokio::{runtime::Runtime, sync::RwLock};
+
+const SENTENCE: &str =
+    "Le soleil brille, illuminant la ville endormie. Les rues sont calmes, baignées dans une douce lumière.";
+/// Entries written before reading or deleting.
+const PREFILLED: usize = 5_000;
+/// Entries written to the memtable before flushing it.
+const MEMTABLE_LENGTH: usize = 1_000;
+/// Memtable threshold high enough to never flush by itself.
+const NEVER_FLUSH_KB: usize = 1_000_000;
+
+#[derive(Serialize, Deserialize, Default)]
+struct Entity {
+    id: String,
+    data: String,
+}
+
+impl Attributes for Entity {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+fn entity(id: usize) -> Entity {
+    Entity {
+        id: id.to_string(),
+        data: SENTENCE.to_string(),
+    }
+}
+
+/// Opens an empty database in its own directory.
+fn open(
+    runtime: &Runtime,
+    name: &str,
+    memtable_kb: usize,
+) -> Arc<RwLock<Instance<Entity>>> {
+    let directory = std::env::temp_dir().join("squid-db-bench").join(name);
+    let _ = fs::remove_dir_all(&directory);
+
+    runtime
+        .block_on(
+            Builder::default()
+                .directory(directory)
+                .memtable_flush_size(memtable_kb)
+                .build(),
+        )
+        .unwrap()
+}
+
+fn set_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    for (name, memtable_kb) in [("set", 0), ("set with memtable", 100)] {
+        let instance = open(&runtime, name, memtable_kb);
+        let mut id = 0;
+
+        c.bench_function(&format!("{} squid-db", name), |b| {
+            b.iter(|| {
+                id += 1;
+                runtime.block_on(async {
+                    instance.write().await.set(entity(id)).await.unwrap()
+                })
+            })
+        });
+    }
+}
+
+fn get_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    for (name, memtable_kb) in
+        [("get", 0), ("get with memtable", NEVER_FLUSH_KB)]
+    {
+        let instance = open(&runtime, name, memtable_kb);
+        runtime.block_on(async {
+            let mut instance = instance.write().await;
+            for id in 0..PREFILLED {
+                instance.set(entity(id)).await.unwrap();
+            }
+        });
+        let mut id = 0;
+
+        c.bench_function(&format!("{} squid-db", name), |b| {
+            b.iter(|| {
+                id = (id + 7) % PREFILLED;
+                runtime
+                    .block_on(instance.read())
+                    .get(black_box(id.to_string()))
+                    .unwrap()
+            })
+        });
+    }
+}
+
+fn delete_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+
+    for (name, memtable_kb) in
+        [("delete", 0), ("delete with memtable", NEVER_FLUSH_KB)]
+    {
+        let instance = open(&runtime, name, memtable_kb);
+        runtime.block_on(async {
+            let mut instance = instance.write().await;
+            for id in 0..PREFILLED {
+                instance.set(entity(id)).await.unwrap();
+            }
+        });
+        let mut id = PREFILLED;
+
+        c.bench_function(&format!("{} squid-db", name), |b| {
+            b.iter_batched(
+                || {
+                    id += 1;
+                    runtime.block_on(async {
+                        instance.write().await.set(entity(id)).await.unwrap()
+                    });
+                    id.to_string()
+                },
+                |id| runtime.block_on(instance.write()).delete(&id).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+fn flush_benchmark(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let instance = open(&runtime, "flush", NEVER_FLUSH_KB);
+    let mut id = 0;
+
+    c.bench_function(
+        &format!("flush {} entries squid-db", MEMTABLE_LENGTH),
+        |b| {
+            b.iter_batched(
+                || {
+                    runtime.block_on(async {
+                        let mut instance = instance.write().await;
+                        for _ in 0..MEMTABLE_LENGTH {
+                            id += 1;
+                            instance.set(entity(id)).await.unwrap();
+                        }
+                    })
+                },
+                |_| runtime.block_on(instance.write()).flush().unwrap(),
+                BatchSize::PerIteration,
+            )
+        },
+    );
+}
+
+criterion_group!(
+    benches,
+    set_benchmark,
+    get_benchmark,
+    delete_benchmark,
+    flush_benchmark
+);
+criterion_main!(benches);
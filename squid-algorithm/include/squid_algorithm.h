@@ -0,0 +1,61 @@
+/*
+ * C bindings of the HashMap algorithm of squid-algorithm, built as a static
+ * and a dynamic library with its `ffi` feature:
+ *
+ *     cargo build -p squid-algorithm --release --features ffi
+ */
+
+#ifndef SQUID_ALGORITHM_H
+#define SQUID_ALGORITHM_H
+
+#include <stdbool.h>
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Exact counter of words. */
+typedef struct SquidMap SquidMap;
+
+/* A ranked word. */
+typedef struct SquidWord {
+    /* NUL-terminated UTF-8 string. */
+    char *word;
+    size_t occurrences;
+} SquidWord;
+
+/* Ranked words, most used first. */
+typedef struct SquidRanking {
+    /* NULL if there is no word. */
+    SquidWord *words;
+    size_t length;
+} SquidRanking;
+
+/* Creates an empty map, freed with `squid_map_free`. */
+SquidMap *squid_map_new(void);
+
+/* Counts a word `weight` times. Returns false if `word` is not UTF-8. */
+bool squid_map_set(SquidMap *map, const char *word, size_t weight);
+
+/* Uncounts a word `weight` times. Returns false if `word` is not UTF-8. */
+bool squid_map_remove(SquidMap *map, const char *word, size_t weight);
+
+/*
+ * Writes the `length` most used words into `ranking`, freed with
+ * `squid_ranking_free`. Returns false, and writes an empty ranking, if a
+ * word contains a NUL byte.
+ */
+bool squid_map_rank(const SquidMap *map, size_t length, SquidRanking *ranking);
+
+/* Frees a ranking written by `squid_map_rank`, even without words. */
+void squid_ranking_free(SquidRanking ranking);
+
+/* Frees a map created by `squid_map_new`. */
+void squid_map_free(SquidMap *map);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SQUID_ALGORITHM_H */
@@ -0,0 +1,261 @@
+use ahash::RandomState;
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// Number of shards if not specified.
+const DEFAULT_SHARDS: usize = 16;
+
+/// Words of a shard.
+#[derive(Debug, Default, Clone)]
+struct Shard {
+    /// Occurrences of each word.
+    words: HashMap<String, usize, RandomState>,
+    /// Occurrences of every word.
+    total: usize,
+}
+
+/// Structure containing the data required by the concurrent HashMap
+/// algorithm.
+///
+/// Words are split between shards, each behind its own lock, so words are
+/// counted and ranked from several threads at once through a shared
+/// reference. Counts are as exact as with the HashMap algorithm.
+#[derive(Debug)]
+pub struct ConcurrentAlgorithm {
+    /// Words, each in the shard given by its hash.
+    shards: Box<[RwLock<Shard>]>,
+    /// Selects the shard of a word.
+    hasher: RandomState,
+}
+
+impl Default for ConcurrentAlgorithm {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+impl Clone for ConcurrentAlgorithm {
+    fn clone(&self) -> Self {
+        Self {
+            shards: (0..self.shards.len())
+                .map(|index| RwLock::new(self.read(index).clone()))
+                .collect(),
+            hasher: self.hasher.clone(),
+        }
+    }
+}
+
+impl ConcurrentAlgorithm {
+    /// Creates an algorithm splitting words between `shards` shards.
+    ///
+    /// More shards let more threads count words at once.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1))
+                .map(|_| RwLock::new(Shard::default()))
+                .collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Returns the index of the shard of a key.
+    fn shard(&self, key: &str) -> usize {
+        self.hasher.hash_one(key) as usize % self.shards.len()
+    }
+
+    fn read(&self, index: usize) -> RwLockReadGuard<'_, Shard> {
+        self.shards[index]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self, index: usize) -> RwLockWriteGuard<'_, Shard> {
+        self.shards[index]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Adds an occurrence of a key.
+    pub fn set<T>(&self, key: T)
+    where
+        T: AsRef<str>,
+    {
+        self.set_weighted(key, 1)
+    }
+
+    /// Adds `weight` occurrences of a key.
+    pub fn set_weighted<T>(&self, key: T, weight: usize)
+    where
+        T: AsRef<str>,
+    {
+        if weight == 0 {
+            return;
+        }
+
+        let key = key.as_ref();
+        let mut shard = self.write(self.shard(key));
+        shard.total += weight;
+        match shard.words.get_mut(key) {
+            Some(count) => *count += weight,
+            None => {
+                shard.words.insert(key.to_string(), weight);
+            },
+        }
+    }
+
+    /// Removes an occurrence of a key.
+    pub fn remove<T>(&self, key: T)
+    where
+        T: AsRef<str>,
+    {
+        self.remove_weighted(key, 1)
+    }
+
+    /// Removes `weight` occurrences of a key.
+    pub fn remove_weighted<T>(&self, key: T, weight: usize)
+    where
+        T: AsRef<str>,
+    {
+        let key = key.as_ref();
+        let mut shard = self.write(self.shard(key));
+        let Some(count) = shard.words.get_mut(key) else {
+            return;
+        };
+        let removed = (*count).min(weight);
+        if *count > weight {
+            *count -= weight;
+        } else {
+            shard.words.remove(key);
+        }
+        shard.total -= removed;
+    }
+
+    /// Removes every word.
+    pub fn clear(&self) {
+        for index in 0..self.shards.len() {
+            let mut shard = self.write(index);
+            shard.words.clear();
+            shard.total = 0;
+        }
+    }
+
+    /// Returns the occurrences of a key.
+    pub fn get<T>(&self, key: T) -> usize
+    where
+        T: AsRef<str>,
+    {
+        let key = key.as_ref();
+        self.read(self.shard(key))
+            .words
+            .get(key)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the number of distinct words.
+    pub fn len(&self) -> usize {
+        (0..self.shards.len())
+            .map(|index| self.read(index).words.len())
+            .sum()
+    }
+
+    /// Returns `true` if no word has been added.
+    pub fn is_empty(&self) -> bool {
+        (0..self.shards.len()).all(|index| self.read(index).words.is_empty())
+    }
+
+    /// Returns the occurrences of every word, without going through them.
+    pub fn total(&self) -> usize {
+        (0..self.shards.len())
+            .map(|index| self.read(index).total)
+            .sum()
+    }
+
+    /// Counts the words of each order of magnitude of occurrences, the
+    /// `i`th count being the words counted more than `10^(i-1)` times and
+    /// at most `10^i` times.
+    pub fn histogram(&self) -> Vec<usize> {
+        let mut histogram = Vec::new();
+        self.for_each(|_, count| crate::record(&mut histogram, count));
+
+        histogram
+    }
+
+    /// Calls `f` with every word and its occurrences, in any order.
+    ///
+    /// Each shard is locked in turn, words of other shards being counted
+    /// meanwhile.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, usize),
+    {
+        for index in 0..self.shards.len() {
+            for (word, count) in self.read(index).words.iter() {
+                f(word, *count)
+            }
+        }
+    }
+
+    /// Estimates the memory used by the words and their occurrences, in
+    /// bytes.
+    pub fn memory(&self) -> usize {
+        (0..self.shards.len())
+            .map(|index| {
+                let words = &self.read(index).words;
+                words.capacity()
+                    * (std::mem::size_of::<String>()
+                        + std::mem::size_of::<usize>())
+                    + words.keys().map(String::capacity).sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Classify the most frequently used words.
+    pub fn rank(&self, length: usize) -> Vec<(String, usize)> {
+        self.rank_by(length, |a, b| b.1.cmp(&a.1))
+    }
+
+    /// Classify the most frequently used words, with the share of the
+    /// occurrences of every word each one represents, between 0 and 1.
+    pub fn rank_with_share(&self, length: usize) -> Vec<(String, usize, f64)> {
+        crate::with_share(self.rank(length), self.total())
+    }
+
+    /// Classify the words in the order given by `compare`, the first ones
+    /// being returned.
+    ///
+    /// The first words of each shard are selected, then the first ones
+    /// among them.
+    pub fn rank_by<F>(
+        &self,
+        length: usize,
+        mut compare: F,
+    ) -> Vec<(String, usize)>
+    where
+        F: FnMut(&(&str, usize), &(&str, usize)) -> Ordering,
+    {
+        let mut ranking = Vec::new();
+        for index in 0..self.shards.len() {
+            let shard = self.read(index);
+            ranking.extend(
+                crate::top(
+                    shard
+                        .words
+                        .iter()
+                        .map(|(word, count)| (word.as_str(), *count)),
+                    length,
+                    &mut compare,
+                )
+                .into_iter()
+                .map(|(word, count)| (word.to_string(), count)),
+            );
+        }
+
+        crate::top(ranking, length, |a, b| {
+            compare(&(a.0.as_str(), a.1), &(b.0.as_str(), b.1))
+        })
+    }
+}
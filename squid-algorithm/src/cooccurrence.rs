@@ -0,0 +1,232 @@
+use crate::{hashtable::MapAlgorithm, intern::Interner};
+use ahash::RandomState;
+use std::collections::HashMap;
+
+/// Number of distinct words of a sentence paired if not specified.
+const DEFAULT_LIMIT: usize = 32;
+
+/// Structure containing the data required to rank the words used together.
+///
+/// Each word counts the sentences in which every other word was used
+/// alongside it, so the words most used with a word are ranked like a
+/// leaderboard. A sentence of `n` distinct words adds `n * (n - 1)` pairs,
+/// so only its first distinct words are paired.
+///
+/// Words are interned, so each word is stored once whichever the number of
+/// pairs it is part of. Interned words are kept until every pair is
+/// cleared.
+#[derive(Debug, Clone)]
+pub struct CooccurrenceAlgorithm {
+    /// Maximum number of distinct words of a sentence paired.
+    limit: usize,
+    /// Identifiers of the words used with each word, and in how many
+    /// sentences.
+    pairs: HashMap<u32, MapAlgorithm<u32>, RandomState>,
+    /// Words of the identifiers.
+    words: Interner,
+}
+
+impl Default for CooccurrenceAlgorithm {
+    fn default() -> Self {
+        Self::new(DEFAULT_LIMIT)
+    }
+}
+
+impl CooccurrenceAlgorithm {
+    /// Creates an empty structure pairing the first `limit` distinct words
+    /// of each sentence.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(2),
+            pairs: HashMap::default(),
+            words: Interner::default(),
+        }
+    }
+
+    /// Returns the first distinct words of a sentence, which are paired.
+    fn distinct<'a>(&self, words: &[&'a str]) -> Vec<&'a str> {
+        let mut distinct = Vec::with_capacity(words.len().min(self.limit));
+        for word in words {
+            if distinct.len() == self.limit {
+                break;
+            }
+            if !distinct.contains(word) {
+                distinct.push(*word);
+            }
+        }
+
+        distinct
+    }
+
+    /// Adds a sentence, once.
+    pub fn set_sentence(&mut self, words: &[&str]) {
+        self.set_sentence_weighted(words, 1)
+    }
+
+    /// Adds `weight` times each pair of distinct words of a sentence.
+    pub fn set_sentence_weighted(&mut self, words: &[&str], weight: usize) {
+        if weight == 0 {
+            return;
+        }
+
+        let ids = self
+            .distinct(words)
+            .into_iter()
+            .map(|word| self.words.intern(word))
+            .collect::<Vec<_>>();
+        for id in &ids {
+            let related = self.pairs.entry(*id).or_default();
+            for other in ids.iter().filter(|other| *other != id) {
+                related.set_weighted(other, weight);
+            }
+        }
+    }
+
+    /// Removes a sentence added once.
+    pub fn remove_sentence(&mut self, words: &[&str]) {
+        self.remove_sentence_weighted(words, 1)
+    }
+
+    /// Removes a sentence added with `weight`.
+    ///
+    /// Words no longer used with any other word are forgotten.
+    pub fn remove_sentence_weighted(&mut self, words: &[&str], weight: usize) {
+        // Words never interned were never paired.
+        let ids = self
+            .distinct(words)
+            .into_iter()
+            .filter_map(|word| self.words.get(word))
+            .collect::<Vec<_>>();
+        for id in &ids {
+            let Some(related) = self.pairs.get_mut(id) else {
+                continue;
+            };
+            for other in ids.iter().filter(|other| *other != id) {
+                related.remove_weighted(other, weight);
+            }
+            if related.is_empty() {
+                self.pairs.remove(id);
+            }
+        }
+    }
+
+    /// Adds `count` sentences using two words together, such as to restore
+    /// the pairs of [`CooccurrenceAlgorithm::for_each`].
+    ///
+    /// Only the second word is added to the ones used with the first.
+    pub fn set_pair(&mut self, word: &str, other: &str, count: usize) {
+        if count == 0 || word == other {
+            return;
+        }
+
+        let other = self.words.intern(other);
+        self.pairs
+            .entry(self.words.intern(word))
+            .or_default()
+            .set_weighted(&other, count)
+    }
+
+    /// Forgets a word, and every pair it is part of.
+    pub fn purge(&mut self, word: &str) {
+        let Some(id) = self.words.get(word) else {
+            return;
+        };
+        let Some(related) = self.pairs.remove(&id) else {
+            return;
+        };
+
+        for (other, count) in related.iter() {
+            if let Some(pairs) = self.pairs.get_mut(other) {
+                pairs.remove_weighted(&id, count);
+                if pairs.is_empty() {
+                    self.pairs.remove(other);
+                }
+            }
+        }
+    }
+
+    /// Returns the number of sentences using two words together.
+    pub fn get(&self, word: &str, other: &str) -> usize {
+        let (Some(word), Some(other)) =
+            (self.words.get(word), self.words.get(other))
+        else {
+            return 0;
+        };
+
+        self.pairs.get(&word).map_or(0, |related| related.get(&other))
+    }
+
+    /// Returns the number of words used with a word.
+    pub fn len_related(&self, word: &str) -> usize {
+        self.words
+            .get(word)
+            .and_then(|id| self.pairs.get(&id))
+            .map_or(0, MapAlgorithm::len)
+    }
+
+    /// Returns the number of words used with at least one other word.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns whether no pair of words was added.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Removes every pair of words and forgets every word, keeping the
+    /// allocated memory.
+    pub fn clear(&mut self) {
+        self.pairs.clear();
+        self.words.clear();
+    }
+
+    /// Calls `f` with every word, each word used with it, and the number
+    /// of sentences using both, in any order.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, &str, usize),
+    {
+        for (word, related) in &self.pairs {
+            let Some(word) = self.words.resolve(*word) else {
+                continue;
+            };
+            for (other, count) in related.iter() {
+                if let Some(other) = self.words.resolve(*other) {
+                    f(word, other, count)
+                }
+            }
+        }
+    }
+
+    /// Estimates the memory used, in bytes.
+    pub fn memory(&self) -> usize {
+        self.pairs.capacity()
+            * (std::mem::size_of::<u32>()
+                + std::mem::size_of::<MapAlgorithm<u32>>())
+            + self
+                .pairs
+                .values()
+                .map(MapAlgorithm::<u32>::memory)
+                .sum::<usize>()
+            + self.words.memory()
+    }
+
+    /// Classify the words used the most with a word, with the number of
+    /// sentences using both.
+    pub fn related(&self, word: &str, length: usize) -> Vec<(String, usize)> {
+        let Some(related) =
+            self.words.get(word).and_then(|id| self.pairs.get(&id))
+        else {
+            return Vec::new();
+        };
+
+        related
+            .rank(length)
+            .into_iter()
+            .filter_map(|(other, count)| {
+                Some((self.words.resolve(other)?.to_string(), count))
+            })
+            .collect()
+    }
+}
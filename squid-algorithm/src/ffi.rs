@@ -0,0 +1,246 @@
+//! C bindings of [`MapAlgorithm`], so other languages can rank words
+//! in-process.
+//!
+//! The declarations are in `include/squid_algorithm.h`.
+
+use crate::hashtable::MapAlgorithm;
+use std::{
+    ffi::{c_char, CStr, CString, NulError},
+    ptr,
+};
+
+/// A ranked word, as returned to C.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SquidWord {
+    /// The word, as a NUL-terminated UTF-8 string.
+    pub word: *mut c_char,
+    /// Occurrences of the word.
+    pub occurrences: usize,
+}
+
+/// Ranked words, most used first, freed with [`squid_ranking_free`].
+#[repr(C)]
+#[derive(Debug)]
+pub struct SquidRanking {
+    /// First word of the ranking, or null if there is none.
+    pub words: *mut SquidWord,
+    /// Number of words.
+    pub length: usize,
+}
+
+/// Reads a word passed from C.
+///
+/// # Safety
+///
+/// `word` must be null or a valid NUL-terminated string.
+unsafe fn word<'a>(word: *const c_char) -> Option<&'a str> {
+    if word.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(word).to_str().ok()
+}
+
+/// Creates an empty map, freed with [`squid_map_free`].
+#[no_mangle]
+pub extern "C" fn squid_map_new() -> *mut MapAlgorithm {
+    Box::into_raw(Box::default())
+}
+
+/// Counts a word `weight` times.
+///
+/// Returns `false` if the word is null or not valid UTF-8.
+///
+/// # Safety
+///
+/// `map` must come from [`squid_map_new`] and `word` must be a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn squid_map_set(
+    map: *mut MapAlgorithm,
+    word: *const c_char,
+    weight: usize,
+) -> bool {
+    match (map.as_mut(), self::word(word)) {
+        (Some(map), Some(word)) => {
+            map.set_weighted(word, weight);
+            true
+        },
+        _ => false,
+    }
+}
+
+/// Uncounts a word `weight` times.
+///
+/// Returns `false` if the word is null or not valid UTF-8.
+///
+/// # Safety
+///
+/// `map` must come from [`squid_map_new`] and `word` must be a valid
+/// NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn squid_map_remove(
+    map: *mut MapAlgorithm,
+    word: *const c_char,
+    weight: usize,
+) -> bool {
+    match (map.as_mut(), self::word(word)) {
+        (Some(map), Some(word)) => {
+            map.remove_weighted(word, weight);
+            true
+        },
+        _ => false,
+    }
+}
+
+/// Writes the `length` most used words into `ranking`, whose words are
+/// null if there is none.
+///
+/// Returns `false`, and writes an empty ranking, if a word contains a NUL
+/// byte, as words counted from Rust or loaded with serde may.
+///
+/// # Safety
+///
+/// `map` must come from [`squid_map_new`] and `ranking` must be valid for
+/// writes.
+#[no_mangle]
+pub unsafe extern "C" fn squid_map_rank(
+    map: *const MapAlgorithm,
+    length: usize,
+    ranking: *mut SquidRanking,
+) -> bool {
+    let Some(ranking) = ranking.as_mut() else {
+        return false;
+    };
+    *ranking = SquidRanking {
+        words: ptr::null_mut(),
+        length: 0,
+    };
+    let Some(map) = map.as_ref() else {
+        return false;
+    };
+
+    // Converted before any word is handed over, so none leaks on error.
+    let Ok(words) = map
+        .rank(length)
+        .into_iter()
+        .map(|(word, occurrences)| Ok((CString::new(word)?, occurrences)))
+        .collect::<Result<Vec<_>, NulError>>()
+    else {
+        return false;
+    };
+    if words.is_empty() {
+        return true;
+    }
+
+    let words: Box<[SquidWord]> = words
+        .into_iter()
+        .map(|(word, occurrences)| SquidWord {
+            word: word.into_raw(),
+            occurrences,
+        })
+        .collect();
+    *ranking = SquidRanking {
+        length: words.len(),
+        words: Box::into_raw(words).cast(),
+    };
+
+    true
+}
+
+/// Frees a ranking written by [`squid_map_rank`], doing nothing if its
+/// words are null.
+///
+/// # Safety
+///
+/// `ranking` must come from [`squid_map_rank`], and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn squid_ranking_free(ranking: SquidRanking) {
+    if ranking.words.is_null() {
+        return;
+    }
+
+    let words = Box::from_raw(ptr::slice_from_raw_parts_mut(
+        ranking.words,
+        ranking.length,
+    ));
+    for word in words.iter() {
+        drop(CString::from_raw(word.word));
+    }
+}
+
+/// Frees a map created by [`squid_map_new`].
+///
+/// # Safety
+///
+/// `map` must come from [`squid_map_new`], and must not be used
+/// afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn squid_map_free(map: *mut MapAlgorithm) {
+    if !map.is_null() {
+        drop(Box::from_raw(map));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Ranks the words of a map through the C functions.
+    unsafe fn rank(map: *const MapAlgorithm) -> Option<Vec<(String, usize)>> {
+        let mut ranking = SquidRanking {
+            words: ptr::null_mut(),
+            length: 0,
+        };
+        if !squid_map_rank(map, 10, &mut ranking) {
+            assert!(ranking.words.is_null());
+            return None;
+        }
+
+        let words = match ranking.words.is_null() {
+            true => Vec::new(),
+            false => std::slice::from_raw_parts(ranking.words, ranking.length)
+                .iter()
+                .map(|word| {
+                    let text = CStr::from_ptr(word.word).to_str().unwrap();
+                    (text.to_string(), word.occurrences)
+                })
+                .collect(),
+        };
+        squid_ranking_free(ranking);
+
+        Some(words)
+    }
+
+    #[test]
+    fn test_rank_through_c() {
+        unsafe {
+            let map = squid_map_new();
+            assert_eq!(rank(map), Some(Vec::new()));
+
+            assert!(squid_map_set(map, c"hello".as_ptr(), 3));
+            assert!(squid_map_set(map, c"world".as_ptr(), 1));
+            assert!(squid_map_remove(map, c"hello".as_ptr(), 1));
+            assert!(!squid_map_set(map, ptr::null(), 1));
+            assert!(!squid_map_set(map, c"\xff".as_ptr(), 1));
+            assert_eq!(
+                rank(map),
+                Some(vec![("hello".to_string(), 2), ("world".to_string(), 1)])
+            );
+
+            // Words counted from Rust may not be C strings.
+            (*map).set_weighted("nul\0word", 5);
+            assert_eq!(rank(map), None);
+
+            assert!(!squid_map_rank(map, 10, ptr::null_mut()));
+            assert_eq!(rank(ptr::null()), None);
+            squid_ranking_free(SquidRanking {
+                words: ptr::null_mut(),
+                length: 0,
+            });
+            squid_map_free(map);
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{borrow::Borrow, cmp::Reverse};
+
+/// Structure containing the words of a [`MapAlgorithm`], once frozen.
+///
+/// Words are sorted once, the most counted first, so leaderboards are
+/// slices of the ranking which are neither sorted nor copied. Words are
+/// stored contiguously, without the spare capacity of a HashMap, and no
+/// word can be added or removed anymore.
+///
+/// [`MapAlgorithm`]: crate::hashtable::MapAlgorithm
+#[derive(Debug, Clone)]
+pub struct FrozenAlgorithm<K = String> {
+    /// Every word with its occurrences, the most counted first, ties being
+    /// sorted by word.
+    ranking: Box<[(K, usize)]>,
+    /// Position of each word in `ranking`, sorted by word.
+    positions: Box<[u32]>,
+    /// Occurrences of every word.
+    total: usize,
+}
+
+impl<K: Ord> FrozenAlgorithm<K> {
+    /// Sorts the words and their occurrences.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more than `u32::MAX` words.
+    pub fn new<I>(words: I) -> Self
+    where
+        I: IntoIterator<Item = (K, usize)>,
+    {
+        let mut ranking = words
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .collect::<Vec<_>>();
+        ranking.sort_unstable_by(|a, b| {
+            (Reverse(a.1), &a.0).cmp(&(Reverse(b.1), &b.0))
+        });
+
+        let length = u32::try_from(ranking.len())
+            .expect("frozen words must fit in 32 bits positions");
+        let mut positions = (0..length).collect::<Vec<_>>();
+        positions.sort_unstable_by(|a, b| {
+            ranking[*a as usize].0.cmp(&ranking[*b as usize].0)
+        });
+
+        Self {
+            total: ranking.iter().map(|(_, count)| count).sum(),
+            ranking: ranking.into_boxed_slice(),
+            positions: positions.into_boxed_slice(),
+        }
+    }
+
+    /// Returns the rank of a key, starting at 0 for the most counted word.
+    pub fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.positions
+            .binary_search_by(|position| {
+                self.ranking[*position as usize].0.borrow().cmp(key)
+            })
+            .ok()
+            .map(|index| self.positions[index] as usize)
+    }
+
+    /// Returns the occurrences of a key.
+    pub fn get<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.position(key).map_or(0, |position| self.ranking[position].1)
+    }
+
+    /// Returns the number of distinct words.
+    pub fn len(&self) -> usize {
+        self.ranking.len()
+    }
+
+    /// Returns `true` if there is no word.
+    pub fn is_empty(&self) -> bool {
+        self.ranking.is_empty()
+    }
+
+    /// Returns the occurrences of every word, without going through them.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns every word with its occurrences, the most counted first.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize)> {
+        self.ranking.iter().map(|(word, count)| (word, *count))
+    }
+
+    /// Classify the most frequently used words, without sorting nor
+    /// copying them.
+    pub fn rank(&self, length: usize) -> &[(K, usize)] {
+        &self.ranking[..length.min(self.ranking.len())]
+    }
+
+    /// Classify the most frequently used words from the `offset`th one,
+    /// returning at most `limit` words, such as ranks 101 to 200.
+    pub fn rank_range(&self, offset: usize, limit: usize) -> &[(K, usize)] {
+        let start = offset.min(self.ranking.len());
+        let end = offset.saturating_add(limit).min(self.ranking.len());
+
+        &self.ranking[start..end]
+    }
+}
+
+impl FrozenAlgorithm {
+    /// Estimates the memory used by the words and their occurrences, in
+    /// bytes.
+    pub fn memory(&self) -> usize {
+        self.ranking.len()
+            * (std::mem::size_of::<(String, usize)>()
+                + std::mem::size_of::<u32>())
+            + self
+                .ranking
+                .iter()
+                .map(|(word, _)| word.capacity())
+                .sum::<usize>()
+    }
+}
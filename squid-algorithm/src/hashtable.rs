@@ -1,52 +1,1276 @@
+use crate::frozen::FrozenAlgorithm;
 use ahash::RandomState;
-use rayon::prelude::*;
-use std::collections::HashMap;
+use hashbrown::HashTable;
+use std::{
+    borrow::Borrow,
+    cmp::Ordering,
+    collections::BTreeSet,
+    hash::Hash,
+    sync::{Mutex, PoisonError},
+    thread,
+};
+
+/// Number of sentences a thread takes at once with
+/// [`MapAlgorithm::ingest_parallel`].
+const INGEST_BATCH_SIZE: usize = 1024;
+
+/// Most counted words, kept ordered as words are counted.
+///
+/// Words which are not kept are never counted more than `bound` times, so
+/// kept words counted at least as many times are ranked in order. Words are
+/// kept by their identifier in the [`MapAlgorithm`], so they are not copied.
+#[derive(Debug, Clone)]
+struct Top {
+    /// Maximum number of words kept.
+    capacity: usize,
+    /// Kept words, by increasing count.
+    words: BTreeSet<(usize, u32)>,
+    /// Highest count a word which is not kept may have.
+    bound: usize,
+    /// Number of times a kept word fell below `bound`.
+    stale: usize,
+}
+
+impl Top {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            words: BTreeSet::new(),
+            bound: 0,
+            stale: 0,
+        }
+    }
+
+    /// Updates the count of a word from `old` to `new`, 0 meaning it is not
+    /// counted.
+    fn update(&mut self, id: u32, old: usize, new: usize) {
+        let mut entry = (old, id);
+        let kept = old > 0 && self.words.remove(&entry);
+        entry.0 = new;
+
+        if new == 0 {
+            return;
+        }
+        if kept {
+            if new < self.bound && old >= self.bound {
+                self.stale += 1;
+            }
+            self.words.insert(entry);
+        } else if self.words.len() < self.capacity {
+            self.words.insert(entry);
+        } else if self.words.first().is_some_and(|min| new > min.0) {
+            if let Some(min) = self.words.pop_first() {
+                self.bound = self.bound.max(min.0);
+            }
+            self.words.insert(entry);
+        } else {
+            self.bound = self.bound.max(new);
+        }
+    }
+
+    /// Returns whether so many kept words fell below `bound` that few can
+    /// still be ranked.
+    fn is_stale(&self) -> bool {
+        self.stale > self.capacity / 2
+    }
+
+    /// Returns the identifiers of the `length` most counted words, unless
+    /// they cannot be known without going through every word.
+    fn rank(&self, length: usize, total: usize) -> Option<Vec<(u32, usize)>> {
+        let ranking = self
+            .words
+            .iter()
+            .rev()
+            .take(length)
+            .map(|(count, id)| (*id, *count))
+            .collect::<Vec<_>>();
+
+        (ranking.len() == length.min(total)
+            && ranking.last().is_none_or(|(_, count)| *count >= self.bound))
+        .then_some(ranking)
+    }
+}
+
+/// Number of hashes of a word selecting its counters in a [`Guard`].
+const GUARD_HASHES: u64 = 3;
+
+/// Counting Bloom filter of the words seen once, which are not counted yet.
+///
+/// A word may be wrongly found as seen once when its counters are shared
+/// by other words, it is then counted once more than it was added.
+#[derive(Debug, Clone)]
+struct Guard {
+    /// Number of words seen once hashed to each counter.
+    counters: Vec<u8>,
+    /// One hasher per hash of a word.
+    hashers: Vec<RandomState>,
+}
+
+impl Guard {
+    fn new(counters: usize) -> Self {
+        Self {
+            counters: vec![0; counters],
+            hashers: (0..GUARD_HASHES)
+                .map(|hash| RandomState::with_seeds(hash, !hash, hash << 32, 1))
+                .collect(),
+        }
+    }
+
+    /// Returns the counters of a word.
+    fn indexes<'a, Q>(&'a self, key: &'a Q) -> impl Iterator<Item = usize> + 'a
+    where
+        Q: Hash + ?Sized,
+    {
+        self.hashers.iter().map(move |hasher| {
+            hasher.hash_one(key) as usize % self.counters.len()
+        })
+    }
+
+    /// Returns whether a word may have been seen once.
+    fn contains<Q: Hash + ?Sized>(&self, key: &Q) -> bool {
+        self.indexes(key).all(|index| self.counters[index] > 0)
+    }
+
+    /// Records a word as seen once.
+    fn insert<Q: Hash + ?Sized>(&mut self, key: &Q) {
+        for index in self.indexes(key).collect::<Vec<_>>() {
+            self.counters[index] = self.counters[index].saturating_add(1);
+        }
+    }
+
+    /// Forgets a word seen once.
+    ///
+    /// Saturated counters are kept, as the number of words they hold is no
+    /// longer known.
+    fn remove<Q: Hash + ?Sized>(&mut self, key: &Q) {
+        for index in self.indexes(key).collect::<Vec<_>>() {
+            if self.counters[index] < u8::MAX {
+                self.counters[index] = self.counters[index].saturating_sub(1);
+            }
+        }
+    }
+}
+
+/// Number of sentences using each word, counted once per sentence.
+#[derive(Debug, Clone, Default)]
+struct Documents {
+    /// Sentences using each word, at the index of its identifier.
+    frequencies: Vec<usize>,
+    /// Sentences added.
+    count: usize,
+}
+
+impl Documents {
+    /// Adds `weight` sentences using each distinct word, which must be
+    /// counted.
+    fn add(&mut self, ids: &[u32], weight: usize) {
+        self.count += weight;
+        for (index, id) in ids.iter().enumerate() {
+            if ids[..index].contains(id) {
+                continue;
+            }
+            let id = *id as usize;
+            if self.frequencies.len() <= id {
+                self.frequencies.resize(id + 1, 0);
+            }
+            self.frequencies[id] += weight;
+        }
+    }
+
+    /// Removes `weight` sentences using each distinct word.
+    fn remove(&mut self, ids: &[u32], weight: usize) {
+        self.count = self.count.saturating_sub(weight);
+        for (index, id) in ids.iter().enumerate() {
+            if ids[..index].contains(id) {
+                continue;
+            }
+            if let Some(frequency) = self.frequencies.get_mut(*id as usize) {
+                *frequency = frequency.saturating_sub(weight);
+            }
+        }
+    }
+
+    /// Returns the number of sentences using a word.
+    fn get(&self, id: u32) -> usize {
+        self.frequencies.get(id as usize).copied().unwrap_or_default()
+    }
+
+    /// Sets the number of sentences using a word.
+    fn set(&mut self, id: u32, frequency: usize) {
+        let id = id as usize;
+        if self.frequencies.len() <= id {
+            self.frequencies.resize(id + 1, 0);
+        }
+        self.frequencies[id] = frequency;
+    }
+
+    /// Returns the inverse document frequency of a word, 0 if every
+    /// sentence uses it.
+    fn idf(&self, id: u32) -> f64 {
+        let count = self.count.max(1);
+
+        (count as f64 / self.get(id).clamp(1, count) as f64).ln()
+    }
+}
+
+/// Word counted by a [`MapAlgorithm`], at the index of its identifier.
+#[derive(Debug, Clone)]
+struct Slot<K> {
+    word: K,
+    count: usize,
+}
 
 /// Structure containing the data required by the HashMap algorithm.
-#[derive(Debug, Default, Clone)]
-pub struct MapAlgorithm {
-    /// Data from the HashMap.
-    data: HashMap<String, usize, RandomState>,
+///
+/// Words are counted as `String` unless another key is chosen, such as
+/// `u32` identifiers of words given by an [`Interner`], which are smaller
+/// and faster to hash. Keys are given by reference, so counted keys are not
+/// copied again.
+///
+/// Each counted word is stored once, with a `u32` identifier indexing its
+/// count, so the most counted words and the sentences using each word
+/// refer to it without a copy. Identifiers of forgotten words are given to
+/// the next new words.
+///
+/// With the `serde` feature, it is saved as the count of each word, so
+/// counts are loaded back without adding every word again. The most
+/// counted words are not saved, see [`MapAlgorithm::with_top`].
+///
+/// [`Interner`]: crate::intern::Interner
+#[derive(Debug, Clone)]
+pub struct MapAlgorithm<K = String> {
+    /// Counted words, at the index of their identifier, `None` once
+    /// forgotten.
+    slots: Vec<Option<Slot<K>>>,
+    /// Identifier of each counted word, hashed by word.
+    ids: HashTable<u32>,
+    /// Identifiers of forgotten words, given again to new words.
+    free: Vec<u32>,
+    /// Hasher of the words.
+    hasher: RandomState,
+    /// Occurrences of every word.
+    total: usize,
+    /// Most counted words, if kept.
+    top: Option<Top>,
+    /// Words seen once, if they are not counted until seen again.
+    guard: Option<Guard>,
+    /// Maximum number of distinct words, if limited.
+    limit: Option<usize>,
+    /// Sentences using each word, if tracked.
+    documents: Option<Documents>,
 }
 
-impl MapAlgorithm {
+impl<K> Default for MapAlgorithm<K> {
+    fn default() -> Self {
+        Self {
+            slots: Vec::new(),
+            ids: HashTable::new(),
+            free: Vec::new(),
+            hasher: RandomState::new(),
+            total: 0,
+            top: None,
+            guard: None,
+            limit: None,
+            documents: None,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize> serde::Serialize for MapAlgorithm<K> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.collect_map(
+            self.slots
+                .iter()
+                .flatten()
+                .map(|slot| (&slot.word, slot.count)),
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for MapAlgorithm<K>
+where
+    K: serde::Deserialize<'de> + Hash + Eq,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let data =
+            std::collections::HashMap::<K, usize>::deserialize(deserializer)?;
+
+        let mut map = Self::default();
+        for (word, count) in data {
+            map.total += count;
+            map.insert(word, count);
+        }
+
+        Ok(map)
+    }
+}
+
+impl<K: Hash + Eq> MapAlgorithm<K> {
+    /// Returns the identifier of a counted key.
+    fn find<Q>(&self, key: &Q) -> Option<u32>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let slots = &self.slots;
+        self.ids
+            .find(self.hasher.hash_one(key), |id| {
+                slots[*id as usize]
+                    .as_ref()
+                    .is_some_and(|slot| slot.word.borrow() == key)
+            })
+            .copied()
+    }
+
+    /// Returns a counted word.
+    fn slot(&self, id: u32) -> &Slot<K> {
+        self.slots[id as usize]
+            .as_ref()
+            .expect("identifiers of counted words must have a slot")
+    }
+
+    /// Returns a counted word, to be counted again.
+    fn slot_mut(&mut self, id: u32) -> &mut Slot<K> {
+        self.slots[id as usize]
+            .as_mut()
+            .expect("identifiers of counted words must have a slot")
+    }
+
+    /// Counts a new word, returning its identifier.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` distinct words are counted.
+    fn insert(&mut self, word: K, count: usize) -> u32 {
+        let hash = self.hasher.hash_one(&word);
+        let id = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(None);
+            u32::try_from(self.slots.len() - 1)
+                .expect("counted words must fit in 32 bits identifiers")
+        });
+        self.slots[id as usize] = Some(Slot { word, count });
+
+        let Self {
+            slots, ids, hasher, ..
+        } = self;
+        ids.insert_unique(hash, id, |id| {
+            slots[*id as usize]
+                .as_ref()
+                .map_or(0, |slot| hasher.hash_one(&slot.word))
+        });
+
+        id
+    }
+
+    /// Forgets a counted word, its identifier being given to the next new
+    /// word.
+    fn forget(&mut self, id: u32) {
+        let Some(slot) = self.slots[id as usize].take() else {
+            return;
+        };
+        if let Ok(entry) = self
+            .ids
+            .find_entry(self.hasher.hash_one(&slot.word), |other| *other == id)
+        {
+            entry.remove();
+        }
+        if let Some(documents) = &mut self.documents {
+            if let Some(frequency) = documents.frequencies.get_mut(id as usize)
+            {
+                *frequency = 0;
+            }
+        }
+        self.free.push(id);
+    }
+
+    /// Estimates the memory used by the counts and the identifiers of the
+    /// words, in bytes, without the memory the words point to.
+    fn table_memory(&self) -> usize {
+        self.slots.capacity() * std::mem::size_of::<Option<Slot<K>>>()
+            // Each identifier is hashed with a control byte.
+            + self.ids.capacity() * (std::mem::size_of::<u32>() + 1)
+            + self.free.capacity() * std::mem::size_of::<u32>()
+            + self.guard.as_ref().map_or(0, |guard| guard.counters.len())
+            + self.top.as_ref().map_or(0, |top| {
+                top.words.len() * std::mem::size_of::<(usize, u32)>()
+            })
+            + self.documents.as_ref().map_or(0, |documents| {
+                documents.frequencies.capacity() * std::mem::size_of::<usize>()
+            })
+    }
+
+    /// Returns every counted word with its identifier.
+    fn words(&self) -> impl Iterator<Item = (u32, &Slot<K>)> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| Some((id as u32, slot.as_ref()?)))
+    }
+}
+
+impl<K> MapAlgorithm<K>
+where
+    K: Hash + Eq + Ord + Clone,
+{
+    /// Keeps the `capacity` most counted words ordered as they are counted,
+    /// so the most used words are ranked without going through every word
+    /// as long as no more than `capacity` are requested. Disabled if 0.
+    pub fn with_top(mut self, capacity: usize) -> Self {
+        self.top = (capacity > 0).then(|| Top::new(capacity));
+        self.rebuild_top();
+        self
+    }
+
+    /// Keeps the words seen once out of the HashMap, in a filter of
+    /// `counters` bytes, so words are only counted from their second
+    /// occurrence. Disabled if 0.
+    ///
+    /// Most words of noisy sentences are seen once, they then use no more
+    /// memory than the filter. Words seen once are neither counted nor
+    /// ranked, and a few words may be counted once more than they were
+    /// added.
+    pub fn with_guard(mut self, counters: usize) -> Self {
+        self.guard = (counters > 0).then(|| Guard::new(counters));
+        self
+    }
+
+    /// Counts the sentences using each word, added with
+    /// [`MapAlgorithm::set_many`], so words are ranked by
+    /// [`MapAlgorithm::rank_tfidf`].
+    pub fn with_documents(mut self) -> Self {
+        self.documents = Some(Documents::default());
+        self
+    }
+
+    /// Counts at most `limit` distinct words, the least counted ones being
+    /// forgotten once exceeded. Disabled if 0.
+    ///
+    /// A tenth of the words are forgotten at once, so they are not sorted
+    /// again at each new word, the first words in order being forgotten
+    /// among the ones counted as many times. A forgotten word is counted
+    /// from 0 if it is added again.
+    pub fn with_word_limit(mut self, limit: usize) -> Self {
+        self.limit = (limit > 0).then_some(limit);
+        self.evict();
+        self
+    }
+
+    /// Forgets the least counted words if there are more than the limit.
+    fn evict(&mut self) {
+        let Some(limit) = self.limit else {
+            return;
+        };
+        if self.len() <= limit {
+            return;
+        }
+
+        let excess = self.len() - (limit - limit / 10);
+        // Words counted as many times are forgotten in the order of the
+        // words, so the same ones are whichever the order they were added.
+        let mut words = self
+            .words()
+            .map(|(id, slot)| (slot.count, &slot.word, id))
+            .collect::<Vec<_>>();
+        words.select_nth_unstable(excess - 1);
+        let forgotten = words[..excess]
+            .iter()
+            .map(|(count, _, id)| (*id, *count))
+            .collect::<Vec<_>>();
+        for (id, count) in forgotten {
+            self.total -= count;
+            if let Some(top) = &mut self.top {
+                top.update(id, count, 0);
+            }
+            self.forget(id);
+        }
+    }
+
+    /// Keeps the most counted words again, from every word.
+    fn rebuild_top(&mut self) {
+        let Some(mut top) = self.top.take() else {
+            return;
+        };
+
+        let mut ranking = crate::top(
+            self.words().map(|(id, slot)| (id, slot.count)),
+            top.capacity.saturating_add(1),
+            |a, b| b.1.cmp(&a.1),
+        );
+        top.bound = if ranking.len() > top.capacity {
+            ranking.pop().map(|(_, count)| count).unwrap_or_default()
+        } else {
+            0
+        };
+        top.words =
+            ranking.into_iter().map(|(id, count)| (count, id)).collect();
+        top.stale = 0;
+        self.top = Some(top);
+    }
+
     /// Adds data to the data contained in the HashMap.
-    pub fn set<T>(&mut self, key: T)
+    pub fn set<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.set_weighted(key, 1)
+    }
+
+    /// Adds data to the data contained in the HashMap, counting it
+    /// `weight` times.
+    ///
+    /// The key is only copied when it is not counted yet.
+    pub fn set_weighted<Q>(&mut self, key: &Q, weight: usize)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        if weight == 0 {
+            return;
+        }
+
+        let id = self.find(key);
+        let weight = match &mut self.guard {
+            Some(guard) if id.is_none() => {
+                if guard.contains(key) {
+                    // Counts the occurrence kept out by the filter too.
+                    guard.remove(key);
+                    weight + 1
+                } else if weight == 1 {
+                    guard.insert(key);
+                    return;
+                } else {
+                    weight
+                }
+            },
+            _ => weight,
+        };
+
+        self.total += weight;
+        let (id, old) = match id {
+            Some(id) => {
+                let slot = self.slot_mut(id);
+                slot.count += weight;
+                (id, slot.count - weight)
+            },
+            None => (self.insert(key.to_owned(), weight), 0),
+        };
+        if let Some(top) = &mut self.top {
+            top.update(id, old, old + weight);
+        }
+
+        if self.limit.is_some_and(|limit| self.len() > limit) {
+            self.evict();
+        }
+    }
+
+    /// Adds every key of a sentence at once.
+    pub fn set_many<'a, I, Q>(&mut self, keys: I)
+    where
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized + 'a,
+    {
+        self.set_many_weighted(keys, 1)
+    }
+
+    /// Adds every key of a sentence at once, counting each one `weight`
+    /// times.
+    ///
+    /// The sentence is counted `weight` times too, see
+    /// [`MapAlgorithm::with_documents`].
+    pub fn set_many_weighted<'a, I, Q>(&mut self, keys: I, weight: usize)
     where
-        T: ToString,
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized + 'a,
     {
-        self.data
-            .entry(key.to_string())
-            .and_modify(|d| *d += 1)
-            .or_insert(1);
+        if self.documents.is_none() {
+            for key in keys {
+                self.set_weighted(key, weight);
+            }
+            return;
+        }
+
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        for key in &keys {
+            self.set_weighted(*key, weight);
+        }
+        if weight == 0 {
+            return;
+        }
+
+        // Words kept out by the guard or forgotten beyond the limit are not
+        // tracked, so they use no more memory.
+        let ids = keys
+            .into_iter()
+            .filter_map(|key| self.find(key))
+            .collect::<Vec<_>>();
+        if let Some(documents) = &mut self.documents {
+            documents.add(&ids, weight);
+        }
+    }
+
+    /// Removes every key of a sentence added with
+    /// [`MapAlgorithm::set_many_weighted`].
+    pub fn remove_many_weighted<'a, I, Q>(&mut self, keys: I, weight: usize)
+    where
+        I: IntoIterator<Item = &'a Q>,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized + 'a,
+    {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        if self.documents.is_some() {
+            let ids = keys
+                .iter()
+                .filter_map(|key| self.find(*key))
+                .collect::<Vec<_>>();
+            if let Some(documents) = &mut self.documents {
+                documents.remove(&ids, weight);
+            }
+        }
+        for key in keys {
+            self.remove_weighted(key, weight);
+        }
     }
 
     /// Removes data from the data contained in the HashMap.
-    pub fn remove<T>(&mut self, key: T)
+    pub fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        self.remove_weighted(key, 1)
+    }
+
+    /// Removes data added with [`MapAlgorithm::set_weighted`] from the data
+    /// contained in the HashMap.
+    pub fn remove_weighted<Q>(&mut self, key: &Q, weight: usize)
     where
-        T: ToString,
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
     {
-        if let Some(count) = self.data.get_mut(&key.to_string()) {
-            if *count > 1 {
-                *count -= 1;
-            } else {
-                self.data.remove(&key.to_string());
+        let Some(id) = self.find(key) else {
+            if let Some(guard) = &mut self.guard {
+                if weight > 0 && guard.contains(key) {
+                    guard.remove(key);
+                }
+            }
+            return;
+        };
+        let slot = self.slot_mut(id);
+        let old = slot.count;
+        if old > weight {
+            slot.count -= weight;
+        } else {
+            self.forget(id);
+        }
+        self.total -= old.min(weight);
+
+        if let Some(top) = &mut self.top {
+            top.update(id, old, old.saturating_sub(weight));
+            if top.is_stale() {
+                self.rebuild_top();
+            }
+        }
+    }
+
+    /// Adds the counts of another instance, such as one filled by another
+    /// thread.
+    ///
+    /// Sentences using each word are added too if both instances count
+    /// them.
+    pub fn merge(&mut self, other: &MapAlgorithm<K>) {
+        for (word, count) in other.iter() {
+            self.set_weighted(word, count);
+        }
+
+        if let Some(others) = &other.documents {
+            let counted = other
+                .frequencies()
+                .filter_map(|(word, frequency)| {
+                    Some((self.find(word)?, frequency))
+                })
+                .collect::<Vec<_>>();
+            if let Some(documents) = &mut self.documents {
+                documents.count += others.count;
+                for (id, frequency) in counted {
+                    documents.set(id, documents.get(id) + frequency);
+                }
             }
         }
     }
 
+    /// Removes every word, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.ids.clear();
+        self.free.clear();
+        self.total = 0;
+        if let Some(guard) = &mut self.guard {
+            guard.counters.fill(0);
+        }
+        if let Some(documents) = &mut self.documents {
+            documents.frequencies.clear();
+            documents.count = 0;
+        }
+        self.rebuild_top();
+    }
+
+    /// Returns the occurrences of a key.
+    pub fn get<Q>(&self, key: &Q) -> usize
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.find(key).map_or(0, |id| self.slot(id).count)
+    }
+
+    /// Returns the number of distinct words.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    /// Returns `true` if no word has been added.
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Returns the occurrences of every word, without going through them.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Returns the number of sentences counted, if the sentences using each
+    /// word are, see [`MapAlgorithm::with_documents`].
+    pub fn sentences(&self) -> Option<usize> {
+        self.documents.as_ref().map(|documents| documents.count)
+    }
+
+    /// Returns every word with the number of sentences using it, in any
+    /// order, if they are counted.
+    pub fn frequencies(&self) -> impl Iterator<Item = (&K, usize)> {
+        self.documents.iter().flat_map(|documents| {
+            self.words()
+                .map(|(id, slot)| (&slot.word, documents.get(id)))
+                .filter(|(_, frequency)| *frequency > 0)
+        })
+    }
+
+    /// Sets the number of sentences counted, such as to restore the ones
+    /// of [`MapAlgorithm::sentences`]. Ignored if they are not counted.
+    pub fn set_sentences(&mut self, sentences: usize) {
+        if let Some(documents) = &mut self.documents {
+            documents.count = sentences;
+        }
+    }
+
+    /// Sets the number of sentences using a word, such as to restore the
+    /// ones of [`MapAlgorithm::frequencies`] once the word is counted.
+    /// Ignored if they are not counted or if the word is not.
+    pub fn set_frequency<Q>(&mut self, key: &Q, frequency: usize)
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ToOwned<Owned = K> + ?Sized,
+    {
+        let Some(id) = self.find(key) else {
+            return;
+        };
+        if let Some(documents) = &mut self.documents {
+            documents.set(id, frequency);
+        }
+    }
+
+    /// Counts the words of each order of magnitude of occurrences, the
+    /// `i`th count being the words counted more than `10^(i-1)` times and
+    /// at most `10^i` times.
+    pub fn histogram(&self) -> Vec<usize> {
+        crate::histogram(self.iter().map(|(_, count)| count))
+    }
+
+    /// Returns every word with its occurrences, in any order, without
+    /// copying them.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, usize)> {
+        self.words().map(|(_, slot)| (&slot.word, slot.count))
+    }
+
     /// Classify the most frequently used words.
-    pub fn rank(&self, length: usize) -> Vec<(String, usize)> {
-        let mut sorted_word_counts: Vec<_> =
-            self.data.clone().into_iter().collect();
-        sorted_word_counts.sort_by(|a, b| b.1.cmp(&a.1));
+    ///
+    /// Kept words are ranked first, see [`MapAlgorithm::with_top`].
+    pub fn rank(&self, length: usize) -> Vec<(K, usize)> {
+        match self.top.as_ref().and_then(|top| top.rank(length, self.len())) {
+            Some(ranking) => ranking
+                .into_iter()
+                .map(|(id, count)| (self.slot(id).word.clone(), count))
+                .collect(),
+            None => self.rank_by(length, |a, b| b.1.cmp(&a.1)),
+        }
+    }
 
-        let most_used_words: Vec<_> = sorted_word_counts
-            .par_iter()
-            .take(length)
-            .map(|(word, count)| (word.clone(), *count))
-            .collect();
+    /// Classify the most frequently used words from the `offset`th one,
+    /// returning at most `limit` words, such as ranks 101 to 200.
+    pub fn rank_range(&self, offset: usize, limit: usize) -> Vec<(K, usize)> {
+        let mut ranking = self.rank(offset.saturating_add(limit));
+        ranking.drain(..offset.min(ranking.len()));
+
+        ranking
+    }
+
+    /// Classify the most frequently used words, skipping the ones counted
+    /// fewer than `min_count` times.
+    pub fn rank_with_min(
+        &self,
+        length: usize,
+        min_count: usize,
+    ) -> Vec<(K, usize)> {
+        let mut ranking = self.rank(length);
+        // The most counted words come first, skipped words are the last.
+        let kept = ranking.partition_point(|(_, count)| *count >= min_count);
+        ranking.truncate(kept);
+
+        ranking
+    }
+
+    /// Classify the most frequently used words, skipping the `excluded`
+    /// ones, such as words hidden by a single request.
+    ///
+    /// Excluded words are ranked then skipped, so `length` words are still
+    /// returned as long as enough words are counted.
+    pub fn rank_excluding<Q>(
+        &self,
+        length: usize,
+        excluded: &[&Q],
+    ) -> Vec<(K, usize)>
+    where
+        K: Borrow<Q>,
+        Q: Eq + ?Sized,
+    {
+        let mut ranking = self.rank(length.saturating_add(excluded.len()));
+        ranking.retain(|(word, _)| !excluded.contains(&word.borrow()));
+        ranking.truncate(length);
+
+        ranking
+    }
+
+    /// Classify the most frequently used words, with the share of the
+    /// occurrences of every word each one represents, between 0 and 1.
+    pub fn rank_with_share(&self, length: usize) -> Vec<(K, usize, f64)> {
+        crate::with_share(self.rank(length), self.total)
+    }
+
+    /// Classify the words by their occurrences weighted by the inverse of
+    /// the share of sentences using them, with this score.
+    ///
+    /// Words used by every sentence, such as filler words, score 0. Falls
+    /// back to [`MapAlgorithm::rank_with_share`] scores if the sentences
+    /// are not counted, see [`MapAlgorithm::with_documents`].
+    pub fn rank_tfidf(&self, length: usize) -> Vec<(K, usize, f64)> {
+        self.rank_tfidf_with_min(length, 0)
+    }
+
+    /// Classify the words by their TF-IDF score, see
+    /// [`MapAlgorithm::rank_tfidf`], skipping the ones counted fewer than
+    /// `min_count` times.
+    ///
+    /// Skipped words are not ranked, so `length` words are still returned
+    /// as long as enough words are counted.
+    pub fn rank_tfidf_with_min(
+        &self,
+        length: usize,
+        min_count: usize,
+    ) -> Vec<(K, usize, f64)> {
+        let Some(documents) = &self.documents else {
+            let mut ranking = self.rank_with_share(length);
+            let kept = ranking.partition_point(|(_, count, _)| {
+                *count >= min_count
+            });
+            ranking.truncate(kept);
+
+            return ranking;
+        };
+
+        crate::top(
+            self.words()
+                .filter(|(_, slot)| slot.count >= min_count)
+                .map(|(id, slot)| {
+                    let score = slot.count as f64 * documents.idf(id);
+                    (&slot.word, slot.count, score)
+                }),
+            length,
+            |a, b| b.2.total_cmp(&a.2).then_with(|| b.1.cmp(&a.1)),
+        )
+        .into_iter()
+        .map(|(word, count, score)| (word.clone(), count, score))
+        .collect()
+    }
+
+    /// Classify the words in the order given by `compare`, the first ones
+    /// being returned.
+    ///
+    /// Only the returned words are sorted and copied.
+    pub fn rank_by<F>(&self, length: usize, compare: F) -> Vec<(K, usize)>
+    where
+        F: FnMut(&(&K, usize), &(&K, usize)) -> Ordering,
+    {
+        crate::top(self.iter(), length, compare)
+        .into_iter()
+        .map(|(word, count)| (word.clone(), count))
+        .collect()
+    }
+
+    /// Sorts every word once into a read-only structure, ranking without
+    /// sorting nor copying words, and using less memory.
+    ///
+    /// Words can no longer be added, such as for a finished period that
+    /// is only read from now on.
+    pub fn freeze(self) -> FrozenAlgorithm<K> {
+        FrozenAlgorithm::new(
+            self.slots
+                .into_iter()
+                .flatten()
+                .map(|slot| (slot.word, slot.count)),
+        )
+    }
+
+    /// Counts the tokens of many sentences from `threads` threads, such as
+    /// to load a whole dataset at once.
+    ///
+    /// Sentences are tokenized by the caller, such as by the words of a
+    /// tokenizer or the identifiers of an [`Interner`]. Tokens are produced
+    /// by the thread counting them, so lazy iterators are tokenized in
+    /// parallel too.
+    ///
+    /// Each thread takes batches of sentences and counts them in its own
+    /// HashMap, then every HashMap is merged. With 0 threads, one thread
+    /// per core is used.
+    ///
+    /// [`Interner`]: crate::intern::Interner
+    pub fn ingest_parallel<I, S>(&mut self, sentences: I, threads: usize)
+    where
+        I: IntoIterator<Item = S>,
+        I::IntoIter: Send,
+        S: IntoIterator<Item = K>,
+        K: Send,
+    {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, usize::from),
+            threads => threads,
+        };
+        let documents = self.documents.is_some();
+        let sentences = Mutex::new(sentences.into_iter());
+
+        let shards = thread::scope(|scope| {
+            let handles = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut shard = MapAlgorithm::default();
+                        if documents {
+                            shard = shard.with_documents();
+                        }
+                        loop {
+                            let batch = sentences
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .by_ref()
+                                .take(INGEST_BATCH_SIZE)
+                                .collect::<Vec<_>>();
+                            if batch.is_empty() {
+                                return shard;
+                            }
+
+                            for sentence in batch {
+                                let tokens =
+                                    sentence.into_iter().collect::<Vec<_>>();
+                                shard.set_many(&tokens);
+                            }
+                        }
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles
+                .into_iter()
+                .map(|handle| {
+                    handle.join().unwrap_or_else(|panic| {
+                        std::panic::resume_unwind(panic)
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+
+        for shard in &shards {
+            self.merge(shard);
+        }
+    }
+}
+
+impl MapAlgorithm {
+    /// Adds an occurrence of each phrase of 2 to `n` adjacent tokens, see
+    /// [`crate::ngram::phrases`].
+    pub fn set_sequence(&mut self, tokens: &[&str], n: usize) {
+        for phrase in crate::ngram::phrases(tokens, n) {
+            self.set(&phrase)
+        }
+    }
+
+    /// Removes the phrases added with [`MapAlgorithm::set_sequence`].
+    pub fn remove_sequence(&mut self, tokens: &[&str], n: usize) {
+        for phrase in crate::ngram::phrases(tokens, n) {
+            self.remove(&phrase)
+        }
+    }
+
+    /// Estimates the memory used by the words and their occurrences, in
+    /// bytes.
+    pub fn memory(&self) -> usize {
+        self.table_memory()
+            + self
+                .iter()
+                .map(|(word, _)| word.capacity())
+                .sum::<usize>()
+    }
+}
+
+impl MapAlgorithm<u32> {
+    /// Estimates the memory used by the identifiers of the words and their
+    /// occurrences, in bytes, the words being stored by their
+    /// [`Interner`].
+    ///
+    /// [`Interner`]: crate::intern::Interner
+    pub fn memory(&self) -> usize {
+        self.table_memory()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns pseudo-random numbers, the same ones at each run.
+    fn numbers(mut seed: u64) -> impl Iterator<Item = u64> {
+        std::iter::repeat_with(move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        })
+    }
+
+    /// Asserts the ranking of `map` is the one of a sort of every word.
+    fn assert_ranked(map: &MapAlgorithm<String>, length: usize) {
+        let mut expected =
+            map.iter().map(|(_, count)| count).collect::<Vec<_>>();
+        expected.sort_unstable_by(|a, b| b.cmp(a));
+        expected.truncate(length);
+
+        let ranking = map.rank(length);
+        assert_eq!(
+            ranking.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+            expected
+        );
+        assert!(ranking.iter().all(|(word, count)| map.get(word) == *count));
+    }
+
+    #[test]
+    fn test_top_ranks_as_sorting() {
+        for seed in 1..20 {
+            let mut map = MapAlgorithm::<String>::default().with_top(8);
+            let mut numbers = numbers(seed);
+            for _ in 0..5000 {
+                let word = format!("word{}", numbers.next().unwrap() % 50);
+                match numbers.next().unwrap() % 3 {
+                    0 => map.remove_weighted(&word, 2),
+                    _ => map.set(&word),
+                }
+
+                assert_ranked(&map, 1);
+                assert_ranked(&map, 8);
+            }
+            assert_ranked(&map, 50);
+        }
+    }
+
+    #[test]
+    fn test_top_ranks_as_sorting_once_words_are_forgotten() {
+        for seed in 1..20 {
+            let mut map = MapAlgorithm::<String>::default()
+                .with_top(8)
+                .with_word_limit(30);
+            let mut numbers = numbers(seed);
+            for _ in 0..5000 {
+                // Few words are used often, so they are not forgotten.
+                let number = numbers.next().unwrap();
+                let word = format!("word{}", number % (1 + number % 100));
+                match numbers.next().unwrap() % 4 {
+                    0 => map.remove(&word),
+                    _ => map.set(&word),
+                }
+
+                assert!(map.len() <= 30);
+                assert_ranked(&map, 8);
+            }
+
+            // The top rebuilt from every word ranks them the same way.
+            map.rebuild_top();
+            assert_ranked(&map, 8);
+        }
+    }
+
+    #[test]
+    fn test_word_limit_forgets_least_counted_words() {
+        let mut map = MapAlgorithm::<String>::default().with_word_limit(10);
+        for i in 0..10 {
+            map.set_weighted(&format!("word{i}"), i + 2);
+        }
+        map.set("new");
+
+        // 11 words are counted, 9 are kept.
+        assert_eq!(map.len(), 9);
+        assert_eq!(map.get("new"), 0);
+        assert_eq!(map.get("word0"), 0);
+        assert_eq!(map.get("word1"), 3);
+        assert_eq!(map.total(), (3..12).sum());
+    }
+
+    #[test]
+    fn test_word_limit_with_equal_counts() {
+        let words = (0..100).map(|i| format!("word{i:02}")).collect::<Vec<_>>();
+
+        // 51 words are counted once, exactly 45 are kept.
+        let mut map = MapAlgorithm::<String>::default().with_word_limit(50);
+        for word in &words[..51] {
+            map.set(word);
+        }
+        assert_eq!(map.len(), 45);
+        assert_eq!(map.total(), 45);
+        assert_eq!(map.get("word05"), 0);
+        assert_eq!(map.get("word06"), 1);
+
+        // Words are hashed differently by each instance, the same ones are
+        // still kept.
+        let mut maps = [(); 2]
+            .map(|_| MapAlgorithm::<String>::default().with_word_limit(50));
+        for map in &mut maps {
+            for word in words.iter().rev().chain(&words) {
+                map.set(word);
+            }
+        }
+        let [kept, others] = maps.map(|map| {
+            let mut kept = map.rank(map.len());
+            kept.sort_unstable();
+            kept
+        });
+        assert_eq!(kept, others);
+    }
+
+    #[test]
+    fn test_rank_tfidf_with_min() {
+        let mut map = MapAlgorithm::<String>::default().with_documents();
+        map.set_many(["common", "frequent", "rare", "rare"]);
+        for _ in 0..18 {
+            map.set_many(["common", "frequent"]);
+        }
+        map.set_many(["common"]);
+
+        // The rare word scores higher but is skipped, the page is still
+        // full.
+        assert_eq!(map.rank_tfidf(1)[0].0, "rare");
+        let ranking = map.rank_tfidf_with_min(2, 3);
+        assert_eq!(
+            ranking
+                .iter()
+                .map(|(word, count, _)| (word.as_str(), *count))
+                .collect::<Vec<_>>(),
+            [("frequent", 19), ("common", 20)]
+        );
+    }
+
+    #[test]
+    fn test_ingest_parallel() {
+        let sentences = (0..10_000)
+            .map(|i| format!("word{} word{} common", i % 7, i % 100))
+            .collect::<Vec<_>>();
+
+        let mut expected = MapAlgorithm::<String>::default();
+        for sentence in &sentences {
+            expected.set_many(sentence.split_whitespace());
+        }
+        let mut map = MapAlgorithm::<String>::default();
+        map.ingest_parallel(
+            sentences.iter().map(|sentence| {
+                sentence.split_whitespace().map(str::to_owned)
+            }),
+            4,
+        );
+
+        assert_eq!(map.total(), expected.total());
+        assert!(expected.iter().all(|(word, count)| map.get(word) == count));
+
+        // Identifiers of interned words are counted the same way.
+        let mut interner = crate::intern::Interner::default();
+        let ids = sentences
+            .iter()
+            .map(|sentence| {
+                sentence
+                    .split_whitespace()
+                    .map(|word| interner.intern(word))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let mut map = MapAlgorithm::<u32>::default().with_documents();
+        map.ingest_parallel(ids, 0);
+
+        let common = interner.get("common").unwrap();
+        assert_eq!(map.get(&common), 10_000);
+        assert_eq!(map.sentences(), Some(10_000));
+        assert_eq!(map.len(), expected.len());
+    }
+
+    #[test]
+    fn test_documents_only_track_counted_words() {
+        let mut map = MapAlgorithm::<String>::default()
+            .with_guard(1024)
+            .with_word_limit(10)
+            .with_documents();
+        for i in 0..100 {
+            let word = format!("word{i}");
+            map.set_many(["common", word.as_str()]);
+        }
+
+        assert_eq!(map.sentences(), Some(100));
+        assert!(map.frequencies().count() <= map.len());
+        assert!(map.frequencies().all(|(word, _)| map.get(word) > 0));
+        assert_eq!(
+            map.frequencies().find(|(word, _)| *word == "common"),
+            Some((&"common".to_string(), 99))
+        );
+    }
+
+    #[test]
+    fn test_forgotten_words_free_their_identifier() {
+        let mut map = MapAlgorithm::<String>::default()
+            .with_top(5)
+            .with_word_limit(10);
+        for i in 0..1000 {
+            map.set(&format!("word{i}"));
+            map.set("common");
+        }
+        for i in 0..3 {
+            map.remove(&format!("word{}", 999 - i));
+        }
 
-        most_used_words
+        assert!(map.slots.len() <= 11);
+        assert_eq!(map.len(), map.ids.len());
+        assert_eq!(map.total(), map.iter().map(|(_, count)| count).sum());
+        assert_eq!(map.get("common"), 1000);
+        assert_eq!(map.rank(1), vec![("common".to_string(), 1000)]);
     }
 }
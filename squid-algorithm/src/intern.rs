@@ -0,0 +1,78 @@
+use ahash::RandomState;
+use std::{collections::HashMap, sync::Arc};
+
+/// Structure giving each distinct word a `u32` identifier.
+///
+/// Each word is allocated once, whichever the number of times it is
+/// interned, so words are counted by [`MapAlgorithm<u32>`] with keys of 4
+/// bytes rather than a copy of the word. Words are never forgotten, unless
+/// every word is cleared.
+///
+/// [`MapAlgorithm<u32>`]: crate::hashtable::MapAlgorithm
+#[derive(Debug, Default, Clone)]
+pub struct Interner {
+    /// Identifier of each word.
+    ids: HashMap<Arc<str>, u32, RandomState>,
+    /// Words, at the index of their identifier.
+    words: Vec<Arc<str>>,
+}
+
+impl Interner {
+    /// Returns the identifier of a word, given to it if it is new.
+    ///
+    /// # Panics
+    ///
+    /// Panics if more than `u32::MAX` distinct words are interned.
+    pub fn intern(&mut self, word: &str) -> u32 {
+        if let Some(id) = self.ids.get(word) {
+            return *id;
+        }
+
+        let id = u32::try_from(self.words.len())
+            .expect("interned words must fit in 32 bits identifiers");
+        let word = Arc::<str>::from(word);
+        self.words.push(Arc::clone(&word));
+        self.ids.insert(word, id);
+
+        id
+    }
+
+    /// Returns the identifier of a word, if it was interned.
+    pub fn get(&self, word: &str) -> Option<u32> {
+        self.ids.get(word).copied()
+    }
+
+    /// Returns the word of an identifier.
+    pub fn resolve(&self, id: u32) -> Option<&str> {
+        self.words.get(id as usize).map(AsRef::as_ref)
+    }
+
+    /// Returns the number of distinct words.
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns `true` if no word has been interned.
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Forgets every word, keeping the allocated memory.
+    pub fn clear(&mut self) {
+        self.ids.clear();
+        self.words.clear();
+    }
+
+    /// Estimates the memory used by the words, in bytes.
+    pub fn memory(&self) -> usize {
+        self.ids.capacity()
+            * (std::mem::size_of::<Arc<str>>() + std::mem::size_of::<u32>())
+            + self.words.capacity() * std::mem::size_of::<Arc<str>>()
+            + self
+                .words
+                .iter()
+                // Both reference counts are stored with the word.
+                .map(|word| word.len() + 2 * std::mem::size_of::<usize>())
+                .sum::<usize>()
+    }
+}
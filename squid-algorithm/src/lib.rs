@@ -3,9 +3,128 @@
 //! crazy algorithms to quickly rank the most frequently used words in a sentence!
 //! Supported algorithms:
 //! - HashMap;
+//! - Concurrent HashMap, split between shards;
+//! - Count-Min Sketch;
+//! - Sliding window, ranking recent words;
+//! - Frozen ranking, read-only once sorted;
+//! - Tagged HashMap, ranking the words of each tag;
 
-#![forbid(unsafe_code)]
+#![cfg_attr(not(feature = "ffi"), forbid(unsafe_code))]
 #![deny(dead_code, unused_imports, unused_mut, missing_docs)]
 
+/// An estimator of the number of distinct words seen.
+pub mod cardinality;
+/// The HashMap algorithm, counting from several threads at once.
+pub mod concurrent;
+/// Words used together in the same sentences.
+pub mod cooccurrence;
+/// C bindings of the HashMap algorithm.
+#[cfg(feature = "ffi")]
+pub mod ffi;
+/// A read-only ranking, sorted once.
+pub mod frozen;
 /// The most accurate algorithm for ranking.
 pub mod hashtable;
+/// Identifiers of words, each word being stored once.
+pub mod intern;
+/// Phrases of adjacent words, counted like words.
+pub mod ngram;
+/// A memory-bounded algorithm estimating occurrences.
+pub mod sketch;
+/// JavaScript bindings of both algorithms.
+#[cfg(feature = "wasm")]
+pub mod wasm;
+/// Words counted within each tag.
+pub mod tagged;
+/// An algorithm ranking the words of the last hours.
+pub mod window;
+
+use std::cmp::Ordering;
+
+/// Returns the first `length` items in the order given by `compare`.
+///
+/// Items are selected by batches, so at most twice `length` items are kept
+/// at once, and only the selected ones are sorted.
+pub fn top<T, I, F>(items: I, length: usize, mut compare: F) -> Vec<T>
+where
+    I: IntoIterator<Item = T>,
+    F: FnMut(&T, &T) -> Ordering,
+{
+    if length == 0 {
+        return Vec::new();
+    }
+
+    let batch = length.saturating_mul(2);
+    let mut top = Vec::new();
+    for item in items {
+        top.push(item);
+        if top.len() == batch {
+            top.select_nth_unstable_by(length - 1, &mut compare);
+            top.truncate(length);
+        }
+    }
+
+    if top.len() > length {
+        top.select_nth_unstable_by(length - 1, &mut compare);
+        top.truncate(length);
+    }
+    top.sort_unstable_by(compare);
+
+    top
+}
+
+/// Counts the words of each order of magnitude of occurrences.
+///
+/// The `i`th count is the number of words counted more than `10^(i-1)`
+/// times and at most `10^i` times: once, 2 to 10 times, 11 to 100 times,
+/// and so on. Words counted 0 times are skipped.
+pub(crate) fn histogram<I>(counts: I) -> Vec<usize>
+where
+    I: IntoIterator<Item = usize>,
+{
+    let mut histogram = Vec::new();
+    for count in counts {
+        record(&mut histogram, count);
+    }
+
+    histogram
+}
+
+/// Adds a word counted `count` times to a [`histogram`].
+pub(crate) fn record(histogram: &mut Vec<usize>, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    let mut index = 0;
+    let mut bound = 1usize;
+    while count > bound {
+        bound = bound.saturating_mul(10);
+        index += 1;
+    }
+
+    if histogram.len() <= index {
+        histogram.resize(index + 1, 0);
+    }
+    histogram[index] += 1;
+}
+
+/// Adds to each ranked word the share of the `total` occurrences it
+/// represents, 0 if there are none.
+pub fn with_share<T>(
+    ranking: Vec<(T, usize)>,
+    total: usize,
+) -> Vec<(T, usize, f64)> {
+    ranking
+        .into_iter()
+        .map(|(word, count)| {
+            let share = if total == 0 {
+                0.0
+            } else {
+                count as f64 / total as f64
+            };
+
+            (word, count, share)
+        })
+        .collect()
+}
@@ -0,0 +1,22 @@
+/// Written between the words of a phrase, as Squid writes spaces within a
+/// word.
+pub const SEPARATOR: &str = "%20";
+
+/// Returns the sequences of `n` adjacent tokens, as phrases.
+pub fn ngrams<'a>(
+    tokens: &'a [&str],
+    n: usize,
+) -> impl Iterator<Item = String> + 'a {
+    tokens
+        .windows(n.max(1))
+        .map(|window| window.join(SEPARATOR))
+}
+
+/// Returns the sequences of 2 to `n` adjacent tokens, as phrases, the
+/// shortest first.
+pub fn phrases<'a>(
+    tokens: &'a [&str],
+    n: usize,
+) -> impl Iterator<Item = String> + 'a {
+    (2..=n).flat_map(move |n| ngrams(tokens, n))
+}
@@ -0,0 +1,295 @@
+use ahash::RandomState;
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Number of counters per row if not specified.
+const DEFAULT_WIDTH: usize = 2048;
+/// Number of rows, and hash functions, if not specified.
+const DEFAULT_DEPTH: usize = 4;
+/// Number of words ranked if not specified.
+const DEFAULT_CAPACITY: usize = 1000;
+
+/// Structure containing the data required by the Count-Min Sketch algorithm.
+///
+/// Occurrences are estimated from a fixed-size matrix of counters, so memory
+/// does not grow with the vocabulary. Only the `capacity` most used words are
+/// remembered to be ranked.
+#[derive(Debug, Clone)]
+pub struct SketchAlgorithm {
+    /// Number of counters per row.
+    width: usize,
+    /// Counters, `depth` rows of `width` counters.
+    counters: Vec<Vec<usize>>,
+    /// One hasher per row.
+    hashers: Vec<RandomState>,
+    /// Maximum number of words remembered.
+    capacity: usize,
+    /// Most used words, with their estimated occurrences.
+    candidates: HashMap<String, usize, RandomState>,
+}
+
+impl Default for SketchAlgorithm {
+    fn default() -> Self {
+        Self::new(DEFAULT_WIDTH, DEFAULT_DEPTH, DEFAULT_CAPACITY)
+    }
+}
+
+impl SketchAlgorithm {
+    /// Creates a sketch of `depth` rows of `width` counters, ranking up to
+    /// `capacity` words.
+    ///
+    /// Larger widths reduce overestimation, more rows reduce the probability
+    /// of overestimating.
+    pub fn new(width: usize, depth: usize, capacity: usize) -> Self {
+        let width = width.max(1);
+        let depth = depth.max(1);
+
+        Self {
+            width,
+            counters: vec![vec![0; width]; depth],
+            hashers: (0..depth as u64)
+                .map(|row| RandomState::with_seeds(row, !row, row << 32, 0))
+                .collect(),
+            capacity,
+            candidates: HashMap::default(),
+        }
+    }
+
+    /// Returns the column of each row associated to a key.
+    fn columns<'a>(&'a self, key: &'a str) -> impl Iterator<Item = usize> + 'a {
+        self.hashers
+            .iter()
+            .map(move |hasher| hasher.hash_one(key) as usize % self.width)
+    }
+
+    /// Estimates the occurrences of a key.
+    ///
+    /// The estimate is never lower than the real value.
+    pub fn estimate<T>(&self, key: T) -> usize
+    where
+        T: ToString,
+    {
+        let key = key.to_string();
+
+        self.columns(&key)
+            .zip(&self.counters)
+            .map(|(column, row)| row[column])
+            .min()
+            .unwrap_or_default()
+    }
+
+    /// Adds an occurrence of a key.
+    pub fn set<T>(&mut self, key: T)
+    where
+        T: ToString,
+    {
+        self.set_weighted(key, 1)
+    }
+
+    /// Adds `weight` occurrences of a key.
+    pub fn set_weighted<T>(&mut self, key: T, weight: usize)
+    where
+        T: ToString,
+    {
+        if weight == 0 {
+            return;
+        }
+
+        let key = key.to_string();
+        let columns = self.columns(&key).collect::<Vec<_>>();
+
+        for (row, column) in self.counters.iter_mut().zip(columns) {
+            row[column] += weight;
+        }
+
+        let estimate = self.estimate(&key);
+        self.candidates.insert(key, estimate);
+
+        if self.candidates.len() > self.capacity {
+            if let Some(least) = self
+                .candidates
+                .iter()
+                .min_by_key(|(_, count)| **count)
+                .map(|(word, _)| word.clone())
+            {
+                self.candidates.remove(&least);
+            }
+        }
+    }
+
+    /// Removes an occurrence of a key.
+    pub fn remove<T>(&mut self, key: T)
+    where
+        T: ToString,
+    {
+        self.remove_weighted(key, 1)
+    }
+
+    /// Removes `weight` occurrences of a key.
+    pub fn remove_weighted<T>(&mut self, key: T, weight: usize)
+    where
+        T: ToString,
+    {
+        let key = key.to_string();
+        let columns = self.columns(&key).collect::<Vec<_>>();
+
+        for (row, column) in self.counters.iter_mut().zip(columns) {
+            row[column] = row[column].saturating_sub(weight);
+        }
+
+        match self.estimate(&key) {
+            0 => {
+                self.candidates.remove(&key);
+            },
+            estimate => {
+                if let Some(count) = self.candidates.get_mut(&key) {
+                    *count = estimate;
+                }
+            },
+        }
+    }
+
+    /// Removes every occurrence and ranked word.
+    pub fn clear(&mut self) {
+        for row in &mut self.counters {
+            row.fill(0);
+        }
+        self.candidates.clear();
+    }
+
+    /// Adds an occurrence of each phrase of 2 to `n` adjacent tokens, see
+    /// [`crate::ngram::phrases`].
+    pub fn set_sequence(&mut self, tokens: &[&str], n: usize) {
+        for phrase in crate::ngram::phrases(tokens, n) {
+            self.set(phrase)
+        }
+    }
+
+    /// Removes the phrases added with [`SketchAlgorithm::set_sequence`].
+    pub fn remove_sequence(&mut self, tokens: &[&str], n: usize) {
+        for phrase in crate::ngram::phrases(tokens, n) {
+            self.remove(phrase)
+        }
+    }
+
+    /// Returns the number of ranked words.
+    ///
+    /// It is at most `capacity`, and not the number of distinct words.
+    pub fn len(&self) -> usize {
+        self.candidates.len()
+    }
+
+    /// Returns `true` if no word is ranked.
+    pub fn is_empty(&self) -> bool {
+        self.candidates.is_empty()
+    }
+
+    /// Returns the occurrences of every word, including the ones which are
+    /// not ranked.
+    ///
+    /// Each row counts every occurrence once, so the total is exact.
+    pub fn total(&self) -> usize {
+        self.counters
+            .first()
+            .map_or(0, |row| row.iter().sum::<usize>())
+    }
+
+    /// Returns the ranked words with their estimated occurrences, in any
+    /// order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.candidates
+            .iter()
+            .map(|(word, count)| (word.as_str(), *count))
+    }
+
+    /// Estimates the memory used by the counters and the ranked words, in
+    /// bytes.
+    pub fn memory(&self) -> usize {
+        self.counters.len() * self.width * std::mem::size_of::<usize>()
+            + self.candidates.capacity()
+                * (std::mem::size_of::<String>() + std::mem::size_of::<usize>())
+            + self.candidates.keys().map(String::capacity).sum::<usize>()
+    }
+
+    /// Classify the most frequently used words.
+    pub fn rank(&self, length: usize) -> Vec<(String, usize)> {
+        self.rank_by(length, |a, b| b.1.cmp(&a.1))
+    }
+
+    /// Classify the most frequently used words from the `offset`th one,
+    /// returning at most `limit` words, such as ranks 101 to 200.
+    pub fn rank_range(
+        &self,
+        offset: usize,
+        limit: usize,
+    ) -> Vec<(String, usize)> {
+        let mut ranking = self.rank(offset.saturating_add(limit));
+        ranking.drain(..offset.min(ranking.len()));
+
+        ranking
+    }
+
+    /// Classify the most frequently used words, skipping the ones counted
+    /// fewer than `min_count` times.
+    pub fn rank_with_min(
+        &self,
+        length: usize,
+        min_count: usize,
+    ) -> Vec<(String, usize)> {
+        let mut ranking = self.rank(length);
+        // The most counted words come first, skipped words are the last.
+        let kept = ranking.partition_point(|(_, count)| *count >= min_count);
+        ranking.truncate(kept);
+
+        ranking
+    }
+
+    /// Classify the most frequently used words, skipping the `excluded`
+    /// ones, such as words hidden by a single request.
+    ///
+    /// Excluded words are ranked then skipped, so `length` words are still
+    /// returned as long as enough words are counted.
+    pub fn rank_excluding(
+        &self,
+        length: usize,
+        excluded: &[&str],
+    ) -> Vec<(String, usize)> {
+        let mut ranking = self.rank(length.saturating_add(excluded.len()));
+        ranking.retain(|(word, _)| !excluded.contains(&word.as_str()));
+        ranking.truncate(length);
+
+        ranking
+    }
+
+    /// Counts the ranked words of each order of magnitude of estimated
+    /// occurrences, the `i`th count being the words counted more than
+    /// `10^(i-1)` times and at most `10^i` times.
+    ///
+    /// Words which are not ranked are not included.
+    pub fn histogram(&self) -> Vec<usize> {
+        crate::histogram(self.candidates.values().copied())
+    }
+
+    /// Classify the most frequently used words, with the share of the
+    /// occurrences of every word each one represents, between 0 and 1.
+    pub fn rank_with_share(&self, length: usize) -> Vec<(String, usize, f64)> {
+        crate::with_share(self.rank(length), self.total())
+    }
+
+    /// Classify the ranked words in the order given by `compare`, the first
+    /// ones being returned.
+    pub fn rank_by<F>(&self, length: usize, compare: F) -> Vec<(String, usize)>
+    where
+        F: FnMut(&(&str, usize), &(&str, usize)) -> Ordering,
+    {
+        crate::top(
+            self.candidates
+                .iter()
+                .map(|(word, count)| (word.as_str(), *count)),
+            length,
+            compare,
+        )
+        .into_iter()
+        .map(|(word, count)| (word.to_string(), count))
+        .collect()
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{hashtable::MapAlgorithm, intern::Interner};
+use ahash::RandomState;
+use std::collections::HashMap;
+
+/// Structure containing the data required to rank the words of each tag.
+///
+/// Each tag counts the words of the sentences tagged with it, so the words
+/// most used within a tag are ranked like a leaderboard. A sentence with
+/// `n` tags is counted once per tag.
+///
+/// Words are interned, so each word is stored once whichever the number of
+/// tags it is used with. Interned words are kept until every tag is
+/// cleared.
+#[derive(Debug, Clone, Default)]
+pub struct TaggedAlgorithm {
+    /// Identifiers of the words of each tag, and their occurrences.
+    tags: HashMap<String, MapAlgorithm<u32>, RandomState>,
+    /// Words of the identifiers.
+    words: Interner,
+}
+
+impl TaggedAlgorithm {
+    /// Creates an empty structure.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the words of a sentence to each of its tags, once.
+    pub fn set_sentence(&mut self, tags: &[&str], words: &[&str]) {
+        self.set_sentence_weighted(tags, words, 1)
+    }
+
+    /// Adds `weight` times the words of a sentence to each of its tags.
+    ///
+    /// A tag given several times is only counted once.
+    pub fn set_sentence_weighted(
+        &mut self,
+        tags: &[&str],
+        words: &[&str],
+        weight: usize,
+    ) {
+        if weight == 0 || words.is_empty() {
+            return;
+        }
+
+        let ids = words
+            .iter()
+            .map(|word| self.words.intern(word))
+            .collect::<Vec<_>>();
+        for (index, tag) in tags.iter().enumerate() {
+            if tags[..index].contains(tag) {
+                continue;
+            }
+
+            let counts = self.tags.entry(tag.to_string()).or_default();
+            for id in &ids {
+                counts.set_weighted(id, weight);
+            }
+        }
+    }
+
+    /// Removes the words of a sentence added once.
+    pub fn remove_sentence(&mut self, tags: &[&str], words: &[&str]) {
+        self.remove_sentence_weighted(tags, words, 1)
+    }
+
+    /// Removes the words of a sentence added with `weight`.
+    ///
+    /// Tags no longer holding any word are forgotten.
+    pub fn remove_sentence_weighted(
+        &mut self,
+        tags: &[&str],
+        words: &[&str],
+        weight: usize,
+    ) {
+        // Words never interned were never counted.
+        let ids = words
+            .iter()
+            .filter_map(|word| self.words.get(word))
+            .collect::<Vec<_>>();
+        for (index, tag) in tags.iter().enumerate() {
+            if tags[..index].contains(tag) {
+                continue;
+            }
+            let Some(counts) = self.tags.get_mut(*tag) else {
+                continue;
+            };
+
+            for id in &ids {
+                counts.remove_weighted(id, weight);
+            }
+            if counts.is_empty() {
+                self.tags.remove(*tag);
+            }
+        }
+    }
+
+    /// Adds `count` occurrences of a word to a tag, such as to restore the
+    /// counts of [`TaggedAlgorithm::for_each`].
+    pub fn set_pair(&mut self, tag: &str, word: &str, count: usize) {
+        if count == 0 {
+            return;
+        }
+
+        let word = self.words.intern(word);
+        self.tags
+            .entry(tag.to_string())
+            .or_default()
+            .set_weighted(&word, count)
+    }
+
+    /// Forgets a tag, and every word counted within it.
+    pub fn purge(&mut self, tag: &str) {
+        self.tags.remove(tag);
+    }
+
+    /// Returns the occurrences of a word within a tag.
+    pub fn get(&self, tag: &str, word: &str) -> usize {
+        let Some(word) = self.words.get(word) else {
+            return 0;
+        };
+
+        self.tags.get(tag).map_or(0, |counts| counts.get(&word))
+    }
+
+    /// Returns the number of distinct words of a tag.
+    pub fn len_tag(&self, tag: &str) -> usize {
+        self.tags.get(tag).map_or(0, MapAlgorithm::len)
+    }
+
+    /// Returns the number of tags holding at least one word.
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    /// Returns whether no word was added to any tag.
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Removes every tag and forgets every word, keeping the allocated
+    /// memory.
+    pub fn clear(&mut self) {
+        self.tags.clear();
+        self.words.clear();
+    }
+
+    /// Calls `f` with every tag, each word counted within it, and its
+    /// occurrences, in any order.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, &str, usize),
+    {
+        for (tag, counts) in &self.tags {
+            for (word, count) in counts.iter() {
+                if let Some(word) = self.words.resolve(*word) {
+                    f(tag, word, count)
+                }
+            }
+        }
+    }
+
+    /// Estimates the memory used, in bytes.
+    pub fn memory(&self) -> usize {
+        self.tags.capacity()
+            * (std::mem::size_of::<String>()
+                + std::mem::size_of::<MapAlgorithm<u32>>())
+            + self
+                .tags
+                .iter()
+                .map(|(tag, counts)| tag.capacity() + counts.memory())
+                .sum::<usize>()
+            + self.words.memory()
+    }
+
+    /// Classify the words used the most within a tag, with their
+    /// occurrences.
+    pub fn rank_by_tag(
+        &self,
+        tag: &str,
+        length: usize,
+    ) -> Vec<(String, usize)> {
+        let Some(counts) = self.tags.get(tag) else {
+            return Vec::new();
+        };
+
+        counts
+            .rank(length)
+            .into_iter()
+            .filter_map(|(word, count)| {
+                Some((self.words.resolve(word)?.to_string(), count))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rank_by_tag() {
+        let mut tagged = TaggedAlgorithm::new();
+        tagged.set_sentence(&["sport", "news"], &["goal", "match", "goal"]);
+        tagged.set_sentence_weighted(&["sport"], &["match", "referee"], 3);
+        tagged.set_sentence(&["news"], &["vote"]);
+
+        assert_eq!(
+            tagged.rank_by_tag("sport", 10),
+            [
+                ("match".to_string(), 4),
+                ("referee".to_string(), 3),
+                ("goal".to_string(), 2),
+            ]
+        );
+        assert_eq!(tagged.rank_by_tag("sport", 1)[0], ("match".to_string(), 4));
+        assert_eq!(tagged.get("news", "goal"), 2);
+        assert_eq!(tagged.get("news", "referee"), 0);
+        assert_eq!(tagged.len_tag("news"), 3);
+        assert!(tagged.rank_by_tag("music", 10).is_empty());
+        assert_eq!(tagged.len(), 2);
+    }
+
+    #[test]
+    fn test_remove_sentence() {
+        let mut tagged = TaggedAlgorithm::new();
+        // A tag given twice is counted once.
+        tagged.set_sentence(&["sport", "sport"], &["goal"]);
+        tagged.set_sentence(&["news"], &["vote"]);
+        assert_eq!(tagged.get("sport", "goal"), 1);
+
+        tagged.remove_sentence(&["sport", "sport"], &["goal", "unknown"]);
+        assert_eq!(tagged.get("sport", "goal"), 0);
+        assert_eq!(tagged.len_tag("sport"), 0);
+        assert_eq!(tagged.len(), 1);
+
+        let mut restored = TaggedAlgorithm::new();
+        tagged.for_each(|tag, word, count| restored.set_pair(tag, word, count));
+        assert_eq!(restored.rank_by_tag("news", 10), [("vote".to_string(), 1)]);
+
+        restored.purge("news");
+        assert!(restored.is_empty());
+    }
+}
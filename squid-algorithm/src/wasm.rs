@@ -0,0 +1,104 @@
+//! Both algorithms, usable from JavaScript in a browser to preview the
+//! rankings of Squid.
+
+use crate::{hashtable::MapAlgorithm, sketch::SketchAlgorithm};
+use wasm_bindgen::prelude::*;
+
+/// A ranked word.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone)]
+pub struct Word {
+    /// The word, as counted.
+    pub word: String,
+    /// Occurrences of the word, estimated with a sketch.
+    pub occurrences: usize,
+}
+
+/// Converts a ranking for JavaScript.
+fn words(ranking: Vec<(String, usize)>) -> Vec<Word> {
+    ranking
+        .into_iter()
+        .map(|(word, occurrences)| Word { word, occurrences })
+        .collect()
+}
+
+/// Counts words with [`MapAlgorithm`].
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Map(MapAlgorithm);
+
+#[wasm_bindgen]
+impl Map {
+    /// Creates an empty map.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a word `weight` times.
+    pub fn set(&mut self, word: &str, weight: usize) {
+        self.0.set_weighted(word, weight)
+    }
+
+    /// Uncounts a word `weight` times.
+    pub fn remove(&mut self, word: &str, weight: usize) {
+        self.0.remove_weighted(word, weight)
+    }
+
+    /// Returns the occurrences of a word.
+    pub fn get(&self, word: &str) -> usize {
+        self.0.get(word)
+    }
+
+    /// Number of distinct words.
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns the `length` most used words.
+    pub fn rank(&self, length: usize) -> Vec<Word> {
+        words(self.0.rank(length))
+    }
+}
+
+/// Counts words with [`SketchAlgorithm`].
+#[wasm_bindgen]
+#[derive(Debug, Default)]
+pub struct Sketch(SketchAlgorithm);
+
+#[wasm_bindgen]
+impl Sketch {
+    /// Creates a sketch of `depth` rows of `width` counters, ranking up to
+    /// `capacity` words.
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, depth: usize, capacity: usize) -> Self {
+        Self(SketchAlgorithm::new(width, depth, capacity))
+    }
+
+    /// Counts a word `weight` times.
+    pub fn set(&mut self, word: &str, weight: usize) {
+        self.0.set_weighted(word, weight)
+    }
+
+    /// Uncounts a word `weight` times.
+    pub fn remove(&mut self, word: &str, weight: usize) {
+        self.0.remove_weighted(word, weight)
+    }
+
+    /// Returns the estimated occurrences of a word.
+    pub fn get(&self, word: &str) -> usize {
+        self.0.estimate(word)
+    }
+
+    /// Number of words which can be ranked.
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns the `length` most used words.
+    pub fn rank(&self, length: usize) -> Vec<Word> {
+        words(self.0.rank(length))
+    }
+}
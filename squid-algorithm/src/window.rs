@@ -0,0 +1,271 @@
+use ahash::RandomState;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Seconds covered by a bucket if not specified.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(3600);
+/// Number of buckets kept if not specified.
+const DEFAULT_BUCKETS: usize = 24;
+
+/// Occurrences counted during a bucket.
+#[derive(Debug, Clone)]
+struct Bucket {
+    /// Start of the bucket, as a UNIX timestamp in seconds.
+    start: u64,
+    /// Occurrences of each word during the bucket.
+    counts: HashMap<String, usize, RandomState>,
+}
+
+/// Structure containing the data required by the sliding window algorithm.
+///
+/// Occurrences are counted in buckets of fixed length, so the most used
+/// words of the last hours are ranked. Buckets older than the window are
+/// dropped as new ones start.
+#[derive(Debug, Clone)]
+pub struct WindowAlgorithm {
+    /// Seconds covered by a bucket.
+    interval: u64,
+    /// Maximum number of buckets kept.
+    capacity: usize,
+    /// Buckets, the oldest first.
+    buckets: VecDeque<Bucket>,
+}
+
+impl Default for WindowAlgorithm {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVAL, DEFAULT_BUCKETS)
+    }
+}
+
+impl WindowAlgorithm {
+    /// Creates a window of `buckets` buckets, each covering `interval`.
+    ///
+    /// Words are ranked over whole buckets, so shorter intervals rank the
+    /// words of a duration more precisely.
+    pub fn new(interval: Duration, buckets: usize) -> Self {
+        Self {
+            interval: interval.as_secs().max(1),
+            capacity: buckets.max(1),
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Returns the time covered by the window.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(self.interval.saturating_mul(self.capacity as u64))
+    }
+
+    /// Adds an occurrence of a key, seen at `timestamp` in seconds.
+    pub fn set_at<T>(&mut self, key: T, timestamp: u64)
+    where
+        T: ToString,
+    {
+        self.set_weighted_at(key, 1, timestamp)
+    }
+
+    /// Adds `weight` occurrences of a key at once, seen at `timestamp` in
+    /// seconds.
+    ///
+    /// Occurrences older than the window are ignored.
+    pub fn set_weighted_at<T>(&mut self, key: T, weight: usize, timestamp: u64)
+    where
+        T: ToString,
+    {
+        if weight == 0 {
+            return;
+        }
+
+        let start = timestamp - timestamp % self.interval;
+        let newest = self.buckets.back().map_or(start, |bucket| bucket.start);
+        if start.saturating_add(self.span()) <= newest {
+            return;
+        }
+
+        let index = match self
+            .buckets
+            .binary_search_by_key(&start, |bucket| bucket.start)
+        {
+            Ok(index) => index,
+            Err(index) => {
+                self.buckets.insert(
+                    index,
+                    Bucket {
+                        start,
+                        counts: HashMap::default(),
+                    },
+                );
+                index
+            },
+        };
+        *self.buckets[index]
+            .counts
+            .entry(key.to_string())
+            .or_default() += weight;
+
+        self.expire();
+    }
+
+    /// Removes `weight` occurrences of a key seen at `timestamp` in
+    /// seconds.
+    pub fn remove_weighted_at<T>(
+        &mut self,
+        key: T,
+        weight: usize,
+        timestamp: u64,
+    ) where
+        T: ToString,
+    {
+        let start = timestamp - timestamp % self.interval;
+        let Ok(index) = self
+            .buckets
+            .binary_search_by_key(&start, |bucket| bucket.start)
+        else {
+            return;
+        };
+
+        let counts = &mut self.buckets[index].counts;
+        let key = key.to_string();
+        if let Some(count) = counts.get_mut(&key) {
+            if *count > weight {
+                *count -= weight;
+            } else {
+                counts.remove(&key);
+            }
+        }
+    }
+
+    /// Removes every bucket.
+    pub fn clear(&mut self) {
+        self.buckets.clear();
+    }
+
+    /// Drops the buckets which left the window.
+    fn expire(&mut self) {
+        let Some(newest) = self.buckets.back().map(|bucket| bucket.start)
+        else {
+            return;
+        };
+
+        while self.buckets.front().is_some_and(|bucket| {
+            bucket.start.saturating_add(self.span()) <= newest
+        }) {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// Seconds covered by the window.
+    fn span(&self) -> u64 {
+        self.duration().as_secs()
+    }
+
+    /// Returns the occurrences of each word in the buckets ending during
+    /// the last `duration`.
+    fn counts(&self, duration: Duration) -> HashMap<&str, usize, RandomState> {
+        let from = now().saturating_sub(duration.as_secs());
+        self.counts_between(from, u64::MAX)
+    }
+
+    /// Returns the occurrences of each word in the buckets ending after
+    /// `from` and up to `to`, as UNIX timestamps in seconds.
+    fn counts_between(
+        &self,
+        from: u64,
+        to: u64,
+    ) -> HashMap<&str, usize, RandomState> {
+        let mut counts = HashMap::default();
+        for bucket in self.buckets.iter().filter(|bucket| {
+            let end = bucket.start.saturating_add(self.interval);
+            end > from && end <= to
+        }) {
+            for (word, count) in &bucket.counts {
+                *counts.entry(word.as_str()).or_default() += count;
+            }
+        }
+
+        counts
+    }
+
+    /// Returns the number of words seen during the last `duration`.
+    pub fn len_window(&self, duration: Duration) -> usize {
+        self.counts(duration).len()
+    }
+
+    /// Estimates the memory used by the buckets, in bytes.
+    pub fn memory(&self) -> usize {
+        self.buckets
+            .iter()
+            .map(|bucket| {
+                bucket.counts.capacity()
+                    * (std::mem::size_of::<String>()
+                        + std::mem::size_of::<usize>())
+                    + bucket.counts.keys().map(String::capacity).sum::<usize>()
+            })
+            .sum()
+    }
+
+    /// Classify the most frequently used words during the last `duration`.
+    ///
+    /// Buckets partly covered by the duration are counted entirely.
+    pub fn rank_window(
+        &self,
+        duration: Duration,
+        length: usize,
+    ) -> Vec<(String, usize)> {
+        crate::top(self.counts(duration), length, |a, b| b.1.cmp(&a.1))
+            .into_iter()
+            .map(|(word, count)| (word.to_string(), count))
+            .collect()
+    }
+
+    /// Classify the words whose occurrences grew the most between the
+    /// `duration` before the last one and the last `duration`, relative to
+    /// their previous occurrences.
+    ///
+    /// Returns each word with its occurrences during the last `duration`
+    /// then during the one before, ordered by [`growth`]. Words not counted
+    /// more than before are skipped.
+    pub fn trending(
+        &self,
+        duration: Duration,
+        length: usize,
+    ) -> Vec<(String, usize, usize)> {
+        let to = now().saturating_sub(duration.as_secs());
+        let current = self.counts_between(to, u64::MAX);
+        let previous =
+            self.counts_between(to.saturating_sub(duration.as_secs()), to);
+
+        crate::top(
+            current.into_iter().filter_map(|(word, count)| {
+                let before = previous.get(word).copied().unwrap_or_default();
+                (count > before).then_some((word, count, before))
+            }),
+            length,
+            |a, b| {
+                growth(b.1, b.2)
+                    .total_cmp(&growth(a.1, a.2))
+                    .then(b.1.cmp(&a.1))
+            },
+        )
+        .into_iter()
+        .map(|(word, count, before)| (word.to_string(), count, before))
+        .collect()
+    }
+}
+
+/// Returns how many times more a word is counted than before.
+///
+/// One occurrence is added to both counts, so words never counted before
+/// do not outgrow the ones counted many times more.
+pub fn growth(current: usize, previous: usize) -> f64 {
+    (current as f64 + 1.0) / (previous as f64 + 1.0)
+}
+
+/// Returns the current UNIX timestamp, in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
@@ -0,0 +1,85 @@
+//! Archives of the counters as Parquet files, to be loaded into a data
+//! lake.
+
+use crate::{
+    database::{self, Counters, Filter},
+    models::config::MessageType,
+};
+use arrow_array::{
+    ArrayRef, RecordBatch, StringArray, TimestampSecondArray, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use parquet::arrow::ArrowWriter;
+use squid_error::{Error, IoError, ResultExt};
+use std::{io::Write, sync::Arc};
+
+/// Columns of an archived leaderboard.
+///
+/// `first_seen` and `last_seen` are empty for the words counted before
+/// they were tracked.
+fn schema() -> Schema {
+    let timestamp = DataType::Timestamp(TimeUnit::Second, Some("UTC".into()));
+
+    Schema::new(vec![
+        Field::new("word", DataType::Utf8, false),
+        Field::new("count", DataType::UInt64, false),
+        Field::new("first_seen", timestamp.clone(), true),
+        Field::new("last_seen", timestamp, true),
+    ])
+}
+
+/// Writes the most used words of a kind, with their occurrences and when
+/// they were seen, as a Parquet file.
+///
+/// Only the words of the sentences matching the filter are written, but
+/// they were seen in any sentence.
+pub async fn leaderboard<W: Write + Send>(
+    counters: &Counters,
+    filter: Filter<'_>,
+    kind: &MessageType,
+    length: usize,
+    writer: W,
+) -> Result<W, Error> {
+    let (ranking, _) = database::rank(counters, filter, kind, 0, length).await;
+    let seen = {
+        let seen = counters.seen.read().await;
+        ranking
+            .iter()
+            .map(|(word, _)| seen.get(word).copied())
+            .collect::<Vec<_>>()
+    };
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(StringArray::from_iter_values(
+            ranking.iter().map(|(word, _)| word.replace("%20", " ")),
+        )),
+        Arc::new(UInt64Array::from_iter_values(
+            ranking.iter().map(|(_, count)| *count as u64),
+        )),
+        Arc::new(
+            TimestampSecondArray::from_iter(
+                seen.iter().map(|seen| seen.map(|seen| seen.first as i64)),
+            )
+            .with_timezone("UTC"),
+        ),
+        Arc::new(
+            TimestampSecondArray::from_iter(
+                seen.iter().map(|seen| seen.map(|seen| seen.last as i64)),
+            )
+            .with_timezone("UTC"),
+        ),
+    ];
+
+    let schema = Arc::new(schema());
+    let batch = RecordBatch::try_new(Arc::clone(&schema), columns)
+        .context(IoError::SerializationError, "while building record batch")?;
+
+    let mut writer = ArrowWriter::try_new(writer, schema, None)
+        .context(IoError::WritingError, "while creating Parquet file")?;
+    writer
+        .write(&batch)
+        .context(IoError::WritingError, "while writing Parquet file")?;
+    writer
+        .into_inner()
+        .context(IoError::WritingError, "while closing Parquet file")
+}
@@ -0,0 +1,250 @@
+//! Append-only log of the operations changing data, recording who made
+//! them and when, so moderation actions can be accounted for.
+
+use crate::{models::config, time::now};
+use serde::{Deserialize, Serialize};
+use squid_db::{Attributes, Instance};
+use squid_error::{Error, IoError, RequestError, ResultExt};
+use std::{
+    fmt,
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::sync::RwLock;
+
+/// Sub-directory of the data directory containing the audit log.
+pub const AUDIT_DIR: &str = "audit";
+/// Seconds during which events are kept if not configured, 30 days.
+const DEFAULT_RETENTION_SEC: u64 = 2_592_000;
+
+/// Operation changing data.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// A sentence was added.
+    Add,
+    /// A sentence was expired on request.
+    Delete,
+    /// The expiration of a sentence was updated.
+    UpdateTtl,
+    /// Memtables were written to disk.
+    Flush,
+    /// Data files were compacted.
+    Compact,
+    /// Words were excluded from leaderboards.
+    Exclude,
+    /// Excluded words were included again.
+    Include,
+    /// A replica was promoted to primary.
+    Promote,
+    /// Every counter of a service was emptied.
+    Reset,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Operation {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Ok(match name {
+            "Add" => Self::Add,
+            "Delete" => Self::Delete,
+            "UpdateTtl" => Self::UpdateTtl,
+            "Flush" => Self::Flush,
+            "Compact" => Self::Compact,
+            "Exclude" => Self::Exclude,
+            "Include" => Self::Include,
+            "Promote" => Self::Promote,
+            "Reset" => Self::Reset,
+            _ => {
+                return Err(Error::from(RequestError::InvalidArgument)
+                    .with_context("unknown operation"))
+            },
+        })
+    }
+}
+
+/// An operation, as logged.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// UNIX timestamp at which the operation was made, in seconds.
+    pub at: u64,
+    /// Name of the API key which made the operation, if any.
+    pub actor: Option<String>,
+    /// What was made.
+    pub operation: Operation,
+    /// Service changed by the operation, empty for the whole server.
+    pub namespace: String,
+    /// Identifier of the changed sentence, if any.
+    pub target: Option<String>,
+    /// What changed, such as the excluded words.
+    pub details: Option<String>,
+}
+
+impl Event {
+    /// Describes an operation made now.
+    pub fn new(
+        actor: Option<String>,
+        operation: Operation,
+        namespace: &str,
+    ) -> Self {
+        Self {
+            at: now(),
+            actor,
+            operation,
+            namespace: namespace.to_string(),
+            target: None,
+            details: None,
+        }
+    }
+
+    /// Sets the identifier of the changed sentence.
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    /// Sets what changed.
+    pub fn details(mut self, details: String) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+/// An event, as stored.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Record {
+    /// Time of the event, its expiration and a unique suffix, as
+    /// `<at>-<expire_at>-<uuid>`.
+    id: String,
+    /// Event, as JSON.
+    event: String,
+}
+
+impl Attributes for Record {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn ttl(&self) -> Option<u64> {
+        self.id
+            .split('-')
+            .nth(1)
+            .and_then(|expire| expire.parse().ok())
+    }
+}
+
+/// Events matching a query, the most recent first.
+#[derive(Debug, Default)]
+pub struct Query {
+    /// UNIX timestamps, in seconds, between which events were made.
+    pub from: Option<u64>,
+    /// Upper bound of `from`.
+    pub to: Option<u64>,
+    /// Name of the API key which made the events.
+    pub actor: Option<String>,
+    /// Operation made by the events.
+    pub operation: Option<Operation>,
+    /// Service changed by the events.
+    pub namespace: Option<String>,
+    /// Maximum number of events returned.
+    pub limit: usize,
+}
+
+impl Query {
+    fn matches(&self, event: &Event) -> bool {
+        self.from.is_none_or(|from| event.at >= from)
+            && self.to.is_none_or(|to| event.at <= to)
+            && self
+                .actor
+                .as_ref()
+                .is_none_or(|actor| event.actor.as_ref() == Some(actor))
+            && self
+                .operation
+                .is_none_or(|operation| event.operation == operation)
+            && self
+                .namespace
+                .as_ref()
+                .is_none_or(|namespace| &event.namespace == namespace)
+    }
+}
+
+/// Log of the operations changing data, each event expiring after the
+/// retention.
+#[derive(Debug)]
+pub struct Audit {
+    retention: u64,
+    instance: Arc<RwLock<Instance<Record>>>,
+}
+
+impl Audit {
+    /// Opens the audit log stored in a directory.
+    pub async fn open(
+        config: &config::Audit,
+        directory: &Path,
+    ) -> Result<Self, Error> {
+        // Events are written at once, so none is lost on a crash.
+        let instance = squid_db::Builder::default()
+            .memtable_flush_size(0)
+            .directory(directory)
+            .with_ttl()
+            .build()
+            .await?;
+
+        Ok(Self {
+            retention: config.retention.unwrap_or(DEFAULT_RETENTION_SEC),
+            instance,
+        })
+    }
+
+    /// Appends an event to the log.
+    pub async fn record(&self, event: Event) -> Result<(), Error> {
+        let id = format!(
+            "{:010}-{:010}-{}",
+            event.at,
+            event.at + self.retention,
+            uuid::Uuid::new_v4()
+        );
+        let event = serde_json::to_string(&event).context(
+            IoError::SerializationError,
+            "while encoding audit event",
+        )?;
+
+        self.instance
+            .write()
+            .await
+            .set(Record {
+                id,
+                event,
+            })
+            .await
+    }
+
+    /// Returns the events matching a query, the most recent first.
+    pub async fn query(&self, query: &Query) -> Result<Vec<Event>, Error> {
+        let instance = self.instance.read().await;
+        let mut events = Vec::new();
+
+        for segment in instance.segments()? {
+            for record in instance.segment(&segment)? {
+                let event = serde_json::from_str::<Event>(&record.event)
+                    .context(
+                        IoError::DeserializationError,
+                        "while reading audit event",
+                    )?;
+                if query.matches(&event) {
+                    events.push(event);
+                }
+            }
+        }
+
+        events.sort_by_key(|event| std::cmp::Reverse(event.at));
+        events.truncate(query.limit);
+        Ok(events)
+    }
+}
@@ -0,0 +1,184 @@
+//! Rankings computed recently, so identical leaderboard requests do not
+//! sort every counted word again.
+
+use crate::{
+    database::{self, Counters, Filter},
+    metrics::METRICS,
+    models::config::{MessageType, RankOrder},
+};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+
+/// Rankings kept at once, per service.
+const MAX_CACHED_RANKINGS: usize = 1024;
+
+/// Ranked words, alongside the number of distinct words.
+type Ranking = (Vec<(String, usize)>, usize);
+
+/// Parameters of a ranking.
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct Key {
+    /// Counters the words are ranked from.
+    boards: &'static str,
+    /// Label of the counter, such as a language.
+    label: String,
+    kind: MessageType,
+    order: RankOrder,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+}
+
+impl Key {
+    fn new(
+        filter: Filter<'_>,
+        kind: &MessageType,
+        order: RankOrder,
+        min_count: usize,
+        offset: usize,
+        length: usize,
+    ) -> Self {
+        let (boards, label) = match filter {
+            Filter::All => ("all", ""),
+            Filter::Lang(lang) => ("lang", lang),
+            Filter::Tag(tag) => ("tag", tag),
+            Filter::Metadata(entry) => ("metadata", entry),
+            Filter::Region(region) => ("region", region),
+            Filter::Author(author) => ("author", author),
+        };
+
+        Self {
+            boards,
+            label: label.to_string(),
+            kind: kind.clone(),
+            order,
+            min_count,
+            offset,
+            length,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    rankings: HashMap<Key, (Instant, Ranking)>,
+    /// Notified each time the counters change.
+    changes: watch::Receiver<()>,
+    /// Incremented each time the rankings are cleared, so rankings computed
+    /// before are not cached.
+    generation: u64,
+}
+
+impl State {
+    /// Clears the rankings if the counters changed since they were
+    /// computed.
+    fn refresh(&mut self) {
+        if self.changes.has_changed().unwrap_or(true) {
+            self.changes.borrow_and_update();
+            self.rankings.clear();
+            self.generation += 1;
+        }
+    }
+}
+
+/// Recent rankings of a service, reused while its counters are unchanged.
+#[derive(Debug)]
+pub struct Cache {
+    /// Duration during which a ranking is reused.
+    /// Rankings are not cached if zero.
+    ttl: Duration,
+    state: Mutex<State>,
+}
+
+impl Cache {
+    /// Creates a cache cleared each time the counters change.
+    pub fn new(ttl: Duration, counters: &Counters) -> Self {
+        Self {
+            ttl,
+            state: Mutex::new(State {
+                rankings: HashMap::new(),
+                changes: counters.changes.subscribe(),
+                generation: 0,
+            }),
+        }
+    }
+
+    /// Ranks words like [`database::rank_by`], reusing a recent identical
+    /// ranking if the counters did not change since.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn rank(
+        &self,
+        counters: &Counters,
+        filter: Filter<'_>,
+        kind: &MessageType,
+        order: RankOrder,
+        min_count: usize,
+        offset: usize,
+        length: usize,
+    ) -> Ranking {
+        if self.ttl.is_zero() {
+            return database::rank_by(
+                counters, filter, kind, order, min_count, offset, length,
+            )
+            .await;
+        }
+
+        let key = Key::new(filter, kind, order, min_count, offset, length);
+        let generation = match self.get(&key) {
+            Ok(ranking) => {
+                METRICS.cache_hits.fetch_add(1, Ordering::Relaxed);
+                return ranking;
+            },
+            Err(generation) => generation,
+        };
+        METRICS.cache_misses.fetch_add(1, Ordering::Relaxed);
+
+        let ranking = database::rank_by(
+            counters, filter, kind, order, min_count, offset, length,
+        )
+        .await;
+        if let Some(generation) = generation {
+            self.insert(key, generation, &ranking);
+        }
+
+        ranking
+    }
+
+    /// Returns a recent ranking, or the generation of the rankings if there
+    /// is none and the cache can be used.
+    fn get(&self, key: &Key) -> Result<Ranking, Option<u64>> {
+        let Ok(mut state) = self.state.lock() else {
+            return Err(None);
+        };
+        state.refresh();
+
+        match state.rankings.get(key) {
+            Some((at, ranking)) if at.elapsed() < self.ttl => Ok(ranking.clone()),
+            _ => Err(Some(state.generation)),
+        }
+    }
+
+    /// Caches a ranking, unless the counters changed since the rankings of
+    /// `generation`.
+    fn insert(&self, key: Key, generation: u64, ranking: &Ranking) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.refresh();
+        if state.generation != generation {
+            return;
+        }
+
+        if state.rankings.len() >= MAX_CACHED_RANKINGS {
+            let ttl = self.ttl;
+            state.rankings.retain(|_, (at, _)| at.elapsed() < ttl);
+        }
+        if state.rankings.len() < MAX_CACHED_RANKINGS {
+            state.rankings.insert(key, (Instant::now(), ranking.clone()));
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Periodic inserts of the counts of the most used words into ClickHouse,
+//! to be queried with other analytics.
+
+use crate::{
+    database::{self, Counters, Filter},
+    models::config::{ClickHouse, MessageType},
+    time::now,
+};
+use serde::Serialize;
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+use tracing::{trace, warn};
+
+/// Seconds between two inserts if not configured.
+const DEFAULT_INTERVAL_SEC: u64 = 60;
+/// Number of words inserted per service if not configured.
+const DEFAULT_LENGTH: usize = 100;
+/// Maximum time to insert the rows.
+const INSERT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The count of a word at a given time, as inserted.
+#[derive(Serialize, Debug)]
+struct Row<'a> {
+    /// UNIX timestamp of the count, in seconds.
+    timestamp: u64,
+    word: String,
+    count: usize,
+    namespace: &'a str,
+}
+
+/// Inserts the counts of the most used words of each service into a
+/// ClickHouse table at each interval.
+///
+/// Rows which cannot be inserted are dropped, the next insert is not
+/// delayed.
+pub async fn schedule(namespaces: Vec<(String, Counters)>, sink: ClickHouse) {
+    let client =
+        match reqwest::Client::builder().timeout(INSERT_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(error) => {
+                warn!(
+                    "ClickHouse sink is disabled, failed to create HTTP \
+                     client: {}",
+                    error
+                );
+                return;
+            },
+        };
+    let length = sink.length.unwrap_or(DEFAULT_LENGTH);
+    let query = format!(
+        "INSERT INTO {} (timestamp, word, count, namespace) FORMAT JSONEachRow",
+        sink.table
+    );
+
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        sink.interval.unwrap_or(DEFAULT_INTERVAL_SEC).max(1),
+    ));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let timestamp = now();
+        let mut body = Vec::new();
+        for (namespace, counters) in &namespaces {
+            let (ranking, _) = database::rank(
+                counters,
+                Filter::All,
+                &MessageType::Anything,
+                0,
+                length,
+            )
+            .await;
+
+            for (word, count) in ranking {
+                let row = Row {
+                    timestamp,
+                    word: word.replace("%20", " "),
+                    count,
+                    namespace,
+                };
+                if serde_json::to_writer(&mut body, &row).is_ok() {
+                    body.push(b'\n');
+                }
+            }
+        }
+
+        if body.is_empty() {
+            continue;
+        }
+
+        let mut request = client.post(&sink.url).query(&[("query", &query)]);
+        if let Some(database) = &sink.database {
+            request = request.query(&[("database", database)]);
+        }
+        if let Some(user) = &sink.user {
+            request = request.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &sink.password {
+            request = request.header("X-ClickHouse-Key", password);
+        }
+
+        match request
+            .body(body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => trace!("Inserted counts into ClickHouse."),
+            Err(error) => {
+                warn!("Failed to insert counts into ClickHouse: {}", error)
+            },
+        }
+    }
+}
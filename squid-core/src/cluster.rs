@@ -0,0 +1,340 @@
+//! Membership of a cluster of Squid servers, and the counts shared by its
+//! nodes.
+//!
+//! At each interval, every node sends the counts of its most used words to
+//! the others, only the ones which changed since their last exchange,
+//! along with the nodes it knows. Nodes then rank the words counted by the
+//! whole cluster by adding the counts of the others to their own.
+
+use crate::{
+    database::{self, Counters, Filter},
+    models::config::{self, MessageType},
+    namespace::Namespaces,
+};
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tokio::sync::{Mutex, RwLock};
+
+/// Seconds between two exchanges if not configured.
+const DEFAULT_INTERVAL_SEC: u64 = 5;
+/// Number of words shared per service if not configured.
+const DEFAULT_LENGTH: usize = 1000;
+/// Exchanges missed before the counts of a node are ignored, and before a
+/// discovered node is forgotten.
+const MAX_MISSED: u32 = 6;
+
+/// Counts of words, per service.
+pub type Counts = HashMap<String, HashMap<String, u64>>;
+
+/// What a node sends to another at each exchange.
+#[derive(Debug, Clone, Default)]
+pub struct Gossip {
+    /// Address of the sending node.
+    pub node: String,
+    /// Whether the counts replace every count sent before, rather than
+    /// updating them.
+    pub full: bool,
+    /// Addresses of the nodes known by the sending node.
+    pub members: Vec<String>,
+    /// Counts which changed since the last exchange, 0 once a word is no
+    /// longer shared.
+    pub counts: Counts,
+}
+
+/// A node receiving the counts of this one.
+#[derive(Debug, Default)]
+struct Member {
+    /// Whether the node is configured rather than discovered, so it is
+    /// never forgotten.
+    seed: bool,
+    /// Exchanges failed in a row.
+    failures: u32,
+    /// Counts known by the node, to only send the ones which changed.
+    /// Unknown until an exchange succeeds.
+    sent: Option<Counts>,
+}
+
+/// Counts received from another node.
+#[derive(Debug)]
+struct Remote {
+    counts: Counts,
+    received_at: Instant,
+}
+
+/// This node and the other nodes of its cluster.
+#[derive(Debug)]
+pub struct Cluster {
+    /// Address of the gRPC server of this node, as reached by the others.
+    pub address: String,
+    /// API key sent to the other nodes.
+    pub api_key: Option<String>,
+    /// Time between two exchanges.
+    pub interval: Duration,
+    /// Number of words shared per service.
+    length: usize,
+    members: Mutex<HashMap<String, Member>>,
+    remotes: RwLock<HashMap<String, Remote>>,
+}
+
+impl Cluster {
+    /// Creates a cluster from the configured nodes, the others being
+    /// discovered through them.
+    pub fn new(config: &config::Cluster) -> Self {
+        let members = config
+            .peers
+            .iter()
+            .filter(|peer| **peer != config.address)
+            .map(|peer| {
+                (
+                    peer.clone(),
+                    Member {
+                        seed: true,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+
+        Self {
+            address: config.address.clone(),
+            api_key: config.api_key.clone(),
+            interval: Duration::from_secs(
+                config.interval.unwrap_or(DEFAULT_INTERVAL_SEC).max(1),
+            ),
+            length: config.length.unwrap_or(DEFAULT_LENGTH),
+            members: Mutex::new(members),
+            remotes: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the addresses of the known nodes, except this one.
+    pub async fn members(&self) -> Vec<String> {
+        self.members.lock().await.keys().cloned().collect()
+    }
+
+    /// Adds the nodes known by another node.
+    pub async fn discover(&self, addresses: impl IntoIterator<Item = String>) {
+        let mut members = self.members.lock().await;
+
+        for address in addresses {
+            if !address.is_empty() && address != self.address {
+                members.entry(address).or_default();
+            }
+        }
+    }
+
+    /// Returns the counts of the most used words of each service of this
+    /// node, as shared with the others.
+    pub async fn local(&self, namespaces: &Namespaces) -> Counts {
+        let mut counts = Counts::new();
+
+        for namespace in namespaces.iter() {
+            let (ranking, _) = database::rank(
+                &namespace.counters,
+                Filter::All,
+                &MessageType::Anything,
+                0,
+                self.length,
+            )
+            .await;
+
+            counts.insert(
+                namespace.service.name.clone(),
+                ranking
+                    .into_iter()
+                    .map(|(word, count)| (word, count as u64))
+                    .collect(),
+            );
+        }
+
+        counts
+    }
+
+    /// Builds the message sent to a node, with the counts which changed
+    /// since its last successful exchange.
+    pub async fn gossip(&self, member: &str, local: &Counts) -> Gossip {
+        let members = self.members.lock().await;
+        let sent = members.get(member).and_then(|member| member.sent.as_ref());
+
+        let counts = match sent {
+            Some(sent) => local
+                .iter()
+                .map(|(namespace, counts)| {
+                    let previous = sent.get(namespace);
+                    let mut changed = counts
+                        .iter()
+                        .filter(|(word, count)| {
+                            previous.and_then(|previous| previous.get(*word))
+                                != Some(count)
+                        })
+                        .map(|(word, count)| (word.clone(), *count))
+                        .collect::<HashMap<_, _>>();
+                    // Words no longer shared are removed by the other node.
+                    changed.extend(
+                        previous
+                            .into_iter()
+                            .flatten()
+                            .filter(|(word, _)| !counts.contains_key(*word))
+                            .map(|(word, _)| (word.clone(), 0)),
+                    );
+
+                    (namespace.clone(), changed)
+                })
+                .filter(|(_, changed)| !changed.is_empty())
+                .collect(),
+            None => local.clone(),
+        };
+
+        Gossip {
+            node: self.address.clone(),
+            full: sent.is_none(),
+            members: members.keys().cloned().collect(),
+            counts,
+        }
+    }
+
+    /// Records that a node received the counts of this one.
+    ///
+    /// If the node asks for it, every count is sent at the next exchange.
+    ///
+    /// Returns the number of exchanges which failed before this one.
+    pub async fn sent(&self, member: &str, local: Counts, resync: bool) -> u32 {
+        match self.members.lock().await.get_mut(member) {
+            Some(member) => {
+                member.sent = (!resync).then_some(local);
+                std::mem::take(&mut member.failures)
+            },
+            None => 0,
+        }
+    }
+
+    /// Records that a node could not be reached, forgetting it after too
+    /// many failures unless it is configured.
+    ///
+    /// Returns the number of exchanges failed in a row.
+    pub async fn failed(&self, member: &str) -> u32 {
+        let mut members = self.members.lock().await;
+        let Some(entry) = members.get_mut(member) else {
+            return 0;
+        };
+
+        entry.failures += 1;
+        let failures = entry.failures;
+        if !entry.seed && failures >= MAX_MISSED {
+            members.remove(member);
+        }
+
+        failures
+    }
+
+    /// Applies the counts sent by another node.
+    ///
+    /// Returns whether the node must send every count at the next exchange,
+    /// as the counts it sent before are unknown.
+    pub async fn receive(&self, gossip: Gossip) -> bool {
+        self.discover(
+            gossip
+                .members
+                .into_iter()
+                .chain(std::iter::once(gossip.node.clone())),
+        )
+        .await;
+
+        let mut remotes = self.remotes.write().await;
+        let resync = !gossip.full && !remotes.contains_key(&gossip.node);
+        let remote = remotes.entry(gossip.node).or_insert_with(|| Remote {
+            counts: Counts::new(),
+            received_at: Instant::now(),
+        });
+
+        if gossip.full {
+            remote.counts.clear();
+        }
+        for (namespace, counts) in gossip.counts {
+            let current = remote.counts.entry(namespace).or_default();
+            for (word, count) in counts {
+                if count == 0 {
+                    current.remove(&word);
+                } else {
+                    current.insert(word, count);
+                }
+            }
+        }
+        remote.received_at = Instant::now();
+
+        resync
+    }
+
+    /// Forgets the counts of the nodes which stopped sending them.
+    pub async fn prune(&self) {
+        let timeout = self.interval * MAX_MISSED;
+
+        self.remotes
+            .write()
+            .await
+            .retain(|_, remote| remote.received_at.elapsed() < timeout);
+    }
+
+    /// Ranks the most used words of a kind counted by the whole cluster,
+    /// skipping the ones counted fewer than `min_count` times, then the
+    /// first `offset` ones.
+    ///
+    /// Only the words shared by each node are ranked, so words outside of
+    /// their most used ones are missing.
+    ///
+    /// Returns the ranked words alongside the number of distinct words.
+    pub async fn rank(
+        &self,
+        namespace: &str,
+        counters: &Counters,
+        kind: &MessageType,
+        min_count: usize,
+        offset: usize,
+        length: usize,
+    ) -> (Vec<(String, usize)>, usize) {
+        let (local, _) =
+            database::rank(counters, Filter::All, kind, 0, self.length).await;
+        let mut counts = local.into_iter().collect::<HashMap<_, _>>();
+
+        let timeout = self.interval * MAX_MISSED;
+        for remote in self
+            .remotes
+            .read()
+            .await
+            .values()
+            .filter(|remote| remote.received_at.elapsed() < timeout)
+        {
+            for (word, count) in
+                remote.counts.get(namespace).into_iter().flatten()
+            {
+                let matches = match kind {
+                    MessageType::Anything => true,
+                    MessageType::Word => !word.starts_with('#'),
+                    MessageType::Hashtag => word.starts_with('#'),
+                    // Phrases are not shared with the other nodes.
+                    MessageType::Phrase => false,
+                };
+                if matches {
+                    *counts.entry(word.clone()).or_default() += *count as usize;
+                }
+            }
+        }
+
+        let total = counts.len();
+        let mut ranking = counts.into_iter().collect::<Vec<_>>();
+        ranking.sort_by(|a, b| (Reverse(a.1), &a.0).cmp(&(Reverse(b.1), &b.0)));
+
+        (
+            ranking
+                .into_iter()
+                .take_while(|(_, count)| *count >= min_count)
+                .skip(offset)
+                .take(length)
+                .collect(),
+            total,
+        )
+    }
+}
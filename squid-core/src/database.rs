@@ -0,0 +1,1587 @@
+//! Counters of each service, and the leaderboards ranked from them.
+
+use crate::{
+    models::{
+        config::{self, MessageType, RankOrder, Service},
+        database::Entity,
+    },
+    profile::{self, Phase},
+    time,
+};
+use serde::{Deserialize, Serialize};
+use squid_algorithm::{
+    cardinality::HyperLogLog,
+    concurrent::ConcurrentAlgorithm,
+    cooccurrence::CooccurrenceAlgorithm,
+    hashtable::MapAlgorithm,
+    ngram,
+    sketch::SketchAlgorithm,
+    window::{self, WindowAlgorithm},
+};
+use squid_db::Instance;
+use squid_error::Error;
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::{watch, RwLock};
+
+/// Maximum number of authors counted at once if not configured.
+const DEFAULT_MAX_AUTHORS: usize = 10_000;
+/// Seconds after which the count of a word is halved when ranked by
+/// recency, if not configured.
+const DEFAULT_HALF_LIFE_SEC: u64 = 3600;
+/// Seconds covered by a bucket of recent words if not configured.
+const DEFAULT_WINDOW_INTERVAL_SEC: u64 = 3600;
+/// Number of buckets of recent words if not configured.
+const DEFAULT_WINDOW_BUCKETS: usize = 24;
+
+/// A word and its occurrences.
+type Ranked = (String, usize);
+/// A word and its occurrences, as compared while ranking.
+type Counted<'a> = (&'a str, usize);
+
+/// The algorithms managed by Squid.
+#[derive(Debug, Clone)]
+pub enum Algorithm {
+    /// Exact counts, see [`MapAlgorithm`].
+    Map(MapAlgorithm),
+    /// Approximate counts in a fixed memory, see [`SketchAlgorithm`].
+    Sketch(SketchAlgorithm),
+    /// Exact counts shared by threads, see [`ConcurrentAlgorithm`].
+    Concurrent(ConcurrentAlgorithm),
+}
+
+impl From<MapAlgorithm> for Algorithm {
+    /// Implements conversion from a MapAlgorithm to Algorithm.
+    fn from(map: MapAlgorithm) -> Self {
+        Algorithm::Map(map)
+    }
+}
+
+impl From<SketchAlgorithm> for Algorithm {
+    /// Implements conversion from a SketchAlgorithm to Algorithm.
+    fn from(sketch: SketchAlgorithm) -> Self {
+        Algorithm::Sketch(sketch)
+    }
+}
+
+impl From<ConcurrentAlgorithm> for Algorithm {
+    /// Implements conversion from a ConcurrentAlgorithm to Algorithm.
+    fn from(concurrent: ConcurrentAlgorithm) -> Self {
+        Algorithm::Concurrent(concurrent)
+    }
+}
+
+impl Algorithm {
+    /// Adds `weight` occurrences of a word.
+    pub fn set(&mut self, key: &str, weight: usize) {
+        match self {
+            Algorithm::Map(implementation) => {
+                implementation.set_weighted(key, weight)
+            },
+            Algorithm::Sketch(implementation) => {
+                implementation.set_weighted(key, weight)
+            },
+            Algorithm::Concurrent(implementation) => {
+                implementation.set_weighted(key, weight)
+            },
+        }
+    }
+
+    /// Adds `weight` occurrences of several words at once.
+    pub fn set_many(&mut self, keys: &[&str], weight: usize) {
+        match self {
+            Algorithm::Map(implementation) => {
+                implementation.set_many_weighted(keys.iter().copied(), weight)
+            },
+            Algorithm::Sketch(implementation) => {
+                for key in keys {
+                    implementation.set_weighted(key, weight)
+                }
+            },
+            Algorithm::Concurrent(implementation) => {
+                for key in keys {
+                    implementation.set_weighted(key, weight)
+                }
+            },
+        }
+    }
+
+    /// Adds `weight` occurrences of several words through a shared
+    /// reference, so words are counted while others are ranked.
+    ///
+    /// Returns `false`, counting nothing, if the algorithm cannot count
+    /// concurrently.
+    pub fn set_many_shared(&self, keys: &[&str], weight: usize) -> bool {
+        match self {
+            Algorithm::Concurrent(implementation) => {
+                for key in keys {
+                    implementation.set_weighted(key, weight)
+                }
+                true
+            },
+            Algorithm::Map(_) | Algorithm::Sketch(_) => false,
+        }
+    }
+
+    /// Removes `weight` occurrences of the words of a sentence at once.
+    pub fn remove_many(&mut self, keys: &[&str], weight: usize) {
+        match self {
+            Algorithm::Map(implementation) => implementation
+                .remove_many_weighted(keys.iter().copied(), weight),
+            Algorithm::Sketch(implementation) => {
+                for key in keys {
+                    implementation.remove_weighted(key, weight)
+                }
+            },
+            Algorithm::Concurrent(implementation) => {
+                for key in keys {
+                    implementation.remove_weighted(key, weight)
+                }
+            },
+        }
+    }
+
+    /// Removes `weight` occurrences of a word.
+    pub fn remove(&mut self, key: &str, weight: usize) {
+        match self {
+            Algorithm::Map(implementation) => {
+                implementation.remove_weighted(key, weight)
+            },
+            Algorithm::Sketch(implementation) => {
+                implementation.remove_weighted(key, weight)
+            },
+            Algorithm::Concurrent(implementation) => {
+                implementation.remove_weighted(key, weight)
+            },
+        }
+    }
+
+    /// Returns the occurrences of a word, which may be estimated.
+    pub fn get(&self, key: &str) -> usize {
+        match self {
+            Algorithm::Map(implementation) => implementation.get(key),
+            Algorithm::Sketch(implementation) => implementation.estimate(key),
+            Algorithm::Concurrent(implementation) => implementation.get(key),
+        }
+    }
+
+    /// Returns the number of words which can be ranked.
+    pub fn len(&self) -> usize {
+        match self {
+            Algorithm::Map(implementation) => implementation.len(),
+            Algorithm::Sketch(implementation) => implementation.len(),
+            Algorithm::Concurrent(implementation) => implementation.len(),
+        }
+    }
+
+    /// Returns whether no word can be ranked.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the occurrences of every word, including the ones which
+    /// cannot be ranked.
+    pub fn total(&self) -> usize {
+        match self {
+            Algorithm::Map(implementation) => implementation.total(),
+            Algorithm::Sketch(implementation) => implementation.total(),
+            Algorithm::Concurrent(implementation) => implementation.total(),
+        }
+    }
+
+    /// Counts the words which can be ranked of each order of magnitude of
+    /// occurrences: once, 2 to 10 times, 11 to 100 times, and so on.
+    pub fn histogram(&self) -> Vec<usize> {
+        match self {
+            Algorithm::Map(implementation) => implementation.histogram(),
+            Algorithm::Sketch(implementation) => implementation.histogram(),
+            Algorithm::Concurrent(implementation) => implementation.histogram(),
+        }
+    }
+
+    /// Removes every word.
+    pub fn clear(&mut self) {
+        match self {
+            Algorithm::Map(implementation) => implementation.clear(),
+            Algorithm::Sketch(implementation) => implementation.clear(),
+            Algorithm::Concurrent(implementation) => implementation.clear(),
+        }
+    }
+
+    /// Calls `f` with the words which can be ranked and their occurrences,
+    /// in any order.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, usize),
+    {
+        match self {
+            Algorithm::Map(implementation) => implementation
+                .iter()
+                .for_each(|(word, count)| f(word, count)),
+            Algorithm::Sketch(implementation) => implementation
+                .iter()
+                .for_each(|(word, count)| f(word, count)),
+            Algorithm::Concurrent(implementation) => implementation.for_each(f),
+        }
+    }
+
+    /// Returns an empty copy of the algorithm counting every word from its
+    /// first occurrence, see [`MapAlgorithm::with_guard`].
+    pub fn unguarded(&self) -> Algorithm {
+        match self {
+            Algorithm::Map(implementation) => {
+                Algorithm::Map(implementation.clone().with_guard(0))
+            },
+            Algorithm::Sketch(_) | Algorithm::Concurrent(_) => self.clone(),
+        }
+    }
+
+    /// Returns the number of sentences counted, if the sentences using each
+    /// word are, which only the Hashmap algorithm does.
+    pub fn sentences(&self) -> Option<usize> {
+        match self {
+            Algorithm::Map(implementation) => implementation.sentences(),
+            Algorithm::Sketch(_) | Algorithm::Concurrent(_) => None,
+        }
+    }
+
+    /// Calls `f` with every word and the number of sentences using it, in
+    /// any order, if they are counted.
+    pub fn for_each_frequency<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, usize),
+    {
+        if let Algorithm::Map(implementation) = self {
+            implementation
+                .frequencies()
+                .for_each(|(word, frequency)| f(word, frequency))
+        }
+    }
+
+    /// Sets the number of sentences counted, if they are.
+    pub fn set_sentences(&mut self, sentences: usize) {
+        if let Algorithm::Map(implementation) = self {
+            implementation.set_sentences(sentences)
+        }
+    }
+
+    /// Sets the number of sentences using a counted word, if they are
+    /// counted.
+    pub fn set_frequency(&mut self, key: &str, frequency: usize) {
+        if let Algorithm::Map(implementation) = self {
+            implementation.set_frequency(key, frequency)
+        }
+    }
+
+    /// Estimates the memory used, in bytes.
+    pub fn memory(&self) -> usize {
+        match self {
+            Algorithm::Map(implementation) => implementation.memory(),
+            Algorithm::Sketch(implementation) => implementation.memory(),
+            Algorithm::Concurrent(implementation) => implementation.memory(),
+        }
+    }
+
+    /// Classify the most frequently used words.
+    pub fn rank(&self, length: usize) -> Vec<(String, usize)> {
+        match self {
+            Algorithm::Map(implementation) => implementation.rank(length),
+            Algorithm::Sketch(implementation) => implementation.rank(length),
+            Algorithm::Concurrent(implementation) => {
+                implementation.rank(length)
+            },
+        }
+    }
+
+    /// Classify the words counted at least `min_count` times by their
+    /// TF-IDF score, with this score.
+    ///
+    /// Only the Hashmap algorithm counts the sentences using each word,
+    /// other algorithms rank by count.
+    pub fn rank_tfidf(
+        &self,
+        length: usize,
+        min_count: usize,
+    ) -> Vec<(String, usize, f64)> {
+        match self {
+            Algorithm::Map(implementation) => {
+                implementation.rank_tfidf_with_min(length, min_count)
+            },
+            Algorithm::Sketch(_) | Algorithm::Concurrent(_) => self
+                .rank(length)
+                .into_iter()
+                // The most counted words come first.
+                .take_while(|(_, count)| *count >= min_count)
+                .map(|(word, count)| (word, count, count as f64))
+                .collect(),
+        }
+    }
+
+    /// Classify the words in the order given by `compare`.
+    pub fn rank_by<F>(&self, length: usize, mut compare: F) -> Vec<Ranked>
+    where
+        F: FnMut(&Counted<'_>, &Counted<'_>) -> Ordering,
+    {
+        match self {
+            Algorithm::Map(implementation) => {
+                implementation.rank_by(length, |a, b| {
+                    compare(&(a.0.as_str(), a.1), &(b.0.as_str(), b.1))
+                })
+            },
+            Algorithm::Sketch(implementation) => {
+                implementation.rank_by(length, compare)
+            },
+            Algorithm::Concurrent(implementation) => {
+                implementation.rank_by(length, compare)
+            },
+        }
+    }
+}
+
+/// Words, hashtags and phrases counted separately, so each can be ranked.
+#[derive(Debug, Clone)]
+pub struct Board {
+    /// Words not starting with `#`.
+    words: Algorithm,
+    /// Words starting with `#`.
+    hashtags: Algorithm,
+    /// Adjacent words, joined by [`ngram::SEPARATOR`].
+    phrases: Algorithm,
+}
+
+impl Board {
+    /// Creates a board from an empty algorithm.
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            hashtags: algorithm.clone(),
+            phrases: algorithm.clone(),
+            words: algorithm,
+        }
+    }
+
+    /// Returns the algorithm counting a word.
+    fn algorithm(&self, key: &str) -> &Algorithm {
+        if key.starts_with('#') {
+            &self.hashtags
+        } else if key.contains(ngram::SEPARATOR) {
+            &self.phrases
+        } else {
+            &self.words
+        }
+    }
+
+    /// Returns the algorithm counting a word.
+    fn algorithm_mut(&mut self, key: &str) -> &mut Algorithm {
+        if key.starts_with('#') {
+            &mut self.hashtags
+        } else if key.contains(ngram::SEPARATOR) {
+            &mut self.phrases
+        } else {
+            &mut self.words
+        }
+    }
+
+    /// Adds `weight` occurrences of a word.
+    pub fn set(&mut self, key: &str, weight: usize) {
+        self.algorithm_mut(key).set(key, weight)
+    }
+
+    /// Adds `weight` occurrences of the words of a sentence at once.
+    pub fn set_many(&mut self, keys: &[&str], weight: usize) {
+        let [words, hashtags, phrases] = partition(keys);
+        self.words.set_many(&words, weight);
+        self.hashtags.set_many(&hashtags, weight);
+        self.phrases.set_many(&phrases, weight);
+    }
+
+    /// Adds `weight` occurrences of the words of a sentence through a
+    /// shared reference.
+    ///
+    /// Returns `false`, counting nothing, if the algorithms cannot count
+    /// concurrently.
+    pub fn set_many_shared(&self, keys: &[&str], weight: usize) -> bool {
+        // Every algorithm of a board is of the same kind.
+        let [words, hashtags, phrases] = partition(keys);
+        self.words.set_many_shared(&words, weight)
+            && self.hashtags.set_many_shared(&hashtags, weight)
+            && self.phrases.set_many_shared(&phrases, weight)
+    }
+
+    /// Removes `weight` occurrences of the words of a sentence at once.
+    pub fn remove_many(&mut self, keys: &[&str], weight: usize) {
+        let [words, hashtags, phrases] = partition(keys);
+        self.words.remove_many(&words, weight);
+        self.hashtags.remove_many(&hashtags, weight);
+        self.phrases.remove_many(&phrases, weight);
+    }
+
+    /// Removes `weight` occurrences of a word.
+    pub fn remove(&mut self, key: &str, weight: usize) {
+        self.algorithm_mut(key).remove(key, weight)
+    }
+
+    /// Returns the occurrences of a word.
+    pub fn get(&self, key: &str) -> usize {
+        self.algorithm(key).get(key)
+    }
+
+    /// Removes every word.
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.hashtags.clear();
+        self.phrases.clear();
+    }
+
+    /// Removes every occurrence of a word.
+    pub fn purge(&mut self, key: &str) {
+        let algorithm = self.algorithm_mut(key);
+        let count = algorithm.get(key);
+        algorithm.remove(key, count)
+    }
+
+    /// Returns the number of words of a kind which can be ranked.
+    pub fn len(&self, kind: &MessageType) -> usize {
+        match kind {
+            MessageType::Anything => self.words.len() + self.hashtags.len(),
+            MessageType::Word => self.words.len(),
+            MessageType::Hashtag => self.hashtags.len(),
+            MessageType::Phrase => self.phrases.len(),
+        }
+    }
+
+    /// Returns the occurrences of every word of a kind.
+    pub fn total(&self, kind: &MessageType) -> usize {
+        match kind {
+            MessageType::Anything => self.words.total() + self.hashtags.total(),
+            MessageType::Word => self.words.total(),
+            MessageType::Hashtag => self.hashtags.total(),
+            MessageType::Phrase => self.phrases.total(),
+        }
+    }
+
+    /// Counts the words of a kind of each order of magnitude of
+    /// occurrences, see [`Algorithm::histogram`].
+    pub fn histogram(&self, kind: &MessageType) -> Vec<usize> {
+        match kind {
+            MessageType::Anything => {
+                let mut histogram = self.words.histogram();
+                for (index, count) in
+                    self.hashtags.histogram().into_iter().enumerate()
+                {
+                    match histogram.get_mut(index) {
+                        Some(total) => *total += count,
+                        None => histogram.push(count),
+                    }
+                }
+
+                histogram
+            },
+            MessageType::Word => self.words.histogram(),
+            MessageType::Hashtag => self.hashtags.histogram(),
+            MessageType::Phrase => self.phrases.histogram(),
+        }
+    }
+
+    /// Calls `f` with every word which can be ranked and its occurrences,
+    /// in any order.
+    pub fn for_each<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, usize),
+    {
+        self.words.for_each(&mut f);
+        self.hashtags.for_each(&mut f);
+        self.phrases.for_each(f);
+    }
+
+    /// Estimates the memory used by every algorithm, in bytes.
+    pub fn memory(&self) -> usize {
+        self.words.memory() + self.hashtags.memory() + self.phrases.memory()
+    }
+
+    /// Returns the number of sentences counted, if the sentences using each
+    /// word are, see [`Algorithm::sentences`].
+    pub fn sentences(&self) -> Option<usize> {
+        // Every algorithm of a board counts the same sentences.
+        self.words.sentences()
+    }
+
+    /// Calls `f` with every word and the number of sentences using it, in
+    /// any order, if they are counted.
+    pub fn for_each_frequency<F>(&self, mut f: F)
+    where
+        F: FnMut(&str, usize),
+    {
+        self.words.for_each_frequency(&mut f);
+        self.hashtags.for_each_frequency(&mut f);
+        self.phrases.for_each_frequency(f);
+    }
+
+    /// Sets the number of sentences counted, if they are.
+    pub fn set_sentences(&mut self, sentences: usize) {
+        self.words.set_sentences(sentences);
+        self.hashtags.set_sentences(sentences);
+        self.phrases.set_sentences(sentences);
+    }
+
+    /// Sets the number of sentences using a counted word, if they are
+    /// counted.
+    pub fn set_frequency(&mut self, key: &str, frequency: usize) {
+        self.algorithm_mut(key).set_frequency(key, frequency)
+    }
+
+    /// Classify the most frequently used words of a kind.
+    pub fn rank(&self, kind: &MessageType, length: usize) -> Vec<(String, usize)> {
+        match kind {
+            MessageType::Anything => {
+                let mut ranking = self.words.rank(length);
+                ranking.extend(self.hashtags.rank(length));
+                ranking.sort_unstable_by_key(|(_, count)| Reverse(*count));
+                ranking.truncate(length);
+
+                ranking
+            },
+            MessageType::Word => self.words.rank(length),
+            MessageType::Hashtag => self.hashtags.rank(length),
+            MessageType::Phrase => self.phrases.rank(length),
+        }
+    }
+
+    /// Classify the words of a kind counted at least `min_count` times by
+    /// their TF-IDF score.
+    pub fn rank_tfidf(
+        &self,
+        kind: &MessageType,
+        length: usize,
+        min_count: usize,
+    ) -> Vec<Ranked> {
+        let mut ranking = match kind {
+            MessageType::Anything => {
+                let mut ranking = self.words.rank_tfidf(length, min_count);
+                ranking.extend(self.hashtags.rank_tfidf(length, min_count));
+                ranking.sort_unstable_by(|a, b| b.2.total_cmp(&a.2));
+                ranking
+            },
+            MessageType::Word => self.words.rank_tfidf(length, min_count),
+            MessageType::Hashtag => {
+                self.hashtags.rank_tfidf(length, min_count)
+            },
+            MessageType::Phrase => self.phrases.rank_tfidf(length, min_count),
+        };
+        ranking.truncate(length);
+
+        ranking
+            .into_iter()
+            .map(|(word, count, _)| (word, count))
+            .collect()
+    }
+
+    /// Classify the words of a kind in the order given by `compare`.
+    pub fn rank_by<F>(
+        &self,
+        kind: &MessageType,
+        length: usize,
+        compare: F,
+    ) -> Vec<Ranked>
+    where
+        F: Fn(&Counted<'_>, &Counted<'_>) -> Ordering,
+    {
+        match kind {
+            MessageType::Anything => {
+                // Both algorithms count different words, so the first words
+                // are among the first of each.
+                let mut ranking = self.words.rank_by(length, &compare);
+                ranking.extend(self.hashtags.rank_by(length, &compare));
+                ranking.sort_unstable_by(|a, b| {
+                    compare(&(a.0.as_str(), a.1), &(b.0.as_str(), b.1))
+                });
+                ranking.truncate(length);
+
+                ranking
+            },
+            MessageType::Word => self.words.rank_by(length, compare),
+            MessageType::Hashtag => self.hashtags.rank_by(length, compare),
+            MessageType::Phrase => self.phrases.rank_by(length, compare),
+        }
+    }
+}
+
+/// Splits words into the words, hashtags and phrases of a [`Board`].
+fn partition<'a>(keys: &[&'a str]) -> [Vec<&'a str>; 3] {
+    let mut words = Vec::with_capacity(keys.len());
+    let mut hashtags = Vec::new();
+    let mut phrases = Vec::new();
+    for key in keys {
+        if key.starts_with('#') {
+            hashtags.push(*key)
+        } else if key.contains(ngram::SEPARATOR) {
+            phrases.push(*key)
+        } else {
+            words.push(*key)
+        }
+    }
+
+    [words, hashtags, phrases]
+}
+
+/// Words and hashtags added recently, counted separately like a [`Board`].
+#[derive(Debug, Clone)]
+pub struct Window {
+    /// Words not starting with `#`.
+    words: WindowAlgorithm,
+    /// Words starting with `#`.
+    hashtags: WindowAlgorithm,
+}
+
+impl Window {
+    /// Creates an empty window from its configuration.
+    pub fn new(config: &config::Window) -> Self {
+        let window = WindowAlgorithm::new(
+            Duration::from_secs(
+                config.interval.unwrap_or(DEFAULT_WINDOW_INTERVAL_SEC),
+            ),
+            config.buckets.unwrap_or(DEFAULT_WINDOW_BUCKETS),
+        );
+
+        Self {
+            hashtags: window.clone(),
+            words: window,
+        }
+    }
+
+    /// Returns the time covered by the window.
+    pub fn duration(&self) -> Duration {
+        self.words.duration()
+    }
+
+    /// Adds `weight` occurrences of a word, seen at `timestamp` in seconds.
+    pub fn set(&mut self, key: &str, weight: usize, timestamp: u64) {
+        if key.starts_with('#') {
+            self.hashtags.set_weighted_at(key, weight, timestamp)
+        } else {
+            self.words.set_weighted_at(key, weight, timestamp)
+        }
+    }
+
+    /// Estimates the memory used by both algorithms, in bytes.
+    pub fn memory(&self) -> usize {
+        self.words.memory() + self.hashtags.memory()
+    }
+
+    /// Removes every word.
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.hashtags.clear();
+    }
+
+    /// Classify the most frequently used words of a kind during the last
+    /// `duration`, with the number of words seen meanwhile.
+    pub fn rank(
+        &self,
+        kind: &MessageType,
+        duration: Duration,
+        offset: usize,
+        length: usize,
+    ) -> (Vec<Ranked>, usize) {
+        let length = offset.saturating_add(length);
+        let (ranking, total) = match kind {
+            MessageType::Anything => {
+                let mut ranking = self.words.rank_window(duration, length);
+                ranking.extend(self.hashtags.rank_window(duration, length));
+                ranking.sort_unstable_by_key(|(_, count)| Reverse(*count));
+                ranking.truncate(length);
+
+                (
+                    ranking,
+                    self.words.len_window(duration)
+                        + self.hashtags.len_window(duration),
+                )
+            },
+            MessageType::Word => (
+                self.words.rank_window(duration, length),
+                self.words.len_window(duration),
+            ),
+            MessageType::Hashtag => (
+                self.hashtags.rank_window(duration, length),
+                self.hashtags.len_window(duration),
+            ),
+            // Phrases are not counted recently.
+            MessageType::Phrase => (Vec::new(), 0),
+        };
+
+        (ranking.into_iter().skip(offset).collect(), total)
+    }
+
+    /// Classify the words of a kind whose occurrences grew the most between
+    /// the `duration` before the last one and the last `duration`.
+    ///
+    /// Returns each word with its occurrences during both durations, the
+    /// last one first.
+    pub fn trending(
+        &self,
+        kind: &MessageType,
+        duration: Duration,
+        length: usize,
+    ) -> Vec<(String, usize, usize)> {
+        match kind {
+            MessageType::Anything => {
+                let mut trending = self.words.trending(duration, length);
+                trending.extend(self.hashtags.trending(duration, length));
+                trending.sort_unstable_by(|a, b| {
+                    window::growth(b.1, b.2)
+                        .total_cmp(&window::growth(a.1, a.2))
+                        .then(b.1.cmp(&a.1))
+                });
+                trending.truncate(length);
+
+                trending
+            },
+            MessageType::Word => self.words.trending(duration, length),
+            MessageType::Hashtag => self.hashtags.trending(duration, length),
+            // Phrases are not counted recently.
+            MessageType::Phrase => Vec::new(),
+        }
+    }
+}
+
+/// Number of distinct words and hashtags seen, estimated whatever the
+/// algorithm. Phrases are not included.
+#[derive(Debug, Default, Clone)]
+pub struct Distinct {
+    /// Words not starting with `#`.
+    words: HyperLogLog,
+    /// Words starting with `#`.
+    hashtags: HyperLogLog,
+}
+
+impl Distinct {
+    /// Records a word as seen.
+    pub fn insert(&mut self, key: &str) {
+        if key.starts_with('#') {
+            self.hashtags.insert(key)
+        } else if !key.contains(ngram::SEPARATOR) {
+            self.words.insert(key)
+        }
+    }
+
+    /// Estimates the number of distinct words seen.
+    pub fn words(&self) -> usize {
+        self.words.estimate()
+    }
+
+    /// Estimates the number of distinct hashtags seen.
+    pub fn hashtags(&self) -> usize {
+        self.hashtags.estimate()
+    }
+
+    /// Returns the memory used by both estimators, in bytes.
+    pub fn memory(&self) -> usize {
+        self.words.memory() + self.hashtags.memory()
+    }
+
+    /// Forgets every word seen.
+    pub fn clear(&mut self) {
+        self.words.clear();
+        self.hashtags.clear();
+    }
+}
+
+/// Counters dedicated to each value of a label, such as a language.
+pub type Boards = Arc<RwLock<HashMap<String, Board>>>;
+
+/// Sentences whose words are ranked.
+#[derive(Debug, Clone, Copy)]
+pub enum Filter<'a> {
+    /// Every sentence.
+    All,
+    /// Sentences written in a language.
+    Lang(&'a str),
+    /// Sentences with a tag.
+    Tag(&'a str),
+    /// Sentences with a metadata entry, written `key=value`.
+    Metadata(&'a str),
+    /// Sentences written in a region.
+    Region(&'a str),
+    /// Sentences written by an author.
+    Author(&'a str),
+}
+
+impl<'a> From<Option<&'a str>> for Filter<'a> {
+    /// Filters on a language, if any.
+    fn from(lang: Option<&'a str>) -> Self {
+        lang.map(Filter::Lang).unwrap_or(Filter::All)
+    }
+}
+
+/// Key of a metadata entry in [`Counters::metadata`].
+pub fn metadata_key(key: &str, value: &str) -> String {
+    format!("{}={}", key, value)
+}
+
+/// When a word was counted for the first and the last time, in seconds
+/// since the Unix epoch.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Seen {
+    /// First time the word was counted.
+    pub first: u64,
+    /// Last time the word was counted.
+    pub last: u64,
+}
+
+/// Every counter updated when a sentence is added or removed.
+#[derive(Debug, Clone)]
+pub struct Counters {
+    /// Counter of the words written in any language.
+    pub algorithm: Arc<RwLock<Board>>,
+    /// Counters of the words written in a specific language.
+    pub languages: Boards,
+    /// Counters of the words of sentences with a specific tag.
+    pub tags: Boards,
+    /// Counters of the words of sentences with a specific metadata entry,
+    /// keyed by `key=value`.
+    pub metadata: Boards,
+    /// Counters of the words of sentences written in a specific region.
+    pub regions: Boards,
+    /// Counters of the words of each author, removed once empty.
+    pub authors: Boards,
+    /// Notified each time a counter changes.
+    pub changes: Arc<watch::Sender<()>>,
+    /// Words which are never counted.
+    pub exclusions: Arc<RwLock<HashSet<String>>>,
+    /// When each counted word was seen, forgotten once it is no longer
+    /// counted.
+    pub seen: Arc<RwLock<HashMap<String, Seen>>>,
+    /// Number of distinct words counted since the counters were rebuilt,
+    /// including the ones no longer counted.
+    pub distinct: Arc<RwLock<Distinct>>,
+    /// Words added during the last hours, if the service counts them.
+    pub window: Option<Arc<RwLock<Window>>>,
+    /// Words used together in the same sentences, if the service counts
+    /// them.
+    pub cooccurrence: Option<Arc<RwLock<CooccurrenceAlgorithm>>>,
+    /// Order of the ranked words, unless overridden.
+    pub order: RankOrder,
+    /// Seconds after which the count of a word not seen since is halved,
+    /// when ranked by recency.
+    half_life: u64,
+    /// Maximum number of adjacent words counted as a phrase, phrases being
+    /// counted from 2 words.
+    ngrams: usize,
+    /// Words counted fewer times are not ranked.
+    pub min_count: usize,
+    /// Empty board copied for each new language.
+    blank: Board,
+}
+
+impl Counters {
+    /// Creates counters around an empty algorithm.
+    /// Language counters use the same algorithm, without its guard.
+    pub fn new<A: Into<Algorithm>>(algorithm: A) -> Self {
+        let algorithm = algorithm.into();
+        // Each board would have its own guard, of the same size.
+        let blank = Board::new(algorithm.unguarded());
+
+        Self {
+            blank,
+            algorithm: Arc::new(RwLock::new(Board::new(algorithm))),
+            languages: Boards::default(),
+            tags: Boards::default(),
+            metadata: Boards::default(),
+            regions: Boards::default(),
+            authors: Boards::default(),
+            changes: Arc::new(watch::channel(()).0),
+            exclusions: Arc::default(),
+            seen: Arc::default(),
+            distinct: Arc::default(),
+            window: None,
+            cooccurrence: None,
+            order: RankOrder::default(),
+            half_life: DEFAULT_HALF_LIFE_SEC,
+            ngrams: 0,
+            min_count: 0,
+        }
+    }
+
+    /// Sets the words which are never counted.
+    pub fn with_exclusions(mut self, exclusions: &[String]) -> Self {
+        self.exclusions =
+            Arc::new(RwLock::new(exclusions.iter().cloned().collect()));
+        self
+    }
+
+    /// Sets the order of the ranked words, and the half-life of the counts
+    /// when ranked by recency.
+    pub fn with_order(
+        mut self,
+        order: RankOrder,
+        half_life: Option<u64>,
+    ) -> Self {
+        self.order = order;
+        self.half_life = half_life.unwrap_or(DEFAULT_HALF_LIFE_SEC).max(1);
+        self
+    }
+
+    /// Counts phrases of 2 to `ngrams` adjacent words, if set.
+    pub fn with_ngrams(mut self, ngrams: Option<usize>) -> Self {
+        self.ngrams = ngrams.unwrap_or_default();
+        self
+    }
+
+    /// Stops ranking the words counted fewer than `min_count` times.
+    pub fn with_min_count(mut self, min_count: Option<usize>) -> Self {
+        self.min_count = min_count.unwrap_or_default();
+        self
+    }
+
+    /// Counts the words added during the last hours, if configured.
+    pub fn with_window(mut self, config: Option<&config::Window>) -> Self {
+        self.window =
+            config.map(|config| Arc::new(RwLock::new(Window::new(config))));
+        self
+    }
+
+    /// Counts the words used together in the same sentences, if
+    /// configured.
+    pub fn with_cooccurrence(
+        mut self,
+        config: Option<&config::Cooccurrence>,
+    ) -> Self {
+        self.cooccurrence = config.map(|config| {
+            Arc::new(RwLock::new(
+                config
+                    .words
+                    .map(CooccurrenceAlgorithm::new)
+                    .unwrap_or_default(),
+            ))
+        });
+        self
+    }
+
+    /// Returns an empty board, using the same algorithm as the counters
+    /// without its guard.
+    pub fn blank(&self) -> Board {
+        self.blank.clone()
+    }
+
+    /// Returns the boards of each language, tag, metadata entry, region
+    /// and author, in this order.
+    pub fn boards(&self) -> [&Boards; 5] {
+        [
+            &self.languages,
+            &self.tags,
+            &self.metadata,
+            &self.regions,
+            &self.authors,
+        ]
+    }
+
+    /// Estimates the memory used by every board, in bytes.
+    pub async fn memory(&self) -> usize {
+        let mut memory = self.algorithm.read().await.memory();
+        for boards in self.boards() {
+            memory += boards
+                .read()
+                .await
+                .values()
+                .map(Board::memory)
+                .sum::<usize>();
+        }
+        if let Some(window) = &self.window {
+            memory += window.read().await.memory();
+        }
+        if let Some(cooccurrence) = &self.cooccurrence {
+            memory += cooccurrence.read().await.memory();
+        }
+        memory += self.distinct.read().await.memory();
+
+        memory
+    }
+}
+
+/// Whether a word must be counted according to the service configuration
+/// and the excluded words.
+pub fn is_counted(
+    service: &Service,
+    exclusions: &HashSet<String>,
+    word: &str,
+) -> bool {
+    if exclusions.contains(word) {
+        return false;
+    }
+
+    match service.message_type {
+        MessageType::Hashtag => word.starts_with('#'),
+        MessageType::Word => !word.starts_with('#'),
+        MessageType::Anything => true,
+        MessageType::Phrase => false,
+    }
+}
+
+/// Returns the phrases of adjacent words of a sentence, if the service
+/// counts them.
+///
+/// Hashtags are not part of phrases, they split the sentence instead.
+fn phrases(counters: &Counters, text: &str) -> Vec<String> {
+    if counters.ngrams < 2 {
+        return Vec::new();
+    }
+
+    let tokens = text.split_whitespace().collect::<Vec<_>>();
+    tokens
+        .split(|token| token.starts_with('#'))
+        .flat_map(|run| ngram::phrases(run, counters.ngrams))
+        .collect()
+}
+
+/// Returns the words of a sentence paired when counting the words used
+/// together, whatever the kind of words the service counts.
+fn paired<'a>(exclusions: &HashSet<String>, text: &'a str) -> Vec<&'a str> {
+    text.split_whitespace()
+        .filter(|word| !exclusions.contains(*word))
+        .collect()
+}
+
+/// Adds the words of an entity to the algorithm and its language counter.
+pub async fn count(service: &Service, counters: &Counters, value: &Entity) {
+    let _timer = profile::start(Phase::Algorithm);
+    let exclusions = counters.exclusions.read().await;
+    let phrases = phrases(counters, &value.post_processing_text);
+    let words = value
+        .post_processing_text
+        .split_whitespace()
+        .filter(|word| is_counted(service, &exclusions, word))
+        .chain(
+            phrases
+                .iter()
+                .map(String::as_str)
+                .filter(|phrase| !exclusions.contains(*phrase)),
+        )
+        .collect::<Vec<_>>();
+    let weight = value.weight();
+    // Near-duplicates may be stored without being counted.
+    if weight == 0 {
+        return;
+    }
+
+    // Concurrent algorithms count without blocking leaderboards.
+    if !counters
+        .algorithm
+        .read()
+        .await
+        .set_many_shared(&words, weight)
+    {
+        counters.algorithm.write().await.set_many(&words, weight);
+    }
+
+    {
+        let mut distinct = counters.distinct.write().await;
+        for word in &words {
+            distinct.insert(word);
+        }
+    }
+
+    if let Some(cooccurrence) = &counters.cooccurrence {
+        cooccurrence
+            .write()
+            .await
+            .set_sentence_weighted(
+                &paired(&exclusions, &value.post_processing_text),
+                weight,
+            );
+    }
+
+    {
+        let now = time::now();
+        let mut seen = counters.seen.write().await;
+        for word in &words {
+            seen.entry(word.to_string())
+                .and_modify(|seen| seen.last = now)
+                .or_insert(Seen {
+                    first: now,
+                    last: now,
+                });
+        }
+    }
+
+    for (boards, keys) in labels(counters, value) {
+        let mut boards = boards.write().await;
+        for key in keys {
+            let board = boards.entry(key).or_insert_with(|| counters.blank());
+            board.set_many(&words, weight);
+        }
+    }
+
+    // New authors are skipped once too many are counted.
+    if let Some(author) = &value.author_id {
+        let mut authors = counters.authors.write().await;
+        if authors.contains_key(author)
+            || authors.len()
+                < service.max_authors.unwrap_or(DEFAULT_MAX_AUTHORS)
+        {
+            let board = authors
+                .entry(author.clone())
+                .or_insert_with(|| counters.blank());
+            board.set_many(&words, weight);
+        }
+    }
+
+    counters.changes.send_replace(());
+}
+
+/// Adds the words of a sentence added just now to the recent words.
+///
+/// Stored sentences are not counted again at startup, as when they were
+/// added is unknown.
+pub async fn count_recent(
+    service: &Service,
+    counters: &Counters,
+    value: &Entity,
+) {
+    let Some(window) = &counters.window else {
+        return;
+    };
+    let weight = value.weight();
+    if weight == 0 {
+        return;
+    }
+
+    let exclusions = counters.exclusions.read().await;
+    let now = time::now();
+    let mut window = window.write().await;
+    for word in value
+        .post_processing_text
+        .split_whitespace()
+        .filter(|word| is_counted(service, &exclusions, word))
+    {
+        window.set(word, weight, now)
+    }
+}
+
+/// Returns the labelled counters of an entity, with its labels.
+fn labels<'a>(
+    counters: &'a Counters,
+    value: &Entity,
+) -> [(&'a Boards, Vec<String>); 4] {
+    [
+        (&counters.languages, vec![value.lang.clone()]),
+        (&counters.tags, value.tags.clone()),
+        (
+            &counters.metadata,
+            value
+                .metadata
+                .iter()
+                .map(|(key, value)| metadata_key(key, value))
+                .collect(),
+        ),
+        (&counters.regions, value.region.iter().cloned().collect()),
+    ]
+}
+
+/// Adds several values to the database, written at once, and to the
+/// algorithm.
+pub async fn set_many(
+    service: &Service,
+    instance: Arc<RwLock<Instance<Entity>>>,
+    counters: &Counters,
+    values: &[Entity],
+) -> Result<(), Error> {
+    let mut instance = instance.write().await;
+    instance.set_many(values.to_vec()).await?;
+    for value in values {
+        count(service, counters, value).await;
+    }
+
+    Ok(())
+}
+
+/// Removes the words of an expired entity from the algorithm and its
+/// language counter.
+pub async fn uncount(counters: &Counters, value: &Entity) {
+    let weight = value.weight();
+    if weight == 0 {
+        return;
+    }
+    let phrases = phrases(counters, &value.post_processing_text);
+    let words = value
+        .post_processing_text
+        .split_ascii_whitespace()
+        .chain(phrases.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+
+    {
+        let mut algorithm = counters.algorithm.write().await;
+        let mut seen = counters.seen.write().await;
+        algorithm.remove_many(&words, weight);
+        for &word in &words {
+            if algorithm.get(word) == 0 {
+                seen.remove(word);
+            }
+        }
+    }
+
+    if let Some(cooccurrence) = &counters.cooccurrence {
+        let exclusions = counters.exclusions.read().await;
+        cooccurrence.write().await.remove_sentence_weighted(
+            &paired(&exclusions, &value.post_processing_text),
+            weight,
+        );
+    }
+
+    for (boards, keys) in labels(counters, value) {
+        let mut boards = boards.write().await;
+        for key in keys {
+            if let Some(board) = boards.get_mut(&key) {
+                board.remove_many(&words, weight)
+            }
+        }
+    }
+
+    if let Some(author) = &value.author_id {
+        let mut authors = counters.authors.write().await;
+        if let Some(board) = authors.get_mut(author) {
+            for &word in &words {
+                board.remove(word, weight)
+            }
+            // Leaves room for other authors.
+            if board.len(&MessageType::Anything) == 0
+                && board.len(&MessageType::Phrase) == 0
+            {
+                authors.remove(author);
+            }
+        }
+    }
+
+    counters.changes.send_replace(());
+}
+
+/// Stops counting words, and removes their occurrences from every counter.
+pub async fn exclude(counters: &Counters, words: Vec<String>) {
+    let mut exclusions = counters.exclusions.write().await;
+
+    {
+        let mut algorithm = counters.algorithm.write().await;
+        let mut seen = counters.seen.write().await;
+        for word in &words {
+            algorithm.purge(word);
+            seen.remove(word);
+        }
+    }
+    if let Some(cooccurrence) = &counters.cooccurrence {
+        let mut cooccurrence = cooccurrence.write().await;
+        for word in &words {
+            cooccurrence.purge(word);
+        }
+    }
+
+    for boards in [
+        &counters.languages,
+        &counters.tags,
+        &counters.metadata,
+        &counters.regions,
+        &counters.authors,
+    ] {
+        for board in boards.write().await.values_mut() {
+            for word in &words {
+                board.purge(word)
+            }
+        }
+    }
+
+    exclusions.extend(words);
+    counters.changes.send_replace(());
+}
+
+/// Removes every counted word, from every counter.
+///
+/// Stored sentences are kept, new ones being counted from zero.
+pub async fn reset(counters: &Counters) {
+    {
+        let mut algorithm = counters.algorithm.write().await;
+        let mut seen = counters.seen.write().await;
+        algorithm.clear();
+        seen.clear();
+    }
+
+    for boards in [
+        &counters.languages,
+        &counters.tags,
+        &counters.metadata,
+        &counters.regions,
+        &counters.authors,
+    ] {
+        boards.write().await.clear();
+    }
+    if let Some(window) = &counters.window {
+        window.write().await.clear();
+    }
+    if let Some(cooccurrence) = &counters.cooccurrence {
+        cooccurrence.write().await.clear();
+    }
+    counters.distinct.write().await.clear();
+
+    counters.changes.send_replace(());
+}
+
+/// Counts words again.
+///
+/// Occurrences removed when the words were excluded are lost, only new
+/// sentences are counted.
+pub async fn include(counters: &Counters, words: &[String]) {
+    let mut exclusions = counters.exclusions.write().await;
+
+    for word in words {
+        exclusions.remove(word);
+    }
+}
+
+/// Removes a value to the algorithm.
+pub async fn _remove(
+    algorithm: &RwLock<Board>,
+    key: String,
+) -> Result<(), Error> {
+    algorithm.write().await.remove(&key, 1);
+
+    Ok(())
+}
+
+/// Rank the most used words of a kind, skipping the first `offset` ones.
+/// Only the words of the sentences matching the filter are ranked.
+///
+/// Returns the ranked words alongside the number of distinct words.
+pub async fn rank(
+    counters: &Counters,
+    filter: Filter<'_>,
+    kind: &MessageType,
+    offset: usize,
+    length: usize,
+) -> (Vec<(String, usize)>, usize) {
+    rank_by(counters, filter, kind, counters.order, 0, offset, length).await
+}
+
+/// Rank the words of a kind like [`rank`], in an order other than the one
+/// of the service, also skipping the words counted fewer than `min_count`
+/// times.
+///
+/// Words are skipped before the first `offset` ones, so pages are only
+/// shorter once there are no more words to rank.
+pub async fn rank_by(
+    counters: &Counters,
+    filter: Filter<'_>,
+    kind: &MessageType,
+    order: RankOrder,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+) -> (Vec<(String, usize)>, usize) {
+    let min_count = min_count.max(counters.min_count);
+    let _timer = profile::start(Phase::Algorithm);
+    let (boards, key) = match filter {
+        Filter::Lang(lang) => (&counters.languages, lang),
+        Filter::Tag(tag) => (&counters.tags, tag),
+        Filter::Metadata(entry) => (&counters.metadata, entry),
+        Filter::Region(region) => (&counters.regions, region),
+        Filter::Author(author) => (&counters.authors, author),
+        Filter::All => {
+            let algorithm = counters.algorithm.read().await;
+            return (
+                ordered(
+                    counters, &algorithm, kind, order, min_count, offset,
+                    length,
+                )
+                .await,
+                algorithm.len(kind),
+            );
+        },
+    };
+
+    let boards = boards.read().await;
+    match boards.get(key) {
+        Some(board) => (
+            ordered(counters, board, kind, order, min_count, offset, length)
+                .await,
+            board.len(kind),
+        ),
+        None => Default::default(),
+    }
+}
+
+/// Ranks the words of a kind counted during the last `duration` like
+/// [`rank_by`] ranks them by count, or returns `None` if the service does
+/// not count recent words.
+///
+/// Returns the ranked words alongside the number of distinct words.
+pub async fn rank_recent(
+    counters: &Counters,
+    kind: &MessageType,
+    duration: Duration,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+) -> Option<(Vec<Ranked>, usize)> {
+    let min_count = min_count.max(counters.min_count);
+    let (ranking, total) = counters
+        .window
+        .as_ref()?
+        .read()
+        .await
+        .rank(kind, duration, offset, length);
+
+    // Words are ranked by count, so the skipped ones end the page.
+    Some((
+        ranking
+            .into_iter()
+            .take_while(|(_, count)| *count >= min_count)
+            .collect(),
+        total,
+    ))
+}
+
+/// Returns the offset and the length to rank so that pages still hold
+/// `length` words once the words excluded by a request are skipped by
+/// [`excluding`].
+pub fn widened(
+    exclude: &[String],
+    offset: usize,
+    length: usize,
+) -> (usize, usize) {
+    if exclude.is_empty() {
+        (offset, length)
+    } else {
+        // Excluded words may come before the page.
+        (0, offset.saturating_add(length).saturating_add(exclude.len()))
+    }
+}
+
+/// Skips the words excluded by a request, then the first `offset` words of
+/// a ranking widened by [`widened`].
+pub fn excluding(
+    ranking: Vec<Ranked>,
+    exclude: &[String],
+    offset: usize,
+    length: usize,
+) -> Vec<Ranked> {
+    if exclude.is_empty() {
+        return ranking;
+    }
+
+    ranking
+        .into_iter()
+        .filter(|(word, _)| !exclude.contains(word))
+        .skip(offset)
+        .take(length)
+        .collect()
+}
+
+/// Returns the occurrences of every word of a kind in the sentences of a
+/// filter, such as to tell the share of each ranked word.
+pub async fn total(
+    counters: &Counters,
+    filter: Filter<'_>,
+    kind: &MessageType,
+) -> usize {
+    let (boards, key) = match filter {
+        Filter::Lang(lang) => (&counters.languages, lang),
+        Filter::Tag(tag) => (&counters.tags, tag),
+        Filter::Metadata(entry) => (&counters.metadata, entry),
+        Filter::Region(region) => (&counters.regions, region),
+        Filter::Author(author) => (&counters.authors, author),
+        Filter::All => return counters.algorithm.read().await.total(kind),
+    };
+
+    boards
+        .read()
+        .await
+        .get(key)
+        .map_or(0, |board| board.total(kind))
+}
+
+/// Classify the words used the most in the same sentences as a word, or
+/// `None` if the service does not count them.
+///
+/// Returns the ranked words alongside the number of words used with it.
+pub async fn related(
+    counters: &Counters,
+    word: &str,
+    length: usize,
+) -> Option<(Vec<Ranked>, usize)> {
+    let cooccurrence = counters.cooccurrence.as_ref()?.read().await;
+
+    Some((cooccurrence.related(word, length), cooccurrence.len_related(word)))
+}
+
+/// Ranks the words of a board in an order, skipping the ones counted fewer
+/// than `min_count` times, then the first `offset` ones.
+async fn ordered(
+    counters: &Counters,
+    board: &Board,
+    kind: &MessageType,
+    order: RankOrder,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+) -> Vec<Ranked> {
+    match order {
+        // The most counted words come first, skipped words are the last.
+        RankOrder::Count => board
+            .rank(kind, offset.saturating_add(length))
+            .into_iter()
+            .take_while(|(_, count)| *count >= min_count)
+            .skip(offset)
+            .collect(),
+        RankOrder::Alphabetical => {
+            paginate(board, kind, min_count, offset, length, |a, b| {
+                b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0))
+            })
+        },
+        RankOrder::TfIdf => board
+            .rank_tfidf(kind, offset.saturating_add(length), min_count)
+            .into_iter()
+            .skip(offset)
+            .collect(),
+        RankOrder::Recency => {
+            let seen = counters.seen.read().await;
+            let now = time::now();
+            let half_life = counters.half_life as f64;
+            // Words never seen count as if seen at the epoch.
+            let score = |(word, count): &Counted<'_>| {
+                let last = seen.get(*word).map_or(0, |seen| seen.last);
+                let age = now.saturating_sub(last) as f64;
+                *count as f64 * 0.5f64.powf(age / half_life)
+            };
+
+            paginate(board, kind, min_count, offset, length, |a, b| {
+                score(b).total_cmp(&score(a)).then_with(|| a.0.cmp(b.0))
+            })
+        },
+    }
+}
+
+/// Ranks `offset + length` words counted at least `min_count` times, and
+/// only keeps the last `length` ones.
+fn paginate<F>(
+    board: &Board,
+    kind: &MessageType,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+    compare: F,
+) -> Vec<Ranked>
+where
+    F: Fn(&Counted<'_>, &Counted<'_>) -> Ordering,
+{
+    // Skipped words are ranked last, so they are only returned once every
+    // other word is.
+    board
+        .rank_by(kind, offset.saturating_add(length), |a, b| {
+            (b.1 >= min_count)
+                .cmp(&(a.1 >= min_count))
+                .then_with(|| compare(a, b))
+        })
+        .into_iter()
+        .take_while(|(_, count)| *count >= min_count)
+        .skip(offset)
+        .collect()
+}
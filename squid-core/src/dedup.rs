@@ -0,0 +1,81 @@
+//! Sentences added again shortly after being added, which are counted
+//! once.
+
+use crate::models::database::Entity;
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Sentences seen recently, keyed by the hash of their tokens.
+#[derive(Debug, Default)]
+struct Seen {
+    /// Identifier of the first sentence of each hash.
+    ids: HashMap<u64, String>,
+    /// Hashes, from the oldest to the newest.
+    order: VecDeque<(Instant, u64)>,
+}
+
+/// Finds sentences identical to a recent one once tokenized, such as
+/// reposts or copy-pastes.
+#[derive(Debug)]
+pub struct Deduplicator {
+    /// How long a sentence is remembered.
+    window: Duration,
+    seen: Mutex<Seen>,
+}
+
+/// Hashes the tokens of a sentence, so that case or stop words do not
+/// matter.
+fn hash(entity: &Entity) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    entity.post_processing_text.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl Deduplicator {
+    /// Creates a deduplicator remembering sentences during `window`.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: Mutex::default(),
+        }
+    }
+
+    /// Returns the identifier of the recent sentence identical to this one,
+    /// or remembers this one if there is none.
+    pub fn check(&self, entity: &Entity) -> Option<String> {
+        let now = Instant::now();
+        let hash = hash(entity);
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+
+        while let Some((at, oldest)) = seen.order.front().copied() {
+            if now.duration_since(at) < self.window {
+                break;
+            }
+            seen.order.pop_front();
+            seen.ids.remove(&oldest);
+        }
+
+        if let Some(id) = seen.ids.get(&hash) {
+            return Some(id.clone());
+        }
+
+        seen.ids.insert(hash, entity.id.clone());
+        seen.order.push_back((now, hash));
+        None
+    }
+
+    /// Forgets a sentence remembered by [`Deduplicator::check`] which has
+    /// not been added after all.
+    pub fn forget(&self, entity: &Entity) {
+        let hash = hash(entity);
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+
+        if seen.ids.get(&hash) == Some(&entity.id) {
+            seen.ids.remove(&hash);
+        }
+    }
+}
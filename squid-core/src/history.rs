@@ -0,0 +1,216 @@
+//! Leaderboards captured periodically by the reports of a service.
+
+use crate::{
+    database::{self, Counters},
+    models::config::{MessageType, Report},
+    time::now,
+};
+use serde::{Deserialize, Serialize};
+use squid_error::Error;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+/// Sub-directory of a service containing the captured leaderboards.
+pub const HISTORY_DIR: &str = "history";
+/// Number of words captured if not configured.
+const DEFAULT_REPORT_LENGTH: usize = 10;
+/// Maximum time to deliver a capture to a webhook.
+#[cfg(feature = "webhooks")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A ranked word, as captured.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Row {
+    /// Rank of the word, starting at 1.
+    pub rank: usize,
+    /// The ranked word.
+    pub word: String,
+    /// Occurrences of the word.
+    pub occurence: usize,
+}
+
+/// A leaderboard at a given time.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Capture {
+    /// Service of the leaderboard.
+    pub namespace: String,
+    /// Name of the report.
+    pub report: String,
+    /// UNIX timestamp of the capture, in seconds.
+    pub captured_at: u64,
+    /// Language of the ranked words, if the report filters them.
+    pub lang: Option<String>,
+    /// Kind of the ranked words.
+    pub kind: MessageType,
+    /// Ranked words, the most used first.
+    pub ranking: Vec<Row>,
+}
+
+/// Captures the leaderboard of a report at each interval, then writes it to
+/// the history of the service or sends it to the webhook of the report.
+///
+/// Without the `webhooks` feature, captures are always written to the
+/// history.
+pub async fn schedule(
+    namespace: String,
+    counters: Counters,
+    directory: PathBuf,
+    report: Report,
+) {
+    #[cfg(feature = "webhooks")]
+    let client = match reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()
+    {
+        Ok(client) => client,
+        Err(error) => {
+            warn!(
+                namespace,
+                report = report.name,
+                "Reports are disabled, failed to create HTTP client: {}",
+                error
+            );
+            return;
+        },
+    };
+    #[cfg(not(feature = "webhooks"))]
+    if report.webhook.is_some() {
+        warn!(
+            namespace,
+            report = report.name,
+            "Leaderboards are saved to the history, Squid was built without \
+             the webhooks feature."
+        );
+    }
+    let directory = directory.join(HISTORY_DIR);
+    let length = report.length.unwrap_or(DEFAULT_REPORT_LENGTH);
+
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(report.interval.max(1) * 60));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The first tick completes immediately.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        let (ranking, _) = database::rank(
+            &counters,
+            report.lang.as_deref().into(),
+            &report.kind,
+            0,
+            length,
+        )
+        .await;
+        let capture = Capture {
+            namespace: namespace.clone(),
+            report: report.name.clone(),
+            captured_at: now(),
+            lang: report.lang.clone(),
+            kind: report.kind.clone(),
+            ranking: ranking
+                .into_iter()
+                .enumerate()
+                .map(|(rank, (word, occurence))| Row {
+                    rank: rank + 1,
+                    word: word.replace("%20", " "),
+                    occurence,
+                })
+                .collect(),
+        };
+
+        match &report.webhook {
+            #[cfg(feature = "webhooks")]
+            Some(url) => {
+                if let Err(error) = client
+                    .post(url)
+                    .json(&capture)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                {
+                    warn!(
+                        namespace,
+                        report = report.name,
+                        "Failed to send leaderboard to {}: {}",
+                        url,
+                        error
+                    );
+                }
+            },
+            _ => {
+                let path = directory.join(format!(
+                    "{}-{}.json",
+                    report.name, capture.captured_at
+                ));
+                match save(&directory, &path, &capture) {
+                    Ok(()) => info!(
+                        namespace,
+                        report = report.name,
+                        "Saved leaderboard to {}.",
+                        path.display()
+                    ),
+                    Err(error) => warn!(
+                        namespace,
+                        report = report.name,
+                        "Failed to save leaderboard to {}: {}",
+                        path.display(),
+                        error
+                    ),
+                }
+            },
+        }
+    }
+}
+
+/// Writes a capture as JSON, creating the directory if needed.
+fn save(directory: &Path, path: &Path, capture: &Capture) -> Result<(), Error> {
+    let data = serde_json::to_vec(capture)?;
+    fs::create_dir_all(directory)?;
+    fs::write(path, data)?;
+
+    Ok(())
+}
+
+/// Reads the captures saved in the history of a service, oldest first.
+///
+/// Only the captures taken between `from` and `to`, UNIX timestamps in
+/// seconds, are read. Files which cannot be read are skipped.
+pub fn read(
+    directory: &Path,
+    from: u64,
+    to: u64,
+) -> Result<Vec<Capture>, Error> {
+    let entries = match fs::read_dir(directory.join(HISTORY_DIR)) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Vec::new())
+        },
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut captures = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            // Files are named `<report>-<captured_at>.json`.
+            let captured_at = path
+                .file_stem()?
+                .to_str()?
+                .rsplit_once('-')?
+                .1
+                .parse::<u64>()
+                .ok()?;
+            if captured_at < from || captured_at > to {
+                return None;
+            }
+
+            serde_json::from_slice::<Capture>(&fs::read(path).ok()?).ok()
+        })
+        .collect::<Vec<_>>();
+    captures.sort_by_key(|capture| capture.captured_at);
+
+    Ok(captures)
+}
@@ -0,0 +1,128 @@
+//! Processing of the added sentences, enabled per service.
+
+use crate::models::database::Entity;
+use squid_error::{Error, RequestError};
+use std::{fmt::Debug, sync::Arc};
+
+/// Custom processing of the sentences added to a service, run once they are
+/// tokenized and before they are stored.
+///
+/// Hooks are enabled by name in the `hooks` option of a service, and run in
+/// the configured order. They run in parallel during imports, so they must
+/// not block.
+pub trait Hook: Debug + Send + Sync {
+    /// Name enabling the hook in the configuration.
+    fn name(&self) -> &'static str;
+
+    /// Updates the entity of a sentence, or returns an error to reject it.
+    ///
+    /// `sentence` is the sentence as written.
+    fn process(&self, sentence: &str, entity: &mut Entity) -> Result<(), Error>;
+}
+
+/// Every hook which can be enabled.
+///
+/// Custom hooks are added here.
+fn registry() -> Vec<Arc<dyn Hook>> {
+    vec![
+        Arc::new(RejectEmpty),
+        Arc::new(TagHashtags),
+        #[cfg(feature = "sentiment")]
+        Arc::new(TagSentiment),
+    ]
+}
+
+/// Returns the hooks enabled by a service, in order.
+///
+/// # Panics
+///
+/// This function panics if a name does not match any hook.
+pub fn load(names: &[String]) -> Vec<Arc<dyn Hook>> {
+    let registry = registry();
+
+    names
+        .iter()
+        .map(|name| {
+            registry
+                .iter()
+                .find(|hook| hook.name() == name)
+                .cloned()
+                .unwrap_or_else(|| panic!("Unknown hook {:?}", name))
+        })
+        .collect()
+}
+
+/// Rejects sentences without any word once tokenized, such as sentences
+/// only made of stop words.
+#[derive(Debug)]
+struct RejectEmpty;
+
+impl Hook for RejectEmpty {
+    fn name(&self) -> &'static str {
+        "RejectEmpty"
+    }
+
+    fn process(&self, _: &str, entity: &mut Entity) -> Result<(), Error> {
+        if entity.post_processing_text.split_whitespace().next().is_none() {
+            return Err(Error::from(RequestError::InvalidArgument)
+                .with_context("sentence has no word"));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tags sentences with their hashtags, so the words used alongside a
+/// hashtag can be ranked.
+#[derive(Debug)]
+struct TagHashtags;
+
+impl Hook for TagHashtags {
+    fn name(&self) -> &'static str {
+        "TagHashtags"
+    }
+
+    fn process(&self, _: &str, entity: &mut Entity) -> Result<(), Error> {
+        for hashtag in entity
+            .post_processing_text
+            .split_whitespace()
+            .filter(|word| word.len() > 1 && word.starts_with('#'))
+        {
+            if !entity.tags.iter().any(|tag| tag == hashtag) {
+                entity.tags.push(hashtag.to_string());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the `sentiment` metadata of sentences to `negative`, `neutral` or
+/// `positive`, so the words of negative sentences can be ranked.
+///
+/// Sentiments set when adding the sentence are kept.
+#[cfg(feature = "sentiment")]
+#[derive(Debug)]
+struct TagSentiment;
+
+#[cfg(feature = "sentiment")]
+impl Hook for TagSentiment {
+    fn name(&self) -> &'static str {
+        "TagSentiment"
+    }
+
+    fn process(
+        &self,
+        sentence: &str,
+        entity: &mut Entity,
+    ) -> Result<(), Error> {
+        entity
+            .metadata
+            .entry("sentiment".to_string())
+            .or_insert_with(|| {
+                crate::sentiment::polarity(sentence).as_str().to_string()
+            });
+
+        Ok(())
+    }
+}
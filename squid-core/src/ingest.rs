@@ -0,0 +1,248 @@
+//! Pipeline of the added sentences: tokenization, hooks, deduplication
+//! and quotas, then counting and storage.
+
+use crate::{
+    database,
+    models::database::{Entity, Meta},
+    namespace::Namespace,
+    time::now,
+};
+use serde::Deserialize;
+use squid_error::{Error, RequestError};
+use squid_tokenizer::{lang::detect, tokenize_with_stopwords};
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+/// ISO 639-2 code for undetermined language.
+const UNDETERMINED_LANGUAGE: &str = "und";
+
+/// A sentence to add, before tokenization.
+#[derive(Deserialize, Debug, Default)]
+pub struct Submission {
+    /// Sentence as written.
+    pub sentence: String,
+    /// Seconds before the sentence expires.
+    /// 0 means the lifetime of the service.
+    #[serde(default)]
+    pub lifetime: u64,
+    /// Language of the sentence, detected if not set.
+    pub lang: Option<String>,
+    /// Number of times each word is counted.
+    pub weight: Option<u32>,
+    /// Whether the sentence is stored as written.
+    pub store_original: Option<bool>,
+    /// Labels used to filter leaderboards.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Additional data, each entry being usable to filter leaderboards.
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+    /// Region where the sentence was written, used to filter leaderboards.
+    pub region: Option<String>,
+    /// Identifier of the account which wrote the sentence.
+    pub author_id: Option<String>,
+    /// Name of the API key adding the sentence.
+    #[serde(skip)]
+    pub owner: Option<String>,
+}
+
+/// Tokenizes a sentence into an entity, according to the configuration of
+/// the namespace, then runs the hooks of the namespace.
+pub fn entity(
+    namespace: &Namespace,
+    submission: Submission,
+) -> Result<Entity, Error> {
+    let meta = Meta {
+        expire_at: Some(submission.lifetime)
+            .filter(|lifetime| *lifetime > 0)
+            .or(namespace.service.lifetime)
+            .map(|lifetime| now().saturating_add(lifetime)),
+        weight: match submission.weight {
+            Some(0) => return Err(invalid_argument("weight must be positive")),
+            weight => weight.map(|weight| weight as usize),
+        },
+        owner: submission.owner.clone(),
+        ..Default::default()
+    };
+    if submission.tags.iter().any(|tag| tag.is_empty()) {
+        return Err(invalid_argument("tags must not be empty"));
+    }
+    // Entries are counted under `key=value`.
+    if submission
+        .metadata
+        .keys()
+        .any(|key| key.is_empty() || key.contains('='))
+    {
+        return Err(invalid_argument(
+            "metadata keys must not be empty nor contain '='",
+        ));
+    }
+
+    let mut entity = Entity {
+        id: uuid::Uuid::new_v4().to_string(),
+        post_processing_text: namespace
+            .tokenize(&submission.sentence)
+            .map_err(|error| {
+                error!(
+                    "Failed to tokenize {:?}: {}",
+                    submission.sentence, error
+                );
+                invalid_argument("failed to tokenize sentence")
+            })?,
+        lang: submission
+            .lang
+            .filter(|lang| !lang.is_empty())
+            .or_else(|| detect(&submission.sentence).map(str::to_string))
+            .or_else(|| namespace.service.lang.clone())
+            .unwrap_or_else(|| UNDETERMINED_LANGUAGE.to_string()),
+        original_text: submission
+            .store_original
+            .unwrap_or(namespace.service.store_original)
+            .then(|| submission.sentence.clone()),
+        meta,
+        tags: submission.tags,
+        metadata: submission.metadata,
+        region: submission.region.filter(|region| !region.is_empty()),
+        author_id: submission.author_id.filter(|author| !author.is_empty()),
+    };
+
+    for hook in &namespace.hooks {
+        hook.process(&submission.sentence, &mut entity)?;
+    }
+
+    Ok(entity)
+}
+
+/// How a sentence would be added.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preview {
+    /// Tokens of the sentence, as stored.
+    pub tokens: Vec<String>,
+    /// Language of the sentence, detected if not set.
+    pub lang: String,
+    /// Words removed from the sentence, as stop words or by hooks.
+    pub stop_words: Vec<String>,
+    /// Tokens counted in the leaderboards.
+    pub counted: Vec<String>,
+    /// Tokens not counted as they are excluded.
+    pub excluded: Vec<String>,
+    /// Labels of the sentence, including the ones set by hooks.
+    pub tags: Vec<String>,
+}
+
+/// Tokenizes a sentence and runs the hooks of the namespace like
+/// [`add`], without adding it, to tell which of its words would be
+/// counted.
+pub async fn preview(
+    namespace: &Namespace,
+    submission: Submission,
+) -> Result<Preview, Error> {
+    let Ok(words) = tokenize_with_stopwords(&submission.sentence, &[]);
+    let entity = entity(namespace, submission)?;
+
+    let tokens = entity
+        .post_processing_text
+        .split_whitespace()
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    let kept = tokens.iter().map(String::as_str).collect::<HashSet<_>>();
+    let mut stop_words = Vec::<String>::new();
+    for word in words.split_whitespace().filter(|word| !kept.contains(word)) {
+        if !stop_words.iter().any(|stop_word| stop_word == word) {
+            stop_words.push(word.to_string());
+        }
+    }
+
+    let exclusions = namespace.counters.exclusions.read().await;
+    Ok(Preview {
+        counted: tokens
+            .iter()
+            .filter(|word| {
+                database::is_counted(&namespace.service, &exclusions, word)
+            })
+            .cloned()
+            .collect(),
+        excluded: tokens
+            .iter()
+            .filter(|word| exclusions.contains(word.as_str()))
+            .cloned()
+            .collect(),
+        tokens,
+        lang: entity.lang,
+        stop_words,
+        tags: entity.tags,
+    })
+}
+
+/// What happened to an added sentence.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The sentence is counted, and will be stored under this identifier.
+    Stored(String),
+    /// The sentence is identical to a recent one, whose identifier is
+    /// returned, and was skipped.
+    Deduplicated(String),
+}
+
+/// A sentence allowed to be added.
+#[derive(Debug)]
+pub enum Admission {
+    /// The sentence may be counted and stored, its size is reserved in the
+    /// quota of its owner.
+    Admitted(Box<Entity>),
+    /// The sentence is identical to a recent one, whose identifier is
+    /// returned, and must be skipped.
+    Deduplicated(String),
+}
+
+/// Tokenizes a sentence, checks whether it duplicates a recent one, lowers
+/// its weight if it nearly duplicates a stored one, then reserves its size
+/// in the quota of its owner, without adding it.
+pub fn admit(
+    namespace: &Namespace,
+    submission: Submission,
+) -> Result<Admission, Error> {
+    let mut entity = entity(namespace, submission)?;
+
+    if let Some(original) = namespace
+        .dedup
+        .as_ref()
+        .and_then(|dedup| dedup.check(&entity))
+    {
+        return Ok(Admission::Deduplicated(original));
+    }
+    if let Some(similar) = &namespace.similar {
+        similar.down_weight(&mut entity);
+    }
+    namespace.quotas.reserve(&entity).inspect_err(|_| {
+        if let Some(dedup) = &namespace.dedup {
+            dedup.forget(&entity);
+        }
+    })?;
+
+    Ok(Admission::Admitted(Box::new(entity)))
+}
+
+/// Adds a sentence to a namespace: tokenizes it, skips it if it duplicates
+/// a recent one, reserves its size in the quota of its owner, then counts
+/// it and queues it to be stored.
+pub async fn add(
+    namespace: &Namespace,
+    submission: Submission,
+) -> Result<Outcome, Error> {
+    match admit(namespace, submission)? {
+        Admission::Admitted(entity) => {
+            let id = entity.id.clone();
+            namespace.add(*entity).await?;
+            Ok(Outcome::Stored(id))
+        },
+        Admission::Deduplicated(original) => {
+            Ok(Outcome::Deduplicated(original))
+        },
+    }
+}
+
+/// Error returned for sentences which cannot be added.
+fn invalid_argument(message: &str) -> Error {
+    Error::from(RequestError::InvalidArgument).with_context(message)
+}
@@ -0,0 +1,76 @@
+#![forbid(unsafe_code)]
+#![deny(dead_code, unused_imports, unused_mut, missing_docs)]
+//! # squid-core
+//!
+//! Squid without its servers: sentences are tokenized, counted by the
+//! configured algorithm, stored with their lifetime and uncounted once
+//! they expire.
+//!
+//! Applications can embed Squid in-process through [`Namespaces`], while
+//! the `squid` binary serves them over gRPC, HTTP and RESP.
+//!
+//! # Examples
+//! ```rust,no_run
+//! use squid_core::{
+//!     database::{self, Filter},
+//!     ingest::{self, Submission},
+//!     models::config::{Config, MessageType},
+//!     Namespaces,
+//! };
+//!
+//! # async fn run() -> Result<(), squid_error::Error> {
+//! let namespaces = Namespaces::open(&Config::default()).await?;
+//! let namespace = namespaces.get("")?;
+//!
+//! ingest::add(
+//!     namespace,
+//!     Submission {
+//!         sentence: "Hello world!".to_string(),
+//!         ..Default::default()
+//!     },
+//! )
+//! .await?;
+//!
+//! let (ranking, _) = database::rank(
+//!     &namespace.counters,
+//!     Filter::All,
+//!     &MessageType::Anything,
+//!     0,
+//!     10,
+//! )
+//! .await;
+//! # Ok(())
+//! # }
+//! ```
+
+#[macro_use]
+extern crate lazy_static;
+
+#[cfg(feature = "parquet")]
+pub mod archive;
+pub mod audit;
+pub mod cache;
+#[cfg(feature = "clickhouse")]
+pub mod clickhouse;
+pub mod cluster;
+pub mod database;
+pub mod dedup;
+pub mod history;
+pub mod hooks;
+pub mod ingest;
+pub mod metrics;
+pub mod models;
+pub mod namespace;
+pub mod profile;
+pub mod quota;
+pub mod raft;
+#[cfg(feature = "sentiment")]
+pub mod sentiment;
+pub mod series;
+pub mod similar;
+pub mod snapshot;
+pub mod startup;
+pub mod time;
+pub mod webhook;
+
+pub use namespace::{Change, Namespace, Namespaces};
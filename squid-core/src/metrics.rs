@@ -0,0 +1,179 @@
+//! Counters and durations of the operations of the server, exposed in the
+//! Prometheus format.
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Upper bounds, in seconds, of the histogram buckets.
+const BUCKETS: [f64; 10] =
+    [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.5, 1.0];
+
+lazy_static! {
+    /// Metrics of the running server.
+    pub static ref METRICS: Metrics = Metrics::default();
+}
+
+/// Distribution of durations.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    buckets: [AtomicU64; BUCKETS.len()],
+    count: AtomicU64,
+    sum_in_micros: AtomicU64,
+}
+
+impl Histogram {
+    /// Records a new duration.
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            if seconds <= bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_in_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    fn render(&self, output: &mut String, name: &str, help: &str) {
+        let _ = writeln!(output, "# HELP {} {}", name, help);
+        let _ = writeln!(output, "# TYPE {} histogram", name);
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                output,
+                "{}_bucket{{le=\"{}\"}} {}",
+                name,
+                bound,
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(output, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+        let _ = writeln!(
+            output,
+            "{}_sum {}",
+            name,
+            self.sum_in_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0
+        );
+        let _ = writeln!(output, "{}_count {}", name, count);
+    }
+}
+
+/// Counters and histograms updated by the server.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Duration of `Add` requests.
+    pub add: Histogram,
+    /// Duration of `Leaderboard` requests.
+    pub leaderboard: Histogram,
+    /// Number of rankings reused from the cache.
+    pub cache_hits: AtomicU64,
+    /// Number of rankings computed, then cached.
+    pub cache_misses: AtomicU64,
+    /// Number of expired entries removed from the algorithm.
+    pub expirations: AtomicU64,
+    /// Seconds between the expiration of the last uncounted entry and the
+    /// moment it was uncounted.
+    pub expiration_lag: AtomicU64,
+}
+
+/// Values read from the database and algorithm when metrics are scraped.
+#[derive(Debug, Default)]
+pub struct Gauges {
+    /// Number of memtable flushes since startup.
+    pub flushes: u64,
+    /// Number of entries waiting in the memtable.
+    pub memtable: usize,
+    /// Number of distinct words in the algorithm.
+    pub vocabulary: usize,
+    /// Number of expired entries waiting to be removed from the algorithm.
+    pub expirations: usize,
+    /// Estimated memory used by the counters, in bytes.
+    pub memory: usize,
+}
+
+impl Metrics {
+    /// Encodes metrics using the Prometheus text format.
+    pub fn render(&self, gauges: &Gauges) -> String {
+        let mut output = String::new();
+
+        self.add.render(
+            &mut output,
+            "squid_add_duration_seconds",
+            "Duration of Add requests.",
+        );
+        self.leaderboard.render(
+            &mut output,
+            "squid_leaderboard_duration_seconds",
+            "Duration of Leaderboard requests.",
+        );
+
+        for (name, kind, help, value) in [
+            (
+                "squid_leaderboard_cache_hits_total",
+                "counter",
+                "Rankings reused from the cache.",
+                self.cache_hits.load(Ordering::Relaxed),
+            ),
+            (
+                "squid_leaderboard_cache_misses_total",
+                "counter",
+                "Rankings computed as they were not cached.",
+                self.cache_misses.load(Ordering::Relaxed),
+            ),
+            (
+                "squid_expirations_total",
+                "counter",
+                "Expired entries removed from the ranking.",
+                self.expirations.load(Ordering::Relaxed),
+            ),
+            (
+                "squid_expiration_lag_seconds",
+                "gauge",
+                "Delay before the last expired entry was removed.",
+                self.expiration_lag.load(Ordering::Relaxed),
+            ),
+            (
+                "squid_expirations_pending",
+                "gauge",
+                "Expired entries waiting to be removed from the ranking.",
+                gauges.expirations as u64,
+            ),
+            (
+                "squid_db_flushes_total",
+                "counter",
+                "Memtable flushes to the disk.",
+                gauges.flushes,
+            ),
+            (
+                "squid_db_memtable_entries",
+                "gauge",
+                "Entries waiting in the memtable.",
+                gauges.memtable as u64,
+            ),
+            (
+                "squid_vocabulary_words",
+                "gauge",
+                "Distinct words in the ranking.",
+                gauges.vocabulary as u64,
+            ),
+            (
+                "squid_counters_memory_bytes",
+                "gauge",
+                "Estimated memory used by the counters.",
+                gauges.memory as u64,
+            ),
+        ] {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind);
+            let _ = writeln!(output, "{} {}", name, value);
+        }
+
+        output
+    }
+}
@@ -0,0 +1,505 @@
+//! Options of the server and its services, as read from `config.yaml`.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::IpAddr};
+
+/// The data in the configuration file for setting up Squid.
+#[derive(Deserialize, Debug, Default)]
+pub struct Config {
+    /// Port of the gRPC server.
+    /// Defaults to 50051.
+    pub port: Option<u16>,
+    /// Addresses on which the gRPC server and the HTTP gateway listen, such
+    /// as `127.0.0.1` or `::1`. Each address is listened on separately.
+    /// Defaults to `0.0.0.0`.
+    #[serde(default)]
+    pub bind: Vec<IpAddr>,
+    /// Directory containing data files.
+    /// Defaults to `./data/`.
+    pub data_dir: Option<String>,
+    /// Seconds between two snapshots of the counters.
+    /// Defaults to 300.
+    pub snapshot_interval: Option<u64>,
+    /// Expired sentences waiting to be uncounted, per service, before
+    /// expirations are held back.
+    /// Defaults to 10000.
+    pub expiration_queue_size: Option<usize>,
+    /// Seconds allowed to save data once a shutdown is requested, through
+    /// CTRL+C, SIGTERM or SIGQUIT, before exiting anyway.
+    /// Defaults to 25, below the termination grace period of Kubernetes.
+    pub shutdown_timeout: Option<u64>,
+    /// Whether leaderboards are rejected as unavailable until the stored
+    /// sentences are counted, instead of being incomplete.
+    #[serde(default)]
+    pub reject_until_ready: bool,
+    /// Port of the HTTP gateway exposing metrics and the trending feed.
+    /// The gateway is disabled if not set.
+    pub http_port: Option<u16>,
+    /// Port of the RESP listener, used by Redis clients.
+    /// The listener is disabled if not set.
+    pub resp_port: Option<u16>,
+    /// Default service, used when a request does not specify a namespace.
+    #[serde(default)]
+    pub service: Service,
+    /// Additional services, each one being a namespace.
+    /// Their data are stored in a sub-directory named after the service.
+    #[serde(default)]
+    pub services: Vec<Service>,
+    /// Server followed by this one, which then only serves reads.
+    /// Each service follows the service of the primary with the same name.
+    pub primary: Option<Primary>,
+    /// Keys allowed to perform requests.
+    /// Authentication is disabled if empty.
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    /// Requests allowed for each API key, or each IP address if
+    /// authentication is disabled.
+    /// Unlimited if not set.
+    pub rate_limit: Option<RateLimit>,
+    /// ClickHouse table receiving the counts of the most used words of each
+    /// service periodically.
+    /// Replicas do not send them.
+    pub clickhouse: Option<ClickHouse>,
+    /// Other servers sharing the counts of their services with this one, so
+    /// each can rank the words counted by the whole cluster.
+    /// Replicas cannot join a cluster.
+    pub cluster: Option<Cluster>,
+    /// Other servers storing the same sentences as this one, each change
+    /// being written by a majority of them before being acknowledged.
+    /// Changes are only accepted by the elected leader.
+    /// Replicas and nodes of a cluster cannot join a Raft group.
+    pub raft: Option<Raft>,
+    /// Log of the operations changing data, stored in the `audit`
+    /// sub-directory of the data directory. Disabled if not set.
+    pub audit: Option<Audit>,
+    /// gRPC requests logged when slower than a threshold, with the time
+    /// spent in each phase. Disabled if not set.
+    pub slow_queries: Option<SlowQueries>,
+}
+
+/// Thresholds from which requests are logged as slow.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct SlowQueries {
+    /// Milliseconds from which a request is slow.
+    /// Defaults to 500.
+    pub threshold_ms: Option<u64>,
+    /// Thresholds of specific methods, such as `Leaderboard`, in
+    /// milliseconds.
+    #[serde(default)]
+    pub methods: HashMap<String, u64>,
+    /// Share of the slow requests logged, between 0 and 1.
+    /// Defaults to 1.
+    pub sample_rate: Option<f64>,
+    /// Maximum number of slow requests logged per second, the others being
+    /// counted in the next log.
+    /// Defaults to 10.
+    pub max_per_second: Option<u32>,
+}
+
+/// Log of the operations changing data.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Audit {
+    /// Seconds during which events are kept.
+    /// Defaults to 2592000, 30 days.
+    pub retention: Option<u64>,
+}
+
+/// Nodes of a Raft group, agreeing on each change.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Raft {
+    /// Address of the gRPC server of this node, as reached by the others,
+    /// such as `http://10.0.0.1:50051`.
+    pub address: String,
+    /// Addresses of every other node, usually two so one can fail.
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// API key with the `Admin` scope, if the other nodes require one.
+    pub api_key: Option<String>,
+    /// Milliseconds between two heartbeats of the leader.
+    /// Defaults to 100.
+    pub heartbeat_ms: Option<u64>,
+    /// Milliseconds without heartbeat before an election, randomly doubled
+    /// at most. Defaults to 1000.
+    pub election_timeout_ms: Option<u64>,
+    /// Applied entries from which the log is compacted, once every node
+    /// stores them. Defaults to 10000.
+    pub snapshot_entries: Option<u64>,
+}
+
+/// Nodes of a cluster, exchanging the counts of their most used words.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Cluster {
+    /// Address of the gRPC server of this node, as reached by the others,
+    /// such as `http://10.0.0.1:50051`.
+    pub address: String,
+    /// Addresses of other nodes, the remaining ones being discovered
+    /// through them.
+    #[serde(default)]
+    pub peers: Vec<String>,
+    /// API key with the `Admin` scope, if the other nodes require one.
+    pub api_key: Option<String>,
+    /// Seconds between two exchanges.
+    /// Defaults to 5.
+    pub interval: Option<u64>,
+    /// Number of words shared per service, the most used first.
+    /// Defaults to 1000.
+    pub length: Option<usize>,
+}
+
+/// A ClickHouse table, with `timestamp`, `word`, `count` and `namespace`
+/// columns, receiving rows over the HTTP interface.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClickHouse {
+    /// URL of the HTTP interface, such as `http://127.0.0.1:8123`.
+    pub url: String,
+    /// Table receiving the rows.
+    pub table: String,
+    /// Database of the table.
+    /// Defaults to the database of the user.
+    pub database: Option<String>,
+    /// User inserting the rows.
+    /// Defaults to `default`.
+    pub user: Option<String>,
+    /// Password of the user.
+    pub password: Option<String>,
+    /// Seconds between two inserts.
+    /// Defaults to 60.
+    pub interval: Option<u64>,
+    /// Number of words inserted per service, the most used first.
+    /// Defaults to 100.
+    pub length: Option<usize>,
+}
+
+/// A server followed by replicas.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Primary {
+    /// Address of the gRPC server, such as `http://127.0.0.1:50051`.
+    pub address: String,
+    /// API key with the `Admin` scope, if the primary requires one.
+    pub api_key: Option<String>,
+    /// Health checks of the primary, promoting the replica once the primary
+    /// stops replying. Only promoted through the `Promote` request if not
+    /// set.
+    pub failover: Option<Failover>,
+}
+
+/// Health checks of a primary by a standby replica.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Failover {
+    /// Seconds between two health checks.
+    /// Defaults to 5.
+    pub interval: Option<u64>,
+    /// Health checks failed in a row before the replica is promoted.
+    /// Defaults to 3.
+    pub failures: Option<u32>,
+}
+
+/// Token bucket settings used to limit requests.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RateLimit {
+    /// Requests allowed per second once the burst is consumed.
+    pub requests_per_second: f64,
+    /// Requests allowed at once.
+    pub burst: u32,
+}
+
+/// A key sent in the `authorization` header and what it can be used for.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    /// Secret value of the key.
+    pub key: String,
+    /// Operations allowed with this key.
+    pub scopes: Vec<Scope>,
+    /// Name recorded on the sentences added with this key.
+    /// Keys sharing a name share their quota.
+    pub name: Option<String>,
+    /// Storage allowed to this key, which requires a name.
+    /// Unlimited if not set.
+    pub quota: Option<Quota>,
+}
+
+/// Maximum storage used by the sentences of an API key.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Quota {
+    /// Maximum number of stored sentences.
+    pub entries: Option<u64>,
+    /// Maximum size of the stored sentences, in bytes.
+    pub bytes: Option<u64>,
+}
+
+/// Operations that can be granted to an API key.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Read rankings.
+    Read,
+    /// Add, update or delete data.
+    Write,
+    /// Use the administration service.
+    Admin,
+}
+
+/// The algorithm used to rank the most frequently used words.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub enum Algorithm {
+    /// Exact counts, memory grows with the vocabulary.
+    #[default]
+    Hashmap,
+    /// Estimated counts using a Count-Min Sketch, memory is bounded.
+    Sketch,
+    /// Exact counts split between shards, so words are added without
+    /// blocking leaderboards.
+    Concurrent,
+}
+
+/// Size of the Count-Min Sketch.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Sketch {
+    /// Number of counters per row.
+    pub width: usize,
+    /// Number of rows.
+    pub depth: usize,
+    /// Maximum number of words that can be ranked.
+    pub capacity: usize,
+}
+
+/// Which words need to be selected to be classified.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq, Hash)]
+pub enum MessageType {
+    #[default]
+    /// Words and hashtags.
+    Anything,
+    /// Words only.
+    Word,
+    /// Hashtags only.
+    Hashtag,
+    /// Adjacent words, counted if the service sets `ngrams`.
+    Phrase,
+}
+
+/// Order of the ranked words.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RankOrder {
+    /// The most counted words first, ties being in any order.
+    #[default]
+    Count,
+    /// The most counted words first, ties being sorted alphabetically.
+    Alphabetical,
+    /// The most counted words first, the count of each word being halved
+    /// every `recency_half_life` seconds since it was last seen.
+    Recency,
+    /// The words counted the most in the fewest sentences first, words
+    /// used by every sentence being last. The sentences using each word
+    /// are only counted if the service ranks in this order, and only by the
+    /// Hashmap algorithm, other algorithms ranking by count.
+    TfIdf,
+}
+
+/// Definition of a service. A service is equal to a database.
+#[derive(Deserialize, Debug, Default, Clone)]
+#[allow(unused)]
+pub struct Service {
+    /// Name of the database.
+    #[serde(default)]
+    pub name: String,
+    /// The algorithm to be used.
+    /// This affects RAM consumption and accuracy.
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    /// Size of the sketch, if the `Sketch` algorithm is used.
+    pub sketch: Option<Sketch>,
+    /// The maximum number of words returned for a query.
+    max_words: Option<u8>,
+    /// What data the algorithm needs to cache.
+    #[serde(default)]
+    pub message_type: MessageType,
+    /// The language of words to be returned when the request does not
+    /// specify one.
+    pub lang: Option<String>,
+    /// Words to exclude from the search.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// File containing the stop words removed from sentences, one per line.
+    /// Defaults to `./stopwords`.
+    pub stopwords: Option<String>,
+    /// Lifetime, in seconds, of sentences added without one.
+    /// Sentences are kept forever if not set.
+    pub lifetime: Option<u64>,
+    /// Whether the sentence is stored as written, alongside its tokens.
+    /// Can be overridden by each request.
+    #[serde(default)]
+    pub store_original: bool,
+    /// Seconds during which sentences identical to a previous one, once
+    /// tokenized, are skipped.
+    /// Duplicates are kept if not set.
+    pub dedup_window: Option<u64>,
+    /// Leaderboards captured periodically.
+    #[serde(default)]
+    pub reports: Vec<Report>,
+    /// Milliseconds during which a leaderboard is reused by identical
+    /// requests, as long as no counter changes.
+    /// Defaults to 1000, 0 disables the cache.
+    pub cache_ttl_ms: Option<u64>,
+    /// Names of the hooks run on each added sentence, in order.
+    /// Hooks can update sentences once tokenized, or reject them.
+    #[serde(default)]
+    pub hooks: Vec<String>,
+    /// URLs notified when sentences are removed.
+    /// Replicas do not notify them.
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+    /// Counts of each word over time, disabled if not set.
+    pub series: Option<Series>,
+    /// Words added during the last hours, ranked by leaderboards with a
+    /// window. Disabled if not set.
+    pub window: Option<Window>,
+    /// Maximum number of adjacent words counted as a phrase, such as 3 to
+    /// count pairs and triples of words. Hashtags are not part of phrases.
+    /// Disabled if not set.
+    pub ngrams: Option<usize>,
+    /// Words counted fewer times are not ranked, hiding one-off words.
+    /// Defaults to 1, ranking every word.
+    pub min_count: Option<usize>,
+    /// Maximum number of authors whose words are counted at once.
+    /// Sentences of other authors are still counted in every other
+    /// leaderboard. Defaults to 10000, 0 disables author leaderboards.
+    pub max_authors: Option<usize>,
+    /// Index of the stored sentences finding near-duplicates, disabled if
+    /// not set.
+    pub similarity: Option<Similarity>,
+    /// Order of the ranked words, unless overridden by a request.
+    #[serde(default)]
+    pub rank_order: RankOrder,
+    /// Seconds after which the count of a word not seen since is halved,
+    /// when ranked by recency.
+    /// Defaults to 3600.
+    pub recency_half_life: Option<u64>,
+    /// Number of the most counted words kept ordered as they are counted,
+    /// so leaderboards no longer than it are ranked without going through
+    /// every word. Only used by the Hashmap algorithm.
+    /// Disabled if not set.
+    pub top_cache: Option<usize>,
+    /// Bytes of the filter keeping the words seen once out of the
+    /// counters, words being counted from their second occurrence. Saves
+    /// memory when most words are seen once. Only used by the Hashmap
+    /// algorithm, and only by the counters of every sentence, the counters
+    /// of each language, tag, metadata entry, region and author counting
+    /// words from their first occurrence.
+    /// Disabled if not set.
+    pub guard: Option<usize>,
+    /// Number of distinct words counted, the least counted ones being
+    /// forgotten beyond it, so memory no longer grows with every new word.
+    /// Only used by the Hashmap algorithm.
+    /// Disabled if not set.
+    pub word_limit: Option<usize>,
+    /// Words used together in the same sentences, ranked by the `Related`
+    /// RPC. Disabled if not set.
+    pub cooccurrence: Option<Cooccurrence>,
+}
+
+/// Counts of each word kept over time, in buckets of fixed length.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Series {
+    /// Seconds covered by a bucket.
+    /// Defaults to 300.
+    pub interval: Option<u64>,
+    /// Seconds during which buckets are kept.
+    /// Defaults to 86400.
+    pub retention: Option<u64>,
+    /// Number of words kept per bucket, the most counted ones.
+    /// Defaults to 1000.
+    pub length: Option<usize>,
+    /// Number of previous buckets to which the current one is compared to
+    /// find anomalies.
+    /// Defaults to 12.
+    pub baseline: Option<usize>,
+    /// Standard deviations above the mean count from which a word is
+    /// announced as a spike to `WatchAnomalies`, once per bucket.
+    /// Defaults to 3.
+    pub alert_threshold: Option<f64>,
+}
+
+/// Words added recently, counted in buckets of fixed length kept in memory.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Window {
+    /// Seconds covered by a bucket.
+    /// Defaults to 3600.
+    pub interval: Option<u64>,
+    /// Number of buckets kept, the most recent ones.
+    /// Defaults to 24.
+    pub buckets: Option<usize>,
+}
+
+/// Words used together in the same sentences, counted in memory.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Cooccurrence {
+    /// Number of distinct words of a sentence paired, the first ones, as a
+    /// sentence of `n` words adds `n * (n - 1)` pairs.
+    /// Defaults to 32.
+    pub words: Option<usize>,
+}
+
+/// Index of the stored sentences, finding the ones nearly identical to a
+/// text.
+#[derive(Deserialize, Debug, Default, Clone)]
+pub struct Similarity {
+    /// Share of identical bits of the fingerprints from which sentences
+    /// are near-duplicates, between 0 and 1.
+    /// Defaults to 0.85.
+    pub threshold: Option<f64>,
+    /// Number of sentences indexed at once, the most recent ones.
+    /// Defaults to 100000.
+    pub capacity: Option<usize>,
+    /// Maximum weight of sentences nearly identical to an indexed one,
+    /// such as 0 to store them without counting them.
+    /// Near-duplicates are counted normally if not set.
+    pub weight: Option<u32>,
+}
+
+/// A leaderboard captured periodically, to follow its trends.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Report {
+    /// Name of the report, prefixing its history files.
+    pub name: String,
+    /// Minutes between two captures.
+    pub interval: u64,
+    /// Number of captured words.
+    /// Defaults to 10.
+    pub length: Option<usize>,
+    /// Language of the captured words, any if not set.
+    pub lang: Option<String>,
+    /// Kind of the captured words.
+    #[serde(default)]
+    pub kind: MessageType,
+    /// URL receiving each capture in the body of a `POST` request.
+    /// Captures are written to the `history` sub-directory of the service
+    /// if not set.
+    pub webhook: Option<String>,
+}
+
+/// A URL notified when sentences are removed.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Webhook {
+    /// URL receiving each notification in the body of a `POST` request.
+    pub url: String,
+    /// Removals notified to the URL.
+    /// Defaults to every removal.
+    #[serde(default = "every_event")]
+    pub events: Vec<Event>,
+    /// Attempts after a failed delivery, waiting longer after each one.
+    /// Defaults to 5.
+    pub retries: Option<u32>,
+}
+
+/// Every removal notified to a webhook.
+fn every_event() -> Vec<Event> {
+    vec![Event::Expired, Event::Deleted]
+}
+
+/// Why a sentence has been removed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// Its lifetime ended.
+    Expired,
+    /// It was expired on request.
+    Deleted,
+}
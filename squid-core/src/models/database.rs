@@ -0,0 +1,286 @@
+//! Sentences as stored in the databases of the services.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use squid_db::Attributes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+/// Additional data associated with an entity.
+///
+/// Stored as JSON text. Entities stored before are written
+/// `key:value,key:value`, such as `expire_at:1714240000,tag:sport`, and
+/// are read back from this format.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Default, Debug, Clone)]
+pub struct Meta {
+    /// UNIX timestamp at which the entity expires, in seconds.
+    /// 0 means infinite.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expire_at: Option<u64>,
+    /// Number of times each word is counted.
+    /// Defaults to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<usize>,
+    /// Name of the API key which added the entity.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    /// Fields of the sentence, from `tag:<String>` entries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Any other entry.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+}
+
+impl Meta {
+    /// Parses entries written `key:value,key:value`.
+    ///
+    /// Numbers which cannot be parsed are kept as other entries.
+    fn legacy(text: &str) -> Self {
+        let mut meta = Self::default();
+
+        for entry in text.split(',').filter(|entry| !entry.is_empty()) {
+            let (key, value) = entry.split_once(':').unwrap_or((entry, ""));
+            match key {
+                "expire_at" if value.parse::<u64>().is_ok() => {
+                    meta.expire_at = value.parse().ok()
+                },
+                "weight" if value.parse::<usize>().is_ok() => {
+                    meta.weight = value.parse().ok()
+                },
+                "owner" => meta.owner = Some(value.to_string()),
+                "tag" => meta.tags.push(value.to_string()),
+                _ => {
+                    meta.extra.insert(key.to_string(), value.to_string());
+                },
+            }
+        }
+
+        meta
+    }
+}
+
+impl From<&str> for Meta {
+    /// Reads JSON text, or entries written `key:value,key:value`.
+    fn from(text: &str) -> Self {
+        if text.starts_with('{') {
+            if let Ok(meta) = serde_json::from_str(text) {
+                return meta;
+            }
+        }
+
+        Self::legacy(text)
+    }
+}
+
+impl fmt::Display for Meta {
+    /// Writes the JSON text of the data.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let json = serde_json::to_string(self).map_err(|_| fmt::Error)?;
+        f.write_str(&json)
+    }
+}
+
+/// Stores [`Meta`] as text, so entities stored when it was a string are
+/// read back by binary formats too.
+mod text {
+    use super::Meta;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        meta: &Meta,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(meta)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Meta, D::Error> {
+        String::deserialize(deserializer).map(|text| Meta::from(text.as_str()))
+    }
+}
+
+/// Text representation in the database.
+#[derive(Serialize, Deserialize, PartialEq, Default, Debug, Clone)]
+pub struct Entity {
+    /// Unique identifier of the text.
+    pub id: String,
+    /// Original text without tokenizer processing.
+    /// If set to null, it will not be possible to modify the processing configuration.
+    pub original_text: Option<String>,
+    /// Text after tokenization, lemmatization and processing.
+    pub post_processing_text: String,
+    /// The language in which the text is written.
+    pub lang: String,
+    /// Additional data associated with the entity.
+    #[serde(with = "text")]
+    pub meta: Meta,
+    /// Labels used to filter leaderboards.
+    #[serde(default, deserialize_with = "lenient")]
+    pub tags: Vec<String>,
+    /// Additional data, each entry being usable to filter leaderboards.
+    #[serde(default, deserialize_with = "lenient")]
+    pub metadata: HashMap<String, String>,
+    /// Region where the sentence was written, such as `FR`, used to filter
+    /// leaderboards.
+    #[serde(default, deserialize_with = "lenient")]
+    pub region: Option<String>,
+    /// Identifier of the account which wrote the sentence, whose most used
+    /// words are counted.
+    #[serde(default, deserialize_with = "lenient")]
+    pub author_id: Option<String>,
+}
+
+/// Deserializes a field added after data was stored, using its default
+/// value when it is missing.
+///
+/// Binary formats cannot skip missing fields, they fail at the end of the
+/// input instead.
+pub fn lenient<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Ok(T::deserialize(deserializer).unwrap_or_default())
+}
+
+impl Attributes for Entity {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn ttl(&self) -> Option<u64> {
+        self.meta.expire_at
+    }
+
+    fn set_ttl(&mut self, ttl: Option<u64>) {
+        self.meta.expire_at = ttl;
+    }
+}
+
+impl Entity {
+    /// Number of times each word of the entity is counted.
+    pub fn weight(&self) -> usize {
+        self.meta.weight.unwrap_or(1)
+    }
+
+    /// Sets the number of times each word of the entity is counted.
+    pub fn set_weight(&mut self, weight: usize) {
+        self.meta.weight = Some(weight);
+    }
+
+    /// Name of the API key which added the entity, if any.
+    pub fn owner(&self) -> Option<&str> {
+        self.meta.owner.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_entity() {
+        let directory = std::env::temp_dir()
+            .join(format!("squid-entities-{}", uuid::Uuid::new_v4()));
+        // Lengths written as a line break, and as a byte outside of ASCII.
+        let entity = Entity {
+            id: "sentence".to_string(),
+            original_text: Some("x".repeat(128)),
+            post_processing_text: "y".repeat(128),
+            lang: "en".to_string(),
+            tags: vec!["basketball".to_string()],
+            ..Default::default()
+        };
+
+        let instance = squid_db::Builder::<Entity>::default()
+            .directory(&directory)
+            .build()
+            .await
+            .unwrap();
+        instance.write().await.set(entity.clone()).await.unwrap();
+        drop(instance);
+
+        let instance = squid_db::Builder::<Entity>::default()
+            .directory(&directory)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(instance.read().await.entries, [entity]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_store_labels() {
+        let directory = std::env::temp_dir()
+            .join(format!("squid-labels-{}", uuid::Uuid::new_v4()));
+        let entities = [
+            Entity {
+                id: "labeled".to_string(),
+                post_processing_text: "labeled".to_string(),
+                tags: vec!["basketball".to_string(), "é".repeat(64)],
+                metadata: HashMap::from([
+                    ("newsletter".to_string(), "v".repeat(200)),
+                    ("\n".to_string(), "\n".repeat(10)),
+                ]),
+                region: Some("x".repeat(10)),
+                author_id: Some("a".repeat(128)),
+                ..Default::default()
+            },
+            Entity {
+                id: "unlabeled".to_string(),
+                post_processing_text: "unlabeled".to_string(),
+                ..Default::default()
+            },
+        ];
+
+        let instance = squid_db::Builder::<Entity>::default()
+            .directory(&directory)
+            .build()
+            .await
+            .unwrap();
+        for entity in &entities {
+            instance.write().await.set(entity.clone()).await.unwrap();
+        }
+        drop(instance);
+
+        let instance = squid_db::Builder::<Entity>::default()
+            .directory(&directory)
+            .build()
+            .await
+            .unwrap();
+        assert_eq!(instance.read().await.entries, entities);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_read_entity_without_labels() {
+        /// Entity as stored before labels were added.
+        #[derive(Serialize)]
+        struct Stored {
+            id: String,
+            original_text: Option<String>,
+            post_processing_text: String,
+            lang: String,
+            meta: String,
+        }
+
+        let stored = bincode::serialize(&Stored {
+            id: "old".to_string(),
+            original_text: None,
+            post_processing_text: "old".to_string(),
+            lang: "en".to_string(),
+            meta: "{}".to_string(),
+        })
+        .unwrap();
+        let entity = bincode::deserialize::<Entity>(&stored).unwrap();
+
+        assert_eq!(entity.id, "old");
+        assert!(entity.tags.is_empty() && entity.metadata.is_empty());
+        assert_eq!((entity.region, entity.author_id), (None, None));
+    }
+}
@@ -0,0 +1,4 @@
+//! Configuration of Squid and the stored sentences.
+
+pub mod config;
+pub mod database;
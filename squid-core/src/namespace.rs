@@ -0,0 +1,890 @@
+//! Services opened from the configuration, each with its own database,
+//! counters and background tasks.
+
+use crate::{
+    cache::Cache,
+    database::{self, Algorithm, Counters},
+    dedup::Deduplicator,
+    history,
+    hooks::{self, Hook},
+    metrics::METRICS,
+    models::{
+        config::{self, Config, Service},
+        database::Entity,
+    },
+    profile::{self, Phase},
+    quota::Quotas,
+    series::{self, Series},
+    similar::{Similar, SimilarityIndex},
+    snapshot::Snapshot,
+    startup,
+    time::now,
+    webhook::Webhooks,
+};
+use serde::{Deserialize, Serialize};
+use squid_algorithm::{
+    concurrent::ConcurrentAlgorithm, hashtable::MapAlgorithm,
+    sketch::SketchAlgorithm,
+};
+use squid_db::{Attributes, Instance};
+use squid_error::{Error, ErrorType, RequestError};
+use squid_tokenizer::{stopwords, tokenize, tokenize_with_stopwords};
+use std::{
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tokio::sync::{broadcast, mpsc, oneshot, Mutex, RwLock};
+use tracing::{error, info, warn};
+
+/// Wait 100kb on memtable before save it on disk.
+pub const FLUSHTABLE_FLUSH_SIZE_KB: usize = 100;
+/// Directory containing data files if not configured.
+pub const DEFAULT_DATA_DIR: &str = "./data/";
+/// Seconds between two snapshots if not configured.
+const DEFAULT_SNAPSHOT_INTERVAL_SEC: u64 = 300;
+/// Milliseconds during which a leaderboard is cached if not configured.
+const DEFAULT_CACHE_TTL_MS: u64 = 1000;
+/// Expired sentences waiting to be uncounted if not configured.
+const DEFAULT_EXPIRATION_QUEUE_SIZE: usize = 10_000;
+/// Sentences waiting to be written before new ones are held back.
+const INGESTION_QUEUE_SIZE: usize = 10_000;
+/// Maximum number of sentences written at once.
+const WRITE_BATCH_SIZE: usize = 1_000;
+/// Changes kept for replicas before the slowest ones are disconnected.
+const REPLICATION_BUFFER: usize = 10_000;
+
+/// Work sent to the background writer.
+#[derive(Debug)]
+enum Write {
+    /// A counted sentence to be stored.
+    Entity(Box<Entity>),
+    /// Answered once every previous sentence is stored.
+    Sync(oneshot::Sender<()>),
+}
+
+/// A change made to a namespace, sent to its replicas, and to the other
+/// nodes of a Raft group.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum Change {
+    /// A sentence is stored.
+    Added(Box<Entity>),
+    /// The expiration of a sentence is updated.
+    Touched {
+        /// Identifier of the sentence.
+        id: String,
+        /// Timestamp at which the sentence expires, if it does.
+        expire_at: Option<u64>,
+    },
+    /// The excluded words are replaced.
+    Excluded(Vec<String>),
+}
+
+/// A service with its own database and counters.
+#[derive(Debug)]
+pub struct Namespace {
+    /// Configuration of the service.
+    pub service: Service,
+    /// Directory containing the data of the service.
+    pub directory: PathBuf,
+    /// Database storing the sentences of the service.
+    pub instance: Arc<RwLock<Instance<Entity>>>,
+    /// Counters of the words of the service.
+    pub counters: Counters,
+    /// Recent leaderboards of the service.
+    pub cache: Cache,
+    /// Storage used by each API key, shared by every service.
+    pub quotas: Arc<Quotas>,
+    /// Finds recent duplicates, if the service skips them.
+    pub dedup: Option<Deduplicator>,
+    /// Notified when sentences are removed.
+    pub webhooks: Arc<Webhooks>,
+    /// Run on each added sentence, once tokenized.
+    pub hooks: Vec<Arc<dyn Hook>>,
+    /// Counts of each word over time, if the service keeps them.
+    pub series: Option<Arc<Series>>,
+    /// Finds near-duplicates of a text, if the service indexes sentences.
+    pub similar: Option<Arc<SimilarityIndex>>,
+    /// Stop words of the service, if it does not use the default ones.
+    stop_words: Option<Vec<String>>,
+    /// Notifies the expiration consumer, kept to measure its backlog.
+    expirations: mpsc::Sender<Entity>,
+    /// Asks the expiration consumer for a snapshot.
+    snapshots: mpsc::Sender<oneshot::Sender<Result<(), Error>>>,
+    /// Sentences waiting to be written by the background writer.
+    writes: mpsc::Sender<Write>,
+    /// Identifier of the sentences counted but not yet written.
+    pending: Arc<Mutex<HashSet<String>>>,
+    /// Changes sent to replicas.
+    replication: broadcast::Sender<Change>,
+}
+
+impl Namespace {
+    /// Loads the database of a service and rebuilds its counters in the
+    /// background, from the snapshot if it is still valid.
+    ///
+    /// See [`startup`] to know when the counters are rebuilt.
+    pub async fn open(
+        service: Service,
+        directory: &Path,
+        snapshot_interval: Duration,
+        expiration_queue_size: usize,
+        quotas: Arc<Quotas>,
+    ) -> Result<Self, Error> {
+        // Set producer channel to receive expired sentences.
+        let (expirations, rx) = mpsc::channel::<Entity>(expiration_queue_size);
+
+        // Start database.
+        let instance = squid_db::Builder::default()
+            .memtable_flush_size(FLUSHTABLE_FLUSH_SIZE_KB)
+            .mpsc_sender(expirations.clone())
+            .directory(directory)
+            .with_ttl()
+            .build()
+            .await?;
+        info!(
+            namespace = service.name,
+            "Loaded instance with {} entities.",
+            instance.read().await.entries.len()
+        );
+
+        // Chose algorithm.
+        let counters = Counters::new(match (&service.algorithm, &service.sketch) {
+            (config::Algorithm::Hashmap, _) => {
+                let map = MapAlgorithm::default()
+                    .with_top(service.top_cache.unwrap_or_default())
+                    .with_guard(service.guard.unwrap_or_default())
+                    .with_word_limit(service.word_limit.unwrap_or_default());
+                Algorithm::from(match service.rank_order {
+                    config::RankOrder::TfIdf => map.with_documents(),
+                    _ => map,
+                })
+            },
+            (config::Algorithm::Sketch, Some(sketch)) => Algorithm::from(
+                SketchAlgorithm::new(sketch.width, sketch.depth, sketch.capacity),
+            ),
+            (config::Algorithm::Sketch, None) => {
+                Algorithm::from(SketchAlgorithm::default())
+            },
+            (config::Algorithm::Concurrent, _) => {
+                Algorithm::from(ConcurrentAlgorithm::default())
+            },
+        })
+        .with_exclusions(&service.exclude)
+        .with_order(service.rank_order, service.recency_half_life)
+        .with_window(service.window.as_ref())
+        .with_cooccurrence(service.cooccurrence.as_ref())
+        .with_ngrams(service.ngrams)
+        .with_min_count(service.min_count);
+        let similar = service
+            .similarity
+            .as_ref()
+            .map(|config| Arc::new(SimilarityIndex::new(config)));
+
+        // Entries are only kept to be counted, then dropped to reduce ram
+        // usage.
+        let entries = std::mem::take(&mut instance.write().await.entries);
+        startup::warming(entries.len());
+
+        // Count entries in the background, then init MPSC consumer, so no
+        // sentence is uncounted before being counted.
+        let pending = Arc::new(Mutex::new(HashSet::new()));
+        let webhooks = Arc::new(Webhooks::new(&service.name, &service.webhooks));
+        let (snapshots, requests) = mpsc::channel(1);
+        {
+            let service = service.clone();
+            let instance = Arc::clone(&instance);
+            let counters = counters.clone();
+            let quotas = Arc::clone(&quotas);
+            let webhooks = Arc::clone(&webhooks);
+            let similar = similar.clone();
+            let pending = Arc::clone(&pending);
+            let directory = directory.to_path_buf();
+            tokio::task::spawn(async move {
+                warm(
+                    &service, &counters, &quotas, &similar, &directory, entries,
+                )
+                .await;
+                startup::warmed();
+
+                consume(
+                    instance,
+                    counters,
+                    quotas,
+                    webhooks,
+                    similar,
+                    pending,
+                    rx,
+                    requests,
+                    directory,
+                    snapshot_interval,
+                )
+                .await
+            });
+        }
+
+        let (replication, _) = broadcast::channel(REPLICATION_BUFFER);
+        let (writes, queue) = mpsc::channel(INGESTION_QUEUE_SIZE);
+        tokio::task::spawn(write(
+            Arc::clone(&instance),
+            counters.clone(),
+            Arc::clone(&quotas),
+            similar.clone(),
+            Arc::clone(&pending),
+            replication.clone(),
+            queue,
+        ));
+
+        for report in &service.reports {
+            tokio::task::spawn(history::schedule(
+                service.name.clone(),
+                counters.clone(),
+                directory.to_path_buf(),
+                report.clone(),
+            ));
+        }
+
+        let series = match &service.series {
+            Some(config) => {
+                let series = Arc::new(
+                    Series::open(config, &directory.join(series::SERIES_DIR))
+                        .await?,
+                );
+                tokio::task::spawn(series::schedule(
+                    service.name.clone(),
+                    Arc::clone(&series),
+                ));
+                Some(series)
+            },
+            None => None,
+        };
+
+        let stop_words = service
+            .stopwords
+            .as_ref()
+            .map(|path| stopwords::load(Path::new(path)));
+
+        let hooks = hooks::load(&service.hooks);
+        let cache = Cache::new(
+            Duration::from_millis(
+                service.cache_ttl_ms.unwrap_or(DEFAULT_CACHE_TTL_MS),
+            ),
+            &counters,
+        );
+
+        let dedup = service
+            .dedup_window
+            .map(|window| Deduplicator::new(Duration::from_secs(window)));
+
+        Ok(Self {
+            service,
+            directory: directory.to_path_buf(),
+            instance,
+            counters,
+            cache,
+            quotas,
+            dedup,
+            webhooks,
+            hooks,
+            series,
+            similar,
+            stop_words,
+            expirations,
+            snapshots,
+            writes,
+            pending,
+            replication,
+        })
+    }
+
+    /// Sends a change to the replicas, if any.
+    pub fn publish(&self, change: Change) {
+        // Fails only if no replica is connected.
+        let _ = self.replication.send(change);
+    }
+
+    /// Returns the changes made from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Change> {
+        self.replication.subscribe()
+    }
+
+    /// Returns whether a sentence is stored or waiting to be written.
+    pub async fn contains(&self, id: &str) -> bool {
+        let _timer = profile::start(Phase::Database);
+        self.pending.lock().await.contains(id)
+            || self.instance.read().await.contains(id)
+    }
+
+    /// Returns the identifier of every sentence, stored or waiting to be
+    /// written.
+    pub async fn ids(&self) -> HashSet<String> {
+        let pending = self.pending.lock().await;
+        let instance = self.instance.read().await;
+
+        instance.ids().chain(pending.iter().cloned()).collect()
+    }
+
+    /// Lists the data files, then `None` for the memtable.
+    ///
+    /// Used with [`Namespace::read`] to go through every sentence without
+    /// loading them all at once.
+    pub async fn segments(&self) -> Result<Vec<Option<String>>, Error> {
+        Ok(self
+            .instance
+            .read()
+            .await
+            .segments()?
+            .into_iter()
+            .map(Some)
+            .chain([None])
+            .collect())
+    }
+
+    /// Reads the sentences of a data file, or of the memtable.
+    ///
+    /// Data files removed by a compaction meanwhile are empty.
+    pub async fn read(&self, segment: Option<&str>) -> Result<Vec<Entity>, Error> {
+        let _timer = profile::start(Phase::Database);
+        let instance = self.instance.read().await;
+
+        match segment {
+            Some(segment) => Ok(instance.segment(segment).unwrap_or_default()),
+            None => instance.memtable(),
+        }
+    }
+
+    /// Counts a sentence, then queues it to be written in the background.
+    ///
+    /// Waits for room in the queue when the writer falls behind. The
+    /// sentence cannot be read back until it is written.
+    pub async fn add(&self, entity: Entity) -> Result<(), Error> {
+        {
+            // Snapshots include pending sentences, which must be counted.
+            let mut pending = self.pending.lock().await;
+            database::count(&self.service, &self.counters, &entity).await;
+            database::count_recent(&self.service, &self.counters, &entity)
+                .await;
+            if let Some(series) = &self.series {
+                series.record(&self.service, &self.counters, &entity).await;
+            }
+            if let Some(similar) = &self.similar {
+                similar.insert(&entity);
+            }
+            pending.insert(entity.id.clone());
+        }
+
+        let _timer = profile::start(Phase::Database);
+        self.writes
+            .send(Write::Entity(Box::new(entity)))
+            .await
+            .map_err(|_| writer_stopped())
+    }
+
+    /// Returns the number of expired sentences waiting to be uncounted.
+    pub fn pending_expirations(&self) -> usize {
+        self.expirations.max_capacity() - self.expirations.capacity()
+    }
+
+    /// Waits until every queued sentence is written.
+    pub async fn sync(&self) -> Result<(), Error> {
+        let (reply, response) = oneshot::channel();
+
+        self.writes
+            .send(Write::Sync(reply))
+            .await
+            .map_err(|_| writer_stopped())?;
+        response.await.map_err(|_| writer_stopped())
+    }
+
+    /// Saves the counters on disk, so they are not rebuilt at startup.
+    pub async fn snapshot(&self) -> Result<(), Error> {
+        let (reply, response) = oneshot::channel();
+        let stopped = || {
+            Error::from(ErrorType::Unspecified)
+                .with_context("expiration consumer stopped")
+        };
+
+        self.snapshots.send(reply).await.map_err(|_| stopped())?;
+        response.await.map_err(|_| stopped())?
+    }
+
+    /// Tokenizes a sentence with the stop words of the service.
+    pub fn tokenize(&self, sentence: &str) -> Result<String, Infallible> {
+        let _timer = profile::start(Phase::Tokenization);
+        match &self.stop_words {
+            Some(stop_words) => tokenize_with_stopwords(sentence, stop_words),
+            None => tokenize(sentence),
+        }
+    }
+
+    /// Tokenizes words the same way as sentences, so they match counted
+    /// words.
+    pub fn tokenize_words(&self, words: &[String]) -> Vec<String> {
+        let mut tokens = Vec::new();
+
+        for word in words {
+            let Ok(tokenized) = self.tokenize(word);
+            tokens.extend(tokenized.split_whitespace().map(str::to_string));
+        }
+
+        tokens
+    }
+
+    /// Returns at most `length` stored sentences nearly identical to a
+    /// text, the most similar first, or `None` if the service does not
+    /// index sentences.
+    ///
+    /// Sentences are returned from the `threshold` similarity, or from the
+    /// one of the service if not set.
+    pub fn find_similar(
+        &self,
+        text: &str,
+        threshold: Option<f64>,
+        length: usize,
+    ) -> Option<Vec<Similar>> {
+        let similar = self.similar.as_ref()?;
+        let Ok(tokens) = self.tokenize(text);
+
+        let _timer = profile::start(Phase::Algorithm);
+        Some(similar.find(
+            &tokens,
+            threshold.unwrap_or(similar.threshold),
+            length,
+        ))
+    }
+}
+
+/// Error returned once the background writer is gone.
+fn writer_stopped() -> Error {
+    Error::from(ErrorType::Unspecified)
+        .with_context("background writer stopped")
+}
+
+/// Writes queued sentences in batches.
+///
+/// Sentences which cannot be written are uncounted, as they would be lost
+/// at restart.
+async fn write(
+    instance: Arc<RwLock<Instance<Entity>>>,
+    counters: Counters,
+    quotas: Arc<Quotas>,
+    similar: Option<Arc<SimilarityIndex>>,
+    pending: Arc<Mutex<HashSet<String>>>,
+    replication: broadcast::Sender<Change>,
+    mut queue: mpsc::Receiver<Write>,
+) {
+    while let Some(first) = queue.recv().await {
+        let mut batch = Vec::new();
+        let mut syncs = Vec::new();
+        let mut next = Some(first);
+
+        while let Some(write) = next.take() {
+            match write {
+                Write::Entity(entity) => batch.push(*entity),
+                Write::Sync(reply) => syncs.push(reply),
+            }
+
+            if batch.len() < WRITE_BATCH_SIZE {
+                next = queue.try_recv().ok();
+            }
+        }
+
+        if !batch.is_empty() {
+            let result = instance.write().await.set_many(batch.clone()).await;
+
+            let mut pending = pending.lock().await;
+            for entity in &batch {
+                pending.remove(&entity.id);
+            }
+
+            match result {
+                Ok(()) => {
+                    for entity in &batch {
+                        let _ = replication
+                            .send(Change::Added(Box::new(entity.clone())));
+                    }
+                },
+                Err(error) => {
+                    error!("Failed to write {} sentences: {}", batch.len(), error);
+                    for entity in &batch {
+                        database::uncount(&counters, entity).await;
+                        quotas.release(entity);
+                        if let Some(similar) = &similar {
+                            similar.remove(&entity.id);
+                        }
+                    }
+                },
+            }
+        }
+
+        for reply in syncs {
+            let _ = reply.send(());
+        }
+    }
+}
+
+/// Counts the sentences stored when a service is opened, from the snapshot
+/// if it is still valid.
+async fn warm(
+    service: &Service,
+    counters: &Counters,
+    quotas: &Quotas,
+    similar: &Option<Arc<SimilarityIndex>>,
+    directory: &Path,
+    entries: Vec<Entity>,
+) {
+    let ids = entries
+        .iter()
+        .map(|data| data.id.as_str())
+        .collect::<HashSet<_>>();
+
+    // Sentences deleted since the snapshot cannot be uncounted.
+    let snapshot = match Snapshot::load(directory) {
+        Ok(Some(snapshot))
+            if snapshot.ids.iter().all(|id| ids.contains(id.as_str())) =>
+        {
+            Some(snapshot)
+        },
+        Ok(Some(_)) => {
+            info!(namespace = service.name, "Snapshot is outdated.");
+            None
+        },
+        Ok(None) => None,
+        Err(error) => {
+            warn!(
+                namespace = service.name,
+                "Failed to load snapshot: {}", error
+            );
+            None
+        },
+    };
+
+    let counted = match snapshot {
+        Some(mut snapshot) => {
+            let counted = std::mem::take(&mut snapshot.ids);
+            snapshot.restore(counters).await;
+            counted
+        },
+        None => HashSet::new(),
+    };
+
+    for data in &entries {
+        quotas.record(data);
+        if let Some(similar) = similar {
+            similar.insert(data);
+        }
+    }
+    startup::counted(counted.len());
+
+    let mut added = 0;
+    for data in entries.iter().filter(|data| !counted.contains(&data.id)) {
+        database::count(service, counters, data).await;
+        startup::counted(1);
+        added += 1;
+    }
+    info!(
+        namespace = service.name,
+        "Restored {} entities from snapshot, counted {} entities.",
+        counted.len(),
+        added
+    );
+}
+
+/// Uncounts expired sentences, and saves snapshots periodically or when
+/// requested.
+///
+/// Snapshots are taken by this task so that no expired sentence is being
+/// uncounted meanwhile.
+#[allow(clippy::too_many_arguments)]
+async fn consume(
+    instance: Arc<RwLock<Instance<Entity>>>,
+    counters: Counters,
+    quotas: Arc<Quotas>,
+    webhooks: Arc<Webhooks>,
+    similar: Option<Arc<SimilarityIndex>>,
+    pending: Arc<Mutex<HashSet<String>>>,
+    mut expired: mpsc::Receiver<Entity>,
+    mut requests: mpsc::Receiver<oneshot::Sender<Result<(), Error>>>,
+    directory: PathBuf,
+    snapshot_interval: Duration,
+) {
+    let mut interval = tokio::time::interval(snapshot_interval);
+    // The first tick completes immediately.
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            data = expired.recv() => match data {
+                Some(data) => {
+                    database::uncount(&counters, &data).await;
+                    quotas.release(&data);
+                    webhooks.notify(&data);
+                    if let Some(similar) = &similar {
+                        similar.remove(&data.id);
+                    }
+                    record_expiration(&data);
+                },
+                None => break,
+            },
+            _ = interval.tick() => {
+                if let Err(error) = snapshot(
+                    &instance,
+                    &counters,
+                    &quotas,
+                    &webhooks,
+                    similar.as_deref(),
+                    &pending,
+                    &mut expired,
+                    &directory,
+                )
+                .await
+                {
+                    error!("Failed to save snapshot: {}", error);
+                }
+            },
+            Some(reply) = requests.recv() => {
+                let _ = reply.send(
+                    snapshot(
+                        &instance,
+                        &counters,
+                        &quotas,
+                        &webhooks,
+                        similar.as_deref(),
+                        &pending,
+                        &mut expired,
+                        &directory,
+                    )
+                    .await,
+                );
+            },
+        }
+    }
+}
+
+/// Updates the metrics once an expired sentence is uncounted.
+fn record_expiration(data: &Entity) {
+    let now = now();
+
+    METRICS.expirations.fetch_add(1, Ordering::Relaxed);
+    if let Some(expire_at) = data.ttl() {
+        METRICS
+            .expiration_lag
+            .store(now.saturating_sub(expire_at), Ordering::Relaxed);
+    }
+}
+
+/// Saves the counters and the sentences they include.
+#[allow(clippy::too_many_arguments)]
+async fn snapshot(
+    instance: &RwLock<Instance<Entity>>,
+    counters: &Counters,
+    quotas: &Quotas,
+    webhooks: &Webhooks,
+    similar: Option<&SimilarityIndex>,
+    pending: &Mutex<HashSet<String>>,
+    expired: &mut mpsc::Receiver<Entity>,
+    directory: &Path,
+) -> Result<(), Error> {
+    // Prevents sentences from being added or expired during the snapshot.
+    let instance = instance.write().await;
+    let pending = pending.lock().await;
+
+    // Expired sentences may still be in the database, waiting to be deleted.
+    let mut uncounted = HashSet::new();
+    while let Ok(data) = expired.try_recv() {
+        database::uncount(counters, &data).await;
+        quotas.release(&data);
+        webhooks.notify(&data);
+        if let Some(similar) = similar {
+            similar.remove(&data.id);
+        }
+        record_expiration(&data);
+        uncounted.insert(data.id);
+    }
+
+    // Queued sentences are counted, and will be written.
+    let ids = instance
+        .ids()
+        .chain(pending.iter().cloned())
+        .filter(|id| !uncounted.contains(id))
+        .collect();
+    Snapshot::take(counters, ids).await.save(directory)
+}
+
+/// Every namespace served by Squid.
+#[derive(Debug)]
+pub struct Namespaces {
+    /// Name of the namespace used when a request does not specify one.
+    default: String,
+    namespaces: HashMap<String, Arc<Namespace>>,
+    /// Storage used by each API key.
+    quotas: Arc<Quotas>,
+}
+
+impl Namespaces {
+    /// Opens the default service and each additional service of the
+    /// configuration.
+    ///
+    /// The default service stores its data in the data directory, while
+    /// each additional service uses a sub-directory named after it.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if an additional service has no name or shares
+    /// its name with another service.
+    pub async fn open(config: &Config) -> Result<Self, Error> {
+        let directory =
+            PathBuf::from(config.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR));
+        let snapshot_interval = Duration::from_secs(
+            config
+                .snapshot_interval
+                .unwrap_or(DEFAULT_SNAPSHOT_INTERVAL_SEC)
+                .max(1),
+        );
+        let expiration_queue_size = config
+            .expiration_queue_size
+            .unwrap_or(DEFAULT_EXPIRATION_QUEUE_SIZE)
+            .max(1);
+        let quotas = Arc::new(Quotas::new(&config.api_keys));
+        let mut namespaces = HashMap::new();
+        // The primary already notifies the removals of its sentences.
+        let configure = |service: &Service| {
+            let mut service = service.clone();
+            if config.primary.is_some() {
+                service.webhooks.clear();
+            }
+            service
+        };
+
+        namespaces.insert(
+            config.service.name.clone(),
+            Arc::new(
+                Namespace::open(
+                    configure(&config.service),
+                    &directory,
+                    snapshot_interval,
+                    expiration_queue_size,
+                    Arc::clone(&quotas),
+                )
+                .await?,
+            ),
+        );
+
+        for service in &config.services {
+            if service.name.is_empty() || namespaces.contains_key(&service.name) {
+                panic!("Services must have a unique and non-empty name");
+            }
+
+            namespaces.insert(
+                service.name.clone(),
+                Arc::new(
+                    Namespace::open(
+                        configure(service),
+                        &directory.join(&service.name),
+                        snapshot_interval,
+                        expiration_queue_size,
+                        Arc::clone(&quotas),
+                    )
+                    .await?,
+                ),
+            );
+        }
+
+        // Replicas would insert the counts of the primary again.
+        #[cfg(feature = "clickhouse")]
+        if let Some(sink) = config
+            .clickhouse
+            .clone()
+            .filter(|_| config.primary.is_none())
+        {
+            tokio::task::spawn(crate::clickhouse::schedule(
+                namespaces
+                    .values()
+                    .map(|namespace| {
+                        (
+                            namespace.service.name.clone(),
+                            namespace.counters.clone(),
+                        )
+                    })
+                    .collect(),
+                sink,
+            ));
+        }
+        #[cfg(not(feature = "clickhouse"))]
+        if config.clickhouse.is_some() {
+            warn!(
+                "ClickHouse sink is ignored, Squid was built without the \
+                 clickhouse feature."
+            );
+        }
+        startup::loaded();
+
+        Ok(Self {
+            default: config.service.name.clone(),
+            namespaces,
+            quotas,
+        })
+    }
+
+    /// Returns a namespace from its name, or the default one if the name is
+    /// empty.
+    pub fn get(&self, name: &str) -> Result<&Arc<Namespace>, Error> {
+        let name = if name.is_empty() { &self.default } else { name };
+
+        self.namespaces.get(name).ok_or_else(|| {
+            Error::from(RequestError::NotFound)
+                .with_context(format!("unknown namespace {:?}", name))
+        })
+    }
+
+    /// Returns the storage used by each API key.
+    pub fn quotas(&self) -> &Quotas {
+        &self.quotas
+    }
+
+    /// Iterates over every namespace.
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<Namespace>> {
+        self.namespaces.values()
+    }
+
+    /// Writes queued sentences, flushes memtables, then saves snapshots and
+    /// series, before the process exits.
+    ///
+    /// Failures are logged, and the next steps are taken anyway.
+    pub async fn close(&self) {
+        info!("Writing queued sentences...");
+        for namespace in self.iter() {
+            if let Err(err) = namespace.sync().await {
+                error!("Some sentences haven't been written: {}", err);
+            }
+        }
+        if FLUSHTABLE_FLUSH_SIZE_KB > 0 {
+            info!("Flushing memtable...");
+            for namespace in self.iter() {
+                if let Err(err) = namespace.instance.write().await.flush() {
+                    error!(
+                        "Some data haven't been flushed from memtable: {}",
+                        err
+                    );
+                }
+            }
+        }
+        info!("Saving snapshots...");
+        for namespace in self.iter() {
+            if let Err(err) = namespace.snapshot().await {
+                error!("Failed to save snapshot: {}", err);
+            }
+        }
+        info!("Saving series...");
+        for namespace in self.iter() {
+            let Some(series) = &namespace.series else {
+                continue;
+            };
+            if let Err(err) = series.save(true).await {
+                error!("Failed to save series: {}", err);
+            }
+        }
+    }
+}
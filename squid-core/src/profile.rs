@@ -0,0 +1,82 @@
+//! Time spent by a request in each phase, so slow requests can be
+//! explained.
+//!
+//! Phases are only recorded within [`scope`], by the task running the
+//! request. Work done by background tasks, such as writing sentences, is
+//! not recorded.
+
+use std::{
+    cell::Cell,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+tokio::task_local! {
+    static PHASES: Cell<Phases>;
+}
+
+/// A part of the handling of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    /// Splitting sentences or words into tokens.
+    Tokenization,
+    /// Reading or writing sentences.
+    Database,
+    /// Counting or ranking words.
+    Algorithm,
+}
+
+/// Time spent in each phase.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Phases {
+    /// Time spent in [`Phase::Tokenization`].
+    pub tokenization: Duration,
+    /// Time spent in [`Phase::Database`].
+    pub database: Duration,
+    /// Time spent in [`Phase::Algorithm`].
+    pub algorithm: Duration,
+}
+
+/// Measures a phase until it is dropped.
+#[derive(Debug)]
+#[must_use = "the phase is measured until the timer is dropped"]
+pub struct Timer {
+    phase: Phase,
+    start: Instant,
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+
+        // Outside of a scope, nothing is recorded.
+        let _ = PHASES.try_with(|phases| {
+            let mut value = phases.get();
+            match self.phase {
+                Phase::Tokenization => value.tokenization += elapsed,
+                Phase::Database => value.database += elapsed,
+                Phase::Algorithm => value.algorithm += elapsed,
+            }
+            phases.set(value);
+        });
+    }
+}
+
+/// Starts measuring a phase of the current request.
+pub fn start(phase: Phase) -> Timer {
+    Timer {
+        phase,
+        start: Instant::now(),
+    }
+}
+
+/// Runs a request, returning its output with the time spent in each
+/// phase.
+pub async fn scope<F: Future>(future: F) -> (F::Output, Phases) {
+    PHASES
+        .scope(Cell::default(), async {
+            let output = future.await;
+            (output, PHASES.with(Cell::get))
+        })
+        .await
+}
@@ -0,0 +1,136 @@
+//! Storage used by the sentences of each API key, and its limits.
+
+use crate::models::{
+    config::{ApiKey, Quota},
+    database::Entity,
+};
+use squid_error::{Error, RequestError};
+use std::{collections::HashMap, sync::Mutex};
+
+/// Sentences stored with an API key.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Usage {
+    /// Number of stored sentences.
+    pub entries: u64,
+    /// Size of the stored sentences, in bytes.
+    pub bytes: u64,
+}
+
+/// Usage and quota of the API keys sharing a name.
+#[derive(Debug, Default)]
+struct Account {
+    quota: Option<Quota>,
+    usage: Mutex<Usage>,
+}
+
+/// Storage used by each named API key, across every namespace.
+#[derive(Debug, Default)]
+pub struct Quotas {
+    accounts: HashMap<String, Account>,
+}
+
+/// Size of a sentence once stored.
+fn size(entity: &Entity) -> u64 {
+    bincode::serialized_size(entity).unwrap_or_default()
+}
+
+impl Quotas {
+    /// Tracks the usage of each named API key.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if an API key has a quota but no name.
+    pub fn new(keys: &[ApiKey]) -> Self {
+        let mut accounts = HashMap::<String, Account>::new();
+
+        for key in keys {
+            match (&key.name, &key.quota) {
+                (Some(name), quota) => {
+                    let account = accounts.entry(name.clone()).or_default();
+                    if quota.is_some() {
+                        account.quota = quota.clone();
+                    }
+                },
+                (None, Some(_)) => {
+                    panic!("API keys with a quota must have a name")
+                },
+                (None, None) => {},
+            }
+        }
+
+        Self { accounts }
+    }
+
+    /// Adds a sentence to the usage of its owner, unless it exceeds its
+    /// quota.
+    pub fn reserve(&self, entity: &Entity) -> Result<(), Error> {
+        let Some((name, account)) = self.account(entity) else {
+            return Ok(());
+        };
+        let size = size(entity);
+        let mut usage = account.usage.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(quota) = &account.quota {
+            if let Some(max) = quota.entries.filter(|max| usage.entries >= *max)
+            {
+                return Err(Error::from(RequestError::ResourceExhausted)
+                    .with_context(format!(
+                        "{} reached its quota of {} sentences",
+                        name, max
+                    )));
+            }
+            if let Some(max) = quota.bytes.filter(|max| usage.bytes + size > *max)
+            {
+                return Err(Error::from(RequestError::ResourceExhausted)
+                    .with_context(format!(
+                        "{} reached its quota of {} bytes",
+                        name, max
+                    )));
+            }
+        }
+
+        usage.entries += 1;
+        usage.bytes += size;
+        Ok(())
+    }
+
+    /// Adds a sentence to the usage of its owner, even if it exceeds its
+    /// quota.
+    pub fn record(&self, entity: &Entity) {
+        if let Some((_, account)) = self.account(entity) {
+            let mut usage =
+                account.usage.lock().unwrap_or_else(|e| e.into_inner());
+            usage.entries += 1;
+            usage.bytes += size(entity);
+        }
+    }
+
+    /// Removes a sentence from the usage of its owner.
+    pub fn release(&self, entity: &Entity) {
+        if let Some((_, account)) = self.account(entity) {
+            let mut usage =
+                account.usage.lock().unwrap_or_else(|e| e.into_inner());
+            usage.entries = usage.entries.saturating_sub(1);
+            usage.bytes = usage.bytes.saturating_sub(size(entity));
+        }
+    }
+
+    /// Returns the usage and quota of each named API key.
+    pub fn usage(&self) -> Vec<(String, Usage, Option<Quota>)> {
+        self.accounts
+            .iter()
+            .map(|(name, account)| {
+                (
+                    name.clone(),
+                    *account.usage.lock().unwrap_or_else(|e| e.into_inner()),
+                    account.quota.clone(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns the account owning a sentence, if any.
+    fn account(&self, entity: &Entity) -> Option<(&String, &Account)> {
+        self.accounts.get_key_value(entity.owner()?)
+    }
+}
@@ -0,0 +1,1193 @@
+//! Consensus on the changes made to the services, so a change is written by
+//! a majority of the nodes before being acknowledged, and survives the
+//! failure of any single node out of three.
+//!
+//! This is Raft without membership changes: nodes are configured. Once
+//! applied and stored by every node, entries are dropped from the log, the
+//! services storing their changes, and the last one is kept as a snapshot
+//! from which the log goes on. The transport is left to the caller, which
+//! sends the requests built here to the other nodes and hands their replies
+//! back.
+
+use crate::{models::config, namespace::Change};
+use serde::{Deserialize, Serialize};
+use squid_error::{Error, IoError, RequestError, ResultExt};
+use std::{
+    collections::HashMap,
+    fs::{self, File, OpenOptions},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::sync::{watch, Mutex, Notify};
+
+/// Milliseconds between two heartbeats if not configured.
+const DEFAULT_HEARTBEAT_MS: u64 = 100;
+/// Milliseconds without heartbeat before an election if not configured.
+const DEFAULT_ELECTION_TIMEOUT_MS: u64 = 1000;
+/// Maximum time to commit a change before giving up on waiting for it.
+const PROPOSAL_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum number of entries sent at once to a node.
+const MAX_BATCH: u64 = 512;
+/// Applied entries kept in the log before it is compacted if not
+/// configured.
+const DEFAULT_SNAPSHOT_ENTRIES: u64 = 10_000;
+/// File containing the snapshot, then the entries of the log after it.
+const LOG_FILE: &str = "log.bin";
+/// File containing the term and the vote of the node.
+const STATE_FILE: &str = "state.json";
+
+/// A change to apply to a service.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Command {
+    /// Name of the changed service.
+    pub namespace: String,
+    /// Change made to the service.
+    pub change: Change,
+}
+
+/// An entry of the log.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Entry {
+    /// Term of the leader which appended the entry.
+    pub term: u64,
+    /// Nothing for the entry appended by each new leader, which commits the
+    /// entries of the previous ones.
+    pub command: Option<Command>,
+}
+
+impl Entry {
+    /// Serializes the entry, to be sent to another node.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        bincode::serialize(self)
+            .context(IoError::SerializationError, "while encoding Raft entry")
+    }
+
+    /// Deserializes an entry sent by another node.
+    pub fn decode(data: &[u8]) -> Result<Self, Error> {
+        bincode::deserialize(data)
+            .context(IoError::DeserializationError, "while decoding Raft entry")
+    }
+}
+
+/// Last entry dropped from the log, the services storing the changes of
+/// the entries up to it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Snapshot {
+    /// Index of the last dropped entry, 0 before the first compaction.
+    pub index: u64,
+    /// Term of the last dropped entry.
+    pub term: u64,
+    /// Changes of the dropped entries which the services do not store, to
+    /// be applied again at startup, such as the last excluded words of each
+    /// service.
+    pub commands: Vec<Command>,
+}
+
+impl Snapshot {
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        bincode::serialize(self).context(
+            IoError::SerializationError,
+            "while encoding Raft snapshot",
+        )
+    }
+
+    fn decode(data: &[u8]) -> Result<Self, Error> {
+        bincode::deserialize(data).context(
+            IoError::DeserializationError,
+            "while decoding Raft snapshot",
+        )
+    }
+}
+
+/// Sent by a candidate to be elected leader.
+#[derive(Debug, Clone)]
+pub struct VoteRequest {
+    /// Term of the candidate.
+    pub term: u64,
+    /// Address of the candidate.
+    pub candidate: String,
+    /// Index of the last entry of the candidate.
+    pub last_index: u64,
+    /// Term of the last entry of the candidate.
+    pub last_term: u64,
+}
+
+/// Whether a node votes for a candidate.
+#[derive(Debug, Clone)]
+pub struct VoteReply {
+    /// Current term of the node, for the candidate to update itself.
+    pub term: u64,
+    /// Whether the node voted for the candidate.
+    pub granted: bool,
+}
+
+/// Sent by the leader to replicate its log, empty to only keep its
+/// leadership.
+#[derive(Debug, Clone)]
+pub struct AppendRequest {
+    /// Term of the leader.
+    pub term: u64,
+    /// Address of the leader.
+    pub leader: String,
+    /// Index of the entry preceding the sent ones.
+    pub prev_index: u64,
+    /// Term of the entry preceding the sent ones.
+    pub prev_term: u64,
+    /// Entries to append, empty to only keep the leadership.
+    pub entries: Vec<Entry>,
+    /// Index of the last entry committed by the leader.
+    pub commit: u64,
+    /// Index of the last entry stored by every node, up to which the log
+    /// may be compacted.
+    pub stored: u64,
+}
+
+/// Whether a node appended the entries of the leader.
+#[derive(Debug, Clone)]
+pub struct AppendReply {
+    /// Current term of the node, for the leader to update itself.
+    pub term: u64,
+    /// Whether the node appended the entries.
+    pub success: bool,
+    /// Index of the last entry matching the log of the leader if the
+    /// entries were appended, or of the last entry which may match it.
+    pub last_index: u64,
+}
+
+/// Term and vote of the node, kept across restarts.
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct Persisted {
+    term: u64,
+    voted_for: Option<String>,
+}
+
+#[derive(Debug)]
+enum Role {
+    Follower,
+    Candidate {
+        /// Votes received, including the one of the node.
+        votes: usize,
+    },
+    Leader {
+        /// Index of the next entry sent to each node.
+        next: HashMap<String, u64>,
+        /// Index of the last entry known to be stored by each node.
+        matched: HashMap<String, u64>,
+    },
+}
+
+#[derive(Debug)]
+struct State {
+    term: u64,
+    voted_for: Option<String>,
+    /// Address of the leader of the current term, once known.
+    leader: Option<String>,
+    role: Role,
+    /// Last entry dropped from the log.
+    snapshot: Snapshot,
+    /// Entries of the log after the snapshot.
+    log: Vec<Entry>,
+    /// Index of the last entry written to the disk, the following ones
+    /// waiting for it.
+    written: u64,
+    /// Index of the last entry stored by a majority of the nodes.
+    commit: u64,
+    /// Index of the last entry stored by every node.
+    stored: u64,
+    /// Time at which an election starts without news of the leader.
+    deadline: Instant,
+}
+
+impl State {
+    fn last_index(&self) -> u64 {
+        self.snapshot.index + self.log.len() as u64
+    }
+
+    /// Returns the term of an entry, 0 before the first one, or nothing if
+    /// the entry is dropped or missing.
+    fn term_at(&self, index: u64) -> Option<u64> {
+        if index == self.snapshot.index {
+            return Some(self.snapshot.term);
+        }
+
+        index
+            .checked_sub(self.snapshot.index + 1)
+            .and_then(|position| self.log.get(position as usize))
+            .map(|entry| entry.term)
+    }
+
+    /// Returns the entries after `from`, up to `to` included, or nothing if
+    /// some of them are dropped.
+    fn entries(&self, from: u64, to: u64) -> &[Entry] {
+        let to = to.min(self.last_index());
+        match from.checked_sub(self.snapshot.index) {
+            Some(start) if from < to => {
+                &self.log[start as usize..(to - self.snapshot.index) as usize]
+            },
+            _ => &[],
+        }
+    }
+}
+
+/// This node and the other nodes agreeing on the changes.
+#[derive(Debug)]
+pub struct Raft {
+    /// Address of the gRPC server of this node, as reached by the others.
+    pub address: String,
+    /// Addresses of the other nodes.
+    pub peers: Vec<String>,
+    /// API key sent to the other nodes.
+    pub api_key: Option<String>,
+    /// Time between two heartbeats of the leader.
+    pub heartbeat: Duration,
+    /// Time without news of the leader before an election, at least.
+    pub election_timeout: Duration,
+    /// Applied entries kept in the log before it is compacted.
+    pub snapshot_entries: u64,
+    directory: PathBuf,
+    state: Mutex<State>,
+    /// File containing the log, always locked before the state, so the
+    /// disk is waited for without holding the state.
+    file: Mutex<File>,
+    /// Index of the last committed entry.
+    commits: watch::Sender<u64>,
+    /// Notified once a change is appended, to send it without waiting for
+    /// the next heartbeat.
+    proposals: Notify,
+}
+
+impl Raft {
+    /// Loads the log and the state of the node from its directory in the
+    /// data directory.
+    pub fn open(config: &config::Raft, data_dir: &Path) -> Result<Self, Error> {
+        let directory = data_dir.join("raft");
+        fs::create_dir_all(&directory)
+            .context(IoError::WritingError, "while creating Raft directory")?;
+
+        let persisted = match fs::read(directory.join(STATE_FILE)) {
+            Ok(data) => serde_json::from_slice::<Persisted>(&data).context(
+                IoError::DeserializationError,
+                "while reading Raft state",
+            )?,
+            Err(_) => Persisted::default(),
+        };
+        let (snapshot, log, file) = read_log(&directory.join(LOG_FILE))?;
+
+        let election_timeout = Duration::from_millis(
+            config
+                .election_timeout_ms
+                .unwrap_or(DEFAULT_ELECTION_TIMEOUT_MS)
+                .max(1),
+        );
+        let raft = Self {
+            address: config.address.clone(),
+            peers: config
+                .peers
+                .iter()
+                .filter(|peer| **peer != config.address)
+                .cloned()
+                .collect(),
+            api_key: config.api_key.clone(),
+            heartbeat: Duration::from_millis(
+                config.heartbeat_ms.unwrap_or(DEFAULT_HEARTBEAT_MS).max(1),
+            ),
+            election_timeout,
+            snapshot_entries: config
+                .snapshot_entries
+                .unwrap_or(DEFAULT_SNAPSHOT_ENTRIES)
+                .max(1),
+            directory,
+            state: Mutex::new(State {
+                term: persisted.term,
+                voted_for: persisted.voted_for,
+                leader: None,
+                role: Role::Follower,
+                written: snapshot.index + log.len() as u64,
+                // Dropped entries were committed and stored by every node.
+                commit: snapshot.index,
+                stored: snapshot.index,
+                snapshot: snapshot.clone(),
+                log,
+                deadline: Instant::now(),
+            }),
+            file: Mutex::new(file),
+            commits: watch::channel(snapshot.index).0,
+            proposals: Notify::new(),
+        };
+        raft.state.try_lock().expect("unshared state").deadline =
+            raft.deadline();
+
+        Ok(raft)
+    }
+
+    /// Returns the address of the leader, if known.
+    pub async fn leader(&self) -> Option<String> {
+        self.state.lock().await.leader.clone()
+    }
+
+    /// Returns an error telling where to send changes unless this node is
+    /// the leader.
+    pub async fn lead(&self) -> Result<(), Error> {
+        let state = self.state.lock().await;
+
+        match state.role {
+            Role::Leader { .. } => Ok(()),
+            _ => Err(not_leader(state.leader.as_deref())),
+        }
+    }
+
+    /// Returns the index of the last committed entry, updated as entries
+    /// are committed.
+    pub fn commits(&self) -> watch::Receiver<u64> {
+        self.commits.subscribe()
+    }
+
+    /// Waits until a change is appended by this node.
+    pub async fn proposed(&self) {
+        self.proposals.notified().await
+    }
+
+    /// Returns the entries after `from`, up to `to` included, or nothing if
+    /// some of them were dropped from the log.
+    pub async fn entries(&self, from: u64, to: u64) -> Vec<Entry> {
+        self.state.lock().await.entries(from, to).to_vec()
+    }
+
+    /// Returns the last entry dropped from the log, from which committed
+    /// entries are applied at startup.
+    pub async fn snapshot(&self) -> Snapshot {
+        self.state.lock().await.snapshot.clone()
+    }
+
+    /// Appends a change to the log, then waits until a majority of the
+    /// nodes store it.
+    ///
+    /// Changes are only accepted by the leader. A change which is not
+    /// committed in time may still be committed later.
+    pub async fn propose(&self, command: Command) -> Result<(), Error> {
+        let (index, term) = {
+            let mut state = self.state.lock().await;
+            if !matches!(state.role, Role::Leader { .. }) {
+                return Err(not_leader(state.leader.as_deref()));
+            }
+
+            let entry = Entry {
+                term: state.term,
+                command: Some(command),
+            };
+            state.log.push(entry);
+
+            (state.last_index(), state.term)
+        };
+        // Sent to the other nodes while written to the disk.
+        self.proposals.notify_one();
+        self.write(index).await?;
+
+        let mut commits = self.commits.subscribe();
+        tokio::time::timeout(
+            PROPOSAL_TIMEOUT,
+            commits.wait_for(|commit| *commit >= index),
+        )
+        .await
+        .map_err(|_| {
+            Error::from(RequestError::Unavailable).with_context(
+                "the change was not committed in time, it may still be",
+            )
+        })?
+        .map_err(|_| {
+            Error::from(RequestError::Unavailable)
+                .with_context("the node is stopping")
+        })?;
+
+        // A new leader may have replaced the entry with one of its own. A
+        // leader never drops its entries, but the entry may be dropped from
+        // the log once applied.
+        let state = self.state.lock().await;
+        match state.term_at(index) {
+            _ if state.term == term => Ok(()),
+            Some(current) if current == term => Ok(()),
+            Some(_) => Err(Error::from(RequestError::Unavailable)
+                .with_context("the leader changed, the change was discarded")),
+            None => Err(Error::from(RequestError::Unavailable).with_context(
+                "the leader changed, the change may have been discarded",
+            )),
+        }
+    }
+
+    /// Returns whether enough entries are applied to compact the log.
+    pub async fn compactable(&self, applied: u64) -> bool {
+        let state = self.state.lock().await;
+        compaction(&state, applied)
+            >= state.snapshot.index + self.snapshot_entries
+    }
+
+    /// Drops the applied entries stored by every node from the log, keeping
+    /// the last one as the snapshot, if enough entries are applied.
+    ///
+    /// The services must store the changes of the applied entries first,
+    /// as they are not applied again at startup.
+    pub async fn compact(&self, applied: u64) -> Result<(), Error> {
+        let mut file = self.file.lock().await;
+        let (snapshot, entries) = {
+            let mut state = self.state.lock().await;
+            let index = compaction(&state, applied);
+            if index < state.snapshot.index + self.snapshot_entries {
+                return Ok(());
+            }
+
+            let position = (index - state.snapshot.index) as usize;
+            let dropped = state.log.drain(..position).collect::<Vec<_>>();
+            let term = dropped.last().map_or(state.snapshot.term, |entry| {
+                entry.term
+            });
+
+            // The excluded words are only kept in memory by the services,
+            // and each change replaces the previous ones.
+            let mut commands = std::mem::take(&mut state.snapshot.commands);
+            for command in dropped.into_iter().filter_map(|entry| entry.command)
+            {
+                if matches!(command.change, Change::Excluded(_)) {
+                    commands.retain(|kept| kept.namespace != command.namespace);
+                    commands.push(command);
+                }
+            }
+            state.snapshot = Snapshot {
+                index,
+                term,
+                commands,
+            };
+
+            let written = state.written;
+            (state.snapshot.clone(), state.entries(index, written).to_vec())
+        };
+
+        *file = self.rewrite_log(&snapshot, &entries)?;
+        Ok(())
+    }
+
+    /// Starts an election if the leader has not been heard of in time.
+    ///
+    /// Returns the request to send to each other node, if any.
+    pub async fn campaign(&self) -> Result<Option<VoteRequest>, Error> {
+        let index = {
+            let mut state = self.state.lock().await;
+            if matches!(state.role, Role::Leader { .. })
+                || Instant::now() < state.deadline
+            {
+                return Ok(None);
+            }
+
+            state.term += 1;
+            state.voted_for = Some(self.address.clone());
+            state.leader = None;
+            state.role = Role::Candidate { votes: 1 };
+            state.deadline = self.deadline();
+            self.persist(&state)?;
+
+            if !self.elected(1) {
+                let last_index = state.last_index();
+                return Ok(Some(VoteRequest {
+                    term: state.term,
+                    candidate: self.address.clone(),
+                    last_index,
+                    last_term: state.term_at(last_index).unwrap_or_default(),
+                }));
+            }
+
+            self.become_leader(&mut state)
+        };
+
+        self.write(index).await?;
+        Ok(None)
+    }
+
+    /// Handles the vote of another node.
+    pub async fn voted(&self, reply: VoteReply) -> Result<(), Error> {
+        let index = {
+            let mut state = self.state.lock().await;
+            if reply.term > state.term {
+                return self.step_down(&mut state, reply.term);
+            }
+
+            let term = state.term;
+            let Role::Candidate { votes } = &mut state.role else {
+                return Ok(());
+            };
+            if reply.term != term || !reply.granted {
+                return Ok(());
+            }
+            *votes += 1;
+            if !self.elected(*votes) {
+                return Ok(());
+            }
+
+            self.become_leader(&mut state)
+        };
+
+        self.write(index).await
+    }
+
+    /// Votes for a candidate, unless this node already voted for another
+    /// one or has a more recent log.
+    pub async fn vote(&self, request: VoteRequest) -> Result<VoteReply, Error> {
+        let mut state = self.state.lock().await;
+        if request.term > state.term {
+            self.step_down(&mut state, request.term)?;
+        }
+
+        let last_index = state.last_index();
+        let last_term = state.term_at(last_index).unwrap_or_default();
+        let granted = request.term == state.term
+            && (request.last_term, request.last_index)
+                >= (last_term, last_index)
+            && state
+                .voted_for
+                .as_ref()
+                .is_none_or(|candidate| *candidate == request.candidate);
+        if granted {
+            state.voted_for = Some(request.candidate);
+            state.deadline = self.deadline();
+            self.persist(&state)?;
+        }
+
+        Ok(VoteReply {
+            term: state.term,
+            granted,
+        })
+    }
+
+    /// Returns the request to send to each other node if this node is the
+    /// leader, with the entries it misses.
+    pub async fn heartbeats(&self) -> Vec<(String, AppendRequest)> {
+        let state = self.state.lock().await;
+        let Role::Leader { next, .. } = &state.role else {
+            return Vec::new();
+        };
+
+        next.iter()
+            .map(|(peer, next)| {
+                // Nodes store every dropped entry.
+                let prev_index = (next - 1).max(state.snapshot.index);
+
+                (
+                    peer.clone(),
+                    AppendRequest {
+                        term: state.term,
+                        leader: self.address.clone(),
+                        prev_index,
+                        prev_term: state
+                            .term_at(prev_index)
+                            .unwrap_or_default(),
+                        entries: state
+                            .entries(prev_index, prev_index + MAX_BATCH)
+                            .to_vec(),
+                        commit: state.commit,
+                        stored: state.stored,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    /// Handles the reply of a node to the entries of the leader.
+    pub async fn appended(
+        &self,
+        peer: &str,
+        reply: AppendReply,
+    ) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        if reply.term > state.term {
+            return self.step_down(&mut state, reply.term);
+        }
+
+        let (term, first) = (state.term, state.snapshot.index + 1);
+        let Role::Leader { next, matched } = &mut state.role else {
+            return Ok(());
+        };
+        if reply.term != term {
+            return Ok(());
+        }
+
+        let (Some(next), Some(matched)) =
+            (next.get_mut(peer), matched.get_mut(peer))
+        else {
+            return Ok(());
+        };
+        if reply.success {
+            *matched = (*matched).max(reply.last_index);
+            *next = *matched + 1;
+        } else {
+            *next = (*next - 1).min(reply.last_index + 1).max(first);
+        }
+
+        self.advance(&mut state);
+        Ok(())
+    }
+
+    /// Appends the entries of the leader, replacing the conflicting ones.
+    pub async fn append(
+        &self,
+        request: AppendRequest,
+    ) -> Result<AppendReply, Error> {
+        let mut file = self.file.lock().await;
+        let mut state = self.state.lock().await;
+        if request.term < state.term {
+            return Ok(AppendReply {
+                term: state.term,
+                success: false,
+                last_index: state.last_index(),
+            });
+        }
+        if request.term > state.term || !matches!(state.role, Role::Follower) {
+            self.step_down(&mut state, request.term)?;
+        }
+        state.leader = Some(request.leader);
+        state.deadline = self.deadline();
+
+        // The leader goes back until both logs match. Dropped entries were
+        // committed, so they match.
+        if request.prev_index > state.last_index()
+            || state
+                .term_at(request.prev_index)
+                .is_some_and(|term| term != request.prev_term)
+        {
+            return Ok(AppendReply {
+                term: state.term,
+                success: false,
+                last_index: request
+                    .prev_index
+                    .saturating_sub(1)
+                    .min(state.last_index()),
+            });
+        }
+
+        let last_index = request.prev_index + request.entries.len() as u64;
+        let mut index = request.prev_index;
+        let mut rewritten = false;
+        for entry in request.entries {
+            index += 1;
+            if index <= state.snapshot.index {
+                continue;
+            }
+            if index <= state.last_index() {
+                if state.term_at(index) == Some(entry.term) {
+                    continue;
+                }
+                // Committed entries never conflict, only uncommitted ones
+                // are dropped.
+                let position = index - state.snapshot.index - 1;
+                state.log.truncate(position as usize);
+                if index <= state.written {
+                    state.written = index - 1;
+                    rewritten = true;
+                }
+            }
+            state.log.push(entry);
+        }
+
+        // Written without holding the state, the file being locked.
+        let (from, snapshot) = (state.written, state.snapshot.clone());
+        let entries = if rewritten {
+            state.entries(snapshot.index, state.last_index()).to_vec()
+        } else {
+            state.entries(from, state.last_index()).to_vec()
+        };
+        drop(state);
+
+        if rewritten {
+            *file = self.rewrite_log(&snapshot, &entries)?;
+        } else if !entries.is_empty() {
+            write_entries(&mut file, &entries)?;
+        }
+
+        let mut state = self.state.lock().await;
+        state.written = if rewritten { snapshot.index } else { from }
+            + entries.len() as u64;
+        let commit = request.commit.min(last_index);
+        if commit > state.commit {
+            state.commit = commit;
+            self.commits.send_replace(commit);
+        }
+        state.stored = state.stored.max(request.stored.min(commit));
+
+        Ok(AppendReply {
+            term: state.term,
+            success: true,
+            last_index,
+        })
+    }
+
+    /// Writes the entries of the log to the disk, up to `index` at least,
+    /// then commits them if a majority of the nodes store them.
+    ///
+    /// Entries appended meanwhile are written together.
+    async fn write(&self, index: u64) -> Result<(), Error> {
+        let mut file = self.file.lock().await;
+        let (from, entries) = {
+            let state = self.state.lock().await;
+            if state.written >= index {
+                return Ok(());
+            }
+
+            let last_index = state.last_index();
+            (state.written, state.entries(state.written, last_index).to_vec())
+        };
+        write_entries(&mut file, &entries)?;
+
+        // Entries are only dropped with the file locked.
+        let mut state = self.state.lock().await;
+        state.written = from + entries.len() as u64;
+        self.advance(&mut state);
+
+        Ok(())
+    }
+
+    /// Returns whether the votes are a majority of the nodes.
+    fn elected(&self, votes: usize) -> bool {
+        votes * 2 > self.peers.len() + 1
+    }
+
+    /// Becomes the leader of the current term, and appends an empty entry
+    /// to commit the entries of the previous leaders.
+    ///
+    /// Returns the index of the entry, to be written to the disk.
+    fn become_leader(&self, state: &mut State) -> u64 {
+        state.log.push(Entry {
+            term: state.term,
+            command: None,
+        });
+
+        let next = state.last_index();
+        state.role = Role::Leader {
+            next: self.peers.iter().map(|peer| (peer.clone(), next)).collect(),
+            matched: self.peers.iter().map(|peer| (peer.clone(), 0)).collect(),
+        };
+        state.leader = Some(self.address.clone());
+
+        next
+    }
+
+    /// Follows the leader of a term, which may not be known yet.
+    fn step_down(&self, state: &mut State, term: u64) -> Result<(), Error> {
+        if term > state.term {
+            state.term = term;
+            state.voted_for = None;
+            state.leader = None;
+            self.persist(state)?;
+        }
+        state.role = Role::Follower;
+        state.deadline = self.deadline();
+
+        Ok(())
+    }
+
+    /// Commits the entries of the current term stored by a majority of the
+    /// nodes, and the ones before them.
+    fn advance(&self, state: &mut State) {
+        let Role::Leader { matched, .. } = &state.role else {
+            return;
+        };
+
+        let mut indexes = matched.values().copied().collect::<Vec<_>>();
+        indexes.push(state.written);
+        indexes.sort_unstable_by(|a, b| b.cmp(a));
+        let majority = indexes[indexes.len() / 2];
+        let every = indexes[indexes.len() - 1];
+
+        state.stored = state.stored.max(every);
+        if majority > state.commit
+            && state.term_at(majority) == Some(state.term)
+        {
+            state.commit = majority;
+            self.commits.send_replace(majority);
+        }
+    }
+
+    /// Returns when to start an election without news of the leader,
+    /// randomly delayed so nodes rarely start one at the same time.
+    fn deadline(&self) -> Instant {
+        let timeout = self.election_timeout.as_millis() as u64;
+        let jitter = (uuid::Uuid::new_v4().as_u128() % timeout as u128) as u64;
+
+        Instant::now() + Duration::from_millis(timeout + jitter)
+    }
+
+    /// Saves the term and the vote of the node.
+    fn persist(&self, state: &State) -> Result<(), Error> {
+        let data = serde_json::to_vec(&Persisted {
+            term: state.term,
+            voted_for: state.voted_for.clone(),
+        })
+        .context(IoError::SerializationError, "while encoding Raft state")?;
+
+        // Written aside then renamed, so the state is never partial.
+        let path = self.directory.join(STATE_FILE);
+        let partial = path.with_extension("part");
+        let mut file = File::create(&partial)
+            .context(IoError::WritingError, "while writing Raft state")?;
+        file.write_all(&data)
+            .and_then(|_| file.sync_data())
+            .context(IoError::WritingError, "while writing Raft state")?;
+        fs::rename(partial, path)
+            .context(IoError::WritingError, "while writing Raft state")
+    }
+
+    /// Replaces the log file, once entries are dropped.
+    fn rewrite_log(
+        &self,
+        snapshot: &Snapshot,
+        entries: &[Entry],
+    ) -> Result<File, Error> {
+        let path = self.directory.join(LOG_FILE);
+        let partial = path.with_extension("part");
+
+        let mut file = File::create(&partial)
+            .context(IoError::WritingError, "while rewriting Raft log")?;
+        let mut buffer = Vec::new();
+        frame(&snapshot.encode()?, &mut buffer);
+        write(&mut file, &buffer)?;
+        write_entries(&mut file, entries)?;
+        fs::rename(&partial, &path)
+            .context(IoError::WritingError, "while rewriting Raft log")?;
+
+        OpenOptions::new()
+            .append(true)
+            .open(path)
+            .context(IoError::WritingError, "while opening Raft log")
+    }
+}
+
+/// Error returned when a change is sent to another node than the leader.
+fn not_leader(leader: Option<&str>) -> Error {
+    Error::from(RequestError::Unavailable).with_context(match leader {
+        Some(leader) => {
+            format!("this node is not the leader, send changes to {}", leader)
+        },
+        None => "no leader is elected yet".to_string(),
+    })
+}
+
+/// Returns the index up to which the log may be compacted: entries are
+/// applied, and stored by every node so none is sent again.
+fn compaction(state: &State, applied: u64) -> u64 {
+    applied.min(state.commit).min(state.stored).min(state.written)
+}
+
+/// Appends data to a buffer, preceded by its length.
+fn frame(data: &[u8], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    buffer.extend_from_slice(data);
+}
+
+/// Appends entries to the log file, each one preceded by its length, then
+/// waits for the disk.
+fn write_entries(file: &mut File, entries: &[Entry]) -> Result<(), Error> {
+    let mut buffer = Vec::new();
+    for entry in entries {
+        frame(&entry.encode()?, &mut buffer);
+    }
+
+    write(file, &buffer)
+}
+
+/// Appends data to the log file, then waits for the disk.
+fn write(file: &mut File, data: &[u8]) -> Result<(), Error> {
+    file.write_all(data)
+        .and_then(|_| file.sync_data())
+        .context(IoError::WritingError, "while writing Raft log")
+}
+
+/// Reads the snapshot and the entries of the log file, and opens it to
+/// append new ones.
+///
+/// An entry partially written before a crash is dropped.
+fn read_log(path: &Path) -> Result<(Snapshot, Vec<Entry>, File), Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .context(IoError::ReadingError, "while opening Raft log")?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data)
+        .context(IoError::ReadingError, "while reading Raft log")?;
+
+    let mut records = Vec::new();
+    let mut position = 0;
+    while let Some(length) = data
+        .get(position..position + 4)
+        .map(|length| u32::from_le_bytes(length.try_into().unwrap_or_default()))
+    {
+        let start = position + 4;
+        let Some(record) = data.get(start..start + length as usize) else {
+            break;
+        };
+
+        records.push(record);
+        position = start + length as usize;
+    }
+
+    if position < data.len() {
+        file.set_len(position as u64)
+            .context(IoError::WritingError, "while repairing Raft log")?;
+    }
+
+    // A new log starts with an empty snapshot.
+    let Some((snapshot, entries)) = records.split_first() else {
+        let snapshot = Snapshot::default();
+        let mut buffer = Vec::new();
+        frame(&snapshot.encode()?, &mut buffer);
+        write(&mut file, &buffer)?;
+
+        return Ok((snapshot, Vec::new(), file));
+    };
+
+    Ok((
+        Snapshot::decode(snapshot)?,
+        entries
+            .iter()
+            .map(|entry| Entry::decode(entry))
+            .collect::<Result<_, _>>()?,
+        file,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODES: [&str; 3] = ["a", "b", "c"];
+
+    /// Opens a node of a group of three, electing itself as soon as asked.
+    fn open(directory: &Path, address: &str, snapshot_entries: u64) -> Raft {
+        Raft::open(
+            &config::Raft {
+                address: address.to_string(),
+                peers: NODES.iter().map(|node| node.to_string()).collect(),
+                api_key: None,
+                heartbeat_ms: None,
+                election_timeout_ms: Some(1),
+                snapshot_entries: Some(snapshot_entries),
+            },
+            &directory.join(address),
+        )
+        .unwrap()
+    }
+
+    fn group(directory: &Path) -> Vec<Raft> {
+        NODES.iter().map(|node| open(directory, node, 4)).collect()
+    }
+
+    fn command(word: &str) -> Command {
+        Command {
+            namespace: "default".to_string(),
+            change: Change::Excluded(vec![word.to_string()]),
+        }
+    }
+
+    /// Returns the term of each entry, and the word of each command.
+    async fn log(node: &Raft) -> Vec<(u64, Option<String>)> {
+        node.entries(node.snapshot().await.index, u64::MAX)
+            .await
+            .into_iter()
+            .map(|entry| {
+                let word = entry.command.and_then(|command| {
+                    match command.change {
+                        Change::Excluded(words) => words.into_iter().next(),
+                        _ => None,
+                    }
+                });
+                (entry.term, word)
+            })
+            .collect()
+    }
+
+    /// Starts an election, and asks the voters for their vote.
+    async fn elect(candidate: &Raft, voters: &[&Raft]) {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let request = candidate.campaign().await.unwrap().unwrap();
+        for voter in voters {
+            let reply = voter.vote(request.clone()).await.unwrap();
+            candidate.voted(reply).await.unwrap();
+        }
+    }
+
+    /// Sends the entries of the leader to the followers until they store
+    /// them.
+    async fn replicate(leader: &Raft, followers: &[&Raft]) {
+        for _ in 0..4 {
+            for (peer, request) in leader.heartbeats().await {
+                let Some(follower) =
+                    followers.iter().find(|node| node.address == peer)
+                else {
+                    continue;
+                };
+                let reply = follower.append(request).await.unwrap();
+                leader.appended(&peer, reply).await.unwrap();
+            }
+        }
+    }
+
+    /// Proposes a change, while replicating it.
+    async fn propose(leader: &Raft, followers: &[&Raft], word: &str) {
+        let proposal = leader.propose(command(word));
+        tokio::pin!(proposal);
+
+        loop {
+            tokio::select! {
+                biased;
+                result = &mut proposal => return result.unwrap(),
+                _ = replicate(leader, followers) => {},
+            }
+        }
+    }
+
+    fn directory() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("squid-raft-{}", uuid::Uuid::new_v4()))
+    }
+
+    #[tokio::test]
+    async fn test_log_matching() {
+        let directory = directory();
+        let [a, b, c] = <[Raft; 3]>::try_from(group(&directory)).unwrap();
+
+        elect(&a, &[&b]).await;
+        assert!(a.lead().await.is_ok());
+        propose(&a, &[&b, &c], "first").await;
+        propose(&a, &[&b, &c], "second").await;
+        replicate(&a, &[&b, &c]).await;
+
+        let expected = vec![
+            (1, None),
+            (1, Some("first".to_string())),
+            (1, Some("second".to_string())),
+        ];
+        for node in [&a, &b, &c] {
+            assert_eq!(log(node).await, expected);
+            assert_eq!(*node.commits().borrow(), 3);
+        }
+
+        // The log is read back once the node restarts.
+        drop(c);
+        let c = open(&directory, "c", 4);
+        assert_eq!(log(&c).await, expected);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_truncate_conflicting_entries() {
+        let directory = directory();
+        let [a, b, c] = <[Raft; 3]>::try_from(group(&directory)).unwrap();
+
+        elect(&a, &[&b]).await;
+        replicate(&a, &[&b, &c]).await;
+
+        // Stored by the leader only, so never committed.
+        let result = tokio::time::timeout(
+            Duration::from_millis(50),
+            a.propose(command("lost")),
+        )
+        .await;
+        assert!(result.is_err());
+        assert_eq!(log(&a).await[1], (1, Some("lost".to_string())));
+
+        elect(&b, &[&c]).await;
+        assert!(b.lead().await.is_ok());
+        propose(&b, &[&a, &c], "kept").await;
+
+        let expected =
+            vec![(1, None), (2, None), (2, Some("kept".to_string()))];
+        for node in [&a, &b, &c] {
+            assert_eq!(log(node).await, expected);
+        }
+        assert!(a.lead().await.is_err());
+
+        // The dropped entry is not read back once the node restarts.
+        drop(a);
+        let a = open(&directory, "a", 4);
+        assert_eq!(log(&a).await, expected);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_election_safety() {
+        let directory = directory();
+        let [a, b, c] = <[Raft; 3]>::try_from(group(&directory)).unwrap();
+
+        // Both candidates run in the same term, a node votes once.
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let first = a.campaign().await.unwrap().unwrap();
+        let second = c.campaign().await.unwrap().unwrap();
+        assert_eq!(first.term, second.term);
+
+        assert!(b.vote(first).await.unwrap().granted);
+        assert!(!b.vote(second.clone()).await.unwrap().granted);
+        assert!(!a.vote(second).await.unwrap().granted);
+
+        // Only one of them leads.
+        a.voted(VoteReply {
+            term: 1,
+            granted: true,
+        })
+        .await
+        .unwrap();
+        assert!(a.lead().await.is_ok());
+        assert!(c.lead().await.is_err());
+
+        // A node missing committed entries is not elected.
+        propose(&a, &[&b], "committed").await;
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let request = c.campaign().await.unwrap().unwrap();
+        assert!(!a.vote(request.clone()).await.unwrap().granted);
+        assert!(!b.vote(request).await.unwrap().granted);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_compact_log() {
+        let directory = directory();
+        let [a, b, c] = <[Raft; 3]>::try_from(group(&directory)).unwrap();
+
+        elect(&a, &[&b]).await;
+        for word in ["first", "second", "third", "fourth"] {
+            propose(&a, &[&b], word).await;
+        }
+
+        // Entries are kept while a node misses them.
+        assert!(!a.compactable(5).await);
+        replicate(&a, &[&b, &c]).await;
+        assert!(a.compactable(5).await);
+
+        for node in [&a, &b, &c] {
+            node.compact(5).await.unwrap();
+            let snapshot = node.snapshot().await;
+            assert_eq!((snapshot.index, snapshot.term), (5, 1));
+            assert!(log(node).await.is_empty());
+        }
+
+        // Each node goes on from the snapshot.
+        propose(&a, &[&b, &c], "fifth").await;
+        drop(c);
+        let c = open(&directory, "c", 4);
+        let snapshot = c.snapshot().await;
+        assert_eq!((snapshot.index, snapshot.term), (5, 1));
+        assert_eq!(*c.commits().borrow(), 5);
+        assert_eq!(log(&c).await, [(1, Some("fifth".to_string()))]);
+
+        // The last excluded words are applied again at startup.
+        let [command] = snapshot.commands.as_slice() else {
+            panic!("expected the last excluded words");
+        };
+        assert!(matches!(
+            &command.change,
+            Change::Excluded(words) if words == &["fourth"]
+        ));
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+//! Lexicon-based sentiment of sentences.
+//!
+//! Each word found in the lexicon adds its valence to the score of the
+//! sentence, inverted if it follows a negation. It is crude, but cheap
+//! enough to run on every added sentence.
+
+/// Valence of English and French words, from -3 to 3.
+const LEXICON: &[(&str, i32)] = &[
+    // English.
+    ("amazing", 3),
+    ("awesome", 3),
+    ("best", 3),
+    ("excellent", 3),
+    ("fantastic", 3),
+    ("love", 3),
+    ("perfect", 3),
+    ("wonderful", 3),
+    ("beautiful", 2),
+    ("enjoy", 2),
+    ("glad", 2),
+    ("great", 2),
+    ("happy", 2),
+    ("nice", 2),
+    ("thanks", 2),
+    ("win", 2),
+    ("fine", 1),
+    ("good", 1),
+    ("like", 1),
+    ("useful", 1),
+    ("bug", -1),
+    ("slow", -1),
+    ("problem", -1),
+    ("sad", -2),
+    ("angry", -2),
+    ("bad", -2),
+    ("broken", -2),
+    ("fail", -2),
+    ("failed", -2),
+    ("lose", -2),
+    ("ugly", -2),
+    ("wrong", -2),
+    ("awful", -3),
+    ("disgusting", -3),
+    ("hate", -3),
+    ("horrible", -3),
+    ("terrible", -3),
+    ("worst", -3),
+    // French.
+    ("génial", 3),
+    ("incroyable", 3),
+    ("magnifique", 3),
+    ("parfait", 3),
+    ("adore", 3),
+    ("bien", 1),
+    ("bon", 1),
+    ("aime", 2),
+    ("beau", 2),
+    ("content", 2),
+    ("heureux", 2),
+    ("merci", 2),
+    ("super", 2),
+    ("lent", -1),
+    ("problème", -1),
+    ("colère", -2),
+    ("mauvais", -2),
+    ("moche", -2),
+    ("nul", -2),
+    ("panne", -2),
+    ("triste", -2),
+    ("affreux", -3),
+    ("déteste", -3),
+    ("pire", -3),
+];
+
+/// Words inverting the valence of the next word of the lexicon.
+///
+/// French negations follow the verb, they are not handled.
+const NEGATIONS: &[&str] =
+    &["not", "no", "never", "don't", "doesn't", "isn't", "wasn't"];
+
+/// Sentiment of a sentence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Polarity {
+    /// More negative than positive words.
+    Negative,
+    /// As many negative as positive words.
+    Neutral,
+    /// More positive than negative words.
+    Positive,
+}
+
+impl Polarity {
+    /// Name of the polarity, as stored in metadata.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Polarity::Negative => "negative",
+            Polarity::Neutral => "neutral",
+            Polarity::Positive => "positive",
+        }
+    }
+}
+
+/// Sums the valence of the words of a sentence, as written.
+pub fn score(sentence: &str) -> i32 {
+    let mut score = 0;
+    let mut negated = false;
+
+    for word in sentence
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+    {
+        if NEGATIONS.contains(&word.as_str()) {
+            negated = true;
+            continue;
+        }
+
+        if let Some((_, valence)) =
+            LEXICON.iter().find(|(entry, _)| *entry == word)
+        {
+            score += if negated { -valence } else { *valence };
+            negated = false;
+        }
+    }
+
+    score
+}
+
+/// Polarity of a sentence, as written.
+pub fn polarity(sentence: &str) -> Polarity {
+    match score(sentence) {
+        score if score < 0 => Polarity::Negative,
+        0 => Polarity::Neutral,
+        _ => Polarity::Positive,
+    }
+}
@@ -0,0 +1,454 @@
+//! Counts of each word over time, in buckets of fixed length, so clients
+//! can draw the trend of a word.
+//!
+//! Words counted during a bucket are kept in memory, then the most counted
+//! ones are stored by squid-db once the bucket ends. Stored buckets are
+//! loaded back at startup, and expire after the retention.
+
+use crate::{
+    database::{self, Counters},
+    models::{config, database::Entity},
+    time::now,
+};
+use serde::{Deserialize, Serialize};
+use squid_db::{Attributes, Instance};
+use squid_error::{Error, IoError, ResultExt};
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+use tokio::{
+    sync::{broadcast, RwLock},
+    time::MissedTickBehavior,
+};
+use tracing::error;
+
+/// Sub-directory of a service containing the stored buckets.
+pub const SERIES_DIR: &str = "series";
+/// Seconds covered by a bucket if not configured.
+const DEFAULT_INTERVAL_SEC: u64 = 300;
+/// Seconds during which buckets are kept if not configured.
+const DEFAULT_RETENTION_SEC: u64 = 86400;
+/// Number of words stored per bucket if not configured.
+const DEFAULT_LENGTH: usize = 1000;
+/// Number of buckets forming the baseline of anomalies if not configured.
+const DEFAULT_BASELINE: usize = 12;
+/// Standard deviations from which a word is announced as a spike if not
+/// configured.
+const DEFAULT_ALERT_THRESHOLD: f64 = 3.0;
+/// Number of spikes kept for subscribers reading them too slowly.
+const ALERTS_CAPACITY: usize = 64;
+/// Maximum time between two checks of the end of the current bucket.
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counts of the words of a bucket.
+type Counts = HashMap<String, u64>;
+
+/// A bucket, as stored.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Record {
+    /// Start of the bucket then its expiration, as `<start>-<expire_at>`.
+    id: String,
+    /// Counts of the bucket, as JSON.
+    counts: String,
+}
+
+impl Attributes for Record {
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn ttl(&self) -> Option<u64> {
+        self.id
+            .split_once('-')
+            .and_then(|(_, expire_at)| expire_at.parse().ok())
+    }
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// Start of the current bucket.
+    start: u64,
+    /// Words counted since the start of the current bucket.
+    current: Counts,
+    /// Ended buckets, by start.
+    buckets: BTreeMap<u64, Counts>,
+    /// Ended buckets not stored yet.
+    unsaved: Vec<u64>,
+    /// Words of the current bucket already announced as spikes.
+    alerted: HashSet<String>,
+}
+
+/// A word counted much more in the current bucket than in the previous
+/// ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Anomaly {
+    /// The unusually counted word.
+    pub word: String,
+    /// Count of the word in the current bucket.
+    pub count: u64,
+    /// Mean count of the word in the previous buckets.
+    pub mean: f64,
+    /// Number of standard deviations between the count and the mean.
+    pub score: f64,
+}
+
+/// Counts of the words of a service over time.
+#[derive(Debug)]
+pub struct Series {
+    /// Time covered by a bucket.
+    pub interval: Duration,
+    retention: Duration,
+    /// Number of words stored per bucket, the most counted first.
+    length: usize,
+    /// Number of previous buckets to which the current one is compared.
+    baseline: usize,
+    /// Standard deviations from which a word is announced as a spike.
+    alert_threshold: f64,
+    /// Spikes announced to subscribers.
+    alerts: broadcast::Sender<Anomaly>,
+    state: Mutex<State>,
+    instance: Arc<RwLock<Instance<Record>>>,
+}
+
+impl Series {
+    /// Loads the stored buckets of a service.
+    pub async fn open(
+        config: &config::Series,
+        directory: &Path,
+    ) -> Result<Self, Error> {
+        let instance = squid_db::Builder::default()
+            .memtable_flush_size(0)
+            .directory(directory)
+            .with_ttl()
+            .build()
+            .await?;
+
+        let series = Self {
+            interval: Duration::from_secs(
+                config.interval.unwrap_or(DEFAULT_INTERVAL_SEC).max(1),
+            ),
+            retention: Duration::from_secs(
+                config.retention.unwrap_or(DEFAULT_RETENTION_SEC),
+            ),
+            length: config.length.unwrap_or(DEFAULT_LENGTH),
+            baseline: config.baseline.unwrap_or(DEFAULT_BASELINE).max(1),
+            alert_threshold: config
+                .alert_threshold
+                .unwrap_or(DEFAULT_ALERT_THRESHOLD),
+            alerts: broadcast::channel(ALERTS_CAPACITY).0,
+            state: Mutex::default(),
+            instance,
+        };
+
+        let mut buckets = BTreeMap::new();
+        {
+            let instance = series.instance.read().await;
+            for segment in instance.segments()? {
+                for record in instance.segment(&segment)? {
+                    let start = record
+                        .id
+                        .split_once('-')
+                        .and_then(|(start, _)| start.parse::<u64>().ok());
+                    let counts = serde_json::from_str::<Counts>(&record.counts)
+                        .context(
+                            IoError::DeserializationError,
+                            "while reading series bucket",
+                        )?;
+
+                    if let Some(start) = start {
+                        buckets.insert(start, counts);
+                    }
+                }
+            }
+        }
+
+        {
+            let mut state = series.lock();
+            state.start = series.start(now());
+            // Stored during a shutdown before the bucket ended.
+            state.current = buckets.remove(&state.start).unwrap_or_default();
+            state.buckets = buckets;
+        }
+        series.prune();
+
+        Ok(series)
+    }
+
+    /// Counts the words of an added sentence in the current bucket.
+    pub async fn record(
+        &self,
+        service: &config::Service,
+        counters: &Counters,
+        entity: &Entity,
+    ) {
+        let exclusions = counters.exclusions.read().await;
+        let weight = entity.weight() as u64;
+        if weight == 0 {
+            return;
+        }
+
+        let mut state = self.lock();
+        self.rotate(&mut state);
+        for word in entity
+            .post_processing_text
+            .split_whitespace()
+            .filter(|word| database::is_counted(service, &exclusions, word))
+        {
+            *state.current.entry(word.to_string()).or_default() += weight;
+        }
+    }
+
+    /// Returns the count of a word in each bucket starting between `from`
+    /// and `to`, both UNIX timestamps in seconds, including the current
+    /// bucket. Buckets without the word count 0.
+    pub fn read(&self, word: &str, from: u64, to: u64) -> Vec<(u64, u64)> {
+        let mut state = self.lock();
+        self.rotate(&mut state);
+
+        let interval = self.interval.as_secs();
+        let oldest = now().saturating_sub(self.retention.as_secs());
+        let mut start = self.start(from.max(oldest));
+        if start < from {
+            start += interval;
+        }
+
+        let mut series = Vec::new();
+        while start <= to.min(state.start) {
+            let counts = if start == state.start {
+                Some(&state.current)
+            } else {
+                state.buckets.get(&start)
+            };
+            series.push((
+                start,
+                counts
+                    .and_then(|counts| counts.get(word))
+                    .copied()
+                    .unwrap_or_default(),
+            ));
+            start += interval;
+        }
+
+        series
+    }
+
+    /// Returns the start of the bucket containing `timestamp`, a UNIX
+    /// timestamp in seconds, with its `length` most counted words, or
+    /// `None` if the bucket is not kept yet or anymore.
+    ///
+    /// The current bucket is ranked too, even though it has not ended.
+    pub fn rank_at(
+        &self,
+        timestamp: u64,
+        length: usize,
+    ) -> Option<(u64, Vec<(String, u64)>)> {
+        let mut state = self.lock();
+        self.rotate(&mut state);
+
+        let start = self.start(timestamp);
+        let oldest = now().saturating_sub(self.retention.as_secs());
+        if start > state.start || start < self.start(oldest) {
+            return None;
+        }
+
+        let counts = if start == state.start {
+            Some(&state.current)
+        } else {
+            state.buckets.get(&start)
+        };
+        // Only the ranked words are sorted and copied.
+        let ranking = squid_algorithm::top(
+            counts.into_iter().flatten(),
+            length,
+            |a, b| (Reverse(a.1), a.0).cmp(&(Reverse(b.1), b.0)),
+        )
+        .into_iter()
+        .map(|(word, count)| (word.clone(), *count))
+        .collect();
+
+        Some((start, ranking))
+    }
+
+    /// Returns the words of the current bucket whose count is at least
+    /// `threshold` standard deviations above their mean count in the
+    /// previous buckets, the most unusual first.
+    ///
+    /// Buckets without any sentence, such as while the server was stopped,
+    /// are left out of the previous buckets. The standard deviation is at
+    /// least 1, so words never counted before are not all flagged.
+    pub fn anomalies(&self, threshold: f64, length: usize) -> Vec<Anomaly> {
+        let mut state = self.lock();
+        self.rotate(&mut state);
+
+        let baseline = state
+            .buckets
+            .range(..state.start)
+            .rev()
+            .take(self.baseline)
+            .map(|(_, counts)| counts)
+            .collect::<Vec<_>>();
+        if baseline.is_empty() {
+            return Vec::new();
+        }
+
+        let buckets = baseline.len() as f64;
+        let mut anomalies = state
+            .current
+            .iter()
+            .filter_map(|(word, count)| {
+                let counts = baseline.iter().map(|counts| {
+                    counts.get(word).copied().unwrap_or_default() as f64
+                });
+                let mean = counts.clone().sum::<f64>() / buckets;
+                let variance =
+                    counts.map(|count| (count - mean).powi(2)).sum::<f64>()
+                        / buckets;
+                let score = (*count as f64 - mean) / variance.sqrt().max(1.0);
+
+                (score >= threshold).then(|| Anomaly {
+                    word: word.clone(),
+                    count: *count,
+                    mean,
+                    score,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        anomalies.sort_unstable_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.word.cmp(&b.word))
+        });
+        anomalies.truncate(length);
+        anomalies
+    }
+
+    /// Returns the spikes announced from now on, each word being announced
+    /// once per bucket, when it becomes an anomaly from the alert
+    /// threshold.
+    pub fn subscribe(&self) -> broadcast::Receiver<Anomaly> {
+        self.alerts.subscribe()
+    }
+
+    /// Announces the words of the current bucket which became anomalies
+    /// since the last call.
+    fn alert(&self) {
+        if self.alerts.receiver_count() == 0 {
+            return;
+        }
+
+        for anomaly in self.anomalies(self.alert_threshold, usize::MAX) {
+            if self.lock().alerted.insert(anomaly.word.clone()) {
+                // Subscribers may leave meanwhile.
+                let _ = self.alerts.send(anomaly);
+            }
+        }
+    }
+
+    /// Stores the ended buckets, and the current one if `current` is set,
+    /// such as before a shutdown.
+    pub async fn save(&self, current: bool) -> Result<(), Error> {
+        let records = {
+            let mut state = self.lock();
+            self.rotate(&mut state);
+
+            let mut starts = std::mem::take(&mut state.unsaved);
+            if current {
+                starts.push(state.start);
+            }
+            starts
+                .into_iter()
+                .filter_map(|start| {
+                    let counts = if start == state.start {
+                        &state.current
+                    } else {
+                        state.buckets.get(&start)?
+                    };
+                    Some(self.record_of(start, counts))
+                })
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut instance = self.instance.write().await;
+        for record in records {
+            // The current bucket may have been stored before it ended.
+            instance.delete(&record.id)?;
+            instance.set(record).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the buckets older than the retention.
+    fn prune(&self) {
+        let oldest = now().saturating_sub(self.retention.as_secs());
+        self.lock().buckets.retain(|start, _| *start >= oldest);
+    }
+
+    /// Ends the current bucket if its time is over, keeping its most
+    /// counted words.
+    fn rotate(&self, state: &mut State) {
+        let start = self.start(now());
+        if start == state.start {
+            return;
+        }
+
+        let counts = squid_algorithm::top(
+            std::mem::take(&mut state.current),
+            self.length,
+            |a, b| (Reverse(a.1), &a.0).cmp(&(Reverse(b.1), &b.0)),
+        );
+        if !counts.is_empty() {
+            state
+                .buckets
+                .insert(state.start, counts.into_iter().collect());
+            state.unsaved.push(state.start);
+        }
+        state.alerted.clear();
+        state.start = start;
+    }
+
+    /// Builds the stored form of a bucket.
+    fn record_of(&self, start: u64, counts: &Counts) -> Result<Record, Error> {
+        let counts = serde_json::to_string(counts).context(
+            IoError::SerializationError,
+            "while encoding series bucket",
+        )?;
+
+        Ok(Record {
+            id: format!("{}-{}", start, start + self.retention.as_secs()),
+            counts,
+        })
+    }
+
+    /// Returns the start of the bucket containing a timestamp.
+    fn start(&self, timestamp: u64) -> u64 {
+        timestamp - timestamp % self.interval.as_secs()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Stores the buckets of a service once they end, forgets the ones older
+/// than the retention, and announces spikes.
+pub async fn schedule(namespace: String, series: Arc<Series>) {
+    let mut interval =
+        tokio::time::interval(series.interval.min(MAX_CHECK_INTERVAL));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+
+        if let Err(error) = series.save(false).await {
+            error!(namespace, "Failed to store series bucket: {}", error);
+        }
+        series.prune();
+        series.alert();
+    }
+}
@@ -0,0 +1,252 @@
+//! SimHash fingerprints of the stored sentences, finding the ones nearly
+//! identical to a text, such as the slightly edited copy-pastes of a spam
+//! campaign.
+//!
+//! Each token of a sentence votes for the bits of its hash, so sentences
+//! sharing most of their tokens have fingerprints differing by few bits.
+
+use crate::models::{config, database::Entity};
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    sync::{Mutex, MutexGuard, PoisonError},
+};
+
+/// Number of bits of a fingerprint.
+const BITS: u32 = 64;
+/// Number of sentences indexed at once if not configured.
+const DEFAULT_CAPACITY: usize = 100_000;
+/// Similarity from which sentences are near-duplicates if not configured.
+const DEFAULT_THRESHOLD: f64 = 0.85;
+
+/// A sentence nearly identical to a text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Similar {
+    /// Identifier of the sentence.
+    pub id: String,
+    /// Share of the bits of the fingerprints which are identical, between
+    /// 0 and 1.
+    pub similarity: f64,
+}
+
+#[derive(Debug, Default)]
+struct Index {
+    /// Fingerprint of each indexed sentence.
+    fingerprints: HashMap<String, u64>,
+    /// Indexed sentences, from the oldest to the newest. May contain
+    /// sentences removed since.
+    order: VecDeque<String>,
+}
+
+/// Fingerprints of the stored sentences of a service, the oldest ones
+/// being forgotten once the capacity is reached.
+#[derive(Debug)]
+pub struct SimilarityIndex {
+    /// Similarity from which sentences are near-duplicates.
+    pub threshold: f64,
+    /// Maximum weight of near-duplicates, counted normally if not set.
+    weight: Option<u32>,
+    capacity: usize,
+    index: Mutex<Index>,
+}
+
+/// Computes the SimHash of tokenized text.
+///
+/// Returns `None` for text without any token.
+pub fn fingerprint(tokens: &str) -> Option<u64> {
+    let mut votes = [0i64; BITS as usize];
+    let mut empty = true;
+
+    for token in tokens.split_whitespace() {
+        let mut hasher = DefaultHasher::new();
+        token.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        for (bit, vote) in votes.iter_mut().enumerate() {
+            *vote += if hash >> bit & 1 == 1 { 1 } else { -1 };
+        }
+        empty = false;
+    }
+
+    (!empty).then(|| {
+        votes
+            .iter()
+            .enumerate()
+            .filter(|(_, vote)| **vote > 0)
+            .fold(0, |fingerprint, (bit, _)| fingerprint | 1 << bit)
+    })
+}
+
+/// Returns the share of identical bits of two fingerprints.
+fn similarity(a: u64, b: u64) -> f64 {
+    1.0 - f64::from((a ^ b).count_ones()) / f64::from(BITS)
+}
+
+impl SimilarityIndex {
+    /// Creates an empty index.
+    pub fn new(config: &config::Similarity) -> Self {
+        Self {
+            threshold: config.threshold.unwrap_or(DEFAULT_THRESHOLD),
+            weight: config.weight,
+            capacity: config.capacity.unwrap_or(DEFAULT_CAPACITY).max(1),
+            index: Mutex::default(),
+        }
+    }
+
+    /// Indexes a stored sentence, forgetting the oldest one if the index
+    /// is full.
+    pub fn insert(&self, entity: &Entity) {
+        let Some(fingerprint) = fingerprint(&entity.post_processing_text)
+        else {
+            return;
+        };
+        let mut index = self.lock();
+
+        if index
+            .fingerprints
+            .insert(entity.id.clone(), fingerprint)
+            .is_none()
+        {
+            index.order.push_back(entity.id.clone());
+        }
+
+        while index.fingerprints.len() > self.capacity {
+            match index.order.pop_front() {
+                Some(oldest) => index.fingerprints.remove(&oldest),
+                None => break,
+            };
+        }
+
+        // Removed sentences are only dropped from the order from time to
+        // time.
+        if index.order.len() > 2 * index.fingerprints.len() + self.capacity {
+            let Index {
+                fingerprints,
+                order,
+            } = &mut *index;
+            order.retain(|id| fingerprints.contains_key(id));
+        }
+    }
+
+    /// Forgets a sentence once removed.
+    pub fn remove(&self, id: &str) {
+        self.lock().fingerprints.remove(id);
+    }
+
+    /// Returns at most `length` indexed sentences whose similarity with
+    /// tokenized text is at least `threshold`, the most similar first.
+    pub fn find(
+        &self,
+        tokens: &str,
+        threshold: f64,
+        length: usize,
+    ) -> Vec<Similar> {
+        let Some(fingerprint) = fingerprint(tokens) else {
+            return Vec::new();
+        };
+
+        let mut similar = self
+            .lock()
+            .fingerprints
+            .iter()
+            .map(|(id, other)| (id, similarity(fingerprint, *other)))
+            .filter(|(_, similarity)| *similarity >= threshold)
+            .map(|(id, similarity)| Similar {
+                id: id.clone(),
+                similarity,
+            })
+            .collect::<Vec<_>>();
+
+        similar.sort_unstable_by(|a, b| {
+            b.similarity
+                .total_cmp(&a.similarity)
+                .then_with(|| a.id.cmp(&b.id))
+        });
+        similar.truncate(length);
+        similar
+    }
+
+    /// Lowers the weight of a sentence nearly identical to an indexed one,
+    /// if the service down-weights near-duplicates.
+    ///
+    /// Returns the identifier of the most similar sentence, if any.
+    pub fn down_weight(&self, entity: &mut Entity) -> Option<String> {
+        let weight = self.weight?;
+        let original = self
+            .find(&entity.post_processing_text, self.threshold, 1)
+            .pop()?;
+
+        entity.set_weight(entity.weight().min(weight as usize));
+        Some(original.id)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a tokenized sentence counted `weight` times.
+    fn entity(id: &str, tokens: &str, weight: usize) -> Entity {
+        let mut entity = Entity {
+            id: id.to_string(),
+            post_processing_text: tokens.to_string(),
+            ..Default::default()
+        };
+        entity.set_weight(weight);
+        entity
+    }
+
+    #[test]
+    fn test_down_weight_near_duplicates() {
+        let index = SimilarityIndex::new(&config::Similarity {
+            weight: Some(1),
+            ..Default::default()
+        });
+        index.insert(&entity(
+            "original",
+            "win free iphone today click link below claim prize before \
+             midnight limited offer only winners selected randomly",
+            1,
+        ));
+
+        // A copy-paste with a word changed.
+        let mut copy = entity(
+            "copy",
+            "win free iphone today click link below claim prize before \
+             midnight limited offer only winners chosen randomly",
+            5,
+        );
+        assert_eq!(index.down_weight(&mut copy).as_deref(), Some("original"));
+        assert_eq!(copy.weight(), 1);
+
+        let mut distinct = entity(
+            "distinct",
+            "match tonight ended draw after late penalty home team \
+             defended well whole second half",
+            5,
+        );
+        assert_eq!(index.down_weight(&mut distinct), None);
+        assert_eq!(distinct.weight(), 5);
+
+        // Removed sentences are no longer matched.
+        index.remove("original");
+        let mut copy = entity("copy", &copy.post_processing_text, 5);
+        assert_eq!(index.down_weight(&mut copy), None);
+        assert_eq!(copy.weight(), 5);
+    }
+
+    #[test]
+    fn test_keep_weight_without_limit() {
+        let index = SimilarityIndex::new(&config::Similarity::default());
+        index.insert(&entity("original", "same words twice", 1));
+
+        let mut copy = entity("copy", "same words twice", 5);
+        assert_eq!(index.down_weight(&mut copy), None);
+        assert_eq!(copy.weight(), 5);
+        assert_eq!(index.find("same words twice", 1.0, 10).len(), 1);
+    }
+}
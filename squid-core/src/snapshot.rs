@@ -0,0 +1,297 @@
+//! Counters saved periodically, so services start without counting every
+//! stored sentence again.
+
+use crate::{
+    database::{Board, Boards, Counters, Seen},
+    models::database::lenient,
+};
+use serde::{Deserialize, Serialize};
+use squid_error::{Error, IoError, ResultExt};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// Name of the snapshot file, in the data directory of each service.
+pub const SNAPSHOT_FILE: &str = "snapshot.algo";
+
+/// Counters saved on disk to avoid counting every sentence at startup.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Snapshot {
+    /// Identifier of every sentence included in the counters.
+    pub ids: HashSet<String>,
+    /// Occurrences of the words written in any language.
+    pub words: Vec<(String, usize)>,
+    /// Occurrences of the words written in a specific language.
+    pub languages: HashMap<String, Vec<(String, usize)>>,
+    /// Occurrences of the words of sentences with a specific tag.
+    #[serde(default, deserialize_with = "lenient")]
+    pub tags: HashMap<String, Vec<(String, usize)>>,
+    /// Occurrences of the words of sentences with a specific metadata
+    /// entry.
+    #[serde(default, deserialize_with = "lenient")]
+    pub metadata: HashMap<String, Vec<(String, usize)>>,
+    /// When the words written in any language were seen.
+    #[serde(default, deserialize_with = "lenient")]
+    pub seen: HashMap<String, Seen>,
+    /// Occurrences of the words of sentences written in a specific region.
+    #[serde(default, deserialize_with = "lenient")]
+    pub regions: HashMap<String, Vec<(String, usize)>>,
+    /// Occurrences of the words of each author.
+    #[serde(default, deserialize_with = "lenient")]
+    pub authors: HashMap<String, Vec<(String, usize)>>,
+    /// Number of sentences using two words together, each pair being saved
+    /// once per word.
+    #[serde(default, deserialize_with = "lenient")]
+    pub cooccurrence: Vec<(String, String, usize)>,
+    /// Sentences using the words written in any language, if the service
+    /// ranks by TF-IDF.
+    #[serde(default, deserialize_with = "lenient")]
+    pub documents: Option<Documents>,
+    /// Sentences using the words of each board of a language, a tag, a
+    /// metadata entry, a region and an author, in this order, if the
+    /// service ranks by TF-IDF.
+    #[serde(default, deserialize_with = "lenient")]
+    pub labeled_documents: Vec<HashMap<String, Documents>>,
+}
+
+/// Sentences counted by a board, and the sentences using each of its
+/// words.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Documents {
+    /// Sentences counted.
+    pub sentences: usize,
+    /// Sentences using each word.
+    pub frequencies: Vec<(String, usize)>,
+}
+
+impl Documents {
+    /// Copies the sentences of a board, if it counts them.
+    fn take(board: &Board) -> Option<Self> {
+        let sentences = board.sentences()?;
+        let mut frequencies = Vec::new();
+        board.for_each_frequency(|word, frequency| {
+            frequencies.push((word.to_string(), frequency))
+        });
+
+        Some(Self {
+            sentences,
+            frequencies,
+        })
+    }
+
+    /// Sets the saved sentences of a board, once its words are restored.
+    fn restore(&self, board: &mut Board) {
+        board.set_sentences(self.sentences);
+        for (word, frequency) in &self.frequencies {
+            board.set_frequency(word, *frequency);
+        }
+    }
+}
+
+impl Snapshot {
+    /// Copies the counters.
+    ///
+    /// Sketches only keep the words that can be ranked, so the occurrences
+    /// of other words are lost once restored.
+    pub async fn take(counters: &Counters, ids: HashSet<String>) -> Self {
+        let (words, documents) = {
+            let algorithm = counters.algorithm.read().await;
+            (dump(&algorithm), Documents::take(&algorithm))
+        };
+        // Words the sketches no longer keep are forgotten.
+        let seen = {
+            let seen = counters.seen.read().await;
+            words
+                .iter()
+                .filter_map(|(word, _)| Some((word.clone(), *seen.get(word)?)))
+                .collect()
+        };
+
+        let mut cooccurrence = Vec::new();
+        if let Some(pairs) = &counters.cooccurrence {
+            pairs.read().await.for_each(|word, other, count| {
+                cooccurrence.push((word.to_string(), other.to_string(), count))
+            });
+        }
+
+        let mut labeled_documents = Vec::new();
+        for boards in counters.boards() {
+            labeled_documents.push(
+                boards
+                    .read()
+                    .await
+                    .iter()
+                    .filter_map(|(key, board)| {
+                        Some((key.clone(), Documents::take(board)?))
+                    })
+                    .collect(),
+            );
+        }
+
+        Self {
+            ids,
+            words,
+            seen,
+            languages: dump_all(&counters.languages).await,
+            tags: dump_all(&counters.tags).await,
+            metadata: dump_all(&counters.metadata).await,
+            regions: dump_all(&counters.regions).await,
+            authors: dump_all(&counters.authors).await,
+            cooccurrence,
+            documents,
+            labeled_documents,
+        }
+    }
+
+    /// Adds the saved occurrences to empty counters.
+    pub async fn restore(self, counters: &Counters) {
+        {
+            let mut algorithm = counters.algorithm.write().await;
+            let mut distinct = counters.distinct.write().await;
+            for (word, count) in &self.words {
+                algorithm.set(word, *count);
+                distinct.insert(word);
+            }
+            if let Some(documents) = &self.documents {
+                documents.restore(&mut algorithm);
+            }
+        }
+        counters.seen.write().await.extend(self.seen);
+        if let Some(pairs) = &counters.cooccurrence {
+            let mut pairs = pairs.write().await;
+            for (word, other, count) in &self.cooccurrence {
+                pairs.set_pair(word, other, *count);
+            }
+        }
+
+        let mut labeled_documents = self.labeled_documents.into_iter();
+        for (boards, saved) in counters.boards().into_iter().zip([
+            self.languages,
+            self.tags,
+            self.metadata,
+            self.regions,
+            self.authors,
+        ]) {
+            let documents = labeled_documents.next().unwrap_or_default();
+            let mut boards = boards.write().await;
+            for (key, words) in saved {
+                let board = boards
+                    .entry(key.clone())
+                    .or_insert_with(|| counters.blank());
+                for (word, count) in &words {
+                    board.set(word, *count);
+                }
+                if let Some(documents) = documents.get(&key) {
+                    documents.restore(board);
+                }
+            }
+        }
+    }
+
+    /// Writes the snapshot in a directory.
+    ///
+    /// The file is replaced atomically, so a crash never leaves a partial
+    /// snapshot.
+    pub fn save(&self, directory: &Path) -> Result<(), Error> {
+        let encoded = bincode::serialize(self).context(
+            IoError::SerializationError,
+            "while serializing snapshot",
+        )?;
+
+        let temporary = directory.join(format!("{}.tmp", SNAPSHOT_FILE));
+        fs::write(&temporary, encoded)
+            .and_then(|_| fs::rename(&temporary, directory.join(SNAPSHOT_FILE)))
+            .context(IoError::WritingError, "while writing snapshot")
+    }
+
+    /// Reads the snapshot of a directory, if any.
+    pub fn load(directory: &Path) -> Result<Option<Self>, Error> {
+        let encoded = match fs::read(directory.join(SNAPSHOT_FILE)) {
+            Ok(encoded) => encoded,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            },
+            Err(error) => {
+                return Err(Error::from(IoError::ReadingError)
+                    .with_cause(error)
+                    .with_context("while reading snapshot"))
+            },
+        };
+
+        bincode::deserialize(&encoded).map(Some).context(
+            IoError::DeserializationError,
+            "while deserializing snapshot",
+        )
+    }
+}
+
+/// Returns the occurrences of every word of each board.
+async fn dump_all(boards: &Boards) -> HashMap<String, Vec<(String, usize)>> {
+    boards
+        .read()
+        .await
+        .iter()
+        .map(|(key, board)| (key.clone(), dump(board)))
+        .collect()
+}
+
+/// Returns the occurrences of every word of a board.
+fn dump(board: &Board) -> Vec<(String, usize)> {
+    let mut words = Vec::new();
+    board.for_each(|word, count| words.push((word.to_string(), count)));
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::config::MessageType;
+    use squid_algorithm::hashtable::MapAlgorithm;
+
+    fn counters() -> Counters {
+        Counters::new(MapAlgorithm::default().with_documents())
+    }
+
+    #[tokio::test]
+    async fn test_restore_documents() {
+        let counters = counters();
+        {
+            let mut algorithm = counters.algorithm.write().await;
+            algorithm.set_many(&["the", "cat", "cat", "#pets"], 1);
+            algorithm.set_many(&["the", "dog"], 1);
+            algorithm.set_many(&["the", "cat", "bird"], 2);
+        }
+        counters
+            .languages
+            .write()
+            .await
+            .entry("en".to_string())
+            .or_insert_with(|| counters.blank())
+            .set_many(&["the", "dog", "dog"], 1);
+
+        let encoded =
+            bincode::serialize(&Snapshot::take(&counters, HashSet::new()).await)
+                .unwrap();
+        let restored = self::counters();
+        bincode::deserialize::<Snapshot>(&encoded)
+            .unwrap()
+            .restore(&restored)
+            .await;
+
+        for kind in [MessageType::Word, MessageType::Anything] {
+            assert_eq!(
+                restored.algorithm.read().await.rank_tfidf(&kind, 10, 0),
+                counters.algorithm.read().await.rank_tfidf(&kind, 10, 0),
+            );
+        }
+        assert_eq!(
+            restored.languages.read().await["en"]
+                .rank_tfidf(&MessageType::Word, 10, 0),
+            counters.languages.read().await["en"]
+                .rank_tfidf(&MessageType::Word, 10, 0),
+        );
+    }
+}
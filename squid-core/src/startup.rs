@@ -0,0 +1,94 @@
+//! Progress of the startup, so clients know when leaderboards are complete.
+//!
+//! Databases are loaded before requests are served. Their sentences are
+//! then counted in the background, leaderboards being incomplete until
+//! every service is ready.
+
+use tokio::sync::watch;
+
+/// Sentences counted between two progress updates.
+const PROGRESS_STEP: usize = 1_000;
+
+lazy_static! {
+    /// Progress of the running server.
+    static ref PROGRESS: watch::Sender<Progress> =
+        watch::channel(Progress::default()).0;
+}
+
+/// Stage of the startup.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// Databases are being loaded.
+    #[default]
+    Loading,
+    /// Stored sentences are being counted, leaderboards are incomplete.
+    Warming,
+    /// Every stored sentence is counted.
+    Ready,
+}
+
+/// Progress of the startup, across every service.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Current step of the startup.
+    pub state: State,
+    /// Stored sentences counted so far.
+    pub counted: usize,
+    /// Stored sentences to be counted, known once databases are loaded.
+    pub total: usize,
+    /// Services whose sentences are still being counted.
+    pub warming: usize,
+}
+
+/// Returns the current progress.
+pub fn progress() -> Progress {
+    *PROGRESS.borrow()
+}
+
+/// Returns whether every stored sentence is counted.
+pub fn is_ready() -> bool {
+    progress().state == State::Ready
+}
+
+/// Returns the progress, updated as the startup goes on.
+pub fn subscribe() -> watch::Receiver<Progress> {
+    PROGRESS.subscribe()
+}
+
+/// Records that a service will count `total` stored sentences.
+pub(crate) fn warming(total: usize) {
+    PROGRESS.send_modify(|progress| {
+        progress.total += total;
+        progress.warming += 1;
+    });
+}
+
+/// Records that every database is loaded.
+pub(crate) fn loaded() {
+    PROGRESS.send_modify(|progress| {
+        progress.state = if progress.warming == 0 {
+            State::Ready
+        } else {
+            State::Warming
+        };
+    });
+}
+
+/// Records counted sentences, notifying subscribers from time to time.
+pub(crate) fn counted(count: usize) {
+    PROGRESS.send_if_modified(|progress| {
+        let before = progress.counted / PROGRESS_STEP;
+        progress.counted += count;
+        progress.counted / PROGRESS_STEP != before
+    });
+}
+
+/// Records that a service counted its stored sentences.
+pub(crate) fn warmed() {
+    PROGRESS.send_modify(|progress| {
+        progress.warming = progress.warming.saturating_sub(1);
+        if progress.warming == 0 && progress.state == State::Warming {
+            progress.state = State::Ready;
+        }
+    });
+}
@@ -0,0 +1,11 @@
+//! Time at which things happen, as stored and exchanged by the services.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns the current UNIX timestamp, in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
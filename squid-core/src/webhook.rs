@@ -0,0 +1,213 @@
+//! Notifications of the sentences removed from a service, sent to its
+//! webhooks.
+
+use crate::models::{
+    config::{Event, Webhook},
+    database::Entity,
+};
+use serde::Serialize;
+use squid_db::Attributes;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+#[cfg(feature = "webhooks")]
+use std::time::Duration;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::warn;
+
+/// Notifications waiting to be delivered, per webhook, before new ones are
+/// dropped.
+#[cfg(feature = "webhooks")]
+const WEBHOOK_QUEUE_SIZE: usize = 10_000;
+/// Attempts after a failed delivery if not configured.
+#[cfg(feature = "webhooks")]
+const DEFAULT_RETRIES: u32 = 5;
+/// Delay before the first retry, doubled after each failure.
+#[cfg(feature = "webhooks")]
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Maximum delay between two retries.
+#[cfg(feature = "webhooks")]
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+/// Maximum time to deliver a notification.
+#[cfg(feature = "webhooks")]
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A sentence removed from a service, as sent to webhooks.
+#[derive(Serialize, Debug, Clone)]
+pub struct Notification {
+    event: Event,
+    namespace: String,
+    id: String,
+    lang: String,
+    tags: Vec<String>,
+    metadata: HashMap<String, String>,
+    region: Option<String>,
+    author_id: Option<String>,
+    /// UNIX timestamp at which the sentence expired, in seconds.
+    expire_at: Option<u64>,
+}
+
+/// Sends the removed sentences of a service to its webhooks.
+#[derive(Debug)]
+pub struct Webhooks {
+    namespace: String,
+    /// Queue of each webhook, with the events it receives.
+    queues: Vec<(Vec<Event>, mpsc::Sender<Notification>)>,
+    /// Sentences being deleted, whose expiration is a deletion.
+    deleted: Mutex<HashSet<String>>,
+}
+
+impl Webhooks {
+    /// Starts delivering the notifications of each webhook.
+    ///
+    /// Without the `webhooks` feature, webhooks are ignored.
+    #[cfg(feature = "webhooks")]
+    pub fn new(namespace: &str, webhooks: &[Webhook]) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .expect("failed to create HTTP client");
+
+        Self {
+            namespace: namespace.to_string(),
+            queues: webhooks
+                .iter()
+                .map(|webhook| {
+                    let (tx, rx) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+                    tokio::task::spawn(deliver(
+                        client.clone(),
+                        namespace.to_string(),
+                        webhook.clone(),
+                        rx,
+                    ));
+
+                    (webhook.events.clone(), tx)
+                })
+                .collect(),
+            deleted: Mutex::default(),
+        }
+    }
+
+    /// Starts delivering the notifications of each webhook.
+    ///
+    /// Without the `webhooks` feature, webhooks are ignored.
+    #[cfg(not(feature = "webhooks"))]
+    pub fn new(namespace: &str, webhooks: &[Webhook]) -> Self {
+        if !webhooks.is_empty() {
+            warn!(
+                namespace,
+                "Webhooks are ignored, Squid was built without the webhooks \
+                 feature."
+            );
+        }
+
+        Self {
+            namespace: namespace.to_string(),
+            queues: Vec::new(),
+            deleted: Mutex::default(),
+        }
+    }
+
+    /// Reports the next expiration of a sentence as a deletion.
+    pub fn delete(&self, id: &str) {
+        if !self.queues.is_empty() {
+            if let Ok(mut deleted) = self.deleted.lock() {
+                deleted.insert(id.to_string());
+            }
+        }
+    }
+
+    /// Reports the next expiration of a sentence as an expiration again.
+    pub fn cancel(&self, id: &str) {
+        if let Ok(mut deleted) = self.deleted.lock() {
+            deleted.remove(id);
+        }
+    }
+
+    /// Queues the notification of a removed sentence.
+    ///
+    /// Notifications are dropped if a webhook is too far behind, so
+    /// expirations are never held back.
+    pub fn notify(&self, entity: &Entity) {
+        if self.queues.is_empty() {
+            return;
+        }
+
+        let deleted = self
+            .deleted
+            .lock()
+            .map(|mut deleted| deleted.remove(&entity.id))
+            .unwrap_or_default();
+        let notification = Notification {
+            event: if deleted {
+                Event::Deleted
+            } else {
+                Event::Expired
+            },
+            namespace: self.namespace.clone(),
+            id: entity.id.clone(),
+            lang: entity.lang.clone(),
+            tags: entity.tags.clone(),
+            metadata: entity.metadata.clone(),
+            region: entity.region.clone(),
+            author_id: entity.author_id.clone(),
+            expire_at: entity.ttl(),
+        };
+
+        for (events, queue) in &self.queues {
+            if !events.contains(&notification.event) {
+                continue;
+            }
+
+            if let Err(TrySendError::Full(notification)) =
+                queue.try_send(notification.clone())
+            {
+                warn!(
+                    namespace = self.namespace,
+                    "Webhook is too far behind, dropped notification of {}.",
+                    notification.id
+                );
+            }
+        }
+    }
+}
+
+/// Sends each notification to a webhook, retrying failed deliveries.
+#[cfg(feature = "webhooks")]
+async fn deliver(
+    client: reqwest::Client,
+    namespace: String,
+    webhook: Webhook,
+    mut notifications: mpsc::Receiver<Notification>,
+) {
+    let retries = webhook.retries.unwrap_or(DEFAULT_RETRIES);
+
+    while let Some(notification) = notifications.recv().await {
+        let mut delay = RETRY_DELAY;
+        let mut attempt = 0;
+
+        while let Err(error) = client
+            .post(&webhook.url)
+            .json(&notification)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            if attempt >= retries {
+                warn!(
+                    namespace,
+                    "Dropped notification of {} after {} attempts: {}",
+                    notification.id,
+                    attempt + 1,
+                    error
+                );
+                break;
+            }
+
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RETRY_DELAY);
+            attempt += 1;
+        }
+    }
+}
@@ -7,17 +7,18 @@
 #[cfg(feature = "compress")]
 mod compress;
 mod manager;
+mod record;
 mod ttl;
 
-pub use manager::Instance;
+pub use manager::{Instance, Stats};
 
 use ttl::TTL;
-use crate::manager::World;
-use squid_error::{Error, ErrorType, IoError};
+use crate::{manager::World, record::Segment};
+use squid_error::{Error, ErrorType, IoError, ResultExt};
 use std::{
     collections::BTreeMap,
-    fs::{create_dir, read_dir, File, OpenOptions},
-    io::{self, BufRead, BufReader},
+    fs::{create_dir_all, read_dir, File, OpenOptions},
+    io,
     marker::PhantomData,
     path::{Path, PathBuf},
     sync::Arc,
@@ -39,6 +40,11 @@ pub trait Attributes {
     fn ttl(&self) -> Option<u64> {
         None
     }
+
+    /// Changes the value returned by [`Attributes::ttl`].
+    ///
+    /// Required by [`Instance::touch`] and [`Instance::expire_now`].
+    fn set_ttl(&mut self, _ttl: Option<u64>) {}
 }
 
 /// [`Builder`] handle database creation.
@@ -59,6 +65,8 @@ pub struct Builder<
     sender: Option<Sender<T>>,
     /// Is TTL manager is enabled.
     ttl: bool,
+    /// Directory containing data files.
+    directory: Option<PathBuf>,
     phantom: PhantomData<T>,
 }
 
@@ -96,11 +104,22 @@ where
     ///
     /// By providing a sender, you enable the database to communicate expiration
     /// events to other parts of your program or system asynchronously.
+    ///
+    /// Expired entries wait for room in the channel before being deleted, so
+    /// a slow receiver delays expirations instead of growing memory.
     pub fn mpsc_sender(mut self, sender: Sender<T>) -> Self {
         self.sender = Some(sender);
         self
     }
 
+    /// Set the directory containing data files.
+    ///
+    /// Defaults to `./data/`. The directory is created if it does not exist.
+    pub fn directory<P: Into<PathBuf>>(mut self, directory: P) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
     /// Enables time-to-live (TTL) on entries.
     pub fn with_ttl(mut self) -> Self {
         self.ttl = true;
@@ -134,27 +153,21 @@ where
     pub async fn build(
         self,
     ) -> Result<Arc<RwLock<manager::Instance<T>>>, Error> {
-        let (entires, index, file, mut file_name) = load::<T>()?;
-
-        let file = file.unwrap_or_else(|| {
-            file_name = uuid::Uuid::new_v4().to_string();
-            let path = PathBuf::from(SOURCE_DIRECTORY)
-                .join(format!("{}.{}", file_name, FILE_EXT));
-
-            OpenOptions::new()
-                .read(true)
-                .append(true)
-                .create(true)
-                .open(&path)
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "failed to create new file on {}",
-                        path.to_string_lossy()
-                    )
-                })
-        });
+        let directory = self
+            .directory
+            .unwrap_or_else(|| PathBuf::from(SOURCE_DIRECTORY));
+        let (entires, index, file, mut file_name) = load::<T>(&directory)?;
+
+        let file = match file {
+            Some(file) => file,
+            None => {
+                file_name = format!("{}.{}", uuid::Uuid::new_v4(), FILE_EXT);
+                record::create(&directory.join(&file_name))?
+            },
+        };
 
         let instance = Arc::new(RwLock::new(manager::Instance {
+            directory,
             file,
             file_name,
             index,
@@ -162,6 +175,8 @@ where
             entries: entires.0,
             memtable: Vec::new(),
             memtable_flush_size_in_kb: self.memtable_flush_size_in_kb,
+            flushes: 0,
+            compactions: 0,
             sender: self.sender,
             phantom: PhantomData,
         }));
@@ -185,7 +200,10 @@ where
 
 /// Loads a specific data file rather than the whole set.
 #[inline(always)]
-fn load_file<T>(mut name: String) -> Result<World<T>, Error>
+fn load_file<T>(
+    directory: &Path,
+    mut name: String,
+) -> Result<World<T>, Error>
 where
     T: serde::Serialize
         + serde::de::DeserializeOwned
@@ -198,51 +216,33 @@ where
         name = format!("{}.{}", name, FILE_EXT);
     }
 
-    let file = OpenOptions::new()
-        .read(true)
-        .append(true)
-        .open(Path::new(SOURCE_DIRECTORY).join(name))
-        .map_err(|error| {
-            Error::new(
-                ErrorType::Unspecified,
-                Some(Box::new(error)),
-                Some("while opening file".to_string()),
-            )
-        })?;
-
-    let reader = BufReader::new(&file);
-    let mut world: World<T> = World(Vec::new());
+    Ok(World(decode(&Segment::read(&directory.join(name))?)?))
+}
 
-    for line in reader.lines() {
-        let line_data: T = bincode::deserialize(
-            line.map_err(|error| {
-                Error::new(
-                    ErrorType::InputOutput(IoError::ReadingError),
-                    Some(Box::new(error)),
-                    Some("cannot read line before deserialization".to_string()),
-                )
-            })?
-            .as_bytes(),
-        )
-        .map_err(|error| {
-            Error::new(
-                ErrorType::InputOutput(IoError::DeserializationError),
-                Some(Box::new(error)),
-                Some("cannot serialize to read file".to_string()),
+/// Decodes the entries of a data file.
+fn decode<T>(segment: &Segment) -> Result<Vec<T>, Error>
+where
+    T: serde::de::DeserializeOwned,
+{
+    segment
+        .records
+        .iter()
+        .map(|record| {
+            bincode::deserialize(record).context(
+                IoError::DeserializationError,
+                "cannot deserialize entry to read file",
             )
-        })?;
-
-        world.0.push(line_data);
-    }
-
-    Ok(world)
+        })
+        .collect()
 }
 
 /// Reads data from each saved file in the source directory,
 /// generates an index, and returns any unfinished files
 /// (those with fewer than the specified maximum entries).
 #[inline(always)]
+#[allow(clippy::type_complexity)]
 fn load<T>(
+    directory: &Path,
 ) -> Result<(World<T>, BTreeMap<String, String>, Option<File>, String), Error>
 where
     T: serde::Serialize
@@ -257,45 +257,42 @@ where
     let mut uncomplete_file: Option<File> = None;
     let mut file_name = String::default();
 
-    let _ = create_dir(SOURCE_DIRECTORY);
+    let _ = create_dir_all(directory);
 
-    for entry in read_dir(SOURCE_DIRECTORY)
-        .map_err(|error| {
-            Error::new(
-                ErrorType::InputOutput(IoError::WritingError),
-                Some(Box::new(error)),
-                Some("cannot read data dir".to_string()),
-            )
-        })?
+    for entry in read_dir(directory)
+        .context(IoError::WritingError, "cannot read data dir")?
         .collect::<Result<Vec<_>, io::Error>>()
-        .map_err(|error| {
-            Error::new(
-                ErrorType::InputOutput(IoError::ReadingError),
-                Some(Box::new(error)),
-                Some("cannot convert into vector".to_string()),
-            )
-        })?
+        .context(IoError::ReadingError, "cannot convert into vector")?
     {
         let filename = entry.file_name().into_string().unwrap_or_default();
-        let mut data: Vec<T> = load_file(filename.to_string())?.0;
+
+        // Directories may belong to other databases, and other files to the
+        // application.
+        if !entry.file_type().is_ok_and(|file_type| file_type.is_file())
+            || !filename.ends_with(FILE_EXT)
+        {
+            continue;
+        }
+
+        let segment = Segment::read(&directory.join(&filename))?;
+        let mut data: Vec<T> = decode(&segment)?;
 
         for line in &data {
             index.insert(line.id(), filename.clone());
         }
 
-        if data.len() < MAX_ENTRIES_PER_FILE {
+        // Files written one entry per line are only read, new entries go
+        // into framed files.
+        if segment.appendable && data.len() < MAX_ENTRIES_PER_FILE {
             uncomplete_file = Some(
                 OpenOptions::new()
                     .read(true)
                     .append(true)
-                    .open(&Path::new(SOURCE_DIRECTORY).join(filename))
-                    .map_err(|error| {
-                        Error::new(
-                            ErrorType::Unspecified,
-                            Some(Box::new(error)),
-                            Some("while opening file to load it".to_string()),
-                        )
-                    })?,
+                    .open(directory.join(filename))
+                    .context(
+                        ErrorType::Unspecified,
+                        "while opening file to load it",
+                    )?,
             );
             file_name = entry.file_name().into_string().unwrap_or_default();
         }
@@ -2,16 +2,17 @@
 //! supports read, write, memtable.
 
 use crate::{
-    ttl::TTL, Attributes, FILE_EXT, MAX_ENTRIES_PER_FILE, SOURCE_DIRECTORY,
+    record::{self, Segment},
+    ttl::TTL, Attributes, FILE_EXT, MAX_ENTRIES_PER_FILE,
 };
 use serde::Serialize;
-use squid_error::{Error, ErrorType, IoError};
+use squid_error::{Error, ErrorType, IoError, ResultExt};
 use std::{
     collections::BTreeMap,
-    fs::{File, OpenOptions},
-    io::{self, BufRead, BufReader, Write},
+    fs::{metadata, read_dir, remove_file, File},
+    io::Write,
     marker::PhantomData,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::Arc,
 };
 use tokio::sync::{mpsc::Sender, RwLock};
@@ -29,6 +30,48 @@ where
         + std::marker::Sync
         + 'static;
 
+/// Statistics about an [`Instance`].
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats {
+    /// Number of stored entries, including those in the memtable.
+    pub entries: usize,
+    /// Number of data files.
+    pub segments: usize,
+    /// Size of the data files, in bytes.
+    pub disk_bytes: u64,
+    /// Number of entries waiting in the memtable.
+    pub memtable: usize,
+    /// Number of memtable flushes.
+    pub flushes: u64,
+    /// Number of compactions.
+    pub compactions: u64,
+    /// Number of entries whose expiration is scheduled.
+    pub expirations: usize,
+}
+
+/// Copies an entry through bincode, as `T` is not required to be `Clone`.
+fn copy<T>(entry: &T) -> Result<T, Error>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    bincode::serialize(entry)
+        .and_then(|encoded| bincode::deserialize(&encoded))
+        .context(
+            IoError::DeserializationError,
+            "while copying entry from memtable",
+        )
+}
+
+/// Lists the name of every data file.
+fn data_files(directory: &Path) -> Result<Vec<String>, Error> {
+    Ok(read_dir(directory)
+        .context(IoError::ReadingError, "cannot read data dir")?
+        .map_while(Result::ok)
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|file_name| file_name.ends_with(FILE_EXT))
+        .collect())
+}
+
 /// Structure representing one instance of the database.
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -40,6 +83,8 @@ pub struct Instance<
         + std::marker::Sync
         + 'static,
 > {
+    /// Directory containing data files.
+    pub(super) directory: PathBuf,
     /// File writing new entries.
     /// There is no need to re-open the file each time.
     pub(super) file: File,
@@ -57,6 +102,10 @@ pub struct Instance<
     /// After how many kb the data is written hard to the disk.
     /// Set to 0 to deactivate the memory table.
     pub(super) memtable_flush_size_in_kb: usize,
+    /// Number of times the memtable has been written to the disk.
+    pub(super) flushes: u64,
+    /// Number of times the files have been compacted.
+    pub(super) compactions: u64,
     /// MPSC consumer used to know expired sentences.
     /// Created by yourself using [`tokio::sync::mpsc`].
     pub(crate) sender: Option<Sender<T>>,
@@ -72,10 +121,50 @@ where
         + std::marker::Sync
         + 'static,
 {
+    /// Returns the unique identifier of every entry, including the ones
+    /// waiting in the memtable.
+    pub fn ids(&self) -> impl Iterator<Item = String> + '_ {
+        self.index
+            .keys()
+            .cloned()
+            .chain(self.memtable.iter().map(|entry| entry.id()))
+    }
+
+    /// Lists the data files, named segments.
+    ///
+    /// Used with [`Instance::segment`] and [`Instance::memtable`] to read
+    /// every entry without loading them all at once.
+    pub fn segments(&self) -> Result<Vec<String>, Error> {
+        data_files(&self.directory)
+    }
+
+    /// Reads the entries of a data file.
+    pub fn segment(&self, name: &str) -> Result<Vec<T>, Error> {
+        Ok(crate::load_file::<T>(&self.directory, name.to_string())?.0)
+    }
+
+    /// Returns a copy of the entries waiting in the memtable.
+    pub fn memtable(&self) -> Result<Vec<T>, Error> {
+        self.memtable.iter().map(copy).collect()
+    }
+
+    /// Returns whether an entry exists, without reading it.
+    pub fn contains(&self, id: &str) -> bool {
+        self.index.contains_key(id)
+            || self.memtable.iter().any(|entry| entry.id() == id)
+    }
+
     /// Get entry from its unique identifier.
+    ///
+    /// Entries waiting in the memtable are returned too.
     pub fn get(&self, id: String) -> Result<Option<T>, Error> {
-        if let Some(file_name) = self.index.get(&id) {
-            let data = crate::load_file::<T>(file_name.to_string())?.0;
+        if let Some(entry) = self.memtable.iter().find(|entry| entry.id() == id)
+        {
+            Ok(Some(copy(entry)?))
+        } else if let Some(file_name) = self.index.get(&id) {
+            let data =
+                crate::load_file::<T>(&self.directory, file_name.to_string())?
+                    .0;
 
             Ok(data.into_iter().find(|entry| entry.id() == id))
         } else {
@@ -129,16 +218,10 @@ where
         match self.memtable_flush_size_in_kb {
             0 => {
                 #[cfg(not(feature = "compress"))]
-                let encoded = bincode::serialize(&data).map_err(|error| {
-                    Error::new(
-                        ErrorType::InputOutput(IoError::DeserializationError),
-                        Some(error),
-                        Some(
-                            "during `bincode` serialization to set new entry"
-                                .to_string(),
-                        ),
-                    )
-                })?;
+                let encoded = bincode::serialize(&data).context(
+                    IoError::DeserializationError,
+                    "during `bincode` serialization to set new entry",
+                )?;
 
                 self.index.insert(data.id(), self.file_name.clone());
                 self.save(&encoded)?
@@ -149,13 +232,10 @@ where
                 if max_kb_size
                     < (self.memtable.len() * std::mem::size_of::<T>()) / 1000
                 {
-                    self.flush().map_err(|error| {
-                        Error::new(
-                            ErrorType::Unspecified,
-                            Some(Box::new(error)),
-                            Some("while flushing database".to_string()),
-                        )
-                    })?
+                    self.flush().context(
+                        ErrorType::Unspecified,
+                        "while flushing database",
+                    )?
                 }
             },
         }
@@ -163,56 +243,53 @@ where
         Ok(())
     }
 
+    /// Adds several entries to the database at once.
+    ///
+    /// Entries go through the memtable, even if it is disabled, so that they
+    /// are written to the disk together.
+    pub async fn set_many(&mut self, data: Vec<T>) -> Result<(), Error> {
+        if let Some(ttl) = &self.ttl {
+            let mut ttl = ttl.write().await;
+            for entry in &data {
+                if let Some(timestamp) = entry.ttl() {
+                    ttl.add_entry(entry.id(), timestamp)?;
+                }
+            }
+        }
+
+        #[cfg(feature = "logging")]
+        trace!(entries = data.len(), "Added new entries.");
+
+        self.memtable.extend(data);
+
+        if self.memtable_flush_size_in_kb == 0
+            || self.memtable_flush_size_in_kb
+                < (self.memtable.len() * std::mem::size_of::<T>()) / 1000
+        {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
     /// Deletes a record from the data based on its unique identifier.
     pub fn delete(&mut self, id: &str) -> Result<(), Error> {
+        self.memtable.retain(|entry| entry.id() != id);
+
         if let Some(file_name) = self.index.get(id) {
-            let file =
-                File::open(PathBuf::from(SOURCE_DIRECTORY).join(file_name))
-                    .map_err(|error| {
-                        Error::new(
-                            ErrorType::InputOutput(IoError::ReadingError),
-                            Some(Box::new(error)),
-                            Some(
-                                "cannot open file to delete entry".to_string(),
-                            ),
-                        )
-                    })?;
-            let reader = BufReader::new(file);
-
-            let lines: Vec<Vec<u8>> = reader
-                .lines()
-                .map_while(Result::ok)
-                .map(|entry| entry.as_bytes().to_vec())
-                .collect();
-
-            let index_to_delete = lines.iter().position(|line| {
-                if let Ok(data) = bincode::deserialize::<T>(line) {
+            let path = self.directory.join(file_name);
+            let mut records = Segment::read(&path)?.records;
+
+            let index_to_delete = records.iter().position(|record| {
+                if let Ok(data) = bincode::deserialize::<T>(record) {
                     return data.id() == id;
                 }
                 false
             });
 
             if let Some(index) = index_to_delete {
-                let mut file = OpenOptions::new()
-                    .write(true)
-                    .truncate(true)
-                    .open(PathBuf::from(SOURCE_DIRECTORY).join(file_name))
-                    .map_err(|error| {
-                        Error::new(
-                            ErrorType::Unspecified,
-                            Some(Box::new(error)),
-                            Some(
-                                "during file opening to delete row".to_string(),
-                            ),
-                        )
-                    })?;
-
-                lines.iter().enumerate().for_each(|(i, line)| {
-                    if i != index {
-                        writeln!(file, "{}", String::from_utf8_lossy(line))
-                            .unwrap_or_default();
-                    }
-                });
+                records.remove(index);
+                record::rewrite(&path, &records)?;
 
                 #[cfg(feature = "logging")]
                 trace!(id = id, file = file_name, "Entry deleted.",);
@@ -228,170 +305,197 @@ where
     #[inline(always)]
     #[allow(unused)]
     fn save(&mut self, buf: &[u8]) -> Result<(), Error> {
-        let mut line_count = io::BufReader::new(&self.file).lines().count();
+        let line_count = self.line_count()?;
         let mut buffer: Vec<u8> = vec![];
 
-        buffer.extend_from_slice(buf);
-        buffer.extend_from_slice(b"\n");
+        record::frame(buf, &mut buffer)?;
 
-        self.file.write_all(&buffer).map_err(|error| {
-            Error::new(
-                ErrorType::Unspecified,
-                Some(Box::new(error)),
-                Some("saving context".to_string()),
-            )
-        })?;
+        self.file.write_all(&buffer).context(
+            ErrorType::Unspecified,
+            "saving context",
+        )?;
 
         if line_count + 1 >= MAX_ENTRIES_PER_FILE {
-            self.file_name = uuid::Uuid::new_v4().to_string();
-            let path = PathBuf::from(SOURCE_DIRECTORY)
-                .join(format!("{}.{}", self.file_name, FILE_EXT));
-
-            self.file = OpenOptions::new()
-                .read(true)
-                .append(true)
-                .create(true)
-                .open(&path)
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "failed to create new file on {}",
-                        path.to_string_lossy()
-                    )
-                });
+            self.rotate()?;
         }
 
         Ok(())
     }
 
+    /// Counts the entries written in the opened file.
+    fn line_count(&self) -> Result<usize, Error> {
+        let path = self.directory.join(&self.file_name);
+
+        Ok(Segment::read(&path)?.records.len())
+    }
+
+    /// Opens a new file to write the next entries.
+    fn rotate(&mut self) -> Result<(), Error> {
+        let file_name = format!("{}.{}", uuid::Uuid::new_v4(), FILE_EXT);
+
+        self.file = record::create(&self.directory.join(&file_name))?;
+        self.file_name = file_name;
+
+        Ok(())
+    }
+
+    /// Returns the number of entries waiting in the memtable.
+    pub fn memtable_len(&self) -> usize {
+        self.memtable.len()
+    }
+
+    /// Returns the number of times the memtable has been flushed.
+    pub fn flushes(&self) -> u64 {
+        self.flushes
+    }
+
     /// Saves the data contained in the buffer to the hard disk.
     pub fn flush(&mut self) -> Result<(), Error> {
-        let line_count = io::BufReader::new(&self.file).lines().count();
-
-        if line_count + self.memtable.len() > MAX_ENTRIES_PER_FILE {
-            // If we just write all, number of lines will exceed maximum allowed.
-            // So, we will split into two different files.
-            let mut buffer: Vec<u8> = Vec::with_capacity(self.memtable.len());
-
-            let mut file_limit = MAX_ENTRIES_PER_FILE - line_count;
-            for n in 0..file_limit {
-                let data = &self.memtable[n];
-
-                buffer.extend_from_slice(&bincode::serialize(&data).map_err(
-                    |error| {
-                        Error::new(
-                            ErrorType::InputOutput(IoError::SerializationError),
-                            Some(Box::new(error)),
-                            Some(
-                                "cannot serialize to flush database"
-                                    .to_string(),
-                            ),
-                        )
-                    },
-                )?);
-                buffer.extend_from_slice(b"\n");
-
-                // Insert new hard entry into index.
-                self.index.insert(data.id(), self.file_name.clone());
-            }
+        self.flushes += 1;
+        self.write_memtable()
+    }
 
-            self.file.write_all(&buffer).map_err(|error| {
-                Error::new(
-                    ErrorType::Unspecified,
-                    Some(Box::new(error)),
-                    Some("flush writing".to_string()),
-                )
-            })?;
-            self.file.flush().map_err(|error| {
-                Error::new(
-                    ErrorType::Unspecified,
-                    Some(Box::new(error)),
-                    Some("re-flush on flush over flush".to_string()),
-                )
-            })?;
-
-            self.file_name = uuid::Uuid::new_v4().to_string();
-            let path = PathBuf::from(SOURCE_DIRECTORY)
-                .join(format!("{}.{}", self.file_name, FILE_EXT));
-
-            self.file = OpenOptions::new()
-                .read(true)
-                .append(true)
-                .create(true)
-                .open(&path)
-                .unwrap_or_else(|_| {
-                    panic!(
-                        "failed to create new file on {}",
-                        path.to_string_lossy()
-                    )
-                });
-
-            for _ in
-                1..(line_count + self.memtable.len() - MAX_ENTRIES_PER_FILE)
-            {
-                file_limit += 1;
-                let data = &self.memtable[file_limit];
-
-                buffer.extend_from_slice(&bincode::serialize(&data).map_err(
-                    |error| {
-                        Error::new(
-                            ErrorType::InputOutput(IoError::SerializationError),
-                            Some(Box::new(error)),
-                            Some(
-                                "cannot serialize to flush database"
-                                    .to_string(),
-                            ),
-                        )
-                    },
-                )?);
-                buffer.extend_from_slice(b"\n");
-
-                // Insert new hard entry into index.
-                self.index.insert(data.id(), self.file_name.clone());
+    /// Writes the memtable into files, without counting it as a flush.
+    fn write_memtable(&mut self) -> Result<(), Error> {
+        let mut line_count = self.line_count()?;
+        let mut buffer: Vec<u8> = Vec::with_capacity(self.memtable.len());
+
+        for data in std::mem::take(&mut self.memtable) {
+            let encoded = bincode::serialize(&data).context(
+                IoError::SerializationError,
+                "cannot serialize to flush database",
+            )?;
+            record::frame(&encoded, &mut buffer)?;
+
+            // Insert new hard entry into index.
+            self.index.insert(data.id(), self.file_name.clone());
+            line_count += 1;
+
+            // If we just write all, number of lines will exceed maximum
+            // allowed. So, we will split into different files.
+            if line_count >= MAX_ENTRIES_PER_FILE {
+                self.write(&buffer)?;
+                buffer.clear();
+                self.rotate()?;
+                line_count = 0;
             }
+        }
 
-            self.file.write_all(&buffer).map_err(|error| {
-                Error::new(
-                    ErrorType::Unspecified,
-                    Some(Box::new(error)),
-                    Some("flush writing".to_string()),
-                )
-            })?;
-        } else {
-            let mut buffer: Vec<u8> = Vec::with_capacity(self.memtable.len());
-
-            for data in &self.memtable {
-                buffer.extend_from_slice(&bincode::serialize(&data).map_err(
-                    |error| {
-                        Error::new(
-                            ErrorType::InputOutput(IoError::SerializationError),
-                            Some(Box::new(error)),
-                            Some(
-                                "cannot serialize to flush database"
-                                    .to_string(),
-                            ),
-                        )
-                    },
-                )?);
-                buffer.extend_from_slice(b"\n");
-
-                // Insert new hard entry into index.
-                self.index.insert(data.id(), self.file_name.clone());
-            }
+        self.write(&buffer)
+    }
 
-            self.file.write_all(&buffer).map_err(|error| {
-                Error::new(
-                    ErrorType::Unspecified,
-                    Some(Box::new(error)),
-                    Some("again flush writing".to_string()),
-                )
-            })?;
+    /// Writes a buffer into the opened file.
+    fn write(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        self.file.write_all(buffer).context(
+            ErrorType::Unspecified,
+            "flush writing",
+        )?;
+        self.file.flush().context(
+            ErrorType::Unspecified,
+            "re-flush on flush over flush",
+        )
+    }
 
-            self.memtable.clear();
+    /// Rewrites every file so that they are filled up to the maximum number
+    /// of entries, reclaiming the space left by deleted entries.
+    ///
+    /// The memtable is flushed beforehand.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        self.flush()?;
+
+        let old_files = data_files(&self.directory)?;
+        let mut entries = Vec::new();
+        for file_name in &old_files {
+            entries.append(
+                &mut crate::load_file::<T>(&self.directory, file_name.clone())?
+                    .0,
+            );
         }
 
+        self.index.clear();
+        self.rotate()?;
+        self.memtable = entries;
+        self.write_memtable()?;
+        self.compactions += 1;
+
+        for file_name in old_files {
+            remove_file(self.directory.join(file_name)).context(
+                IoError::WritingError,
+                "cannot remove compacted file",
+            )?;
+        }
+
+        #[cfg(feature = "logging")]
+        trace!(entries = self.index.len(), "Database compacted.");
+
         Ok(())
     }
-    
+
+    /// Returns statistics about the stored entries.
+    pub async fn stats(&self) -> Result<Stats, Error> {
+        let mut disk_bytes = 0;
+        let files = data_files(&self.directory)?;
+        for file_name in &files {
+            let path = self.directory.join(file_name);
+            disk_bytes += metadata(path)
+                .context(IoError::ReadingError, "cannot read file metadata")?
+                .len();
+        }
+
+        Ok(Stats {
+            entries: self.index.len() + self.memtable.len(),
+            segments: files.len(),
+            disk_bytes,
+            memtable: self.memtable.len(),
+            flushes: self.flushes,
+            compactions: self.compactions,
+            expirations: match &self.ttl {
+                Some(ttl) => ttl.read().await.scheduled(),
+                None => 0,
+            },
+        })
+    }
+
+    /// Changes when an entry expires, or makes it permanent with `None`.
+    ///
+    /// The new value is saved through [`Attributes::set_ttl`], so it is
+    /// kept after a restart.
+    ///
+    /// Returns `false` if there is no entry with this identifier.
+    pub async fn touch(
+        &mut self,
+        id: &str,
+        ttl: Option<u64>,
+    ) -> Result<bool, Error> {
+        let Some(mut entry) = self.get(id.to_string())? else {
+            return Ok(false);
+        };
+
+        if let Some(manager) = &self.ttl {
+            manager.write().await.remove_entry(id)?;
+        }
+
+        entry.set_ttl(ttl);
+        self.delete(id)?;
+        self.set(entry).await?;
+
+        Ok(true)
+    }
+
+    /// Expires an entry now, notifying the MPSC sender as if its lifetime
+    /// had ended. Does nothing more than [`Instance::touch`] if TTL is not
+    /// enabled with [`crate::Builder::with_ttl`].
+    ///
+    /// Returns `false` if there is no entry with this identifier.
+    pub async fn expire_now(&mut self, id: &str) -> Result<bool, Error> {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        self.touch(id, Some(now)).await
+    }
+
     pub(super) fn ttl(&mut self, ttl: Arc<RwLock<TTL<T>>>) {
         self.ttl = Some(ttl);
     }
@@ -0,0 +1,141 @@
+//! Framing of the entries written in data files.
+//!
+//! Data files start with [`MAGIC`], then each entry is written as its
+//! length, a little-endian `u32`, followed by its `bincode` encoding. Entries
+//! may then hold any byte, such as line breaks in the length of a string.
+//!
+//! Files without [`MAGIC`] were written by earlier versions, one entry per
+//! line. They are still read, and rewritten framed once an entry is deleted
+//! or the files are compacted.
+
+use squid_error::{Error, IoError, ResultExt};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+};
+
+/// First bytes of every framed data file.
+pub(crate) const MAGIC: &[u8; 8] = b"SQUIDDB\x01";
+
+/// Bytes of the length written before each entry.
+const LENGTH_SIZE: usize = std::mem::size_of::<u32>();
+
+/// Encoded entries of a data file.
+pub(crate) struct Segment {
+    /// `bincode` encoding of each entry, in the order they were written.
+    pub(crate) records: Vec<Vec<u8>>,
+    /// Whether new entries may be appended to the file, which is framed and
+    /// does not end with an entry cut while being written.
+    pub(crate) appendable: bool,
+}
+
+impl Segment {
+    /// Reads the entries of a data file.
+    pub(crate) fn read(path: &Path) -> Result<Self, Error> {
+        let data = fs::read(path)
+            .context(IoError::ReadingError, "cannot read data file")?;
+
+        let Some(mut rest) = data.strip_prefix(MAGIC.as_slice()) else {
+            // Written one entry per line by an earlier version.
+            return Ok(Segment {
+                records: data
+                    .split(|byte| *byte == b'\n')
+                    .filter(|line| !line.is_empty())
+                    .map(<[u8]>::to_vec)
+                    .collect(),
+                appendable: false,
+            });
+        };
+
+        let mut records = Vec::new();
+        while let Some((length, tail)) = rest.split_first_chunk::<LENGTH_SIZE>()
+        {
+            let length = u32::from_le_bytes(*length) as usize;
+            let Some((record, tail)) = tail.split_at_checked(length) else {
+                break;
+            };
+            records.push(record.to_vec());
+            rest = tail;
+        }
+
+        Ok(Segment {
+            records,
+            // Entries appended after a cut one could not be read back.
+            appendable: rest.is_empty(),
+        })
+    }
+}
+
+/// Appends an encoded entry, framed, to a buffer written to a data file.
+pub(crate) fn frame(record: &[u8], buffer: &mut Vec<u8>) -> Result<(), Error> {
+    let length = u32::try_from(record.len()).context(
+        IoError::SerializationError,
+        "entry is too large to be stored",
+    )?;
+
+    buffer.extend_from_slice(&length.to_le_bytes());
+    buffer.extend_from_slice(record);
+
+    Ok(())
+}
+
+/// Creates an empty data file, to which entries are appended.
+pub(crate) fn create(path: &Path) -> Result<File, Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create_new(true)
+        .open(path)
+        .context(IoError::WritingError, "cannot create new file")?;
+    file.write_all(MAGIC)
+        .context(IoError::WritingError, "cannot write new file")?;
+
+    Ok(file)
+}
+
+/// Replaces the entries of a data file, framing them.
+pub(crate) fn rewrite(path: &Path, records: &[Vec<u8>]) -> Result<(), Error> {
+    let mut buffer = MAGIC.to_vec();
+    for record in records {
+        frame(record, &mut buffer)?;
+    }
+
+    fs::write(path, buffer)
+        .context(IoError::WritingError, "cannot rewrite data file")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_framed_records() {
+        let directory = std::env::temp_dir()
+            .join(format!("squid-db-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("segment.bin");
+
+        // Lengths holding a line break and a byte outside of ASCII.
+        let records = vec![vec![b'\n'; 10], vec![0x80; 128], Vec::new()];
+        rewrite(&path, &records).unwrap();
+        let segment = Segment::read(&path).unwrap();
+        assert_eq!(segment.records, records);
+        assert!(segment.appendable);
+
+        // An entry cut while being written is skipped, and nothing more is
+        // appended after it.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[12, 0, 0, 0, 1]).unwrap();
+        let segment = Segment::read(&path).unwrap();
+        assert_eq!(segment.records, records);
+        assert!(!segment.appendable);
+
+        fs::write(&path, b"first\nsecond\n").unwrap();
+        let segment = Segment::read(&path).unwrap();
+        assert_eq!(segment.records, [b"first".to_vec(), b"second".to_vec()]);
+        assert!(!segment.appendable);
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -54,17 +54,21 @@
 //! ```
 
 use crate::{Attributes, Instance};
-use squid_error::Error;
+use squid_error::{Error, IoError};
 use std::{
     collections::HashMap,
     sync::{Arc, RwLock},
-    thread::sleep,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
-use tokio::sync::RwLock as AsyncRwLock;
+use tokio::{sync::RwLock as AsyncRwLock, time::sleep};
+#[cfg(feature = "logging")]
+use tracing::warn;
 
 const SECONDS_IN_HOUR: u64 = 3600;
 
+/// Expiration timestamp of each entry, keyed by identifier.
+type Deadlines = Arc<RwLock<HashMap<String, u64>>>;
+
 #[derive(Debug, Clone)]
 #[allow(unused)]
 struct Entry {
@@ -73,6 +77,7 @@ struct Entry {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::upper_case_acronyms)]
 pub struct TTL<
     T: serde::Serialize
         + serde::de::DeserializeOwned
@@ -82,9 +87,63 @@ pub struct TTL<
         + 'static,
 > {
     periods: Arc<RwLock<HashMap<u64, Vec<Entry>>>>,
+    /// Latest expiration of each entry.
+    /// Timers of outdated expirations do nothing.
+    deadlines: Deadlines,
     instance: Arc<AsyncRwLock<Instance<T>>>,
 }
 
+/// Returns the current UNIX timestamp, in seconds.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Waits until `timestamp`, then notifies and deletes the entry if its
+/// expiration has not been changed meanwhile.
+async fn expire<T>(
+    instance: Arc<AsyncRwLock<Instance<T>>>,
+    deadlines: Deadlines,
+    id: String,
+    timestamp: u64,
+) where
+    T: serde::Serialize
+        + serde::de::DeserializeOwned
+        + Attributes
+        + std::marker::Send
+        + std::marker::Sync
+        + 'static,
+{
+    sleep(Duration::from_secs(timestamp.saturating_sub(now()))).await;
+
+    {
+        let Ok(mut deadlines) = deadlines.write() else {
+            return;
+        };
+        if deadlines.get(&id) != Some(&timestamp) {
+            return;
+        }
+        deadlines.remove(&id);
+    }
+
+    let (sender, data) = {
+        let instance = instance.read().await;
+        (instance.sender.clone(), instance.get(id.clone()))
+    };
+
+    // The instance is not locked while waiting for room in the channel, so
+    // the receiver can keep using it.
+    if let (Some(sender), Ok(Some(data))) = (sender, data) {
+        if sender.send(data).await.is_err() {
+            #[cfg(feature = "logging")]
+            warn!(id, "Receiver closed, expired entry was not notified.");
+        }
+    }
+    let _ = instance.write().await.delete(&id);
+}
+
 impl<T> TTL<T>
 where
     T: serde::Serialize
@@ -98,57 +157,42 @@ where
         Self {
             instance,
             periods: Arc::new(RwLock::new(HashMap::default())),
+            deadlines: Deadlines::default(),
         }
     }
 
+    /// Schedules the expiration of an entry.
+    /// Replaces its previous expiration, if any.
     pub fn add_entry(
         &mut self,
         id: String,
         timestamp: u64,
     ) -> Result<(), Error> {
-        let actual_hour = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs();
-
-        if actual_hour >= timestamp {
-            // Remove expired entry.
-            let instance = Arc::clone(&self.instance);
-            tokio::task::spawn(async move {
-                if let Some(sender) = &instance.read().await.sender {
-                    if let Ok(Some(data)) =
-                        instance.read().await.get(id.clone())
-                    {
-                        let _ = sender.send(data).await;
-                    }
-                }
-                let _ = instance.write().await.delete(&id);
-            });
-        } else if actual_hour / SECONDS_IN_HOUR == timestamp / SECONDS_IN_HOUR {
-            let instance = Arc::clone(&self.instance);
-            tokio::task::spawn(async move {
-                sleep(Duration::from_secs(timestamp - actual_hour));
-
-                if let Some(sender) = &instance.read().await.sender {
-                    if let Ok(Some(data)) =
-                        instance.read().await.get(id.clone())
-                    {
-                        let _ = sender.send(data).await;
-                    }
-                }
-                let _ = instance.write().await.delete(&id);
-            });
+        let actual_hour = now();
+
+        self.deadlines
+            .write()
+            .map_err(|_| {
+                Error::from(IoError::WritingError)
+                    .with_context("cannot get `deadlines`")
+            })?
+            .insert(id.clone(), timestamp);
+
+        if actual_hour >= timestamp
+            || actual_hour / SECONDS_IN_HOUR == timestamp / SECONDS_IN_HOUR
+        {
+            tokio::task::spawn(expire(
+                Arc::clone(&self.instance),
+                Arc::clone(&self.deadlines),
+                id,
+                timestamp,
+            ));
         } else {
             self.periods
                 .write()
                 .map_err(|_| {
-                    Error::new(
-                        squid_error::ErrorType::InputOutput(
-                            squid_error::IoError::WritingError,
-                        ),
-                        None,
-                        Some("cannot get `periods`".to_string()),
-                    )
+                    Error::from(IoError::WritingError)
+                        .with_context("cannot get `periods`")
                 })?
                 .entry(timestamp / SECONDS_IN_HOUR)
                 .and_modify(|e| {
@@ -169,56 +213,34 @@ where
     #[allow(unreachable_code)]
     fn spawn_timers(&self) {
         let periods = Arc::clone(&self.periods);
+        let deadlines = Arc::clone(&self.deadlines);
         let instance = Arc::clone(&self.instance);
 
         tokio::task::spawn(async move {
             loop {
-                let now = SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .unwrap_or_default()
-                    .as_secs();
+                let now = now();
 
                 // Sleep until next hour.
                 sleep(Duration::from_secs(
                     SECONDS_IN_HOUR - (now % SECONDS_IN_HOUR),
-                ));
+                ))
+                .await;
 
                 if let Some(timers) = periods
-                    .read()
+                    .write()
                     .map_err(|_| {
-                        Error::new(
-                            squid_error::ErrorType::InputOutput(
-                                squid_error::IoError::WritingError,
-                            ),
-                            None,
-                            Some("cannot get `periods`".to_string()),
-                        )
+                        Error::from(IoError::WritingError)
+                            .with_context("cannot get `periods`")
                     })?
-                    .get(&(now / SECONDS_IN_HOUR))
+                    .remove(&(now / SECONDS_IN_HOUR + 1))
                 {
                     for timer in timers {
-                        let entry = timer.clone();
-                        let instance = Arc::clone(&instance);
-
-                        tokio::task::spawn(async move {
-                            sleep(Duration::from_secs(
-                                entry.exact_expiration
-                                    - SystemTime::now()
-                                        .duration_since(UNIX_EPOCH)
-                                        .unwrap_or_default()
-                                        .as_secs(),
-                            ));
-
-                            if let Some(sender) = &instance.read().await.sender
-                            {
-                                if let Ok(Some(data)) =
-                                    instance.read().await.get(entry.id.clone())
-                                {
-                                    let _ = sender.send(data).await;
-                                }
-                            }
-                            let _ = instance.write().await.delete(&entry.id);
-                        });
+                        tokio::task::spawn(expire(
+                            Arc::clone(&instance),
+                            Arc::clone(&deadlines),
+                            timer.id,
+                            timer.exact_expiration,
+                        ));
                     }
                 }
             }
@@ -227,6 +249,27 @@ where
         });
     }
 
+    /// Returns the number of entries whose expiration is scheduled.
+    pub fn scheduled(&self) -> usize {
+        self.deadlines
+            .read()
+            .map(|deadlines| deadlines.len())
+            .unwrap_or_default()
+    }
+
+    /// Cancels the expiration of an entry.
+    pub fn remove_entry(&mut self, id: &str) -> Result<(), Error> {
+        self.deadlines
+            .write()
+            .map_err(|_| {
+                Error::from(IoError::WritingError)
+                    .with_context("cannot get `deadlines`")
+            })?
+            .remove(id);
+
+        Ok(())
+    }
+
     // Starts the periodic check and recent counters.
     pub fn init(&self) {
         self.spawn_timers();
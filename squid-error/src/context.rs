@@ -0,0 +1,49 @@
+//! Helpers adding context to errors.
+
+use crate::{BError, Error, ErrorType};
+
+/// Converts the errors of a [`Result`](core::result::Result) into [`Error`],
+/// keeping them as their cause.
+pub trait ResultExt<T> {
+    /// Wraps the error into an [`Error`] of type `etype`, explained by
+    /// `context`.
+    fn context(
+        self,
+        etype: impl Into<ErrorType>,
+        context: impl Into<String>,
+    ) -> core::result::Result<T, Error>;
+
+    /// Like [`ResultExt::context`], but the context is only built if there
+    /// is an error.
+    fn with_context<C: Into<String>>(
+        self,
+        etype: impl Into<ErrorType>,
+        context: impl FnOnce() -> C,
+    ) -> core::result::Result<T, Error>;
+}
+
+impl<T, E: Into<BError>> ResultExt<T> for core::result::Result<T, E> {
+    fn context(
+        self,
+        etype: impl Into<ErrorType>,
+        context: impl Into<String>,
+    ) -> core::result::Result<T, Error> {
+        self.map_err(|error| {
+            Error::from(etype.into())
+                .with_cause(error)
+                .with_context(context)
+        })
+    }
+
+    fn with_context<C: Into<String>>(
+        self,
+        etype: impl Into<ErrorType>,
+        context: impl FnOnce() -> C,
+    ) -> core::result::Result<T, Error> {
+        self.map_err(|error| {
+            Error::from(etype.into())
+                .with_cause(error)
+                .with_context(context())
+        })
+    }
+}
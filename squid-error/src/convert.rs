@@ -0,0 +1,51 @@
+//! Conversions of common errors into [`Error`], so they can be returned
+//! with `?`.
+//!
+//! The converted error is kept as the cause, without context.
+
+use crate::{Error, ErrorType, IoError};
+
+/// Returns the type of an I/O error.
+fn io_type(error: &std::io::Error) -> ErrorType {
+    match error.kind() {
+        std::io::ErrorKind::InvalidData | std::io::ErrorKind::UnexpectedEof => {
+            IoError::ReadingError.into()
+        },
+        std::io::ErrorKind::WriteZero => IoError::WritingError.into(),
+        _ => ErrorType::Unspecified,
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::from(io_type(&error)).with_cause(error)
+    }
+}
+
+#[cfg(feature = "bincode")]
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Self {
+        let etype = match &*error {
+            bincode::ErrorKind::Io(error) => io_type(error),
+            bincode::ErrorKind::SizeLimit
+            | bincode::ErrorKind::SequenceMustHaveLength => {
+                IoError::SerializationError.into()
+            },
+            _ => IoError::DeserializationError.into(),
+        };
+
+        Error::from(etype).with_cause(error)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        let etype = match error.io_error_kind() {
+            Some(kind) => io_type(&kind.into()),
+            None => IoError::DeserializationError.into(),
+        };
+
+        Error::from(etype).with_cause(error)
+    }
+}
@@ -0,0 +1,209 @@
+//! Conversion of [`Error`] into gRPC statuses.
+
+use crate::{Error, ErrorType, IoError, RequestError};
+use prost::Message;
+use std::{error::Error as StdError, io::ErrorKind};
+use tonic::{metadata::MetadataValue, Code, Status};
+
+/// Metadata key holding the [category](ErrorType::category) of the error.
+pub const CATEGORY_METADATA_KEY: &str = "x-squid-error";
+
+/// Sent in the details of failed requests.
+///
+/// Identical to the `ErrorDetails` message of `squid.proto`, which the
+/// tests of the `squid` server check.
+#[derive(Clone, PartialEq, Message)]
+pub struct ErrorDetails {
+    /// Type of the error, such as `WritingError`.
+    #[prost(string, tag = "1")]
+    pub reason: String,
+    /// What was being done when the error occurred, outermost first.
+    #[prost(string, repeated, tag = "2")]
+    pub context: Vec<String>,
+    /// Stable category of the error, such as `io.writing`.
+    #[prost(string, tag = "3")]
+    pub category: String,
+    /// Stable numeric code of the error, such as `3004`.
+    #[prost(uint32, tag = "4")]
+    pub code: u32,
+}
+
+impl ErrorType {
+    /// Returns the gRPC code of the error type.
+    pub fn grpc_code(&self) -> Code {
+        match self {
+            // Encoding only fails on the server, whatever the request.
+            ErrorType::InputOutput(IoError::SerializationError) => {
+                Code::Internal
+            },
+            ErrorType::Request(error) => match error {
+                RequestError::InvalidArgument => Code::InvalidArgument,
+                RequestError::NotFound => Code::NotFound,
+                RequestError::ResourceExhausted => Code::ResourceExhausted,
+                RequestError::Unavailable => Code::Unavailable,
+            },
+            _ => Code::Internal,
+        }
+    }
+}
+
+impl Error {
+    /// Converts the error into a gRPC status with a custom message.
+    ///
+    /// The code depends on the deepest error type and on the underlying I/O
+    /// error, if any. The type, category, code and context of the error are
+    /// sent as [`ErrorDetails`], and the category as
+    /// [`CATEGORY_METADATA_KEY`] metadata.
+    pub fn to_status(&self, message: &str) -> Status {
+        let mut contexts = Vec::new();
+        let mut code = None;
+        let mut current = self;
+
+        // `squid-db` wraps errors into others, the root cause is the deepest
+        // one.
+        let errors = std::iter::successors(
+            Some(self as &(dyn StdError + 'static)),
+            |error| (*error).source(),
+        );
+        for error in errors {
+            if let Some(error) = error.downcast_ref::<Error>() {
+                if let Some(context) = &error.context {
+                    contexts.push(context.clone());
+                }
+                current = error;
+            } else if let Some(error) = error.downcast_ref::<std::io::Error>() {
+                code = code.or_else(|| io_code(error));
+            }
+        }
+
+        let category = current.etype.category();
+        let details = ErrorDetails {
+            reason: reason(&current.etype),
+            context: contexts,
+            category: category.to_string(),
+            code: current.etype.code().into(),
+        };
+
+        let mut status = Status::with_details(
+            code.unwrap_or_else(|| current.etype.grpc_code()),
+            message,
+            details.encode_to_vec().into(),
+        );
+        status.metadata_mut().insert(
+            CATEGORY_METADATA_KEY,
+            MetadataValue::from_static(category),
+        );
+
+        status
+    }
+}
+
+impl From<Error> for Status {
+    /// Converts the error into a gRPC status, with the context of request
+    /// errors as message.
+    fn from(error: Error) -> Self {
+        match (&error.etype, &error.context) {
+            (ErrorType::Request(_), Some(context)) => error.to_status(context),
+            _ => error.to_status(&error.etype.to_string()),
+        }
+    }
+}
+
+/// Returns the name of an error type, such as `WritingError`.
+fn reason(etype: &ErrorType) -> String {
+    match etype {
+        ErrorType::Unspecified => format!("{:?}", etype),
+        ErrorType::Database(error) => format!("{:?}", error),
+        ErrorType::InputOutput(error) => format!("{:?}", error),
+        ErrorType::Request(error) => format!("{:?}", error),
+    }
+}
+
+/// Returns the code of I/O errors which are not internal errors.
+fn io_code(error: &std::io::Error) -> Option<Code> {
+    match error.kind() {
+        ErrorKind::OutOfMemory | ErrorKind::StorageFull => {
+            Some(Code::ResourceExhausted)
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResultExt;
+    use std::io;
+
+    #[test]
+    fn test_grpc_code() {
+        let codes = [
+            (RequestError::InvalidArgument.into(), Code::InvalidArgument),
+            (RequestError::NotFound.into(), Code::NotFound),
+            (RequestError::ResourceExhausted.into(), Code::ResourceExhausted),
+            (RequestError::Unavailable.into(), Code::Unavailable),
+            (IoError::SerializationError.into(), Code::Internal),
+            (IoError::WritingError.into(), Code::Internal),
+            (ErrorType::Unspecified, Code::Internal),
+        ];
+
+        for (etype, code) in codes {
+            assert_eq!(etype.grpc_code(), code, "{:?}", etype);
+        }
+    }
+
+    #[test]
+    fn test_to_status() {
+        // The deepest error sets the type and the code of the status.
+        let error = Err::<(), _>(io::Error::from(ErrorKind::StorageFull))
+            .context(IoError::WritingError, "cannot write entry")
+            .context(ErrorType::Unspecified, "while flushing")
+            .unwrap_err();
+        let status = error.to_status("failed to flush");
+
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(status.message(), "failed to flush");
+        assert_eq!(
+            status.metadata().get(CATEGORY_METADATA_KEY).unwrap(),
+            "io.writing"
+        );
+        assert_eq!(
+            ErrorDetails::decode(status.details()).unwrap(),
+            ErrorDetails {
+                reason: "WritingError".to_string(),
+                context: vec![
+                    "while flushing".to_string(),
+                    "cannot write entry".to_string(),
+                ],
+                category: "io.writing".to_string(),
+                code: 3004,
+            }
+        );
+
+        let error = Err::<(), _>(io::Error::from(ErrorKind::NotFound))
+            .context(IoError::ReadingError, "cannot read data file")
+            .unwrap_err();
+        assert_eq!(error.to_status("failed").code(), Code::Internal);
+    }
+
+    #[test]
+    fn test_request_error_status() {
+        // The context of request errors is meant for clients.
+        let status = Status::from(
+            Error::from(RequestError::InvalidArgument)
+                .with_context("tag is empty"),
+        );
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(status.message(), "tag is empty");
+
+        let status = Status::from(
+            Error::from(IoError::ReadingError).with_context("secret.bin"),
+        );
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(
+            status.message(),
+            ErrorType::from(IoError::ReadingError).to_string(),
+            "contexts of other errors stay on the server"
+        );
+    }
+}
@@ -16,9 +16,31 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! Errors of other libraries are wrapped with a context:
+//! ```rust
+//! use squid_error::{Error, IoError, ResultExt};
+//!
+//! fn read(path: &str) -> std::result::Result<String, Error> {
+//!     std::fs::read_to_string(path).with_context(IoError::ReadingError, || {
+//!         format!("cannot read {}", path)
+//!     })
+//! }
+//!
+//! let error = read("missing.txt").unwrap_err();
+//! assert_eq!(error.context.as_deref(), Some("cannot read missing.txt"));
+//! ```
 
+mod context;
+mod convert;
+#[cfg(feature = "grpc")]
+mod grpc;
+
+pub use context::ResultExt;
+#[cfg(feature = "grpc")]
+pub use grpc::{ErrorDetails, CATEGORY_METADATA_KEY};
 use std::error::Error as StdError;
-use std::fmt;
+use thiserror::Error;
 
 /// Boxed error to bypass specific [Error](StdError).
 type BError = Box<dyn StdError + Send + Sync>;
@@ -26,11 +48,14 @@ type BError = Box<dyn StdError + Send + Sync>;
 pub type Result<T> = core::result::Result<T, BError>;
 
 /// The struct that represents an error
-#[derive(Debug)]
+#[derive(Debug, Error)]
+#[error("{etype}")]
+#[non_exhaustive]
 pub struct Error {
     /// The error type.
     pub etype: ErrorType,
-    /// The cause of this error.
+    /// The cause of this error, returned as its [source](StdError::source).
+    #[source]
     pub cause: Option<BError>,
     /// Explains the context in which the error occurs.
     pub context: Option<String>,
@@ -49,84 +74,219 @@ impl Error {
             context,
         }
     }
+
+    /// Sets the cause of the error.
+    pub fn with_cause(mut self, cause: impl Into<BError>) -> Self {
+        self.cause = Some(cause.into());
+        self
+    }
+
+    /// Sets the context in which the error occurs.
+    pub fn with_context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
 }
-impl fmt::Display for Error {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self.etype)
+
+impl From<ErrorType> for Error {
+    fn from(etype: ErrorType) -> Self {
+        Error::new(etype, None, None)
+    }
+}
+
+impl From<DatabaseError> for Error {
+    fn from(error: DatabaseError) -> Self {
+        Error::from(ErrorType::Database(error))
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(error: IoError) -> Self {
+        Error::from(ErrorType::InputOutput(error))
+    }
+}
+
+impl From<RequestError> for Error {
+    fn from(error: RequestError) -> Self {
+        Error::from(ErrorType::Request(error))
     }
 }
-impl StdError for Error {}
 
 /// Errors in Squid.
-#[derive(Debug)]
+#[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum ErrorType {
     /// Generic error that returns no additional information.
+    #[error("An error has occurred, but no further information is provided.")]
     Unspecified,
     /// Errors related to `squid-db`.
-    Database(DatabaseError),
+    #[error(transparent)]
+    Database(#[from] DatabaseError),
     /// IO errors, especially due to std::fs.
-    InputOutput(IoError),
+    #[error(transparent)]
+    InputOutput(#[from] IoError),
+    /// Requests which cannot be fulfilled, such as invalid sentences.
+    #[error(transparent)]
+    Request(#[from] RequestError),
 }
 
-impl fmt::Display for ErrorType {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl ErrorType {
+    /// Returns the stable numeric code of the error type, which clients and
+    /// monitoring can rely on across releases.
+    ///
+    /// Codes are grouped by kind:
+    /// - `1000`: [`ErrorType::Unspecified`];
+    /// - `2000` to `2999`: [`DatabaseError`];
+    /// - `3000` to `3999`: [`IoError`];
+    /// - `4000` to `4999`: [`RequestError`].
+    ///
+    /// A code is never reused, even once its variant is removed.
+    pub fn code(&self) -> u16 {
         match self {
-            ErrorType::Unspecified => {
-                write!(f, "An error has occurred, but no further information is provided.")
+            ErrorType::Unspecified => 1000,
+            ErrorType::Database(error) => error.code(),
+            ErrorType::InputOutput(error) => error.code(),
+            ErrorType::Request(error) => error.code(),
+        }
+    }
+
+    /// Returns a stable name of the error type, such as `io.writing`, which
+    /// clients can rely on.
+    pub fn category(&self) -> &'static str {
+        match self {
+            ErrorType::Unspecified => "unspecified",
+            ErrorType::Database(DatabaseError::FailedCompression) => {
+                "database.failed_compression"
+            },
+            ErrorType::InputOutput(error) => match error {
+                IoError::DeserializationError => "io.deserialization",
+                IoError::SerializationError => "io.serialization",
+                IoError::ReadingError => "io.reading",
+                IoError::WritingError => "io.writing",
+            },
+            ErrorType::Request(error) => match error {
+                RequestError::InvalidArgument => "request.invalid_argument",
+                RequestError::NotFound => "request.not_found",
+                RequestError::ResourceExhausted => {
+                    "request.resource_exhausted"
+                },
+                RequestError::Unavailable => "request.unavailable",
             },
-            ErrorType::Database(error) => write!(f, "{:?}", error),
-            ErrorType::InputOutput(error) => write!(f, "{:?}", error),
         }
     }
 }
-impl StdError for ErrorType {}
 
 /// Errors related to `squid-db`.
-#[derive(Debug)]
+///
+/// Each variant is numbered with its [code](DatabaseError::code).
+#[derive(Debug, Clone, Copy, Error)]
+#[non_exhaustive]
+#[repr(u16)]
 pub enum DatabaseError {
     /// File compression failed.
-    FailedCompression,
+    #[error("File compression failed.")]
+    FailedCompression = 2001,
 }
 
-impl fmt::Display for DatabaseError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            DatabaseError::FailedCompression => {
-                write!(f, "File compression failed.")
-            },
-        }
+impl DatabaseError {
+    /// Returns the stable numeric code of the error, between `2000` and
+    /// `2999`.
+    pub fn code(&self) -> u16 {
+        *self as u16
     }
 }
-impl StdError for DatabaseError {}
 
 /// Errors related to [`std`].
-#[derive(Debug)]
+///
+/// Each variant is numbered with its [code](IoError::code).
+#[derive(Debug, Clone, Copy, Error)]
+#[non_exhaustive]
+#[repr(u16)]
 pub enum IoError {
     /// Deserialization failed.
-    DeserializationError,
+    #[error("Deserialization failed.")]
+    DeserializationError = 3001,
     /// Serialization failed.
-    SerializationError,
+    #[error("Serialization failed.")]
+    SerializationError = 3002,
     /// Data are corrupted or not in the correct format (UTF-8).
-    ReadingError,
+    #[error("Data are corrupted or not in the correct format (UTF-8).")]
+    ReadingError = 3003,
     /// Failed unwrap Rwlock or Mutex for writing.
-    WritingError,
+    #[error("Failed unwrap Rwlock or Mutex for writing.")]
+    WritingError = 3004,
 }
 
-impl fmt::Display for IoError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            IoError::DeserializationError => {
-                write!(f, "Deserialization failed.")
-            },
-            IoError::SerializationError => write!(f, "Serialization failed."),
-            IoError::ReadingError => write!(
-                f,
-                "Data are corrupted or not in the correct format (UTF-8)."
-            ),
-            IoError::WritingError => {
-                write!(f, "Failed unwrap Rwlock or Mutex for writing.")
-            },
-        }
+impl IoError {
+    /// Returns the stable numeric code of the error, between `3000` and
+    /// `3999`.
+    pub fn code(&self) -> u16 {
+        *self as u16
+    }
+}
+
+/// Errors caused by a request rather than by Squid.
+///
+/// The [context](Error::context) of these errors tells the client what is
+/// wrong, and is sent back as is.
+///
+/// Each variant is numbered with its [code](RequestError::code).
+#[derive(Debug, Clone, Copy, Error)]
+#[non_exhaustive]
+#[repr(u16)]
+pub enum RequestError {
+    /// The request is invalid, such as an empty tag.
+    #[error("The request is invalid.")]
+    InvalidArgument = 4001,
+    /// The request targets something which does not exist.
+    #[error("The requested resource does not exist.")]
+    NotFound = 4002,
+    /// The request exceeds a quota.
+    #[error("A quota is exhausted.")]
+    ResourceExhausted = 4003,
+    /// The request cannot be served by this server right now, such as a
+    /// change sent to a node which is not the leader.
+    #[error("The request cannot be served now.")]
+    Unavailable = 4004,
+}
+
+impl RequestError {
+    /// Returns the stable numeric code of the error, between `4000` and
+    /// `4999`.
+    pub fn code(&self) -> u16 {
+        *self as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_code() {
+        let etypes = [
+            ErrorType::Unspecified,
+            DatabaseError::FailedCompression.into(),
+            IoError::DeserializationError.into(),
+            IoError::SerializationError.into(),
+            IoError::ReadingError.into(),
+            IoError::WritingError.into(),
+            RequestError::InvalidArgument.into(),
+            RequestError::NotFound.into(),
+            RequestError::ResourceExhausted.into(),
+            RequestError::Unavailable.into(),
+        ];
+
+        // Codes are sent to clients, so they never change.
+        let codes = etypes.iter().map(ErrorType::code).collect::<Vec<_>>();
+        assert_eq!(
+            codes,
+            [1000, 2001, 3001, 3002, 3003, 3004, 4001, 4002, 4003, 4004]
+        );
+
+        let categories =
+            etypes.iter().map(ErrorType::category).collect::<HashSet<_>>();
+        assert_eq!(categories.len(), etypes.len());
     }
 }
-impl StdError for IoError {}
@@ -0,0 +1,88 @@
+//! guesses the language in which a sentence is written.
+
+/// Most frequent words of each supported language.
+/// They are rarely shared between languages, which is enough to tell short
+/// sentences apart.
+const LANGUAGES: [(&str, &[&str]); 6] = [
+    (
+        "de",
+        &[
+            "der", "die", "das", "und", "ist", "nicht", "ich", "ein", "eine",
+            "zu", "mit", "auf", "für", "sich", "den", "von", "wir", "sie",
+        ],
+    ),
+    (
+        "en",
+        &[
+            "the", "and", "is", "are", "of", "to", "in", "that", "it", "with",
+            "for", "this", "was", "you", "have", "not", "but", "be",
+        ],
+    ),
+    (
+        "es",
+        &[
+            "el", "los", "las", "y", "es", "que", "del", "por", "con", "una",
+            "para", "como", "pero", "muy", "está", "yo", "su", "se",
+        ],
+    ),
+    (
+        "fr",
+        &[
+            "le", "la", "les", "et", "est", "des", "du", "un", "une", "que",
+            "pour", "dans", "pas", "sur", "avec", "je", "nous", "vous", "ce",
+        ],
+    ),
+    (
+        "it",
+        &[
+            "il", "lo", "gli", "e", "è", "di", "che", "non", "per", "una",
+            "sono", "con", "della", "anche", "ma", "mi", "come", "questo",
+        ],
+    ),
+    (
+        "pt",
+        &[
+            "o", "os", "as", "e", "é", "de", "que", "não", "em", "um", "uma",
+            "para", "com", "do", "da", "mas", "eu", "você",
+        ],
+    ),
+];
+
+/// Detects the language of a raw sentence, before tokenization.
+///
+/// Returns the ISO 639-1 code of the language sharing the most frequent
+/// words with the sentence, or `None` if no language stands out.
+///
+/// # Examples
+/// ```rust
+/// use squid_tokenizer::lang::detect;
+///
+/// assert_eq!(detect("Le chat est sur la table"), Some("fr"));
+/// assert_eq!(detect("Gravitalia"), None);
+/// ```
+pub fn detect(sentence: &str) -> Option<&'static str> {
+    let words = sentence
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>();
+
+    let mut scores = LANGUAGES
+        .iter()
+        .map(|(lang, frequent)| {
+            (
+                *lang,
+                words
+                    .iter()
+                    .filter(|word| frequent.contains(&word.as_str()))
+                    .count(),
+            )
+        })
+        .collect::<Vec<_>>();
+    scores.sort_by_key(|(_, score)| std::cmp::Reverse(*score));
+
+    match scores.as_slice() {
+        [(lang, best), (_, second), ..] if *best > *second => Some(lang),
+        _ => None,
+    }
+}
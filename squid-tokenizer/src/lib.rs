@@ -1,17 +1,42 @@
+pub mod lang;
 pub mod stopwords;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-use std::{collections::HashSet, convert::Infallible, path::Path};
+use std::{collections::HashSet, convert::Infallible};
 
 /// Lowercase words, remove punctuation, separate words into tokens and convert them into numbers.
+///
+/// Stop words are read from `./stopwords` with the `fs` feature.
 pub fn tokenize<T: ToString>(text: T) -> Result<String, Infallible> {
-    stopwords::init(Path::new("./stopwords").to_path_buf());
+    #[cfg(feature = "fs")]
+    stopwords::init(std::path::Path::new("./stopwords").to_path_buf());
 
+    tokenize_with(text, stopwords::remove_words_from_sentence)
+}
+
+/// Same as [`tokenize`], but removes the given stop words instead of the
+/// ones read from `./stopwords`.
+pub fn tokenize_with_stopwords<T: ToString>(
+    text: T,
+    stop_words: &[String],
+) -> Result<String, Infallible> {
+    tokenize_with(text, |sentence| {
+        stopwords::remove_from_sentence(sentence, stop_words)
+    })
+}
+
+fn tokenize_with<T, F>(text: T, remove_stop_words: F) -> Result<String, Infallible>
+where
+    T: ToString,
+    F: FnOnce(String) -> String,
+{
     let punctuation: HashSet<char> = ['!', ',', '.', ':', ';', '?', '-', '\"', '(', ')']
         .iter()
         .cloned()
         .collect();
 
-    let result_string: String = stopwords::remove_words_from_sentence(
+    let result_string: String = remove_stop_words(
         text.to_string()
             .replace('\'', " ")
             .to_lowercase()
@@ -1,35 +1,94 @@
 //! filters unnecessary words and removes it from sentences.
 
+#[cfg(feature = "fs")]
 use std::{
     fs::OpenOptions,
     io::{BufRead, BufReader},
-    path::PathBuf,
-    sync::OnceLock,
+    path::{Path, PathBuf},
 };
+use std::sync::OnceLock;
 
 static STOP_WORDS: OnceLock<Vec<String>> = OnceLock::new();
 
 /// Inits `STOP_WORDS` by adding every lines from a text file
 /// to the cache.
+#[cfg(feature = "fs")]
 pub fn init(path: PathBuf) {
-    STOP_WORDS.get_or_init(|| {
-        if let Ok(file) = OpenOptions::new().read(true).open(path) {
-            let reader = BufReader::new(&file);
-
-            let mut words: Vec<String> = vec![];
-            for word in reader.lines().map_while(Result::ok) {
-                words.push(word)
-            }
-
-            words
-        } else {
-            Vec::default()
+    STOP_WORDS.get_or_init(|| load(&path));
+}
+
+/// Reads every lines from a text file.
+/// Returns an empty list if the file cannot be opened.
+#[cfg(feature = "fs")]
+pub fn load(path: &Path) -> Vec<String> {
+    if let Ok(file) = OpenOptions::new().read(true).open(path) {
+        let reader = BufReader::new(&file);
+
+        let mut words: Vec<String> = vec![];
+        for word in reader.lines().map_while(Result::ok) {
+            words.push(word)
         }
-    });
+
+        words
+    } else {
+        Vec::default()
+    }
+}
+
+/// URL of the stop words of a language, maintained by the stopwords-iso
+/// project.
+#[cfg(feature = "fetch")]
+const STOPWORDS_URL: &str =
+    "https://raw.githubusercontent.com/stopwords-iso/stopwords-{lang}/master/stopwords-{lang}.txt";
+
+/// Downloads the stop words of a language into a file, one per line, to be
+/// read with [`init`] or [`load`].
+///
+/// `lang` is an ISO 639-1 code, such as `fr`. Nothing is downloaded if the
+/// file already contains words, so it can be called on each start.
+///
+/// # Example
+/// ```no_run,rust
+/// use std::path::Path;
+/// use squid_tokenizer::stopwords::{download, load};
+///
+/// download("fr", Path::new("./stopwords")).unwrap();
+/// assert!(load(Path::new("./stopwords")).contains(&"les".to_string()));
+/// ```
+#[cfg(feature = "fetch")]
+pub fn download(lang: &str, dest: &Path) -> std::io::Result<()> {
+    use std::io::{Error, ErrorKind, Read};
+
+    if !load(dest).is_empty() {
+        return Ok(());
+    }
+
+    if lang.len() != 2 || !lang.bytes().all(|c| c.is_ascii_lowercase()) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{:?} is not an ISO 639-1 code", lang),
+        ));
+    }
+
+    let mut words = String::new();
+    ureq::get(&STOPWORDS_URL.replace("{lang}", lang))
+        .call()
+        .map_err(Error::other)?
+        .into_reader()
+        .read_to_string(&mut words)?;
+
+    // Written aside then renamed, so a failed download never leaves a
+    // partial list.
+    let partial = dest.with_extension("part");
+    std::fs::write(&partial, words)?;
+    std::fs::rename(partial, dest)
 }
 
 /// Removes every stop words from a sentence.
 ///
+/// Without the `fs` feature, no stop word is loaded, so the sentence is
+/// returned unchanged.
+///
 /// # Example
 /// ```no_run,rust
 /// use std::{fs::File, io::prelude::*, path::Path};
@@ -51,8 +110,12 @@ pub fn init(path: PathBuf) {
 /// assert_eq!(remove_words_from_sentence(sentence), "Hans".to_string());
 /// ```
 pub fn remove_words_from_sentence(sentence: String) -> String {
-    let stop_words = STOP_WORDS.get_or_init(Vec::default);
+    remove_from_sentence(sentence, STOP_WORDS.get_or_init(Vec::default))
+}
 
+/// Removes the given stop words from a sentence, rather than the ones
+/// loaded by [`init`].
+pub fn remove_from_sentence(sentence: String, stop_words: &[String]) -> String {
     sentence
         .split_whitespace()
         .filter(|word| !stop_words.contains(&word.to_lowercase()))
@@ -0,0 +1,16 @@
+//! JavaScript bindings, so sentences can be tokenized in a browser the
+//! same way as by Squid.
+
+use wasm_bindgen::prelude::*;
+
+/// Tokenizes a sentence, removing the given stop words.
+#[wasm_bindgen]
+pub fn tokenize(text: &str, stop_words: Vec<String>) -> String {
+    crate::tokenize_with_stopwords(text, &stop_words).unwrap_or_default()
+}
+
+/// Detects the language of a sentence, such as `fr` or `en`.
+#[wasm_bindgen]
+pub fn detect(sentence: &str) -> Option<String> {
+    crate::lang::detect(sentence).map(str::to_string)
+}
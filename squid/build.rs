@@ -1,4 +1,7 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::compile_protos("proto/squid.proto")?;
+    tonic_build::configure().compile_protos(
+        &["proto/squid.proto", "proto/squid_v2.proto"],
+        &["proto"],
+    )?;
     Ok(())
 }
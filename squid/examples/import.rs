@@ -0,0 +1,47 @@
+use squid::squid_client::SquidClient;
+use squid::{ImportFormat, ImportRequest};
+
+pub mod squid {
+    tonic::include_proto!("squid");
+}
+
+/// Size of each chunk sent to the server.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[tokio::main]
+async fn main() {
+    // Usage: cargo run --example import -- sentences.txt
+    let path = std::env::args()
+        .nth(1)
+        .expect("Usage: import <file>, one sentence per line");
+    let format = if path.ends_with(".jsonl") {
+        ImportFormat::Jsonl
+    } else {
+        ImportFormat::Lines
+    };
+    let content = std::fs::read(path).unwrap();
+
+    let chunks = content
+        .chunks(CHUNK_SIZE)
+        .map(|data| ImportRequest {
+            data: data.to_vec(),
+            format: format.into(),
+            ..Default::default()
+        })
+        .collect::<Vec<_>>();
+
+    let mut progress = SquidClient::connect("http://localhost:50051")
+        .await
+        .unwrap()
+        .import(tokio_stream::iter(chunks))
+        .await
+        .unwrap()
+        .into_inner();
+
+    while let Some(progress) = progress.message().await.unwrap() {
+        println!(
+            "Imported {} sentences, {} failed",
+            progress.imported, progress.failed
+        );
+    }
+}
@@ -17,7 +17,10 @@ async fn main() {
     let response = SquidClient::connect("http://localhost:50051")
         .await
         .unwrap()
-        .leaderboard(LeaderboardRequest { length: 10 })
+        .leaderboard(LeaderboardRequest {
+            length: 10,
+            ..Default::default()
+        })
         .await
         .unwrap()
         .into_inner();
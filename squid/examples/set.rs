@@ -40,6 +40,7 @@ async fn main() {
             .add(AddRequest {
                 sentence: sentence.to_string(),
                 lifetime: 10,
+                ..Default::default()
             })
             .await
             .unwrap()
@@ -0,0 +1,20 @@
+use squid::admin_client::AdminClient;
+use squid::Void;
+
+pub mod squid {
+    tonic::include_proto!("squid");
+}
+
+#[tokio::main]
+async fn main() {
+    let mut client = AdminClient::connect("http://localhost:50051")
+        .await
+        .unwrap();
+
+    // Write pending sentences to the disk before reading statistics.
+    client.flush(Void {}).await.unwrap();
+
+    let response = client.stats(Void {}).await.unwrap().into_inner();
+
+    println!("{:#?}", response);
+}
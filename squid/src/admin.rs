@@ -0,0 +1,426 @@
+//! Administration of the server and its services.
+
+use crate::{
+    helpers::{
+        self, database, namespace::Namespaces, raft::Consensus,
+        standby::Standby,
+    },
+    service::{
+        audit, clustered, exclusion_status, message_type, replicated, writable,
+    },
+    squid::{
+        admin_server::Admin, AppendEntriesReply, AppendEntriesRequest,
+        AuditEvent, AuditLogReply, AuditLogRequest, Change, Exclusions,
+        ExportChunk, ExportCorpusRequest, ExportFormat, GossipReply,
+        GossipRequest, HistogramBucket, HistogramReply, HistogramRequest,
+        KeyUsage, LoadProgressReply, ReplicateRequest, ResetCountersRequest,
+        StatsReply, Void, VoteReply, VoteRequest,
+    },
+};
+use squid_core::{
+    audit::{Audit, Event, Operation, Query},
+    cluster::Cluster,
+    models::config::{MessageType, Scope},
+};
+use std::{sync::Arc, time::Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+use tracing::error;
+
+/// Number of audit events returned if the request does not set it.
+const DEFAULT_AUDIT_LIMIT: usize = 100;
+
+/// Serves the Admin service.
+pub struct SuperAdmin {
+    pub namespaces: Arc<Namespaces>,
+    pub started_at: Instant,
+    /// Primary followed by this server until it is promoted, if any.
+    pub standby: Option<Arc<Standby>>,
+    /// Other nodes sharing their counts, if clustering is enabled.
+    pub cluster: Option<Arc<Cluster>>,
+    /// Other nodes agreeing on each change, if Raft is enabled.
+    pub consensus: Option<Arc<Consensus>>,
+    /// Log of the operations changing data, if enabled.
+    pub audit: Option<Arc<Audit>>,
+}
+
+#[tonic::async_trait]
+impl Admin for SuperAdmin {
+    async fn flush(
+        &self,
+        request: Request<Void>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        let actor = helpers::auth::name(&request);
+
+        for namespace in self.namespaces.iter() {
+            namespace.instance.write().await.flush().map_err(|error| {
+                error!("Failed to flush memtable: {}", error);
+                error.to_status("failed to flush memtable")
+            })?;
+        }
+
+        audit(&self.audit, Event::new(actor, Operation::Flush, "")).await;
+        Ok(Response::new(Void {}))
+    }
+
+    async fn compact(
+        &self,
+        request: Request<Void>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        let actor = helpers::auth::name(&request);
+
+        for namespace in self.namespaces.iter() {
+            namespace
+                .instance
+                .write()
+                .await
+                .compact()
+                .map_err(|error| {
+                    error!("Failed to compact database: {}", error);
+                    error.to_status("failed to compact database")
+                })?;
+        }
+
+        audit(&self.audit, Event::new(actor, Operation::Compact, "")).await;
+        Ok(Response::new(Void {}))
+    }
+
+    async fn stats(
+        &self,
+        request: Request<Void>,
+    ) -> Result<Response<StatsReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let mut reply = StatsReply {
+            uptime: self.started_at.elapsed().as_secs(),
+            ..Default::default()
+        };
+
+        for namespace in self.namespaces.iter() {
+            let stats = namespace.instance.read().await.stats().await.map_err(
+                |error| {
+                    error!("Failed to read database statistics: {}", error);
+                    error.to_status("failed to read database statistics")
+                },
+            )?;
+            let vocabulary = namespace
+                .counters
+                .algorithm
+                .read()
+                .await
+                .len(&MessageType::Anything);
+
+            reply.entries += stats.entries as u64;
+            reply.segments += stats.segments as u64;
+            reply.disk_bytes += stats.disk_bytes;
+            reply.memtable += stats.memtable as u64;
+            reply.vocabulary += vocabulary as u64;
+            reply.memory_bytes += namespace.counters.memory().await as u64;
+            reply.scheduled_expirations += stats.expirations as u64;
+            reply.pending_expirations += namespace.pending_expirations() as u64;
+            let distinct = namespace.counters.distinct.read().await;
+            reply.distinct_words += distinct.words() as u64;
+            reply.distinct_hashtags += distinct.hashtags() as u64;
+        }
+
+        reply.usage = self
+            .namespaces
+            .quotas()
+            .usage()
+            .into_iter()
+            .map(|(name, usage, quota)| KeyUsage {
+                name,
+                entries: usage.entries,
+                bytes: usage.bytes,
+                max_entries: quota.as_ref().and_then(|quota| quota.entries),
+                max_bytes: quota.and_then(|quota| quota.bytes),
+            })
+            .collect();
+
+        Ok(Response::new(reply))
+    }
+
+    async fn histogram(
+        &self,
+        request: Request<HistogramRequest>,
+    ) -> Result<Response<HistogramReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let histogram = namespace
+            .counters
+            .algorithm
+            .read()
+            .await
+            .histogram(&message_type(data.kind()));
+
+        Ok(Response::new(HistogramReply {
+            buckets: histogram
+                .into_iter()
+                .enumerate()
+                .map(|(index, words)| {
+                    // The bucket of index `i` holds counts up to `10^i`.
+                    let max = 10u64.saturating_pow(index as u32);
+                    HistogramBucket {
+                        min: if index == 0 { 1 } else { max / 10 + 1 },
+                        max,
+                        words: words as u64,
+                    }
+                })
+                .collect(),
+        }))
+    }
+
+    async fn add_exclusions(
+        &self,
+        request: Request<Exclusions>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        writable(&self.standby)?;
+
+        let actor = helpers::auth::name(&request);
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+
+        let words = namespace.tokenize_words(&data.words);
+        let event =
+            Event::new(actor, Operation::Exclude, &namespace.service.name)
+                .details(words.join(" "));
+
+        match &self.consensus {
+            Some(consensus) => consensus
+                .exclude(namespace, words, true)
+                .await
+                .map_err(exclusion_status)?,
+            None => {
+                database::exclude(&namespace.counters, words).await;
+                namespace.publish(helpers::replica::excluded(namespace).await);
+            },
+        }
+
+        audit(&self.audit, event).await;
+        Ok(Response::new(Void {}))
+    }
+
+    async fn remove_exclusions(
+        &self,
+        request: Request<Exclusions>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        writable(&self.standby)?;
+
+        let actor = helpers::auth::name(&request);
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+
+        let words = namespace.tokenize_words(&data.words);
+        let event =
+            Event::new(actor, Operation::Include, &namespace.service.name)
+                .details(words.join(" "));
+
+        match &self.consensus {
+            Some(consensus) => consensus
+                .exclude(namespace, words, false)
+                .await
+                .map_err(exclusion_status)?,
+            None => {
+                database::include(&namespace.counters, &words).await;
+                namespace.publish(helpers::replica::excluded(namespace).await);
+            },
+        }
+
+        audit(&self.audit, event).await;
+        Ok(Response::new(Void {}))
+    }
+
+    async fn reset_counters(
+        &self,
+        request: Request<ResetCountersRequest>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        writable(&self.standby)?;
+        if self.consensus.is_some() {
+            return Err(Status::failed_precondition(
+                "counters cannot be reset in a Raft group",
+            ));
+        }
+
+        let actor = helpers::auth::name(&request);
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+
+        database::reset(&namespace.counters).await;
+        audit(
+            &self.audit,
+            Event::new(actor, Operation::Reset, &namespace.service.name),
+        )
+        .await;
+
+        Ok(Response::new(Void {}))
+    }
+
+    type ReplicateStream = ReceiverStream<Result<Change, Status>>;
+
+    async fn replicate(
+        &self,
+        request: Request<ReplicateRequest>,
+    ) -> Result<Response<Self::ReplicateStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let data = request.into_inner();
+        let namespace = Arc::clone(self.namespaces.get(&data.namespace)?);
+
+        Ok(Response::new(helpers::replica::replicate(namespace)))
+    }
+
+    type ExportCorpusStream = ReceiverStream<Result<ExportChunk, Status>>;
+
+    async fn export_corpus(
+        &self,
+        request: Request<ExportCorpusRequest>,
+    ) -> Result<Response<Self::ExportCorpusStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let data = request.into_inner();
+        let namespace = Arc::clone(self.namespaces.get(&data.namespace)?);
+        if data.format() == ExportFormat::Parquet {
+            return Err(Status::invalid_argument(
+                "only leaderboards can be exported as Parquet files",
+            ));
+        }
+
+        Ok(Response::new(helpers::export::corpus(
+            namespace,
+            data.format(),
+        )))
+    }
+
+    async fn gossip(
+        &self,
+        request: Request<GossipRequest>,
+    ) -> Result<Response<GossipReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        let cluster = clustered(&self.cluster)?;
+
+        let resync = cluster.receive(request.into_inner().into()).await;
+
+        Ok(Response::new(GossipReply {
+            members: cluster.members().await,
+            resync,
+        }))
+    }
+
+    async fn request_vote(
+        &self,
+        request: Request<VoteRequest>,
+    ) -> Result<Response<VoteReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        let raft = replicated(&self.consensus)?;
+
+        let reply =
+            raft.vote(request.into_inner().into())
+                .await
+                .map_err(|error| {
+                    error!("Failed to vote: {}", error);
+                    error.to_status("failed to vote")
+                })?;
+
+        Ok(Response::new(reply.into()))
+    }
+
+    async fn append_entries(
+        &self,
+        request: Request<AppendEntriesRequest>,
+    ) -> Result<Response<AppendEntriesReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+        let raft = replicated(&self.consensus)?;
+
+        let request = request.into_inner().try_into().map_err(
+            |error: squid_error::Error| error.to_status("invalid entries"),
+        )?;
+        let reply = raft.append(request).await.map_err(|error| {
+            error!("Failed to append entries: {}", error);
+            error.to_status("failed to append entries")
+        })?;
+
+        Ok(Response::new(reply.into()))
+    }
+
+    async fn promote(
+        &self,
+        request: Request<Void>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let standby = self.standby.as_ref().ok_or_else(|| {
+            Status::failed_precondition("this server does not follow a primary")
+        })?;
+        if standby.promote() {
+            let actor = helpers::auth::name(&request);
+            audit(&self.audit, Event::new(actor, Operation::Promote, "")).await;
+        }
+
+        Ok(Response::new(Void {}))
+    }
+
+    type LoadProgressStream = ReceiverStream<Result<LoadProgressReply, Status>>;
+
+    async fn load_progress(
+        &self,
+        request: Request<Void>,
+    ) -> Result<Response<Self::LoadProgressStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        Ok(Response::new(helpers::health::progress()))
+    }
+
+    async fn audit_log(
+        &self,
+        request: Request<AuditLogRequest>,
+    ) -> Result<Response<AuditLogReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Admin)?;
+
+        let audit = self.audit.as_ref().ok_or_else(|| {
+            Status::failed_precondition("the audit log is disabled")
+        })?;
+        let data = request.into_inner();
+        let query = Query {
+            from: Some(data.from).filter(|from| *from > 0),
+            to: Some(data.to).filter(|to| *to > 0),
+            actor: Some(data.actor).filter(|actor| !actor.is_empty()),
+            operation: Some(data.operation)
+                .filter(|operation| !operation.is_empty())
+                .map(|operation| operation.parse::<Operation>())
+                .transpose()
+                .map_err(Status::from)?,
+            namespace: Some(data.namespace)
+                .filter(|namespace| !namespace.is_empty()),
+            limit: match data.limit {
+                0 => DEFAULT_AUDIT_LIMIT,
+                limit => limit as usize,
+            },
+        };
+
+        let events = audit.query(&query).await.map_err(|error| {
+            error!("Failed to read audit log: {}", error);
+            error.to_status("failed to read audit log")
+        })?;
+
+        Ok(Response::new(AuditLogReply {
+            events: events
+                .into_iter()
+                .map(|event| AuditEvent {
+                    at: event.at,
+                    actor: event.actor,
+                    operation: event.operation.to_string(),
+                    namespace: event.namespace,
+                    target: event.target,
+                    details: event.details,
+                })
+                .collect(),
+        }))
+    }
+}
@@ -0,0 +1,181 @@
+use crate::models::config::{ApiKey, Scope};
+use std::{collections::HashMap, sync::Arc};
+use tonic::{service::Interceptor, Request, Status};
+
+/// Name of the metadata containing the API key.
+const AUTHORIZATION: &str = "authorization";
+/// Optional prefix before the API key.
+const BEARER: &str = "Bearer ";
+
+/// What the caller of a request is allowed to do.
+/// Inserted into the request extensions by [`Authenticator`].
+#[derive(Debug, Clone)]
+pub struct Grant {
+    /// API key used to authenticate, if authentication is enabled.
+    pub key: Option<String>,
+    /// Operations allowed to the caller.
+    pub scopes: Vec<Scope>,
+    /// Name of the API key, recorded on the sentences it adds.
+    pub name: Option<String>,
+}
+
+/// Interceptor validating the `authorization` metadata against the
+/// configured API keys.
+#[derive(Debug, Clone, Default)]
+pub struct Authenticator {
+    keys: Arc<HashMap<String, ApiKey>>,
+}
+
+impl Authenticator {
+    /// Creates an [`Authenticator`] accepting the given keys.
+    ///
+    /// If there are no keys, every request is allowed.
+    pub fn new(keys: &[ApiKey]) -> Self {
+        Self {
+            keys: Arc::new(
+                keys.iter().map(|key| (key.key.clone(), key.clone())).collect(),
+            ),
+        }
+    }
+}
+
+impl Authenticator {
+    /// Returns what the caller is allowed to do from the value of its
+    /// `authorization` header, such as for HTTP requests.
+    pub fn grant(&self, authorization: Option<&str>) -> Result<Grant, Status> {
+        if self.keys.is_empty() {
+            return Ok(Grant {
+                key: None,
+                scopes: vec![Scope::Read, Scope::Write, Scope::Admin],
+                name: None,
+            });
+        }
+
+        let key = authorization
+            .map(|value| value.strip_prefix(BEARER).unwrap_or(value))
+            .map(str::to_string)
+            .ok_or_else(|| {
+                Status::unauthenticated("missing `authorization` header")
+            })?;
+        let api_key = self
+            .keys
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| Status::unauthenticated("invalid API key"))?;
+
+        Ok(Grant {
+            key: Some(key),
+            scopes: api_key.scopes,
+            name: api_key.name,
+        })
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let grant = self.grant(
+            request
+                .metadata()
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok()),
+        )?;
+
+        request.extensions_mut().insert(grant);
+        Ok(request)
+    }
+}
+
+/// Returns the name of the API key used for a request, if any.
+pub fn name<T>(request: &Request<T>) -> Option<String> {
+    request
+        .extensions()
+        .get::<Grant>()
+        .and_then(|grant| grant.name.clone())
+}
+
+/// Checks that the caller of a request has been granted a scope.
+pub fn authorize<T>(request: &Request<T>, scope: Scope) -> Result<(), Status> {
+    permit(request.extensions().get::<Grant>(), scope)
+}
+
+/// Checks that a grant, if any, includes a scope.
+pub fn permit(grant: Option<&Grant>, scope: Scope) -> Result<(), Status> {
+    match grant {
+        Some(grant) if grant.scopes.contains(&scope) => Ok(()),
+        Some(_) => Err(Status::permission_denied(format!(
+            "API key is missing the {:?} scope",
+            scope
+        ))),
+        None => Err(Status::unauthenticated("request is not authenticated")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(&[ApiKey {
+            key: "reader".to_string(),
+            scopes: vec![Scope::Read],
+            name: Some("dashboard".to_string()),
+            quota: None,
+        }])
+    }
+
+    /// Sends a request with an `authorization` header, if any.
+    fn call(authorization: Option<&str>) -> Result<Request<()>, Status> {
+        let mut request = Request::new(());
+        if let Some(value) = authorization {
+            request
+                .metadata_mut()
+                .insert(AUTHORIZATION, value.parse().unwrap());
+        }
+
+        authenticator().call(request)
+    }
+
+    #[test]
+    fn test_missing_header() {
+        let status = call(None).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "missing `authorization` header");
+
+        // Every request is allowed without keys.
+        let grant = Authenticator::default().grant(None).unwrap();
+        assert_eq!(grant.scopes, [Scope::Read, Scope::Write, Scope::Admin]);
+    }
+
+    #[test]
+    fn test_malformed_header() {
+        for value in ["Bearer Bearer reader", "Basic reader", "Bearerreader"] {
+            let status = call(Some(value)).unwrap_err();
+            assert_eq!(status.code(), Code::Unauthenticated);
+        }
+
+        assert!(call(Some("Bearer reader")).is_ok());
+        assert!(call(Some("reader")).is_ok());
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let status = call(Some("Bearer writer")).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+        assert_eq!(status.message(), "invalid API key");
+    }
+
+    #[test]
+    fn test_scoped_key() {
+        let request = call(Some("Bearer reader")).unwrap();
+        assert_eq!(name(&request).as_deref(), Some("dashboard"));
+        assert!(authorize(&request, Scope::Read).is_ok());
+
+        let status = authorize(&request, Scope::Write).unwrap_err();
+        assert_eq!(status.code(), Code::PermissionDenied);
+
+        // Requests which did not go through the interceptor are rejected.
+        let status = authorize(&Request::new(()), Scope::Read).unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{
+    helpers::{database, namespace::Namespace},
+    models::config::MessageType,
+    squid::{ranking_event::Kind, RankingEvent, RankingEvents},
+};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+
+/// Minimum delay between two checks of a watched leaderboard.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A word whose rank or number of occurrences changed.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Change {
+    /// Position of the word in the leaderboard, starting at 1.
+    pub rank: usize,
+    pub word: String,
+    pub occurence: usize,
+}
+
+/// Differences between two leaderboards.
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct Delta {
+    /// Words that entered the leaderboard, moved or changed count.
+    pub changed: Vec<Change>,
+    /// Words that left the leaderboard.
+    pub left: Vec<String>,
+}
+
+impl Delta {
+    /// Returns `true` if both leaderboards are identical.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.left.is_empty()
+    }
+}
+
+/// Computes what changed between two leaderboards.
+pub fn diff(previous: &[(String, usize)], current: &[(String, usize)]) -> Delta {
+    let changed = current
+        .iter()
+        .enumerate()
+        .filter(|(index, entry)| previous.get(*index) != Some(entry))
+        .map(|(index, (word, occurence))| Change {
+            rank: index + 1,
+            word: word.clone(),
+            occurence: *occurence,
+        })
+        .collect();
+
+    let left = previous
+        .iter()
+        .filter(|(word, _)| !current.iter().any(|(other, _)| other == word))
+        .map(|(word, _)| word.clone())
+        .collect();
+
+    Delta { changed, left }
+}
+
+/// Computes which words entered, left or moved between two leaderboards.
+///
+/// Words whose number of occurrences changed without moving are ignored.
+pub fn events(
+    previous: &[(String, usize)],
+    current: &[(String, usize)],
+) -> Vec<RankingEvent> {
+    let ranks = |ranking: &[(String, usize)]| {
+        ranking
+            .iter()
+            .enumerate()
+            .map(|(index, (word, _))| (word.clone(), index as u32 + 1))
+            .collect::<HashMap<_, _>>()
+    };
+    let (before, after) = (ranks(previous), ranks(current));
+
+    let mut events = current
+        .iter()
+        .filter_map(|(word, _)| {
+            let rank = after[word];
+            let kind = match before.get(word) {
+                None => Kind::Entered,
+                Some(previous_rank) if *previous_rank != rank => Kind::Moved,
+                Some(_) => return None,
+            };
+
+            Some(RankingEvent {
+                kind: kind.into(),
+                word: word.replace("%20", " "),
+                previous_rank: before.get(word).copied().unwrap_or_default(),
+                rank,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    events.extend(
+        previous
+            .iter()
+            .filter(|(word, _)| !after.contains_key(word))
+            .map(|(word, _)| RankingEvent {
+                kind: Kind::Left.into(),
+                word: word.replace("%20", " "),
+                previous_rank: before[word],
+                rank: 0,
+            }),
+    );
+
+    events
+}
+
+/// Streams the words entering, leaving or moving in the leaderboard.
+pub fn watch(
+    namespace: Arc<Namespace>,
+    lang: Option<String>,
+    kind: MessageType,
+    length: usize,
+) -> ReceiverStream<Result<RankingEvents, Status>> {
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        let counters = &namespace.counters;
+        let mut changes = counters.changes.subscribe();
+        let (mut previous, _) =
+            database::rank(counters, lang.as_deref().into(), &kind, 0, length).await;
+
+        while changes.changed().await.is_ok() {
+            let (current, _) =
+                database::rank(counters, lang.as_deref().into(), &kind, 0, length)
+                    .await;
+
+            let events = events(&previous, &current);
+            if !events.is_empty() {
+                if tx.send(Ok(RankingEvents { events })).await.is_err() {
+                    return;
+                }
+                previous = current;
+            }
+
+            tokio::time::sleep(WATCH_INTERVAL).await;
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
@@ -0,0 +1,127 @@
+use crate::{
+    helpers::namespace::Namespaces,
+    squid::{
+        admin_client::AdminClient, GossipReply, GossipRequest, ServiceCounts,
+    },
+};
+use squid_core::cluster::{Cluster, Gossip};
+use std::{collections::HashMap, sync::Arc};
+use tokio::time::MissedTickBehavior;
+use tonic::{
+    transport::{Channel, Endpoint},
+    Request, Status,
+};
+use tracing::{info, warn};
+
+impl From<Gossip> for GossipRequest {
+    fn from(gossip: Gossip) -> Self {
+        GossipRequest {
+            node: gossip.node,
+            full: gossip.full,
+            members: gossip.members,
+            services: gossip
+                .counts
+                .into_iter()
+                .map(|(namespace, counts)| ServiceCounts { namespace, counts })
+                .collect(),
+        }
+    }
+}
+
+impl From<GossipRequest> for Gossip {
+    fn from(request: GossipRequest) -> Self {
+        Gossip {
+            node: request.node,
+            full: request.full,
+            members: request.members,
+            counts: request
+                .services
+                .into_iter()
+                .map(|service| (service.namespace, service.counts))
+                .collect(),
+        }
+    }
+}
+
+/// Sends the counts of this node to every other node of the cluster at
+/// each interval.
+pub async fn gossip(cluster: Arc<Cluster>, namespaces: Arc<Namespaces>) {
+    // Connections are reused between exchanges.
+    let mut channels: HashMap<String, Channel> = HashMap::new();
+    let mut interval = tokio::time::interval(cluster.interval);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        interval.tick().await;
+        cluster.prune().await;
+
+        let local = cluster.local(&namespaces).await;
+        let members = cluster.members().await;
+        for member in &members {
+            let channel = match channels.get(member) {
+                Some(channel) => channel.clone(),
+                None => match Endpoint::from_shared(member.clone()) {
+                    Ok(endpoint) => {
+                        let channel =
+                            endpoint.timeout(cluster.interval).connect_lazy();
+                        channels.insert(member.clone(), channel.clone());
+                        channel
+                    },
+                    Err(error) => {
+                        if cluster.failed(member).await == 1 {
+                            warn!(
+                                "Invalid address of node {}: {}",
+                                member, error
+                            );
+                        }
+                        continue;
+                    },
+                },
+            };
+
+            let gossip = cluster.gossip(member, &local).await;
+            match exchange(channel, cluster.api_key.as_deref(), gossip).await {
+                Ok(reply) => {
+                    cluster.discover(reply.members).await;
+                    if cluster.sent(member, local.clone(), reply.resync).await
+                        > 0
+                    {
+                        info!("Node {} is reachable again.", member);
+                    }
+                },
+                Err(status) => {
+                    if cluster.failed(member).await == 1 {
+                        warn!(
+                            "Failed to send counts to node {}: {}",
+                            member,
+                            status.message()
+                        );
+                    }
+                },
+            }
+        }
+
+        channels.retain(|address, _| members.contains(address));
+    }
+}
+
+/// Sends counts to a node, and returns the nodes it knows.
+async fn exchange(
+    channel: Channel,
+    api_key: Option<&str>,
+    gossip: Gossip,
+) -> Result<GossipReply, Status> {
+    let mut request = Request::new(GossipRequest::from(gossip));
+    if let Some(key) = api_key {
+        request.metadata_mut().insert(
+            "authorization",
+            key.parse()
+                .map_err(|_| Status::invalid_argument("invalid API key"))?,
+        );
+    }
+
+    Ok(AdminClient::new(channel)
+        .gossip(request)
+        .await?
+        .into_inner())
+}
@@ -1,28 +1,130 @@
 use crate::models::config::Config;
-use std::fs::File;
+use serde::de::DeserializeOwned;
+use std::{
+    env,
+    fs::File,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+use tracing::warn;
 
 /// The name of the configuration file.
 const FILE_NAME: &str = "config.yaml";
+/// Prefix of the environment variables overriding the configuration.
+const ENV_PREFIX: &str = "SQUID_";
 
 /// Reads the configuration file and returns the parsed configuration.
 ///
-/// This function opens the configuration file named `config.yaml` and attempts
-/// to deserialize its contents into a `Config` struct. If the file cannot be found
-/// or its contents cannot be properly deserialized, the function will panic.
+/// This function opens the configuration file named `config.yaml`, or the
+/// one specified by `SQUID_CONFIG`, and attempts to deserialize its contents
+/// into a `Config` struct. If the file cannot be found, the default
+/// configuration is used.
+///
+/// Then, each value can be overridden by an environment variable:
+/// - `SQUID_PORT`;
+/// - `SQUID_BIND`, as a comma-separated list;
+/// - `SQUID_HTTP_PORT`;
+/// - `SQUID_RESP_PORT`;
+/// - `SQUID_DATA_DIR`;
+/// - `SQUID_SERVICE_NAME`;
+/// - `SQUID_ALGORITHM`;
+/// - `SQUID_MESSAGE_TYPE`;
+/// - `SQUID_LANG`;
+/// - `SQUID_EXCLUDE`, as a comma-separated list.
 ///
 /// # Panics
 ///
-/// This function may panic if the `config.yaml` file cannot be found or if its
-/// contents cannot be deserialized into a `Config` struct.
+/// This function may panic if the file specified by `SQUID_CONFIG` cannot be
+/// found, if the contents of the file cannot be deserialized into a `Config`
+/// struct or if an environment variable has an invalid value.
 ///
 /// # Returns
 ///
 /// The parsed `Config` struct representing the configuration from the file.
 pub fn read() -> Config {
-    let config: Config = serde_yaml::from_reader(
-        File::open(FILE_NAME).expect("Failed to open config.yaml file"),
-    )
-    .expect("Failed to deserialize config.yaml contents");
+    let mut config: Config = match env::var(format!("{}CONFIG", ENV_PREFIX)) {
+        Ok(path) => serde_yaml::from_reader(
+            File::open(&path)
+                .unwrap_or_else(|_| panic!("Failed to open {} file", path)),
+        )
+        .unwrap_or_else(|_| panic!("Failed to deserialize {} contents", path)),
+        Err(_) => match File::open(FILE_NAME) {
+            Ok(file) => serde_yaml::from_reader(file)
+                .expect("Failed to deserialize config.yaml contents"),
+            Err(_) => {
+                warn!("No config.yaml file found, using default configuration.");
+                Config::default()
+            },
+        },
+    };
+
+    if let Some(port) = var("PORT") {
+        config.port = Some(port);
+    }
+    if let Ok(bind) = env::var(format!("{}BIND", ENV_PREFIX)) {
+        config.bind = bind
+            .split(',')
+            .map(str::trim)
+            .filter(|addr| !addr.is_empty())
+            .map(|addr| {
+                addr.parse().unwrap_or_else(|_| {
+                    panic!("Invalid value for {}BIND: {}", ENV_PREFIX, addr)
+                })
+            })
+            .collect();
+    }
+    if let Some(port) = var("HTTP_PORT") {
+        config.http_port = Some(port);
+    }
+    if let Some(port) = var("RESP_PORT") {
+        config.resp_port = Some(port);
+    }
+    if let Some(directory) = var("DATA_DIR") {
+        config.data_dir = Some(directory);
+    }
+    if let Some(name) = var("SERVICE_NAME") {
+        config.service.name = name;
+    }
+    if let Some(algorithm) = var("ALGORITHM") {
+        config.service.algorithm = algorithm;
+    }
+    if let Some(message_type) = var("MESSAGE_TYPE") {
+        config.service.message_type = message_type;
+    }
+    if let Some(lang) = var("LANG") {
+        config.service.lang = Some(lang);
+    }
+    if let Ok(exclude) = env::var(format!("{}EXCLUDE", ENV_PREFIX)) {
+        config.service.exclude = exclude
+            .split(',')
+            .map(|word| word.trim().to_string())
+            .filter(|word| !word.is_empty())
+            .collect();
+    }
 
     config
 }
+
+/// Returns the addresses to listen on with a port, every IPv4 address if
+/// none is configured.
+pub fn addresses(config: &Config, port: u16) -> Vec<SocketAddr> {
+    if config.bind.is_empty() {
+        vec![SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port)]
+    } else {
+        config
+            .bind
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, port))
+            .collect()
+    }
+}
+
+/// Reads an environment variable prefixed by `SQUID_` and parses it the same
+/// way as a YAML value.
+fn var<T: DeserializeOwned>(name: &str) -> Option<T> {
+    let name = format!("{}{}", ENV_PREFIX, name);
+
+    env::var(&name).ok().map(|value| {
+        serde_yaml::from_str(&value)
+            .unwrap_or_else(|_| panic!("Invalid value for {}: {}", name, value))
+    })
+}
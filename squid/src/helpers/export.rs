@@ -0,0 +1,289 @@
+use crate::{
+    helpers::{database, namespace::Namespace},
+    models::{config::MessageType, database::Meta},
+    squid::{ExportChunk, ExportFormat},
+};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tracing::error;
+
+/// Size from which a chunk is sent.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A ranked word, as exported.
+#[derive(Serialize, Debug)]
+struct Row<'a> {
+    rank: usize,
+    word: &'a str,
+    occurence: usize,
+}
+
+/// A sentence, as exported.
+#[derive(Serialize, Debug)]
+struct Document<'a> {
+    id: &'a str,
+    lang: &'a str,
+    text: &'a str,
+    original_text: Option<&'a str>,
+    meta: &'a Meta,
+    tags: &'a [String],
+    metadata: &'a HashMap<String, String>,
+    region: Option<&'a str>,
+    author_id: Option<&'a str>,
+}
+
+/// Writes rows into chunks of the requested format.
+struct Writer {
+    buffer: Vec<u8>,
+    sender: mpsc::Sender<Result<ExportChunk, Status>>,
+}
+
+impl Writer {
+    /// Creates a writer, starting with the CSV header if needed.
+    fn new(
+        format: ExportFormat,
+        header: &[&str],
+        sender: mpsc::Sender<Result<ExportChunk, Status>>,
+    ) -> Self {
+        let mut writer = Self {
+            buffer: Vec::new(),
+            sender,
+        };
+        if format == ExportFormat::Csv {
+            writer.csv(header);
+        }
+
+        writer
+    }
+
+    /// Appends a CSV line, quoting fields when needed.
+    fn csv(&mut self, fields: &[&str]) {
+        let line = fields
+            .iter()
+            .map(|field| {
+                if field.contains([',', '"', '\n', '\r']) {
+                    format!("\"{}\"", field.replace('"', "\"\""))
+                } else {
+                    field.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+
+        self.buffer.extend_from_slice(line.as_bytes());
+        self.buffer.push(b'\n');
+    }
+
+    /// Appends a JSON line.
+    fn json<T: Serialize>(&mut self, value: &T) {
+        if serde_json::to_writer(&mut self.buffer, value).is_ok() {
+            self.buffer.push(b'\n');
+        }
+    }
+
+    /// Sends the buffer if it is large enough, or if `force` is set.
+    ///
+    /// Returns `false` if the client is gone.
+    async fn send(&mut self, force: bool) -> bool {
+        if self.buffer.is_empty() || (!force && self.buffer.len() < CHUNK_SIZE)
+        {
+            return true;
+        }
+
+        let data = std::mem::take(&mut self.buffer);
+        self.sender.send(Ok(ExportChunk { data })).await.is_ok()
+    }
+}
+
+/// Streams the leaderboard.
+pub fn leaderboard(
+    namespace: Arc<Namespace>,
+    format: ExportFormat,
+    lang: Option<String>,
+    kind: MessageType,
+    length: usize,
+) -> ReceiverStream<Result<ExportChunk, Status>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        #[cfg(feature = "parquet")]
+        if format == ExportFormat::Parquet {
+            return parquet(&namespace, lang.as_deref(), &kind, length, &tx)
+                .await;
+        }
+
+        let (ranking, _) = database::rank(
+            &namespace.counters,
+            lang.as_deref().into(),
+            &kind,
+            0,
+            length,
+        )
+        .await;
+
+        let mut writer = Writer::new(format, &["rank", "word", "occurence"], tx);
+        for (rank, (word, occurence)) in ranking.iter().enumerate() {
+            let word = word.replace("%20", " ");
+            let row = Row {
+                rank: rank + 1,
+                word: &word,
+                occurence: *occurence,
+            };
+
+            match format {
+                ExportFormat::Csv => writer.csv(&[
+                    &row.rank.to_string(),
+                    row.word,
+                    &row.occurence.to_string(),
+                ]),
+                ExportFormat::Jsonl => writer.json(&row),
+                ExportFormat::Parquet => unreachable!("written at once"),
+            }
+
+            if !writer.send(false).await {
+                return;
+            }
+        }
+        writer.send(true).await;
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Sends the leaderboard as a Parquet file, which is built at once.
+#[cfg(feature = "parquet")]
+async fn parquet(
+    namespace: &Namespace,
+    lang: Option<&str>,
+    kind: &MessageType,
+    length: usize,
+    sender: &mpsc::Sender<Result<ExportChunk, Status>>,
+) {
+    let file = match squid_core::archive::leaderboard(
+        &namespace.counters,
+        lang.into(),
+        kind,
+        length,
+        Vec::new(),
+    )
+    .await
+    {
+        Ok(file) => file,
+        Err(error) => {
+            error!("Failed to write Parquet file: {}", error);
+            let _ = sender.send(Err(error.to_status("failed to export"))).await;
+            return;
+        },
+    };
+
+    for data in file.chunks(CHUNK_SIZE) {
+        let chunk = ExportChunk {
+            data: data.to_vec(),
+        };
+        if sender.send(Ok(chunk)).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Streams every stored sentence, one data file at a time.
+///
+/// Sentences added, expired or compacted during the export may be missing
+/// or duplicated.
+pub fn corpus(
+    namespace: Arc<Namespace>,
+    format: ExportFormat,
+) -> ReceiverStream<Result<ExportChunk, Status>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let mut writer = Writer::new(
+            format,
+            &[
+                "id",
+                "lang",
+                "text",
+                "original_text",
+                "meta",
+                "tags",
+                "metadata",
+                "region",
+                "author_id",
+            ],
+            tx.clone(),
+        );
+
+        let segments = match namespace.segments().await {
+            Ok(segments) => segments,
+            Err(error) => {
+                error!("Failed to list data files: {}", error);
+                let _ = tx
+                    .send(Err(error.to_status("failed to export")))
+                    .await;
+                return;
+            },
+        };
+
+        for segment in &segments {
+            let entities = match namespace.read(segment.as_deref()).await {
+                Ok(entities) => entities,
+                Err(error) => {
+                    error!("Failed to read memtable: {}", error);
+                    let _ = tx
+                        .send(Err(error.to_status("failed to export")))
+                        .await;
+                    return;
+                },
+            };
+
+            for entity in &entities {
+                let document = Document {
+                    id: &entity.id,
+                    lang: &entity.lang,
+                    text: &entity.post_processing_text,
+                    original_text: entity.original_text.as_deref(),
+                    meta: &entity.meta,
+                    tags: &entity.tags,
+                    metadata: &entity.metadata,
+                    region: entity.region.as_deref(),
+                    author_id: entity.author_id.as_deref(),
+                };
+
+                match format {
+                    ExportFormat::Csv => writer.csv(&[
+                        document.id,
+                        document.lang,
+                        document.text,
+                        document.original_text.unwrap_or_default(),
+                        &document.meta.to_string(),
+                        &document.tags.join(" "),
+                        &document
+                            .metadata
+                            .iter()
+                            .map(|(key, value)| {
+                                database::metadata_key(key, value)
+                            })
+                            .collect::<Vec<_>>()
+                            .join(" "),
+                        document.region.unwrap_or_default(),
+                        document.author_id.unwrap_or_default(),
+                    ]),
+                    ExportFormat::Jsonl => writer.json(&document),
+                    ExportFormat::Parquet => {
+                        unreachable!("corpora are not exported as Parquet")
+                    },
+                }
+
+                if !writer.send(false).await {
+                    return;
+                }
+            }
+        }
+        writer.send(true).await;
+    });
+
+    ReceiverStream::new(rx)
+}
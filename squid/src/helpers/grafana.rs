@@ -0,0 +1,234 @@
+//! Grafana JSON datasource, charting the leaderboard and the history of
+//! the counts of words.
+
+use crate::{
+    helpers::{
+        database::{self, Filter},
+        history,
+        http::Gateway,
+    },
+    models::config::{MessageType, Scope},
+};
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use squid_core::time;
+use std::time::UNIX_EPOCH;
+
+/// Number of words suggested when searching for a metric.
+const SEARCH_LENGTH: usize = 100;
+/// Number of words of a table if not specified.
+const DEFAULT_TABLE_LENGTH: usize = 10;
+
+/// Routes of the datasource, under `/grafana`.
+///
+/// `/grafana` only answers to the connection test of Grafana, which may
+/// append a slash to the URL of the datasource. Other routes require an API
+/// key with the `Read` scope, set as the `Authorization` header of the
+/// datasource, if API keys are configured.
+pub fn router() -> Router<Gateway> {
+    Router::new()
+        .route("/grafana", get(|| async { StatusCode::OK }))
+        .route("/grafana/", get(|| async { StatusCode::OK }))
+        .route("/grafana/search", post(search))
+        .route("/grafana/query", post(query))
+}
+
+/// Options of a target, set in its payload.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Payload {
+    /// Namespace to read from, the default one if not specified.
+    namespace: String,
+    /// Number of words of a table.
+    length: Option<usize>,
+}
+
+/// Body of `/search`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct SearchRequest {
+    /// Part of the searched words.
+    target: String,
+    payload: Payload,
+}
+
+/// Suggests the most used words containing the searched text.
+async fn search(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Json(request): Json<SearchRequest>,
+) -> Result<Json<Vec<String>>, (StatusCode, String)> {
+    gateway.authorize(&headers, Scope::Read)?;
+    let namespace =
+        gateway.namespaces.get(&request.payload.namespace).map_err(
+            |error| (StatusCode::NOT_FOUND, error.context.unwrap_or_default()),
+        )?;
+    let (ranking, _) = database::rank(
+        &namespace.counters,
+        Filter::All,
+        &MessageType::Anything,
+        0,
+        SEARCH_LENGTH,
+    )
+    .await;
+
+    let target = request.target.to_lowercase();
+    Ok(Json(
+        ranking
+            .into_iter()
+            .map(|(word, _)| word.replace("%20", " "))
+            .filter(|word| word.contains(&target))
+            .collect(),
+    ))
+}
+
+/// Time range of a query, as RFC 3339 dates.
+#[derive(Deserialize, Debug)]
+struct Range {
+    from: String,
+    to: String,
+}
+
+/// A word to chart, or the leaderboard if the target is a table.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Target {
+    target: String,
+    #[serde(rename = "type")]
+    kind: String,
+    payload: Payload,
+}
+
+/// Body of `/query`.
+#[derive(Deserialize, Debug)]
+struct QueryRequest {
+    range: Range,
+    #[serde(default)]
+    targets: Vec<Target>,
+}
+
+/// Column of a table.
+#[derive(Serialize, Debug)]
+struct Column {
+    text: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+/// Result of a target.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+enum Response {
+    /// Counts of a word, as `[count, timestamp in milliseconds]`.
+    Series {
+        target: String,
+        datapoints: Vec<(usize, u64)>,
+    },
+    /// The current leaderboard.
+    Table {
+        #[serde(rename = "type")]
+        kind: &'static str,
+        columns: Vec<Column>,
+        rows: Vec<(String, usize)>,
+    },
+}
+
+/// Charts the counts of words from the history of their namespace, then
+/// their current count if the range includes now.
+async fn query(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Json(request): Json<QueryRequest>,
+) -> Result<Json<Vec<Response>>, (StatusCode, String)> {
+    gateway.authorize(&headers, Scope::Read)?;
+    let from = timestamp(&request.range.from)?;
+    let to = timestamp(&request.range.to)?;
+    let now = time::now();
+
+    let mut responses = Vec::with_capacity(request.targets.len());
+    for target in request.targets {
+        let namespace = gateway
+            .namespaces
+            .get(&target.payload.namespace)
+            .map_err(|error| {
+                (StatusCode::NOT_FOUND, error.context.unwrap_or_default())
+            })?;
+
+        if target.kind == "table" {
+            let (ranking, _) = database::rank(
+                &namespace.counters,
+                namespace.service.lang.as_deref().into(),
+                &MessageType::Anything,
+                0,
+                target.payload.length.unwrap_or(DEFAULT_TABLE_LENGTH),
+            )
+            .await;
+
+            responses.push(Response::Table {
+                kind: "table",
+                columns: vec![
+                    Column {
+                        text: "word",
+                        kind: "string",
+                    },
+                    Column {
+                        text: "occurrences",
+                        kind: "number",
+                    },
+                ],
+                rows: ranking
+                    .into_iter()
+                    .map(|(word, count)| (word.replace("%20", " "), count))
+                    .collect(),
+            });
+            continue;
+        }
+
+        let captures =
+            history::read(&namespace.directory, from, to).map_err(|error| {
+                (StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+            })?;
+        let mut datapoints = captures
+            .iter()
+            .filter_map(|capture| {
+                let row = capture
+                    .ranking
+                    .iter()
+                    .find(|row| row.word == target.target)?;
+                Some((row.occurence, capture.captured_at * 1000))
+            })
+            .collect::<Vec<_>>();
+        if (from..=to).contains(&now) {
+            let count = namespace
+                .counters
+                .algorithm
+                .read()
+                .await
+                .get(&target.target.replace(' ', "%20"));
+            datapoints.push((count, now * 1000));
+        }
+
+        responses.push(Response::Series {
+            target: target.target,
+            datapoints,
+        });
+    }
+
+    Ok(Json(responses))
+}
+
+/// Converts an RFC 3339 date into a UNIX timestamp, in seconds.
+fn timestamp(date: &str) -> Result<u64, (StatusCode, String)> {
+    humantime::parse_rfc3339_weak(date)
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .ok_or_else(|| {
+            (StatusCode::BAD_REQUEST, format!("invalid date {:?}", date))
+        })
+}
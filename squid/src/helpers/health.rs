@@ -0,0 +1,67 @@
+//! Progress of the startup, reported by the gRPC health service and the
+//! `LoadProgress` stream.
+
+use crate::squid::{LoadProgressReply, LoadState};
+use squid_core::startup::{self, Progress, State};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::Status;
+use tonic_health::{server::HealthReporter, ServingStatus};
+
+impl From<Progress> for LoadProgressReply {
+    fn from(progress: Progress) -> Self {
+        Self {
+            state: match progress.state {
+                State::Loading => LoadState::Loading,
+                State::Warming => LoadState::Warming,
+                State::Ready => LoadState::Ready,
+            }
+            .into(),
+            counted: progress.counted as u64,
+            total: progress.total as u64,
+        }
+    }
+}
+
+/// Streams the progress of the startup, until every stored sentence is
+/// counted.
+pub fn progress() -> ReceiverStream<Result<LoadProgressReply, Status>> {
+    let (tx, rx) = mpsc::channel(1);
+    let mut changes = startup::subscribe();
+
+    tokio::spawn(async move {
+        loop {
+            let progress = *changes.borrow_and_update();
+            if tx.send(Ok(progress.into())).await.is_err()
+                || progress.state == State::Ready
+                || changes.changed().await.is_err()
+            {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Reports the server, and each of its services, as serving once every
+/// stored sentence is counted.
+pub async fn report(mut reporter: HealthReporter, services: Vec<&'static str>) {
+    // The empty name stands for the whole server.
+    let services = [""].into_iter().chain(services).collect::<Vec<_>>();
+    for service in &services {
+        reporter
+            .set_service_status(service, ServingStatus::NotServing)
+            .await;
+    }
+
+    // Fails only if the progress is dropped, which it never is.
+    let _ = startup::subscribe()
+        .wait_for(|progress| progress.state == State::Ready)
+        .await;
+    for service in &services {
+        reporter
+            .set_service_status(service, ServingStatus::Serving)
+            .await;
+    }
+}
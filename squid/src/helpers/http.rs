@@ -0,0 +1,189 @@
+use crate::{
+    helpers::{
+        auth::{self, Authenticator},
+        changes,
+        grafana,
+        metrics::{Gauges, METRICS},
+        namespace::{Namespace, Namespaces},
+    },
+    models::config::{MessageType, Scope},
+};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
+    routing::get,
+    Router,
+};
+use serde::Deserialize;
+use std::{convert::Infallible, net::SocketAddr, sync::Arc, time::Duration};
+use tokio_stream::Stream;
+use tonic::Code;
+use tracing::info;
+
+/// Content type of the Prometheus text format.
+const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+/// Minimum delay between two events of the trending feed.
+const TRENDING_INTERVAL: Duration = Duration::from_secs(1);
+/// Number of words followed by the trending feed if not specified.
+const DEFAULT_TRENDING_LENGTH: usize = 10;
+
+/// Data shared with the HTTP gateway.
+#[derive(Clone)]
+pub struct Gateway {
+    pub namespaces: Arc<Namespaces>,
+    /// Checks the API keys, as for gRPC requests.
+    pub authenticator: Authenticator,
+}
+
+impl Gateway {
+    /// Checks that the API key of a request, sent in its `authorization`
+    /// header as for gRPC requests, has been granted a scope.
+    pub fn authorize(
+        &self,
+        headers: &HeaderMap,
+        scope: Scope,
+    ) -> Result<(), (StatusCode, String)> {
+        self.authenticator
+            .grant(
+                headers
+                    .get(header::AUTHORIZATION)
+                    .and_then(|value| value.to_str().ok()),
+            )
+            .and_then(|grant| auth::permit(Some(&grant), scope))
+            .map_err(|status| {
+                let code = match status.code() {
+                    Code::PermissionDenied => StatusCode::FORBIDDEN,
+                    _ => StatusCode::UNAUTHORIZED,
+                };
+                (code, status.message().to_string())
+            })
+    }
+}
+
+/// Starts the HTTP gateway, exposing `/metrics`, `/trending` and the
+/// Grafana datasource under `/grafana`.
+pub async fn serve(addr: SocketAddr, gateway: Gateway) -> std::io::Result<()> {
+    let router = Router::new()
+        .route("/metrics", get(metrics))
+        .route("/trending", get(trending))
+        .merge(grafana::router())
+        .with_state(gateway);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("HTTP gateway started on {}", addr);
+
+    axum::serve(listener, router).await
+}
+
+/// Prometheus metrics.
+async fn metrics(State(gateway): State<Gateway>) -> impl IntoResponse {
+    let mut gauges = Gauges::default();
+
+    for namespace in gateway.namespaces.iter() {
+        let instance = namespace.instance.read().await;
+        gauges.flushes += instance.flushes();
+        gauges.memtable += instance.memtable_len();
+        gauges.expirations += namespace.pending_expirations();
+        gauges.vocabulary += namespace
+            .counters
+            .algorithm
+            .read()
+            .await
+            .len(&MessageType::Anything);
+        gauges.memory += namespace.counters.memory().await;
+    }
+
+    (
+        [(header::CONTENT_TYPE, PROMETHEUS_CONTENT_TYPE)],
+        METRICS.render(&gauges),
+    )
+}
+
+/// Query parameters of the trending feed.
+#[derive(Deserialize, Debug)]
+struct TrendingQuery {
+    /// Number of words to follow.
+    length: Option<usize>,
+    /// Only follow the words written in this language.
+    lang: Option<String>,
+    /// Namespace to follow, the default one if not specified.
+    #[serde(default)]
+    namespace: String,
+    /// Kind of words to follow, `Word`, `Hashtag` or `Phrase`. Words and
+    /// hashtags if not specified.
+    #[serde(default)]
+    kind: MessageType,
+}
+
+/// Server-Sent Events feed of the leaderboard.
+///
+/// Sends a `ranking` event with the whole leaderboard on connection, then
+/// a `delta` event each time it changes. Requires an API key with the
+/// `Read` scope, if API keys are configured.
+///
+/// Leaderboards are ranked through the cache of the namespace, so clients
+/// following the same one rank it once per change.
+async fn trending(
+    State(gateway): State<Gateway>,
+    headers: HeaderMap,
+    Query(query): Query<TrendingQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)>
+{
+    gateway.authorize(&headers, Scope::Read)?;
+    let namespace = Arc::clone(gateway.namespaces.get(&query.namespace).map_err(
+        |error| (StatusCode::NOT_FOUND, error.context.unwrap_or_default()),
+    )?);
+    let length = query.length.unwrap_or(DEFAULT_TRENDING_LENGTH);
+    let mut changes = namespace.counters.changes.subscribe();
+
+    let stream = async_stream::stream! {
+        let mut previous = rank(&namespace, &query, length).await;
+        let ranking = changes::diff(&[], &previous).changed;
+        if let Ok(event) = Event::default().event("ranking").json_data(ranking) {
+            yield Ok(event);
+        }
+
+        while changes.changed().await.is_ok() {
+            let current = rank(&namespace, &query, length).await;
+
+            let delta = changes::diff(&previous, &current);
+            if !delta.is_empty() {
+                if let Ok(event) = Event::default().event("delta").json_data(&delta) {
+                    yield Ok(event);
+                }
+                previous = current;
+            }
+
+            tokio::time::sleep(TRENDING_INTERVAL).await;
+        }
+    };
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Ranks the leaderboard followed by the trending feed, through the cache
+/// of its namespace.
+async fn rank(
+    namespace: &Namespace,
+    query: &TrendingQuery,
+    length: usize,
+) -> Vec<(String, usize)> {
+    let (ranking, _) = namespace
+        .cache
+        .rank(
+            &namespace.counters,
+            query.lang.as_deref().into(),
+            &query.kind,
+            namespace.counters.order,
+            0,
+            0,
+            length,
+        )
+        .await;
+
+    ranking
+}
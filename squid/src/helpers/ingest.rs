@@ -0,0 +1,231 @@
+use crate::{
+    helpers::{
+        database,
+        namespace::{Change, Namespace, Namespaces},
+    },
+    squid::{AddRequest, ImportFormat, ImportProgress, ImportRequest},
+};
+use rayon::prelude::*;
+use squid_error::Error;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Status, Streaming};
+use tracing::error;
+
+pub use squid_core::ingest::{
+    add, admit, entity, preview, Admission, Outcome, Submission,
+};
+
+/// Number of lines tokenized and written at once during an import.
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+impl From<AddRequest> for Submission {
+    fn from(request: AddRequest) -> Self {
+        Self {
+            sentence: request.sentence,
+            lifetime: request.lifetime,
+            lang: request.lang,
+            weight: request.weight,
+            store_original: request.store_original,
+            tags: request.tags,
+            metadata: request.metadata,
+            region: Some(request.region),
+            author_id: Some(request.author_id),
+            owner: None,
+        }
+    }
+}
+
+/// Imports the sentences of a file, sent in chunks.
+///
+/// Lines are tokenized in parallel and written by batches. Progress is sent
+/// after each batch.
+pub fn import(
+    namespaces: Arc<Namespaces>,
+    owner: Option<String>,
+    mut chunks: Streaming<ImportRequest>,
+) -> ReceiverStream<Result<ImportProgress, Status>> {
+    let (tx, rx) = mpsc::channel(1);
+
+    tokio::spawn(async move {
+        let mut namespace = None;
+        let mut format = None;
+        let mut lifetime = 0;
+        let mut buffer = Vec::new();
+        let mut lines = Vec::new();
+        let mut progress = ImportProgress::default();
+
+        loop {
+            let chunk = match chunks.message().await {
+                Ok(chunk) => chunk,
+                Err(status) => {
+                    let _ = tx.send(Err(status)).await;
+                    return;
+                },
+            };
+
+            // The last line may not end with a new line.
+            let last = chunk.is_none();
+            if let Some(chunk) = chunk {
+                if namespace.is_none() {
+                    match namespaces.get(&chunk.namespace) {
+                        Ok(found) => namespace = Some(Arc::clone(found)),
+                        Err(error) => {
+                            let _ = tx.send(Err(error.into())).await;
+                            return;
+                        },
+                    }
+                }
+                format.get_or_insert(chunk.format());
+                if lifetime == 0 {
+                    lifetime = chunk.lifetime;
+                }
+                buffer.extend_from_slice(&chunk.data);
+            } else {
+                buffer.push(b'\n');
+            }
+
+            while let Some(position) = buffer.iter().position(|c| *c == b'\n')
+            {
+                let line = buffer.drain(..=position).collect::<Vec<_>>();
+                lines.push(String::from_utf8_lossy(&line).trim().to_string());
+            }
+            lines.retain(|line| !line.is_empty());
+
+            if let Some(namespace) = namespace.as_ref().filter(|_| {
+                lines.len() >= IMPORT_BATCH_SIZE || (last && !lines.is_empty())
+            }) {
+                let batch = std::mem::take(&mut lines);
+                if let Err(status) = import_batch(
+                    namespace,
+                    batch,
+                    format.unwrap_or_default(),
+                    lifetime,
+                    owner.clone(),
+                    &mut progress,
+                )
+                .await
+                {
+                    let _ = tx.send(Err(status)).await;
+                    return;
+                }
+
+                if tx.send(Ok(progress)).await.is_err() {
+                    return;
+                }
+            }
+
+            if last {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Tokenizes lines in parallel, then writes and counts them.
+async fn import_batch(
+    namespace: &Arc<Namespace>,
+    lines: Vec<String>,
+    format: ImportFormat,
+    lifetime: u64,
+    owner: Option<String>,
+    progress: &mut ImportProgress,
+) -> Result<(), Status> {
+    let tokenizer = Arc::clone(namespace);
+    let results = tokio::task::spawn_blocking(move || {
+        lines
+            .into_par_iter()
+            .map(|line| {
+                let submission = match format {
+                    ImportFormat::Lines => Submission {
+                        sentence: line,
+                        ..Default::default()
+                    },
+                    ImportFormat::Jsonl => {
+                        serde_json::from_str(&line).map_err(Error::from)?
+                    },
+                };
+
+                entity(
+                    &tokenizer,
+                    Submission {
+                        lifetime: Some(submission.lifetime)
+                            .filter(|lifetime| *lifetime > 0)
+                            .unwrap_or(lifetime),
+                        owner: owner.clone(),
+                        ..submission
+                    },
+                )
+            })
+            .collect::<Vec<_>>()
+    })
+    .await
+    .map_err(|_| Status::internal("tokenization task stopped"))?;
+
+    let mut entities = Vec::with_capacity(results.len());
+    for result in results {
+        match result {
+            Ok(entity)
+                if namespace
+                    .dedup
+                    .as_ref()
+                    .and_then(|dedup| dedup.check(&entity))
+                    .is_some() =>
+            {
+                progress.deduplicated += 1
+            },
+            // Sentences beyond the quota fail, like invalid ones.
+            Ok(mut entity) => {
+                // Near-duplicates within the batch are found too.
+                if let Some(similar) = &namespace.similar {
+                    similar.down_weight(&mut entity);
+                }
+                match namespace.quotas.reserve(&entity) {
+                    Ok(()) => {
+                        if let Some(similar) = &namespace.similar {
+                            similar.insert(&entity);
+                        }
+                        entities.push(entity)
+                    },
+                    Err(_) => {
+                        if let Some(dedup) = &namespace.dedup {
+                            dedup.forget(&entity);
+                        }
+                        progress.failed += 1
+                    },
+                }
+            },
+            Err(_) => progress.failed += 1,
+        }
+    }
+
+    database::set_many(
+        &namespace.service,
+        Arc::clone(&namespace.instance),
+        &namespace.counters,
+        &entities,
+    )
+    .await
+    .map_err(|error| {
+        error!("Failed to import sentences: {}", error);
+        for entity in &entities {
+            namespace.quotas.release(entity);
+            if let Some(dedup) = &namespace.dedup {
+                dedup.forget(entity);
+            }
+            if let Some(similar) = &namespace.similar {
+                similar.remove(&entity.id);
+            }
+        }
+        error.to_status("failed to import sentences")
+    })?;
+    progress.imported += entities.len() as u64;
+    for entity in &entities {
+        namespace.publish(Change::Added(Box::new(entity.clone())));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,172 @@
+use crate::{helpers::auth::Grant, models::config::RateLimit};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tonic::{Request, Status};
+
+/// Time between two sweeps forgetting the full buckets.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tokens available for a client.
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Buckets of the clients seen since they were last full.
+#[derive(Debug)]
+struct Buckets {
+    clients: HashMap<String, Bucket>,
+    /// Time at which full buckets were last forgotten.
+    swept_at: Instant,
+}
+
+/// Token bucket rate limiter keyed by API key, or by IP address when
+/// authentication is disabled.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    /// Tokens added to each bucket per second.
+    rate: f64,
+    /// Maximum tokens in a bucket.
+    burst: f64,
+    buckets: Arc<Mutex<Buckets>>,
+}
+
+impl RateLimiter {
+    /// Creates a new [`RateLimiter`] from the configuration.
+    pub fn new(config: &RateLimit) -> Self {
+        Self {
+            rate: config.requests_per_second,
+            burst: config.burst.max(1) as f64,
+            buckets: Arc::new(Mutex::new(Buckets {
+                clients: HashMap::new(),
+                swept_at: Instant::now(),
+            })),
+        }
+    }
+
+    /// Consumes one token of the client, or returns
+    /// [`tonic::Code::ResourceExhausted`] if the bucket is empty.
+    pub fn acquire(&self, client: &str) -> Result<(), Status> {
+        self.acquire_at(client, Instant::now())
+    }
+
+    fn acquire_at(&self, client: &str, now: Instant) -> Result<(), Status> {
+        let mut buckets = self
+            .buckets
+            .lock()
+            .map_err(|_| Status::internal("rate limiter is poisoned"))?;
+
+        // A full bucket is the same as a new one, so it is forgotten. Sweeps
+        // are spaced so requests do not go through every client.
+        if now.duration_since(buckets.swept_at) >= SWEEP_INTERVAL {
+            buckets
+                .clients
+                .retain(|_, bucket| self.refill(bucket, now) < self.burst);
+            buckets.swept_at = now;
+        }
+
+        let bucket =
+            buckets.clients.entry(client.to_string()).or_insert(Bucket {
+                tokens: self.burst,
+                updated_at: now,
+            });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Status::resource_exhausted("too many requests"));
+        }
+        bucket.tokens -= 1.0;
+
+        Ok(())
+    }
+
+    /// Returns the tokens of a bucket once refilled up to a time.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        (bucket.tokens
+            + now.duration_since(bucket.updated_at).as_secs_f64() * self.rate)
+            .min(self.burst)
+    }
+
+    /// Consumes one token of the caller of an authenticated request.
+    pub fn check(&self, request: Request<()>) -> Result<Request<()>, Status> {
+        let client = request
+            .extensions()
+            .get::<Grant>()
+            .and_then(|grant| grant.key.clone())
+            .or_else(|| request.remote_addr().map(|addr| addr.ip().to_string()))
+            .unwrap_or_default();
+
+        self.acquire(&client)?;
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(&RateLimit {
+            requests_per_second: 1.0,
+            burst: 2,
+        })
+    }
+
+    #[test]
+    fn test_reject_empty_bucket() {
+        let limiter = limiter();
+        let now = Instant::now();
+
+        assert!(limiter.acquire_at("a", now).is_ok());
+        assert!(limiter.acquire_at("a", now).is_ok());
+        let status = limiter.acquire_at("a", now).unwrap_err();
+        assert_eq!(status.code(), Code::ResourceExhausted);
+
+        // Each client has its own bucket.
+        assert!(limiter.acquire_at("b", now).is_ok());
+    }
+
+    #[test]
+    fn test_refill_bucket() {
+        let limiter = limiter();
+        let now = Instant::now();
+        for _ in 0..2 {
+            limiter.acquire_at("a", now).unwrap();
+        }
+
+        let now = now + Duration::from_millis(1500);
+        assert!(limiter.acquire_at("a", now).is_ok());
+        assert!(limiter.acquire_at("a", now).is_err());
+
+        // Buckets hold the burst at most.
+        let now = now + Duration::from_secs(10);
+        assert!(limiter.acquire_at("a", now).is_ok());
+        assert!(limiter.acquire_at("a", now).is_ok());
+        assert!(limiter.acquire_at("a", now).is_err());
+    }
+
+    #[test]
+    fn test_forget_full_buckets() {
+        let limiter = limiter();
+        let now = Instant::now();
+        limiter.acquire_at("a", now).unwrap();
+        for _ in 0..2 {
+            limiter.acquire_at("b", now + SWEEP_INTERVAL).unwrap();
+        }
+
+        // Only the bucket of `a` was full during the sweep.
+        let now = now + SWEEP_INTERVAL + Duration::from_secs(1);
+        limiter.acquire_at("c", now).unwrap();
+        assert_eq!(limiter.buckets.lock().unwrap().clients.len(), 2);
+
+        limiter.acquire_at("c", now + SWEEP_INTERVAL * 2).unwrap();
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.clients.keys().collect::<Vec<_>>(), ["c"]);
+    }
+}
@@ -1,2 +1,18 @@
+pub mod auth;
+pub mod changes;
+pub mod cluster;
 pub mod config;
-pub mod database;
+pub mod export;
+pub mod grafana;
+pub mod health;
+pub mod http;
+pub mod ingest;
+pub mod limit;
+pub mod raft;
+pub mod replica;
+pub mod resp;
+pub mod slow;
+pub mod standby;
+pub mod systemd;
+
+pub use squid_core::{database, history, metrics, namespace};
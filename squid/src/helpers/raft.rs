@@ -0,0 +1,491 @@
+use crate::{
+    helpers::{
+        ingest::{self, Admission, Outcome, Submission},
+        namespace::{Change, Namespace, Namespaces},
+        replica,
+    },
+    models::database::Entity,
+    squid::{
+        admin_client::AdminClient, AppendEntriesReply, AppendEntriesRequest,
+        VoteReply, VoteRequest,
+    },
+};
+use squid_core::{
+    raft::{self, Command, Entry, Raft},
+    time,
+};
+use squid_db::Attributes;
+use squid_error::Error;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+use tokio::time::MissedTickBehavior;
+use tonic::{
+    transport::{Channel, Endpoint},
+    Request, Status,
+};
+use tracing::{error, info, warn};
+
+/// Maximum number of committed entries applied at once.
+const APPLY_BATCH: u64 = 512;
+
+impl From<raft::VoteRequest> for VoteRequest {
+    fn from(request: raft::VoteRequest) -> Self {
+        VoteRequest {
+            term: request.term,
+            candidate: request.candidate,
+            last_index: request.last_index,
+            last_term: request.last_term,
+        }
+    }
+}
+
+impl From<VoteRequest> for raft::VoteRequest {
+    fn from(request: VoteRequest) -> Self {
+        raft::VoteRequest {
+            term: request.term,
+            candidate: request.candidate,
+            last_index: request.last_index,
+            last_term: request.last_term,
+        }
+    }
+}
+
+impl From<raft::VoteReply> for VoteReply {
+    fn from(reply: raft::VoteReply) -> Self {
+        VoteReply {
+            term: reply.term,
+            granted: reply.granted,
+        }
+    }
+}
+
+impl From<VoteReply> for raft::VoteReply {
+    fn from(reply: VoteReply) -> Self {
+        raft::VoteReply {
+            term: reply.term,
+            granted: reply.granted,
+        }
+    }
+}
+
+impl TryFrom<raft::AppendRequest> for AppendEntriesRequest {
+    type Error = Error;
+
+    fn try_from(request: raft::AppendRequest) -> Result<Self, Error> {
+        Ok(AppendEntriesRequest {
+            term: request.term,
+            leader: request.leader,
+            prev_index: request.prev_index,
+            prev_term: request.prev_term,
+            entries: request
+                .entries
+                .iter()
+                .map(Entry::encode)
+                .collect::<Result<_, _>>()?,
+            commit: request.commit,
+            stored: request.stored,
+        })
+    }
+}
+
+impl TryFrom<AppendEntriesRequest> for raft::AppendRequest {
+    type Error = Error;
+
+    fn try_from(request: AppendEntriesRequest) -> Result<Self, Error> {
+        Ok(raft::AppendRequest {
+            term: request.term,
+            leader: request.leader,
+            prev_index: request.prev_index,
+            prev_term: request.prev_term,
+            entries: request
+                .entries
+                .iter()
+                .map(|entry| Entry::decode(entry))
+                .collect::<Result<_, _>>()?,
+            commit: request.commit,
+            stored: request.stored,
+        })
+    }
+}
+
+impl From<raft::AppendReply> for AppendEntriesReply {
+    fn from(reply: raft::AppendReply) -> Self {
+        AppendEntriesReply {
+            term: reply.term,
+            success: reply.success,
+            last_index: reply.last_index,
+        }
+    }
+}
+
+impl From<AppendEntriesReply> for raft::AppendReply {
+    fn from(reply: AppendEntriesReply) -> Self {
+        raft::AppendReply {
+            term: reply.term,
+            success: reply.success,
+            last_index: reply.last_index,
+        }
+    }
+}
+
+/// Changes made through the Raft group rather than directly.
+#[derive(Debug)]
+pub struct Consensus {
+    pub raft: Arc<Raft>,
+    /// Sentences added through this node, whose size is already reserved
+    /// in the quota of their owner.
+    reserved: Mutex<HashSet<String>>,
+}
+
+impl Consensus {
+    pub fn new(raft: Arc<Raft>) -> Self {
+        Self {
+            raft,
+            reserved: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Adds a sentence once a majority of the nodes store it.
+    ///
+    /// The sentence is counted by each node once it is committed.
+    pub async fn add(
+        &self,
+        namespace: &Namespace,
+        submission: Submission,
+    ) -> Result<Outcome, Error> {
+        self.raft.lead().await?;
+
+        let entity = match ingest::admit(namespace, submission)? {
+            Admission::Admitted(entity) => entity,
+            Admission::Deduplicated(original) => {
+                return Ok(Outcome::Deduplicated(original))
+            },
+        };
+        let id = entity.id.clone();
+        self.reserve(&id);
+
+        let command = self.command(namespace, Change::Added(entity.clone()));
+        if let Err(error) = self.raft.propose(command).await {
+            // Applied meanwhile if no longer reserved.
+            if self.release(&id) {
+                namespace.quotas.release(&entity);
+                if let Some(dedup) = &namespace.dedup {
+                    dedup.forget(&entity);
+                }
+            }
+            return Err(error);
+        }
+
+        Ok(Outcome::Stored(id))
+    }
+
+    /// Updates the expiration of a sentence once a majority of the nodes
+    /// store the change.
+    ///
+    /// Returns `false` if there is no sentence with this identifier.
+    pub async fn touch(
+        &self,
+        namespace: &Namespace,
+        id: &str,
+        expire_at: Option<u64>,
+    ) -> Result<bool, Error> {
+        self.raft.lead().await?;
+        if !namespace.contains(id).await {
+            return Ok(false);
+        }
+
+        self.raft
+            .propose(self.command(namespace, replica::touched(id, expire_at)))
+            .await?;
+        Ok(true)
+    }
+
+    /// Excludes words, or counts them again, once a majority of the nodes
+    /// store the change.
+    pub async fn exclude(
+        &self,
+        namespace: &Namespace,
+        words: Vec<String>,
+        excluded: bool,
+    ) -> Result<(), Error> {
+        self.raft.lead().await?;
+
+        let mut exclusions = namespace.counters.exclusions.read().await.clone();
+        if excluded {
+            exclusions.extend(words);
+        } else {
+            for word in &words {
+                exclusions.remove(word);
+            }
+        }
+
+        self.raft
+            .propose(self.command(
+                namespace,
+                Change::Excluded(exclusions.into_iter().collect()),
+            ))
+            .await
+    }
+
+    fn command(&self, namespace: &Namespace, change: Change) -> Command {
+        Command {
+            namespace: namespace.service.name.clone(),
+            change,
+        }
+    }
+
+    fn reserve(&self, id: &str) {
+        lock(&self.reserved).insert(id.to_string());
+    }
+
+    /// Returns whether the sentence was reserved and not applied yet.
+    fn release(&self, id: &str) -> bool {
+        lock(&self.reserved).remove(id)
+    }
+}
+
+/// Holds elections, and sends the entries of the log to the other nodes
+/// once this node leads.
+pub async fn run(raft: Arc<Raft>) {
+    let channels = raft
+        .peers
+        .iter()
+        .map(|peer| {
+            let channel = Endpoint::from_shared(peer.clone())
+                .unwrap_or_else(|error| {
+                    panic!("Invalid address of Raft node {}: {}", peer, error)
+                })
+                .timeout(raft.election_timeout)
+                .connect_lazy();
+            (peer.clone(), channel)
+        })
+        .collect::<HashMap<_, _>>();
+    // Entries are not sent again to a node until it replies.
+    let sending = Arc::new(Mutex::new(HashSet::new()));
+    // Nodes which failed to reply, only reported once.
+    let unreachable = Arc::new(Mutex::new(HashSet::new()));
+    let mut leader = None;
+
+    let mut interval = tokio::time::interval(raft.heartbeat);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = raft.proposed() => {},
+        }
+
+        match raft.campaign().await {
+            Ok(Some(request)) => {
+                for channel in channels.values() {
+                    let (raft, channel) = (Arc::clone(&raft), channel.clone());
+                    let request = request.clone();
+                    tokio::spawn(async move {
+                        let request =
+                            authorized(&raft, VoteRequest::from(request))?;
+                        let reply = AdminClient::new(channel)
+                            .request_vote(request)
+                            .await?
+                            .into_inner();
+                        if let Err(error) = raft.voted(reply.into()).await {
+                            error!("Failed to count vote: {}", error);
+                        }
+                        Ok::<_, Status>(())
+                    });
+                }
+            },
+            Ok(None) => {},
+            Err(error) => error!("Failed to start Raft election: {}", error),
+        }
+
+        let current = raft.leader().await;
+        if current != leader {
+            match &current {
+                Some(current) => info!("Raft node {} leads.", current),
+                None => info!("Raft group has no leader."),
+            }
+            leader = current;
+        }
+
+        for (peer, request) in raft.heartbeats().await {
+            let Some(channel) = channels.get(&peer) else {
+                continue;
+            };
+            if !lock(&sending).insert(peer.clone()) {
+                continue;
+            }
+
+            let (raft, channel) = (Arc::clone(&raft), channel.clone());
+            let (sending, unreachable) =
+                (Arc::clone(&sending), Arc::clone(&unreachable));
+            tokio::spawn(async move {
+                let result = append(&raft, channel, request).await;
+                lock(&sending).remove(&peer);
+
+                match result {
+                    Ok(reply) => {
+                        if lock(&unreachable).remove(&peer) {
+                            info!("Raft node {} is reachable again.", peer);
+                        }
+                        if let Err(error) = raft.appended(&peer, reply).await {
+                            error!("Failed to handle Raft reply: {}", error);
+                        }
+                    },
+                    Err(status) => {
+                        if lock(&unreachable).insert(peer.clone()) {
+                            warn!(
+                                "Failed to send entries to Raft node {}: {}",
+                                peer,
+                                status.message()
+                            );
+                        }
+                    },
+                }
+            });
+        }
+    }
+}
+
+/// Locks a set of sentences or nodes, even if a task panicked with it.
+fn lock(set: &Mutex<HashSet<String>>) -> MutexGuard<'_, HashSet<String>> {
+    set.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Applies the committed changes to the namespaces, in order, then
+/// compacts the log.
+///
+/// The log is applied again from its snapshot once the node restarts, so
+/// changes already applied are skipped.
+pub async fn apply(consensus: Arc<Consensus>, namespaces: Arc<Namespaces>) {
+    let mut commits = consensus.raft.commits();
+    let snapshot = consensus.raft.snapshot().await;
+    let mut applied = snapshot.index;
+
+    for command in snapshot.commands {
+        if let Err(error) = execute(&consensus, &namespaces, command).await {
+            error!("Failed to apply Raft snapshot: {}", error);
+        }
+    }
+
+    loop {
+        let commit = *commits.borrow_and_update();
+
+        while applied < commit {
+            let to = commit.min(applied + APPLY_BATCH);
+            for entry in consensus.raft.entries(applied, to).await {
+                applied += 1;
+                let Some(command) = entry.command else {
+                    continue;
+                };
+
+                if let Err(error) =
+                    execute(&consensus, &namespaces, command).await
+                {
+                    error!("Failed to apply Raft entry {}: {}", applied, error);
+                }
+            }
+        }
+
+        if consensus.raft.compactable(applied).await {
+            let compacted = compact(&consensus.raft, &namespaces, applied);
+            if let Err(error) = compacted.await {
+                error!("Failed to compact Raft log: {}", error);
+            }
+        }
+
+        if commits.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Writes the sentences of each namespace to the disk, then drops the
+/// applied entries from the log.
+async fn compact(
+    raft: &Raft,
+    namespaces: &Namespaces,
+    applied: u64,
+) -> Result<(), Error> {
+    for namespace in namespaces.iter() {
+        namespace.sync().await?;
+        namespace.instance.write().await.flush()?;
+    }
+
+    raft.compact(applied).await
+}
+
+/// Applies a committed change to its namespace.
+async fn execute(
+    consensus: &Consensus,
+    namespaces: &Namespaces,
+    command: Command,
+) -> Result<(), Error> {
+    let namespace = namespaces.get(&command.namespace)?;
+
+    match command.change {
+        Change::Added(entity) => {
+            let reserved = consensus.release(&entity.id);
+            if namespace.contains(&entity.id).await || expired(&entity) {
+                if reserved {
+                    namespace.quotas.release(&entity);
+                }
+                return Ok(());
+            }
+
+            // The node which received the sentence enforced the quota.
+            if !reserved {
+                namespace.quotas.record(&entity);
+            }
+            namespace.add(*entity).await
+        },
+        Change::Touched { id, expire_at } => {
+            replica::touch(namespace, &id, expire_at).await
+        },
+        Change::Excluded(words) => {
+            replica::exclude(namespace, words.into_iter().collect()).await;
+            Ok(())
+        },
+    }
+}
+
+/// Returns whether a sentence expired, as when its entry is applied again
+/// after a restart.
+fn expired(entity: &Entity) -> bool {
+    let now = time::now();
+
+    entity
+        .ttl()
+        .is_some_and(|expire_at| expire_at != 0 && expire_at <= now)
+}
+
+/// Sends entries of the log to a node.
+async fn append(
+    raft: &Raft,
+    channel: Channel,
+    request: raft::AppendRequest,
+) -> Result<raft::AppendReply, Status> {
+    let request = AppendEntriesRequest::try_from(request)
+        .map_err(|error| error.to_status("failed to encode entries"))?;
+
+    Ok(AdminClient::new(channel)
+        .append_entries(authorized(raft, request)?)
+        .await?
+        .into_inner()
+        .into())
+}
+
+/// Wraps a message sent to another node with the API key, if any.
+fn authorized<T>(raft: &Raft, message: T) -> Result<Request<T>, Status> {
+    let mut request = Request::new(message);
+    if let Some(key) = &raft.api_key {
+        request.metadata_mut().insert(
+            "authorization",
+            key.parse()
+                .map_err(|_| Status::invalid_argument("invalid API key"))?,
+        );
+    }
+
+    Ok(request)
+}
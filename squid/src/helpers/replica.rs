@@ -0,0 +1,367 @@
+use crate::{
+    helpers::{
+        database,
+        namespace::{self, Namespace},
+    },
+    models::{
+        config::Primary,
+        database::{Entity, Meta},
+    },
+    squid::{
+        admin_client::AdminClient, change, Change, Entry, ExcludedWords,
+        ReplicateRequest, Touched, Void,
+    },
+};
+use squid_core::startup::{self, State};
+use squid_error::Error;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::sync::{broadcast::error::RecvError, mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Status};
+use tracing::{error, info, warn};
+
+/// Delay before following the primary again, doubled after each failure.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Maximum delay before following the primary again.
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+impl From<&Entity> for Entry {
+    fn from(entity: &Entity) -> Self {
+        Entry {
+            id: entity.id.clone(),
+            text: entity.post_processing_text.clone(),
+            original_text: entity.original_text.clone(),
+            lang: entity.lang.clone(),
+            meta: entity.meta.to_string(),
+            tags: entity.tags.clone(),
+            metadata: entity.metadata.clone(),
+            region: entity.region.clone(),
+            author_id: entity.author_id.clone(),
+        }
+    }
+}
+
+impl From<Entry> for Entity {
+    fn from(entry: Entry) -> Self {
+        Entity {
+            id: entry.id,
+            original_text: entry.original_text,
+            post_processing_text: entry.text,
+            lang: entry.lang,
+            meta: Meta::from(entry.meta.as_str()),
+            tags: entry.tags,
+            metadata: entry.metadata,
+            region: entry.region,
+            author_id: entry.author_id,
+        }
+    }
+}
+
+impl From<namespace::Change> for change::Change {
+    fn from(change: namespace::Change) -> Self {
+        match change {
+            namespace::Change::Added(entity) => {
+                change::Change::Added(Entry::from(&*entity))
+            },
+            namespace::Change::Touched { id, expire_at } => {
+                change::Change::Touched(Touched { id, expire_at })
+            },
+            namespace::Change::Excluded(words) => {
+                change::Change::Excluded(ExcludedWords { words })
+            },
+        }
+    }
+}
+
+/// Change sent once the expiration of a sentence is updated.
+pub fn touched(id: &str, expire_at: Option<u64>) -> namespace::Change {
+    namespace::Change::Touched {
+        id: id.to_string(),
+        expire_at,
+    }
+}
+
+/// Change sent once the excluded words are updated.
+pub async fn excluded(namespace: &Namespace) -> namespace::Change {
+    namespace::Change::Excluded(
+        namespace
+            .counters
+            .exclusions
+            .read()
+            .await
+            .iter()
+            .cloned()
+            .collect(),
+    )
+}
+
+/// Updates the expiration of a sentence as another server did, then
+/// sends the change to the replicas of this one.
+pub async fn touch(
+    namespace: &Namespace,
+    id: &str,
+    expire_at: Option<u64>,
+) -> Result<(), Error> {
+    // The sentence may still be waiting to be written.
+    namespace.sync().await?;
+    namespace
+        .instance
+        .write()
+        .await
+        .touch(id, expire_at)
+        .await?;
+    namespace.publish(touched(id, expire_at));
+
+    Ok(())
+}
+
+/// Replaces the excluded words by the ones of another server, then sends
+/// the change to the replicas of this one.
+pub async fn exclude(namespace: &Namespace, words: HashSet<String>) {
+    let current = namespace.counters.exclusions.read().await.clone();
+
+    database::include(
+        &namespace.counters,
+        &current.difference(&words).cloned().collect::<Vec<_>>(),
+    )
+    .await;
+    database::exclude(
+        &namespace.counters,
+        words.difference(&current).cloned().collect(),
+    )
+    .await;
+    namespace.publish(excluded(namespace).await);
+}
+
+/// Streams the content of a namespace, then each change made to it.
+///
+/// Replicas falling too far behind are disconnected, and must follow the
+/// namespace again.
+pub fn replicate(
+    namespace: Arc<Namespace>,
+) -> ReceiverStream<Result<Change, Status>> {
+    let (tx, rx) = mpsc::channel(16);
+
+    tokio::spawn(async move {
+        // Changes made while the content is sent are kept.
+        let mut changes = namespace.subscribe();
+        let send = |change: change::Change| {
+            tx.send(Ok(Change {
+                change: Some(change),
+            }))
+        };
+
+        if send(excluded(&namespace).await.into()).await.is_err() {
+            return;
+        }
+
+        let segments = match namespace.segments().await {
+            Ok(segments) => segments,
+            Err(error) => {
+                error!("Failed to list data files: {}", error);
+                let _ = tx
+                    .send(Err(error.to_status("failed to replicate")))
+                    .await;
+                return;
+            },
+        };
+
+        for segment in &segments {
+            let entities = match namespace.read(segment.as_deref()).await {
+                Ok(entities) => entities,
+                Err(error) => {
+                    error!("Failed to read memtable: {}", error);
+                    let _ = tx
+                        .send(Err(error.to_status("failed to replicate")))
+                        .await;
+                    return;
+                },
+            };
+
+            for entity in &entities {
+                if send(change::Change::Added(Entry::from(entity)))
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+        }
+
+        if send(change::Change::Synced(Void {})).await.is_err() {
+            return;
+        }
+
+        loop {
+            let change = match changes.recv().await {
+                Ok(change) => change,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!(
+                        namespace = namespace.service.name,
+                        "Disconnecting replica which missed {} changes.", missed
+                    );
+                    let _ = tx
+                        .send(Err(Status::aborted("replica fell behind")))
+                        .await;
+                    return;
+                },
+                Err(RecvError::Closed) => return,
+            };
+
+            if send(change.into()).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(rx)
+}
+
+/// Mirrors the namespace of the primary with the same name, following it
+/// again whenever the stream ends, until the replica is promoted.
+pub async fn follow(
+    namespace: Arc<Namespace>,
+    primary: Primary,
+    mut promoted: watch::Receiver<bool>,
+) {
+    let mut delay = RECONNECT_DELAY;
+
+    // Sentences removed by the primary are uncounted, so the stored ones
+    // are counted first.
+    let _ = startup::subscribe()
+        .wait_for(|progress| progress.state == State::Ready)
+        .await;
+
+    loop {
+        let result =
+            sync(&namespace, &primary, &mut delay, &mut promoted).await;
+        if *promoted.borrow() {
+            info!(
+                namespace = namespace.service.name,
+                "Stopped following primary {}.", primary.address
+            );
+            return;
+        }
+
+        match result {
+            Ok(()) => info!(
+                namespace = namespace.service.name,
+                "Primary {} closed the stream.", primary.address
+            ),
+            Err(error) => warn!(
+                namespace = namespace.service.name,
+                "Lost primary {}: {}", primary.address, error
+            ),
+        }
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = promoted.wait_for(|promoted| *promoted) => {},
+        }
+        delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+}
+
+/// Receives the content of the primary, removes the sentences it no longer
+/// has, then applies its changes until the stream ends or the replica is
+/// promoted.
+async fn sync(
+    namespace: &Namespace,
+    primary: &Primary,
+    delay: &mut Duration,
+    promoted: &mut watch::Receiver<bool>,
+) -> Result<(), Status> {
+    let mut client = AdminClient::connect(primary.address.clone())
+        .await
+        .map_err(|error| Status::unavailable(error.to_string()))?;
+
+    let mut request = Request::new(ReplicateRequest {
+        namespace: namespace.service.name.clone(),
+    });
+    if let Some(key) = &primary.api_key {
+        request.metadata_mut().insert(
+            "authorization",
+            key.parse()
+                .map_err(|_| Status::invalid_argument("invalid API key"))?,
+        );
+    }
+
+    let mut stream = client.replicate(request).await?.into_inner();
+    info!(
+        namespace = namespace.service.name,
+        "Following primary {}.", primary.address
+    );
+
+    // Sentences sent by the primary before it is synced.
+    let mut received = Some(HashSet::new());
+
+    loop {
+        // Changes are never applied partially.
+        let message = tokio::select! {
+            message = stream.message() => message?,
+            _ = promoted.wait_for(|promoted| *promoted) => return Ok(()),
+        };
+        let Some(Change { change }) = message else {
+            break;
+        };
+
+        match change {
+            Some(change::Change::Added(entry)) => {
+                if let Some(received) = &mut received {
+                    received.insert(entry.id.clone());
+                }
+
+                // Sentences added during the synchronization may be sent
+                // twice.
+                if !namespace.contains(&entry.id).await {
+                    let entity = Entity::from(entry);
+                    // The primary already enforced the quota.
+                    namespace.quotas.record(&entity);
+                    namespace.add(entity).await.map_err(|error| {
+                        error.to_status("failed to add sentence")
+                    })?;
+                }
+            },
+            Some(change::Change::Touched(touched)) => {
+                touch(namespace, &touched.id, touched.expire_at)
+                    .await
+                    .map_err(|error| {
+                        error.to_status("failed to update lifetime")
+                    })?;
+            },
+            Some(change::Change::Excluded(excluded)) => {
+                exclude(namespace, excluded.words.into_iter().collect()).await;
+            },
+            Some(change::Change::Synced(_)) => {
+                let received = received.take().unwrap_or_default();
+                namespace.sync().await.map_err(|error| {
+                    error.to_status("failed to write sentences")
+                })?;
+
+                // Deleted or expired on the primary while not followed.
+                let mut removed = 0;
+                for id in namespace.ids().await.difference(&received) {
+                    namespace
+                        .instance
+                        .write()
+                        .await
+                        .expire_now(id)
+                        .await
+                        .map_err(|error| {
+                            error.to_status("failed to remove sentence")
+                        })?;
+                    removed += 1;
+                }
+
+                info!(
+                    namespace = namespace.service.name,
+                    "Synced with primary, removed {} sentences.", removed
+                );
+                *delay = RECONNECT_DELAY;
+            },
+            None => {},
+        }
+    }
+
+    Ok(())
+}
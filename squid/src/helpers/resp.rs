@@ -0,0 +1,484 @@
+use crate::{
+    helpers,
+    models::config::Scope,
+    service::SuperSquid,
+    squid::{squid_server::Squid, AddRequest, LeaderboardRequest},
+};
+use std::net::SocketAddr;
+use tokio::{
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader,
+    },
+    net::{TcpListener, TcpStream},
+};
+use tonic::{
+    metadata::MetadataValue, service::Interceptor,
+    transport::server::TcpConnectInfo, Code, Request, Status,
+};
+use tracing::{info, trace};
+
+/// Maximum length of a line, such as an inline command or a header.
+const MAX_LINE_LENGTH: u64 = 64 * 1024;
+/// Maximum length of an argument.
+const MAX_ARGUMENT_LENGTH: usize = 1024 * 1024;
+/// Maximum number of arguments of a command.
+const MAX_ARGUMENTS: usize = 64;
+
+/// A reply in the Redis serialization protocol.
+#[derive(Debug)]
+enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(String),
+    Array(Vec<Reply>),
+}
+
+impl Reply {
+    fn encode(&self, buffer: &mut Vec<u8>) {
+        match self {
+            Reply::Simple(value) => {
+                buffer.extend_from_slice(format!("+{}\r\n", value).as_bytes())
+            },
+            Reply::Error(message) => {
+                // A new line would end the error early.
+                let message = message.replace(['\r', '\n'], " ");
+                buffer.extend_from_slice(format!("-{}\r\n", message).as_bytes())
+            },
+            Reply::Integer(value) => {
+                buffer.extend_from_slice(format!(":{}\r\n", value).as_bytes())
+            },
+            Reply::Bulk(value) => {
+                buffer.extend_from_slice(
+                    format!("${}\r\n", value.len()).as_bytes(),
+                );
+                buffer.extend_from_slice(value.as_bytes());
+                buffer.extend_from_slice(b"\r\n");
+            },
+            Reply::Array(values) => {
+                buffer.extend_from_slice(
+                    format!("*{}\r\n", values.len()).as_bytes(),
+                );
+                for value in values {
+                    value.encode(buffer);
+                }
+            },
+        }
+    }
+}
+
+impl From<Status> for Reply {
+    fn from(status: Status) -> Self {
+        let prefix = match status.code() {
+            Code::Unauthenticated => "NOAUTH",
+            Code::PermissionDenied => "NOPERM",
+            _ => "ERR",
+        };
+
+        Reply::Error(format!("{} {}", prefix, status.message()))
+    }
+}
+
+/// Starts the RESP listener, so Redis clients can use Squid through the
+/// `SQUID.ADD`, `SQUID.TOP` and `SQUID.COUNT` commands.
+///
+/// Requests go through the same checks as gRPC requests. If authentication
+/// is enabled, clients send their API key with `AUTH` first.
+pub async fn serve<I>(
+    addr: SocketAddr,
+    squid: SuperSquid,
+    interceptor: I,
+) -> std::io::Result<()>
+where
+    I: Interceptor + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind(addr).await?;
+    info!("RESP listener started on {}", addr);
+
+    loop {
+        let (stream, remote_addr) = listener.accept().await?;
+        let connection = Connection {
+            squid: squid.clone(),
+            interceptor: interceptor.clone(),
+            info: TcpConnectInfo {
+                local_addr: stream.local_addr().ok(),
+                remote_addr: Some(remote_addr),
+            },
+            key: None,
+        };
+
+        tokio::spawn(async move {
+            if let Err(error) = connection.run(stream).await {
+                trace!(
+                    "RESP connection from {} closed: {}",
+                    remote_addr,
+                    error
+                );
+            }
+        });
+    }
+}
+
+/// A client connected to the RESP listener.
+struct Connection<I> {
+    squid: SuperSquid,
+    interceptor: I,
+    /// Addresses of the connection, used to limit requests.
+    info: TcpConnectInfo,
+    /// API key sent with `AUTH`, if any.
+    key: Option<String>,
+}
+
+impl<I: Interceptor> Connection<I> {
+    /// Replies to each command until the client disconnects.
+    async fn run(mut self, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+        let mut buffer = Vec::new();
+
+        loop {
+            // Whether the connection is closed once the reply is sent.
+            let (reply, close) = match read_command(&mut reader).await {
+                Ok(Some(arguments)) if arguments.is_empty() => continue,
+                Ok(Some(arguments)) => {
+                    let quit = arguments[0].eq_ignore_ascii_case("QUIT");
+                    (self.execute(arguments).await, quit)
+                },
+                Ok(None) => return Ok(()),
+                // The stream cannot be parsed any further.
+                Err(error)
+                    if error.kind() == std::io::ErrorKind::InvalidData =>
+                {
+                    let message = format!("ERR Protocol error: {}", error);
+                    (Reply::Error(message), true)
+                },
+                Err(error) => return Err(error),
+            };
+
+            buffer.clear();
+            reply.encode(&mut buffer);
+            writer.write_all(&buffer).await?;
+
+            if close {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs a command and returns its reply.
+    async fn execute(&mut self, arguments: Vec<String>) -> Reply {
+        let mut arguments = arguments.into_iter();
+        let command = arguments.next().unwrap_or_default().to_ascii_uppercase();
+        let arguments: Vec<String> = arguments.collect();
+
+        let result = match (command.as_str(), arguments.as_slice()) {
+            ("PING", []) => Ok(Reply::Simple("PONG".to_string())),
+            ("PING", [message]) => Ok(Reply::Bulk(message.clone())),
+            ("QUIT", []) => Ok(Reply::Simple("OK".to_string())),
+            // Redis 6 clients send a user name before the password.
+            ("AUTH", [key]) | ("AUTH", [_, key]) => self.auth(key),
+            ("SQUID.ADD", [sentence]) => self.add(sentence, "").await,
+            ("SQUID.ADD", [sentence, namespace]) => {
+                self.add(sentence, namespace).await
+            },
+            ("SQUID.TOP", [length]) => self.top(length, "").await,
+            ("SQUID.TOP", [length, namespace]) => {
+                self.top(length, namespace).await
+            },
+            ("SQUID.COUNT", [word]) => self.count(word, "").await,
+            ("SQUID.COUNT", [word, namespace]) => {
+                self.count(word, namespace).await
+            },
+            (
+                "PING" | "QUIT" | "AUTH" | "SQUID.ADD" | "SQUID.TOP"
+                | "SQUID.COUNT",
+                _,
+            ) => Err(Status::invalid_argument(format!(
+                "wrong number of arguments for '{}' command",
+                command.to_ascii_lowercase()
+            ))),
+            _ => Err(Status::unimplemented(format!(
+                "unknown command '{}'",
+                command.to_ascii_lowercase()
+            ))),
+        };
+
+        result.unwrap_or_else(Reply::from)
+    }
+
+    /// Authenticates the following commands with an API key.
+    fn auth(&mut self, key: &str) -> Result<Reply, Status> {
+        let previous = self.key.replace(key.to_string());
+        if let Err(status) = self.request(()) {
+            self.key = previous;
+            return Err(status);
+        }
+
+        Ok(Reply::Simple("OK".to_string()))
+    }
+
+    /// Wraps a message into a request checked like a gRPC request.
+    fn request<T>(&mut self, message: T) -> Result<Request<T>, Status> {
+        let mut request = Request::new(());
+        request.extensions_mut().insert(self.info.clone());
+        if let Some(key) = &self.key {
+            let value = MetadataValue::try_from(key.as_str())
+                .map_err(|_| Status::unauthenticated("invalid API key"))?;
+            request.metadata_mut().insert("authorization", value);
+        }
+
+        let (metadata, extensions, _) =
+            self.interceptor.call(request)?.into_parts();
+        Ok(Request::from_parts(metadata, extensions, message))
+    }
+
+    /// `SQUID.ADD sentence [namespace]`, replies with the identifier of the
+    /// sentence.
+    async fn add(
+        &mut self,
+        sentence: &str,
+        namespace: &str,
+    ) -> Result<Reply, Status> {
+        let request = self.request(AddRequest {
+            sentence: sentence.to_string(),
+            namespace: namespace.to_string(),
+            ..Default::default()
+        })?;
+        let reply = self.squid.add(request).await?.into_inner();
+
+        Ok(Reply::Bulk(reply.id))
+    }
+
+    /// `SQUID.TOP length [namespace]`, replies with the most used words
+    /// followed by their occurrences.
+    async fn top(
+        &mut self,
+        length: &str,
+        namespace: &str,
+    ) -> Result<Reply, Status> {
+        let length = length
+            .parse()
+            .map_err(|_| Status::invalid_argument("value is not an integer"))?;
+        let request = self.request(LeaderboardRequest {
+            length,
+            namespace: namespace.to_string(),
+            ..Default::default()
+        })?;
+        let ranking = self.squid.leaderboard(request).await?.into_inner();
+
+        Ok(Reply::Array(
+            ranking
+                .word
+                .into_iter()
+                .flat_map(|word| {
+                    [
+                        Reply::Bulk(word.word),
+                        Reply::Integer(
+                            word.occurence.try_into().unwrap_or(i64::MAX),
+                        ),
+                    ]
+                })
+                .collect(),
+        ))
+    }
+
+    /// `SQUID.COUNT word [namespace]`, replies with the occurrences of a
+    /// word.
+    async fn count(
+        &mut self,
+        word: &str,
+        namespace: &str,
+    ) -> Result<Reply, Status> {
+        let request = self.request(())?;
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let namespace = self.squid.namespaces.get(namespace)?;
+        let occurrences = namespace
+            .counters
+            .algorithm
+            .read()
+            .await
+            .get(&word.replace(' ', "%20"));
+
+        Ok(Reply::Integer(occurrences.try_into().unwrap_or(i64::MAX)))
+    }
+}
+
+/// Reads the next command, either as an array of bulk strings or inline.
+///
+/// Returns `None` once the client disconnects.
+async fn read_command<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Option<Vec<String>>> {
+    let Some(line) = read_line(reader).await? else {
+        return Ok(None);
+    };
+
+    let Some(count) = line.strip_prefix('*') else {
+        return Ok(Some(line.split_whitespace().map(str::to_string).collect()));
+    };
+    let count: usize = count
+        .parse()
+        .ok()
+        .filter(|count| *count <= MAX_ARGUMENTS)
+        .ok_or_else(|| invalid_data("invalid multibulk length"))?;
+
+    let mut arguments = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_line(reader)
+            .await?
+            .ok_or_else(|| invalid_data("unexpected end of stream"))?;
+        let length: usize = line
+            .strip_prefix('$')
+            .and_then(|length| length.parse().ok())
+            .filter(|length| *length <= MAX_ARGUMENT_LENGTH)
+            .ok_or_else(|| invalid_data("invalid bulk length"))?;
+
+        // The argument is followed by `\r\n`.
+        let mut argument = vec![0; length + 2];
+        reader.read_exact(&mut argument).await?;
+        if !argument.ends_with(b"\r\n") {
+            return Err(invalid_data("expected CRLF after bulk string"));
+        }
+        argument.truncate(length);
+        arguments.push(
+            String::from_utf8(argument)
+                .map_err(|_| invalid_data("argument is not valid UTF-8"))?,
+        );
+    }
+
+    Ok(Some(arguments))
+}
+
+/// Reads a line without its `\r\n`.
+///
+/// Returns `None` once the client disconnects.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> std::io::Result<Option<String>> {
+    let mut line = Vec::new();
+    (&mut *reader)
+        .take(MAX_LINE_LENGTH)
+        .read_until(b'\n', &mut line)
+        .await?;
+
+    if !line.ends_with(b"\n") {
+        return match line.len() as u64 {
+            MAX_LINE_LENGTH => Err(invalid_data("line too long")),
+            // The client disconnected.
+            _ => Ok(None),
+        };
+    }
+
+    let line = String::from_utf8(line)
+        .map_err(|_| invalid_data("line is not valid UTF-8"))?;
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+fn invalid_data(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn read(mut input: &[u8]) -> std::io::Result<Option<Vec<String>>> {
+        read_command(&mut input).await
+    }
+
+    fn message(result: std::io::Result<Option<Vec<String>>>) -> String {
+        let error = result.unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        error.to_string()
+    }
+
+    #[tokio::test]
+    async fn test_read_inline_command() {
+        assert_eq!(
+            read(b"SQUID.TOP  10 default\r\n").await.unwrap(),
+            Some(vec![
+                "SQUID.TOP".to_string(),
+                "10".to_string(),
+                "default".to_string()
+            ])
+        );
+        // Lines may only end with `\n`.
+        assert_eq!(
+            read(b"PING\n").await.unwrap(),
+            Some(vec!["PING".to_string()])
+        );
+        assert_eq!(read(b"\r\n").await.unwrap(), Some(Vec::new()));
+        assert_eq!(read(b"").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_read_bulk_strings() {
+        assert_eq!(
+            read(b"*2\r\n$9\r\nSQUID.ADD\r\n$12\r\nhello\r\nworld\r\n")
+                .await
+                .unwrap(),
+            Some(vec!["SQUID.ADD".to_string(), "hello\r\nworld".to_string()])
+        );
+        assert_eq!(
+            read(b"*1\r\n$0\r\n\r\n").await.unwrap(),
+            Some(vec![String::new()])
+        );
+        assert_eq!(read(b"*0\r\n").await.unwrap(), Some(Vec::new()));
+    }
+
+    #[tokio::test]
+    async fn test_reject_oversized_lengths() {
+        let arguments = format!("*{}\r\n", MAX_ARGUMENTS + 1);
+        assert_eq!(
+            message(read(arguments.as_bytes()).await),
+            "invalid multibulk length"
+        );
+
+        let argument = format!("*1\r\n${}\r\n", MAX_ARGUMENT_LENGTH + 1);
+        assert_eq!(
+            message(read(argument.as_bytes()).await),
+            "invalid bulk length"
+        );
+
+        let line = vec![b'a'; MAX_LINE_LENGTH as usize + 1];
+        assert_eq!(message(read(&line).await), "line too long");
+    }
+
+    #[tokio::test]
+    async fn test_reject_negative_lengths() {
+        assert_eq!(message(read(b"*-1\r\n").await), "invalid multibulk length");
+        assert_eq!(
+            message(read(b"*1\r\n$-1\r\n").await),
+            "invalid bulk length"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_truncated_input() {
+        assert_eq!(
+            message(read(b"*2\r\n$4\r\nPING\r\n").await),
+            "unexpected end of stream"
+        );
+        assert_eq!(
+            read(b"*1\r\n$4\r\nPI").await.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            message(read(b"*1\r\n$4").await),
+            "unexpected end of stream"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_missing_crlf() {
+        assert_eq!(
+            message(read(b"*1\r\n$4\r\nPINGXX").await),
+            "expected CRLF after bulk string"
+        );
+        assert_eq!(
+            message(read(b"*1\r\nPING\r\n").await),
+            "invalid bulk length"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+//! Logs the gRPC requests slower than a threshold, with the time spent in
+//! each phase, sampled so a loaded server does not flood its logs.
+
+use crate::models::config::SlowQueries;
+use squid_core::profile::{self, Phases};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tonic::{
+    codegen::{http, BoxFuture, Context, Poll, Service},
+    server::NamedService,
+};
+use tracing::warn;
+
+/// Milliseconds from which a request is slow if not configured.
+const DEFAULT_THRESHOLD_MS: u64 = 500;
+/// Slow requests logged per second if not configured.
+const DEFAULT_MAX_PER_SECOND: u32 = 10;
+
+/// Slow requests of the current second.
+#[derive(Debug, Default)]
+struct Window {
+    start: Option<Instant>,
+    /// Slow requests logged during the second.
+    logged: u32,
+    /// Slow requests seen since startup, logged or not.
+    seen: u64,
+    /// Slow requests not logged since the last log.
+    skipped: u64,
+}
+
+/// Logs slow requests.
+#[derive(Debug)]
+pub struct SlowLog {
+    threshold: Duration,
+    /// Thresholds of specific methods.
+    methods: HashMap<String, Duration>,
+    sample_rate: f64,
+    max_per_second: u32,
+    window: Mutex<Window>,
+}
+
+impl SlowLog {
+    /// Creates a new [`SlowLog`] from the configuration.
+    pub fn new(config: &SlowQueries) -> Self {
+        Self {
+            threshold: Duration::from_millis(
+                config.threshold_ms.unwrap_or(DEFAULT_THRESHOLD_MS),
+            ),
+            methods: config
+                .methods
+                .iter()
+                .map(|(method, threshold)| {
+                    (method.clone(), Duration::from_millis(*threshold))
+                })
+                .collect(),
+            sample_rate: config.sample_rate.unwrap_or(1.0).clamp(0.0, 1.0),
+            max_per_second: config
+                .max_per_second
+                .unwrap_or(DEFAULT_MAX_PER_SECOND),
+            window: Mutex::default(),
+        }
+    }
+
+    /// Returns the threshold of a method, from its path.
+    fn threshold(&self, path: &str) -> Duration {
+        // Paths are `/<package>.<service>/<method>`.
+        path.rsplit('/')
+            .next()
+            .and_then(|method| self.methods.get(method))
+            .copied()
+            .unwrap_or(self.threshold)
+    }
+
+    /// Logs a request if it is slow, unless sampled out or too many slow
+    /// requests were already logged during the current second.
+    fn record(&self, path: &str, elapsed: Duration, phases: Phases) {
+        if elapsed < self.threshold(path) {
+            return;
+        }
+
+        let skipped = {
+            let mut window =
+                self.window.lock().unwrap_or_else(PoisonError::into_inner);
+            let now = Instant::now();
+            if window.start.is_none_or(|start| {
+                now.duration_since(start) >= Duration::from_secs(1)
+            }) {
+                window.start = Some(now);
+                window.logged = 0;
+            }
+
+            // Sampled requests are spread evenly.
+            window.seen += 1;
+            let sampled = (window.seen as f64 * self.sample_rate).floor()
+                > ((window.seen - 1) as f64 * self.sample_rate).floor();
+            if !sampled || window.logged >= self.max_per_second {
+                window.skipped += 1;
+                return;
+            }
+
+            window.logged += 1;
+            std::mem::take(&mut window.skipped)
+        };
+
+        warn!(
+            method = path,
+            skipped,
+            "Slow request took {:?}: {:?} tokenizing, {:?} in the database, {:?} in the algorithm.",
+            elapsed,
+            phases.tokenization,
+            phases.database,
+            phases.algorithm
+        );
+    }
+}
+
+/// Times the requests of a gRPC service, logging the slow ones.
+///
+/// Streams are timed until they start.
+#[derive(Debug, Clone)]
+pub struct Timed<S> {
+    inner: S,
+    log: Option<Arc<SlowLog>>,
+}
+
+impl<S> Timed<S> {
+    /// Times the requests of a service if slow requests are logged.
+    pub fn new(inner: S, log: Option<Arc<SlowLog>>) -> Self {
+        Self { inner, log }
+    }
+}
+
+impl<S, B> Service<http::Request<B>> for Timed<S>
+where
+    S: Service<http::Request<B>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<S::Response, S::Error>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let path = request.uri().path().to_string();
+        let future = self.inner.call(request);
+
+        match &self.log {
+            Some(log) => {
+                let log = Arc::clone(log);
+                Box::pin(async move {
+                    let start = Instant::now();
+                    let (response, phases) = profile::scope(future).await;
+                    log.record(&path, start.elapsed(), phases);
+                    response
+                })
+            },
+            None => Box::pin(future),
+        }
+    }
+}
+
+impl<S: NamedService> NamedService for Timed<S> {
+    const NAME: &'static str = S::NAME;
+}
@@ -0,0 +1,136 @@
+use crate::{
+    helpers::{namespace::Namespaces, replica},
+    models::config::{Failover, Primary},
+    squid::{admin_client::AdminClient, Void},
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::MissedTickBehavior};
+use tonic::{transport::Endpoint, Request, Status};
+use tracing::{info, warn};
+
+/// Seconds between two health checks if not configured.
+const DEFAULT_INTERVAL_SEC: u64 = 5;
+/// Health checks failed in a row before a promotion if not configured.
+const DEFAULT_FAILURES: u32 = 3;
+
+/// A replica following a primary until it is promoted, then accepting
+/// changes itself.
+#[derive(Debug)]
+pub struct Standby {
+    pub primary: Primary,
+    promoted: watch::Sender<bool>,
+}
+
+impl Standby {
+    /// Follows the primary with every namespace.
+    pub fn follow(namespaces: &Namespaces, primary: Primary) -> Arc<Self> {
+        let standby = Arc::new(Self {
+            primary,
+            promoted: watch::channel(false).0,
+        });
+
+        for namespace in namespaces.iter() {
+            tokio::spawn(replica::follow(
+                Arc::clone(namespace),
+                standby.primary.clone(),
+                standby.promoted.subscribe(),
+            ));
+        }
+
+        standby
+    }
+
+    /// Returns whether changes are only received from the primary.
+    pub fn read_only(&self) -> bool {
+        !*self.promoted.borrow()
+    }
+
+    /// Stops following the primary, so changes are accepted.
+    ///
+    /// Changes being applied are applied entirely first. The promotion
+    /// lasts until the server restarts, remove `primary` from the
+    /// configuration to keep it.
+    ///
+    /// Returns `false` if the replica was already promoted.
+    pub fn promote(&self) -> bool {
+        let promoted = !self.promoted.send_replace(true);
+        if promoted {
+            warn!(
+                "Promoted to primary, {} is no longer followed.",
+                self.primary.address
+            );
+        }
+
+        promoted
+    }
+}
+
+/// Checks the health of the primary at each interval, and promotes the
+/// replica once too many checks failed in a row.
+///
+/// A primary unreachable from the replica only may still accept changes,
+/// which are then lost for the replica.
+pub async fn monitor(standby: Arc<Standby>, failover: Failover) {
+    let period = Duration::from_secs(
+        failover.interval.unwrap_or(DEFAULT_INTERVAL_SEC).max(1),
+    );
+    let limit = failover.failures.unwrap_or(DEFAULT_FAILURES).max(1);
+    let mut failures = 0;
+
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    while standby.read_only() {
+        interval.tick().await;
+
+        match check(&standby.primary, period).await {
+            Ok(()) => {
+                if failures > 0 {
+                    info!(
+                        "Primary {} is healthy again.",
+                        standby.primary.address
+                    );
+                }
+                failures = 0;
+            },
+            Err(status) => {
+                failures += 1;
+                warn!(
+                    "Health check {}/{} of primary {} failed: {}",
+                    failures,
+                    limit,
+                    standby.primary.address,
+                    status.message()
+                );
+
+                if failures >= limit {
+                    standby.promote();
+                }
+            },
+        }
+    }
+}
+
+/// Asks the statistics of the primary, failing if it does not reply in
+/// time.
+async fn check(primary: &Primary, timeout: Duration) -> Result<(), Status> {
+    let channel = Endpoint::from_shared(primary.address.clone())
+        .map_err(|error| Status::invalid_argument(error.to_string()))?
+        .connect_timeout(timeout)
+        .timeout(timeout)
+        .connect()
+        .await
+        .map_err(|error| Status::unavailable(error.to_string()))?;
+
+    let mut request = Request::new(Void {});
+    if let Some(key) = &primary.api_key {
+        request.metadata_mut().insert(
+            "authorization",
+            key.parse()
+                .map_err(|_| Status::invalid_argument("invalid API key"))?,
+        );
+    }
+
+    AdminClient::new(channel).stats(request).await?;
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Integration with systemd: serving the sockets it opened for Squid, and
+//! notifying it once Squid is ready.
+//!
+//! Both are skipped when Squid is not started by systemd, as the
+//! environment variables it sets are missing.
+
+#[cfg(unix)]
+use std::{
+    env,
+    ffi::OsStr,
+    io,
+    os::{
+        fd::{FromRawFd, OwnedFd, RawFd},
+        unix::net::UnixDatagram,
+    },
+};
+use squid_core::startup::{self, State};
+use std::{
+    net::TcpListener,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(unix)]
+use tracing::warn;
+
+/// First file descriptor passed by systemd, after the standard streams.
+#[cfg(unix)]
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Whether the sockets passed by systemd were taken.
+static TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns the listeners passed by systemd through socket activation, to
+/// be served instead of binding the gRPC port.
+///
+/// systemd keeps the sockets open while Squid restarts, so clients
+/// connecting meanwhile wait instead of being refused.
+///
+/// # Panics
+///
+/// This function panics if a passed socket is not a TCP listener.
+pub fn listeners() -> Vec<TcpListener> {
+    // Sockets are owned by the first caller only.
+    if TAKEN.swap(true, Ordering::SeqCst) {
+        return Vec::new();
+    }
+
+    #[cfg(unix)]
+    {
+        // The variables may be inherited from a parent process.
+        if env::var("LISTEN_PID")
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok())
+            != Some(std::process::id())
+        {
+            return Vec::new();
+        }
+        let count = env::var("LISTEN_FDS")
+            .ok()
+            .and_then(|count| count.parse::<RawFd>().ok())
+            .unwrap_or_default();
+
+        (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count))
+            .map(|fd| {
+                // SAFETY: systemd passes `LISTEN_FDS` open descriptors
+                // from 3 to the process named by `LISTEN_PID`, checked
+                // above. Nothing else in Squid uses them, and they are
+                // only taken once.
+                #[allow(unsafe_code)]
+                let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+                let listener = TcpListener::from(fd);
+
+                listener
+                    .local_addr()
+                    .and_then(|_| listener.set_nonblocking(true))
+                    .map(|()| listener)
+                    .unwrap_or_else(|error| {
+                        panic!("Invalid socket passed by systemd: {}", error)
+                    })
+            })
+            .collect()
+    }
+
+    #[cfg(not(unix))]
+    Vec::new()
+}
+
+/// Tells systemd Squid is ready once every stored sentence is counted, as
+/// `/health` does, since leaderboards are incomplete before.
+pub async fn notify_ready() {
+    // Fails only if the progress is dropped, which it never is.
+    let _ = startup::subscribe()
+        .wait_for(|progress| progress.state == State::Ready)
+        .await;
+    notify("READY=1");
+}
+
+/// Sends a state to systemd, such as `READY=1`, if it waits for one.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    {
+        let Some(path) = env::var_os("NOTIFY_SOCKET") else {
+            return;
+        };
+
+        if let Err(error) = UnixDatagram::unbound()
+            .and_then(|socket| send(&socket, &path, state))
+        {
+            warn!("Failed to notify systemd of {:?}: {}", state, error);
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Sends a state to the socket of systemd.
+#[cfg(unix)]
+fn send(socket: &UnixDatagram, path: &OsStr, state: &str) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    use std::os::{
+        linux::net::SocketAddrExt,
+        unix::{ffi::OsStrExt, net::SocketAddr},
+    };
+
+    // Abstract sockets start with `@`.
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.as_bytes().strip_prefix(b"@") {
+        let address = SocketAddr::from_abstract_name(name)?;
+        return socket.send_to_addr(state.as_bytes(), &address).map(|_| ());
+    }
+
+    socket.send_to(state.as_bytes(), path).map(|_| ())
+}
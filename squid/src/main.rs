@@ -1,94 +1,83 @@
-#![forbid(unsafe_code)]
+// Only the sockets passed by systemd are taken through unsafe code.
+#![deny(unsafe_code)]
+// `tonic::Status` is large, but it is what handlers must return.
+#![allow(clippy::result_large_err)]
 
+mod admin;
 mod helpers;
-mod models;
+mod service;
+mod v1;
+mod v2;
 
-#[macro_use]
-extern crate lazy_static;
-
-use crate::models::database::Entity;
-use squid::{
-    squid_server::{Squid, SquidServer},
-    {AddRequest, LeaderboardRequest, Ranking, Void, Word},
+use crate::{
+    admin::SuperAdmin,
+    helpers::{
+        namespace::{Namespaces, DEFAULT_DATA_DIR},
+        raft::Consensus,
+        slow::Timed,
+        standby::Standby,
+    },
+    service::{SuperSquid, CLOSING},
+};
+use squid::{admin_server::AdminServer, squid_server::SquidServer};
+use squid_core::{
+    audit::{Audit, AUDIT_DIR},
+    cluster::Cluster,
+    models,
+    raft::Raft,
 };
-use squid_tokenizer::tokenize;
 use std::{
-    ops::Add,
-    sync::Arc,
-    time::{Duration, SystemTime, UNIX_EPOCH},
+    path::Path,
+    pin::Pin,
+    sync::{atomic::Ordering, Arc},
+    time::{Duration, Instant},
 };
-use tokio::signal;
-use tokio::sync::{mpsc, RwLock};
-use tonic::{transport::Server, Request, Response, Status};
-use tracing::{error, info, Level};
+use tokio::{net::TcpStream, signal};
+use tokio_stream::{Stream, StreamExt};
+use tonic::{
+    server::NamedService,
+    service::Interceptor,
+    transport::{server::TcpIncoming, Server},
+};
+use tracing::{error, info, warn, Level};
 use tracing_subscriber::fmt;
 
 pub mod squid {
     tonic::include_proto!("squid");
-}
-struct SuperSquid {
-    algorithm: helpers::database::Algorithm,
-    config: models::config::Config,
-    instance: Arc<RwLock<squid_db::Instance<models::database::Entity>>>,
-}
 
-const FLUSHTABLE_FLUSH_SIZE_KB: usize = 100; // wait 100kb on memtable before save it on disk.
-
-#[tonic::async_trait]
-impl Squid for SuperSquid {
-    async fn leaderboard(
-        &self,
-        request: Request<LeaderboardRequest>,
-    ) -> Result<Response<Ranking>, Status> {
-        Ok(Response::new(Ranking {
-            word: helpers::database::rank(
-                self.algorithm.clone(),
-                request.into_inner().length as usize,
-            )
-            .await
-            .iter()
-            .map(|(word, occurence)| Word {
-                word: word.to_string().replace("%20", " "),
-                occurence: (*occurence).try_into().unwrap_or_default(),
-            })
-            .collect::<Vec<_>>(),
-        }))
+    pub mod v2 {
+        tonic::include_proto!("squid.v2");
     }
+}
 
-    async fn add(&self, request: Request<AddRequest>) -> Result<Response<Void>, Status> {
-        let data = request.into_inner();
-
-        helpers::database::set(
-            &self.config,
-            Arc::clone(&self.instance),
-            self.algorithm.clone(),
-            models::database::Entity {
-                id: uuid::Uuid::new_v4().to_string(),
-                original_text: None,
-                post_processing_text: tokenize(&data.sentence).map_err(|error| {
-                    error!("Failed to tokenize {:?}: {}", data.sentence, error);
-                    Status::invalid_argument("failed to tokenize sentence")
-                })?,
-                lang: "fr".to_string(),
-                meta: if data.lifetime == 0 {
-                    String::default()
-                } else {
-                    format!(
-                        "expire_at:{}",
-                        SystemTime::now()
-                            .duration_since(UNIX_EPOCH)
-                            .unwrap_or_default()
-                            .add(Duration::from_secs(data.lifetime))
-                            .as_secs()
-                    )
-                },
-            },
-        )
-        .await
-        .unwrap();
+/// Seconds allowed to save data once a shutdown is requested, if not
+/// configured.
+const DEFAULT_SHUTDOWN_TIMEOUT_SEC: u64 = 25;
+
+/// Waits for CTRL+C, or for SIGTERM and SIGQUIT on Unix.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate())
+            .expect("failed to listen for SIGTERM");
+        let mut quit =
+            signal(SignalKind::quit()).expect("failed to listen for SIGQUIT");
 
-        Ok(Response::new(Void {}))
+        tokio::select! {
+            result = signal::ctrl_c() => {
+                result.expect("failed to listen for ctrl+c event")
+            },
+            _ = terminate.recv() => {},
+            _ = quit.recv() => {},
+        }
     }
+
+    #[cfg(not(unix))]
+    signal::ctrl_c()
+        .await
+        .expect("failed to listen for ctrl+c event");
 }
 
 #[tokio::main]
@@ -109,92 +98,232 @@ async fn main() {
         .with_max_level(Level::TRACE)
         .init();
 
+    let started_at = Instant::now();
     let config = helpers::config::read();
 
-    // Set producer channel to receive expired sentences.
-    let (tx, mut rx) = mpsc::channel::<Entity>(2305843009213693951);
+    // Start one database per service.
+    let namespaces = Arc::new(Namespaces::open(&config).await.unwrap());
 
-    // Start database.
-    let instance = squid_db::Builder::default()
-        .memtable_flush_size(FLUSHTABLE_FLUSH_SIZE_KB)
-        .mpsc_sender(tx)
-        .with_ttl()
-        .build()
-        .await
-        .unwrap();
-    info!(
-        "Loaded instance with {} entities.",
-        instance.read().await.entries.len()
+    // Waiting for a signal to save memtables.
+    let shutdown_namespaces = Arc::clone(&namespaces);
+    let shutdown_timeout = Duration::from_secs(
+        config
+            .shutdown_timeout
+            .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT_SEC),
     );
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        info!("Shutting down, changes are now rejected.");
+        CLOSING.store(true, Ordering::SeqCst);
+        helpers::systemd::notify("STOPPING=1");
 
-    // Chose algorithm.
-    let algo = Arc::new(RwLock::new(match config.service.algorithm {
-        models::config::Algorithm::Hashmap => squid_algorithm::hashtable::MapAlgorithm::default(),
-    }));
-
-    // Init MPSC consumer.
-    let ttl_algo = Arc::clone(&algo);
-    tokio::task::spawn(async move {
-        while let Some(data) = rx.recv().await {
-            for word in data.post_processing_text.split_ascii_whitespace() {
-                ttl_algo.write().await.remove(word);
-            }
+        if tokio::time::timeout(shutdown_timeout, shutdown_namespaces.close())
+            .await
+            .is_err()
+        {
+            error!(
+                "Shutdown took more than {} seconds, some data may be lost.",
+                shutdown_timeout.as_secs()
+            );
+            std::process::exit(1);
         }
+        info!("Closing Squid server...");
+        std::process::exit(0);
     });
 
-    // Add each words to algorithm.
-    for data in &instance.read().await.entries {
-        for str in data.post_processing_text.split_whitespace() {
-            if !config.service.exclude.contains(&str.to_string()) {
-                match config.service.message_type {
-                    models::config::MessageType::Hashtag => {
-                        if str.starts_with('#') {
-                            algo.write().await.set(str)
-                        }
-                    }
-                    models::config::MessageType::Word => {
-                        if !str.starts_with('#') {
-                            algo.write().await.set(str)
-                        }
-                    }
-                    _ => algo.write().await.set(str),
-                }
-            }
+    // Mirror the primary, if any, until promoted.
+    let standby = config
+        .primary
+        .as_ref()
+        .map(|primary| Standby::follow(&namespaces, primary.clone()));
+    if let Some(standby) = &standby {
+        if let Some(failover) = &standby.primary.failover {
+            tokio::spawn(helpers::standby::monitor(
+                Arc::clone(standby),
+                failover.clone(),
+            ));
         }
     }
 
-    // Waiting for CTRL+C to save memtable.
-    let ctrlc_instance = Arc::clone(&instance);
-    tokio::spawn(async move {
-        signal::ctrl_c()
-            .await
-            .expect("failed to listen for ctrl+c event");
-        if FLUSHTABLE_FLUSH_SIZE_KB > 0 {
-            info!("Flushing memtable...");
-            if let Err(err) = ctrlc_instance.write().await.flush() {
-                error!("Some data haven't been flushed from memtable: {}", err);
-            }
+    // Share counts with the other nodes, if any.
+    let cluster = config.cluster.as_ref().map(|cluster| {
+        if standby.is_some() {
+            panic!("Replicas cannot join a cluster");
         }
-        info!("Closing Squid server...");
-        std::process::exit(0);
+        Arc::new(Cluster::new(cluster))
     });
+    if let Some(cluster) = &cluster {
+        tokio::spawn(helpers::cluster::gossip(
+            Arc::clone(cluster),
+            Arc::clone(&namespaces),
+        ));
+    }
+
+    // Agree on each change with the other nodes, if any.
+    let consensus = config.raft.as_ref().map(|raft| {
+        if standby.is_some() || cluster.is_some() {
+            panic!("Replicas and nodes of a cluster cannot join a Raft group");
+        }
+        let data_dir =
+            Path::new(config.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR));
+        Arc::new(Consensus::new(Arc::new(
+            Raft::open(raft, data_dir).unwrap(),
+        )))
+    });
+    if let Some(consensus) = &consensus {
+        tokio::spawn(helpers::raft::run(Arc::clone(&consensus.raft)));
+        tokio::spawn(helpers::raft::apply(
+            Arc::clone(consensus),
+            Arc::clone(&namespaces),
+        ));
+    }
+
+    // Log the operations changing data, if enabled.
+    let audit = match &config.audit {
+        Some(audit) => {
+            let data_dir = Path::new(
+                config.data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR),
+            );
+            Some(Arc::new(
+                Audit::open(audit, &data_dir.join(AUDIT_DIR)).await.unwrap(),
+            ))
+        },
+        None => None,
+    };
 
-    let addr = format!("0.0.0.0:{}", config.port.unwrap_or(50051))
-        .parse()
+    // Accept connections from every address at once.
+    let mut incoming: Pin<
+        Box<dyn Stream<Item = Result<TcpStream, std::io::Error>> + Send>,
+    > = Box::pin(tokio_stream::empty());
+    // Sockets passed by systemd replace the configured addresses.
+    let listeners = helpers::systemd::listeners();
+    if listeners.is_empty() {
+        for addr in
+            helpers::config::addresses(&config, config.port.unwrap_or(50051))
+        {
+            let listener =
+                TcpIncoming::new(addr, true, None).unwrap_or_else(|error| {
+                    panic!("Failed to bind {}: {}", addr, error)
+                });
+            incoming = Box::pin(incoming.merge(listener));
+
+            info!("Server started on {}", addr);
+        }
+    }
+    for listener in listeners {
+        let addr = listener.local_addr().unwrap();
+        let listener = TcpIncoming::from_listener(
+            tokio::net::TcpListener::from_std(listener).unwrap(),
+            true,
+            None,
+        )
         .unwrap();
+        incoming = Box::pin(incoming.merge(listener));
 
-    info!("Server started on {}", addr);
+        info!("Server started on {} through systemd", addr);
+    }
 
-    // Remove entires to reduce ram usage.
-    instance.write().await.entries.clear();
+    if config.api_keys.is_empty() {
+        warn!("No API key configured, authentication is disabled.");
+    }
+    let mut authenticator = helpers::auth::Authenticator::new(&config.api_keys);
+
+    if let Some(port) = config.http_port {
+        for addr in helpers::config::addresses(&config, port) {
+            let gateway = helpers::http::Gateway {
+                namespaces: Arc::clone(&namespaces),
+                authenticator: authenticator.clone(),
+            };
+            tokio::spawn(async move {
+                if let Err(error) = helpers::http::serve(addr, gateway).await {
+                    error!("HTTP gateway stopped: {}", error);
+                }
+            });
+        }
+    }
+    let limiter = config
+        .rate_limit
+        .as_ref()
+        .map(helpers::limit::RateLimiter::new);
+    let interceptor = move |request| {
+        let request = authenticator.call(request)?;
+        match &limiter {
+            Some(limiter) => limiter.check(request),
+            None => Ok(request),
+        }
+    };
+
+    let squid = SuperSquid {
+        namespaces: Arc::clone(&namespaces),
+        standby: standby.clone(),
+        cluster: cluster.clone(),
+        consensus: consensus.clone(),
+        audit: audit.clone(),
+        reject_until_ready: config.reject_until_ready,
+    };
+
+    if let Some(port) = config.resp_port {
+        for addr in helpers::config::addresses(&config, port) {
+            let (squid, interceptor) = (squid.clone(), interceptor.clone());
+            tokio::spawn(async move {
+                if let Err(error) =
+                    helpers::resp::serve(addr, squid, interceptor).await
+                {
+                    error!("RESP listener stopped: {}", error);
+                }
+            });
+        }
+    }
+
+    // Sockets listen already, so early connections wait to be served, but
+    // systemd waits for the stored sentences to be counted.
+    tokio::spawn(helpers::systemd::notify_ready());
+
+    let slow = config
+        .slow_queries
+        .as_ref()
+        .map(|config| Arc::new(helpers::slow::SlowLog::new(config)));
+
+    // Serving once the stored sentences are counted.
+    let (reporter, health) = tonic_health::server::health_reporter();
+    tokio::spawn(helpers::health::report(
+        reporter,
+        vec![
+            <AdminServer<SuperAdmin> as NamedService>::NAME,
+            <SquidServer<SuperSquid> as NamedService>::NAME,
+            <squid::v2::squid_server::SquidServer<SuperSquid>
+                as NamedService>::NAME,
+        ],
+    ));
 
     Server::builder()
-        .add_service(SquidServer::new(SuperSquid {
-            algorithm: helpers::database::Algorithm::Map(algo),
-            config,
-            instance,
-        }))
-        .serve(addr)
+        .add_service(health)
+        .add_service(Timed::new(
+            AdminServer::with_interceptor(
+                SuperAdmin {
+                    namespaces: Arc::clone(&namespaces),
+                    started_at,
+                    standby,
+                    cluster,
+                    consensus,
+                    audit,
+                },
+                interceptor.clone(),
+            ),
+            slow.clone(),
+        ))
+        .add_service(Timed::new(
+            SquidServer::with_interceptor(squid.clone(), interceptor.clone()),
+            slow.clone(),
+        ))
+        .add_service(Timed::new(
+            squid::v2::squid_server::SquidServer::with_interceptor(
+                squid,
+                interceptor,
+            ),
+            slow,
+        ))
+        .serve_with_incoming(incoming)
         .await
         .unwrap();
 }
@@ -0,0 +1,207 @@
+//! State shared by the gRPC services, and the checks and conversions of
+//! their requests.
+
+use crate::{
+    helpers::{
+        database::{self, Counters},
+        namespace::{Namespace, Namespaces},
+        raft::Consensus,
+        standby::Standby,
+    },
+    squid::{RankOrder, TokenKind},
+};
+use squid_core::{
+    audit::{Audit, Event},
+    cluster::Cluster,
+    models::config::{self, MessageType},
+    raft::Raft,
+    series::Series,
+    startup,
+};
+use squid_error::ErrorType;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tonic::Status;
+use tracing::error;
+
+/// Set once the server is shutting down, so no change is lost.
+pub static CLOSING: AtomicBool = AtomicBool::new(false);
+
+/// Serves both versions of the Squid service.
+#[derive(Clone)]
+pub struct SuperSquid {
+    pub namespaces: Arc<Namespaces>,
+    /// Primary followed by this server until it is promoted, if any.
+    pub standby: Option<Arc<Standby>>,
+    /// Other nodes sharing their counts, if clustering is enabled.
+    pub cluster: Option<Arc<Cluster>>,
+    /// Other nodes agreeing on each change, if Raft is enabled.
+    pub consensus: Option<Arc<Consensus>>,
+    /// Log of the operations changing data, if enabled.
+    pub audit: Option<Arc<Audit>>,
+    /// Whether leaderboards are rejected until the stored sentences are
+    /// counted.
+    pub reject_until_ready: bool,
+}
+
+/// Converts the kind of words requested into the configuration type.
+pub fn message_type(kind: TokenKind) -> MessageType {
+    match kind {
+        TokenKind::Any => MessageType::Anything,
+        TokenKind::Word => MessageType::Word,
+        TokenKind::Hashtag => MessageType::Hashtag,
+        TokenKind::Phrase => MessageType::Phrase,
+    }
+}
+
+/// Converts the order of a request, the order of the service being used by
+/// default.
+pub fn rank_order(order: RankOrder, counters: &Counters) -> config::RankOrder {
+    match order {
+        RankOrder::Service => counters.order,
+        RankOrder::Count => config::RankOrder::Count,
+        RankOrder::Alphabetical => config::RankOrder::Alphabetical,
+        RankOrder::Recency => config::RankOrder::Recency,
+        RankOrder::TfIdf => config::RankOrder::TfIdf,
+    }
+}
+
+/// Returns the cluster of the node, or an error if clustering is disabled.
+pub fn clustered(
+    cluster: &Option<Arc<Cluster>>,
+) -> Result<&Arc<Cluster>, Status> {
+    cluster
+        .as_ref()
+        .ok_or_else(|| Status::failed_precondition("clustering is disabled"))
+}
+
+/// Returns the Raft group of the node, or an error if Raft is disabled.
+pub fn replicated(consensus: &Option<Arc<Consensus>>) -> Result<&Raft, Status> {
+    consensus
+        .as_ref()
+        .map(|consensus| consensus.raft.as_ref())
+        .ok_or_else(|| Status::failed_precondition("Raft is disabled"))
+}
+
+/// Appends an operation to the audit log, if enabled.
+///
+/// The operation is already made, so a failure is only logged.
+pub async fn audit(audit: &Option<Arc<Audit>>, event: Event) {
+    if let Some(audit) = audit {
+        if let Err(error) = audit.record(event).await {
+            error!("Failed to write audit log: {}", error);
+        }
+    }
+}
+
+/// Converts the error of a change to the exclusions made through Raft.
+pub fn exclusion_status(error: squid_error::Error) -> Status {
+    match error.etype {
+        ErrorType::Request(_) => Status::from(error),
+        _ => {
+            error!("Failed to update exclusions: {}", error);
+            error.to_status("failed to update exclusions")
+        },
+    }
+}
+
+/// Rejects requests changing data on a replica not promoted yet, or during
+/// a shutdown.
+pub fn writable(standby: &Option<Arc<Standby>>) -> Result<(), Status> {
+    if standby.as_ref().is_some_and(|standby| standby.read_only()) {
+        Err(Status::failed_precondition(
+            "replicas only serve reads, send changes to the primary",
+        ))
+    } else if CLOSING.load(Ordering::SeqCst) {
+        Err(Status::unavailable("server is shutting down"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Rejects leaderboards while the stored sentences are counted, if the
+/// server is configured to.
+pub fn warmed(reject_until_ready: bool) -> Result<(), Status> {
+    if reject_until_ready && !startup::is_ready() {
+        Err(Status::unavailable("stored sentences are still being counted"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns the counts over time of a namespace, if it keeps them.
+pub fn series(namespace: &Namespace) -> Result<&Series, Status> {
+    namespace.series.as_deref().ok_or_else(|| {
+        Status::failed_precondition("series are not kept by this service")
+    })
+}
+
+/// Ranks the words added to a namespace during the last `seconds`, if it
+/// counts recent words, skipping the ones counted fewer than `min_count`
+/// times or than the minimum of the service.
+pub async fn rank_window(
+    namespace: &Namespace,
+    kind: &MessageType,
+    seconds: u64,
+    min_count: usize,
+    offset: usize,
+    length: usize,
+) -> Result<(Vec<(String, usize)>, usize), Status> {
+    database::rank_recent(
+        &namespace.counters,
+        kind,
+        Duration::from_secs(seconds),
+        min_count,
+        offset,
+        length,
+    )
+    .await
+    .ok_or_else(|| {
+        Status::failed_precondition(
+            "recent words are not counted by this service",
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::squid;
+    use prost::Message;
+
+    #[test]
+    fn test_error_details_match_proto() {
+        // Every field is set, so a field added to either message fails to
+        // build or to decode the same.
+        let generated = squid::ErrorDetails {
+            reason: "WritingError".to_string(),
+            context: vec![
+                "while flushing".to_string(),
+                "cannot write entry".to_string(),
+            ],
+            category: "io.writing".to_string(),
+            code: 3004,
+        };
+        let details = squid_error::ErrorDetails {
+            reason: generated.reason.clone(),
+            context: generated.context.clone(),
+            category: generated.category.clone(),
+            code: generated.code,
+        };
+
+        assert_eq!(generated.encode_to_vec(), details.encode_to_vec());
+        assert_eq!(
+            squid_error::ErrorDetails::decode(&*generated.encode_to_vec())
+                .unwrap(),
+            details
+        );
+        assert_eq!(
+            squid::ErrorDetails::decode(&*details.encode_to_vec()).unwrap(),
+            generated
+        );
+    }
+}
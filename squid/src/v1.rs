@@ -0,0 +1,725 @@
+//! First version of the Squid service.
+
+use crate::{
+    helpers::{
+        self,
+        database::{self, Filter},
+        ingest::Outcome,
+        metrics::METRICS,
+    },
+    service::{
+        audit, clustered, message_type, rank_order, rank_window, series,
+        warmed, writable, SuperSquid,
+    },
+    squid::{
+        squid_server::Squid, AddReply, AddRequest, AnomaliesReply,
+        AnomaliesRequest, Anomaly, AuthorTopRequest, ExportChunk, ExportFormat,
+        ExportLeaderboardRequest, FindSimilarReply, FindSimilarRequest,
+        GetRequest, HistoryReply, HistoryRequest, ImportProgress,
+        ImportRequest, LeaderboardRequest, Point, PreviewReply, PreviewRequest,
+        RankAtReply, RankAtRequest, RankOrder, Ranking, RankingEvents,
+        RelatedRequest, Sentence, SimilarSentence, TrendingReply,
+        TrendingRequest, TrendingWord, UpdateTtlRequest, Void,
+        WatchAnomaliesRequest, WatchChangesRequest, Word,
+    },
+};
+use squid_core::{
+    audit::{Event, Operation},
+    models::config::Scope,
+    profile::{self, Phase},
+    time,
+};
+use squid_error::ErrorType;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use tracing::error;
+
+/// Standard deviations above the mean from which a word is an anomaly, if
+/// the request does not set it.
+const DEFAULT_ANOMALY_THRESHOLD: f64 = 3.0;
+/// Number of anomalies returned if the request does not set it.
+const DEFAULT_ANOMALIES: usize = 10;
+/// Number of words of a bucket returned if the request does not set it.
+const DEFAULT_RANK_AT: usize = 10;
+/// Number of trending words returned if the request does not set it.
+const DEFAULT_TRENDING: usize = 10;
+/// Number of related words returned if the request does not set it.
+const DEFAULT_RELATED: usize = 10;
+/// Number of near-duplicates returned if the request does not set it.
+const DEFAULT_SIMILAR: usize = 10;
+
+#[tonic::async_trait]
+impl Squid for SuperSquid {
+    async fn leaderboard(
+        &self,
+        request: Request<LeaderboardRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+        warmed(self.reject_until_ready)?;
+        let start = Instant::now();
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let length = data.length as usize;
+        let offset = data.offset as usize;
+        let kind = message_type(data.kind());
+        if data.global
+            && (!data.lang.is_empty()
+                || !data.tag.is_empty()
+                || !data.metadata.is_empty()
+                || !data.region.is_empty())
+        {
+            return Err(Status::invalid_argument(
+                "global leaderboards cannot be filtered",
+            ));
+        }
+        if data.global && data.order() != RankOrder::Service {
+            return Err(Status::invalid_argument(
+                "global leaderboards are ranked by count",
+            ));
+        }
+        if data.window > 0
+            && (data.global
+                || !data.lang.is_empty()
+                || !data.tag.is_empty()
+                || !data.metadata.is_empty()
+                || !data.region.is_empty()
+                || data.order() != RankOrder::Service)
+        {
+            return Err(Status::invalid_argument(
+                "windowed leaderboards cannot be filtered, and are ranked \
+                 by count",
+            ));
+        }
+        if data.share && (data.global || data.window > 0) {
+            return Err(Status::invalid_argument(
+                "shares are not returned by global or windowed leaderboards",
+            ));
+        }
+        let order = rank_order(data.order(), &namespace.counters);
+        let exclude = namespace.tokenize_words(&data.exclude);
+        let (offset, length) = database::widened(&exclude, offset, length);
+        let lang = Some(data.lang)
+            .filter(|lang| !lang.is_empty())
+            .or_else(|| namespace.service.lang.clone());
+        // The default language of the service only applies without any
+        // other filter.
+        let filter = if !data.tag.is_empty() {
+            Filter::Tag(&data.tag)
+        } else if !data.metadata.is_empty() {
+            Filter::Metadata(&data.metadata)
+        } else if !data.region.is_empty() {
+            Filter::Region(&data.region)
+        } else {
+            lang.as_deref().into()
+        };
+
+        let min_count = data.min_count.try_into().unwrap_or(usize::MAX);
+        let (ranking, total_words) = if data.window > 0 {
+            rank_window(
+                namespace,
+                &kind,
+                data.window,
+                min_count,
+                offset,
+                length,
+            )
+            .await?
+        } else if data.global {
+            clustered(&self.cluster)?
+                .rank(
+                    &namespace.service.name,
+                    &namespace.counters,
+                    &kind,
+                    min_count.max(namespace.counters.min_count),
+                    offset,
+                    length,
+                )
+                .await
+        } else {
+            namespace
+                .cache
+                .rank(
+                    &namespace.counters,
+                    filter,
+                    &kind,
+                    order,
+                    min_count,
+                    offset,
+                    length,
+                )
+                .await
+        };
+
+        let ranking = database::excluding(
+            ranking,
+            &exclude,
+            data.offset as usize,
+            data.length as usize,
+        );
+        let total = if data.share {
+            database::total(&namespace.counters, filter, &kind).await
+        } else {
+            0
+        };
+
+        let response = Response::new(Ranking {
+            word: squid_algorithm::with_share(ranking, total)
+                .into_iter()
+                .map(|(word, occurence, share)| Word {
+                    word: word.replace("%20", " "),
+                    occurence: occurence.try_into().unwrap_or_default(),
+                    share,
+                })
+                .collect::<Vec<_>>(),
+            total_words: total_words as u64,
+        });
+        METRICS.leaderboard.observe(start.elapsed());
+
+        Ok(response)
+    }
+
+    async fn add(
+        &self,
+        request: Request<AddRequest>,
+    ) -> Result<Response<AddReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Write)?;
+        writable(&self.standby)?;
+        let start = Instant::now();
+
+        let owner = helpers::auth::name(&request);
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let submission = helpers::ingest::Submission {
+            owner: owner.clone(),
+            ..data.into()
+        };
+        let outcome = match &self.consensus {
+            Some(consensus) => consensus.add(namespace, submission).await,
+            None => helpers::ingest::add(namespace, submission).await,
+        }
+        .map_err(|error| match error.etype {
+            ErrorType::Request(_) => Status::from(error),
+            _ => {
+                error!("Failed to add sentence: {}", error);
+                error.to_status("failed to add sentence")
+            },
+        })?;
+        METRICS.add.observe(start.elapsed());
+
+        if let Outcome::Stored(id) = &outcome {
+            audit(
+                &self.audit,
+                Event::new(owner, Operation::Add, &namespace.service.name)
+                    .target(id),
+            )
+            .await;
+        }
+
+        Ok(Response::new(match outcome {
+            Outcome::Stored(id) => AddReply {
+                id,
+                deduplicated: false,
+            },
+            Outcome::Deduplicated(id) => AddReply {
+                id,
+                deduplicated: true,
+            },
+        }))
+    }
+
+    type ExportLeaderboardStream = ReceiverStream<Result<ExportChunk, Status>>;
+
+    async fn export_leaderboard(
+        &self,
+        request: Request<ExportLeaderboardRequest>,
+    ) -> Result<Response<Self::ExportLeaderboardStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = Arc::clone(self.namespaces.get(&data.namespace)?);
+        let kind = message_type(data.kind());
+        let format = data.format();
+        if cfg!(not(feature = "parquet")) && format == ExportFormat::Parquet {
+            return Err(Status::unimplemented(
+                "Squid was built without the parquet feature",
+            ));
+        }
+        let length = match data.length {
+            0 => usize::MAX,
+            length => length as usize,
+        };
+        let lang = Some(data.lang)
+            .filter(|lang| !lang.is_empty())
+            .or_else(|| namespace.service.lang.clone());
+
+        Ok(Response::new(helpers::export::leaderboard(
+            namespace, format, lang, kind, length,
+        )))
+    }
+
+    type WatchChangesStream = ReceiverStream<Result<RankingEvents, Status>>;
+
+    async fn watch_changes(
+        &self,
+        request: Request<WatchChangesRequest>,
+    ) -> Result<Response<Self::WatchChangesStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = Arc::clone(self.namespaces.get(&data.namespace)?);
+        let kind = message_type(data.kind());
+        let lang = Some(data.lang)
+            .filter(|lang| !lang.is_empty())
+            .or_else(|| namespace.service.lang.clone());
+
+        Ok(Response::new(helpers::changes::watch(
+            namespace,
+            lang,
+            kind,
+            data.length as usize,
+        )))
+    }
+
+    type ImportStream = ReceiverStream<Result<ImportProgress, Status>>;
+
+    async fn import(
+        &self,
+        request: Request<Streaming<ImportRequest>>,
+    ) -> Result<Response<Self::ImportStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Write)?;
+        writable(&self.standby)?;
+        if self.consensus.is_some() {
+            return Err(Status::unimplemented(
+                "imports are not replicated through Raft, send sentences \
+                 with Add",
+            ));
+        }
+
+        Ok(Response::new(helpers::ingest::import(
+            Arc::clone(&self.namespaces),
+            helpers::auth::name(&request),
+            request.into_inner(),
+        )))
+    }
+
+    async fn get(
+        &self,
+        request: Request<GetRequest>,
+    ) -> Result<Response<Sentence>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+
+        let _timer = profile::start(Phase::Database);
+        let entity = namespace
+            .instance
+            .read()
+            .await
+            .get(data.id)
+            .map_err(|error| {
+                error!("Failed to get sentence: {}", error);
+                error.to_status("failed to get sentence")
+            })?
+            .ok_or_else(|| Status::not_found("sentence not found"))?;
+
+        Ok(Response::new(Sentence {
+            id: entity.id,
+            text: entity.post_processing_text,
+            original_text: entity.original_text,
+            lang: entity.lang,
+            tags: entity.tags,
+            metadata: entity.metadata,
+            region: entity.region,
+            author_id: entity.author_id,
+        }))
+    }
+
+    async fn update_ttl(
+        &self,
+        request: Request<UpdateTtlRequest>,
+    ) -> Result<Response<Void>, Status> {
+        helpers::auth::authorize(&request, Scope::Write)?;
+        writable(&self.standby)?;
+
+        let actor = helpers::auth::name(&request);
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let now = time::now();
+
+        // Expiring now is the same as expiring at the current second.
+        let ttl = if data.expire_now {
+            Some(now)
+        } else {
+            Some(data.lifetime)
+                .filter(|lifetime| *lifetime > 0)
+                .map(|lifetime| now.saturating_add(lifetime))
+        };
+
+        if data.expire_now {
+            namespace.webhooks.delete(&data.id);
+        }
+
+        // Through Raft, the change is applied and sent to replicas once
+        // committed.
+        let updated = match &self.consensus {
+            Some(consensus) => consensus.touch(namespace, &data.id, ttl).await,
+            None => {
+                let _timer = profile::start(Phase::Database);
+                namespace.instance.write().await.touch(&data.id, ttl).await
+            },
+        }
+        .inspect_err(|_| namespace.webhooks.cancel(&data.id))
+        .map_err(|error| match error.etype {
+            ErrorType::Request(_) => Status::from(error),
+            _ => {
+                error!("Failed to update lifetime: {}", error);
+                error.to_status("failed to update lifetime")
+            },
+        })?;
+
+        if updated {
+            if self.consensus.is_none() {
+                namespace.publish(helpers::replica::touched(&data.id, ttl));
+            }
+
+            let event = if data.expire_now {
+                Event::new(actor, Operation::Delete, &namespace.service.name)
+            } else {
+                Event::new(actor, Operation::UpdateTtl, &namespace.service.name)
+                    .details(ttl.map_or_else(
+                        || "permanent".to_string(),
+                        |ttl| format!("expire_at:{}", ttl),
+                    ))
+            };
+            audit(&self.audit, event.target(&data.id)).await;
+
+            Ok(Response::new(Void {}))
+        } else {
+            namespace.webhooks.cancel(&data.id);
+            Err(Status::not_found("sentence not found"))
+        }
+    }
+
+    async fn history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<HistoryReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let series = series(namespace)?;
+        let word = match namespace.tokenize_words(&[data.word]).as_slice() {
+            [word] => word.clone(),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "word must be a single counted word once tokenized",
+                ))
+            },
+        };
+
+        let to = if data.to == 0 { u64::MAX } else { data.to };
+        Ok(Response::new(HistoryReply {
+            points: series
+                .read(&word, data.from, to)
+                .into_iter()
+                .map(|(start, count)| Point { start, count })
+                .collect(),
+            interval: series.interval.as_secs(),
+        }))
+    }
+
+    async fn rank_at(
+        &self,
+        request: Request<RankAtRequest>,
+    ) -> Result<Response<RankAtReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let series = series(namespace)?;
+        let at = match data.at {
+            0 => time::now(),
+            at => at,
+        };
+        let length = match data.length {
+            0 => DEFAULT_RANK_AT,
+            length => length as usize,
+        };
+
+        let (start, ranking) = series
+            .rank_at(at, length)
+            .ok_or_else(|| Status::out_of_range("bucket is not kept"))?;
+        Ok(Response::new(RankAtReply {
+            start,
+            interval: series.interval.as_secs(),
+            words: ranking
+                .into_iter()
+                .map(|(word, occurence)| Word {
+                    word,
+                    occurence,
+                    ..Default::default()
+                })
+                .collect(),
+        }))
+    }
+
+    async fn anomalies(
+        &self,
+        request: Request<AnomaliesRequest>,
+    ) -> Result<Response<AnomaliesReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let series = series(namespace)?;
+        let threshold = match data.threshold {
+            0.0 => DEFAULT_ANOMALY_THRESHOLD,
+            threshold if threshold.is_finite() && threshold > 0.0 => threshold,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "threshold must be positive",
+                ))
+            },
+        };
+        let length = match data.length {
+            0 => DEFAULT_ANOMALIES,
+            length => length as usize,
+        };
+
+        Ok(Response::new(AnomaliesReply {
+            anomalies: series
+                .anomalies(threshold, length)
+                .into_iter()
+                .map(|anomaly| Anomaly {
+                    word: anomaly.word,
+                    count: anomaly.count,
+                    mean: anomaly.mean,
+                    score: anomaly.score,
+                })
+                .collect(),
+        }))
+    }
+
+    type WatchAnomaliesStream = ReceiverStream<Result<Anomaly, Status>>;
+
+    async fn watch_anomalies(
+        &self,
+        request: Request<WatchAnomaliesRequest>,
+    ) -> Result<Response<Self::WatchAnomaliesStream>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let mut alerts = series(namespace)?.subscribe();
+        let (tx, rx) = tokio::sync::mpsc::channel(16);
+
+        tokio::spawn(async move {
+            loop {
+                let anomaly = match alerts.recv().await {
+                    Ok(anomaly) => anomaly,
+                    // Spikes missed by slow clients are skipped.
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return,
+                };
+                let anomaly = Anomaly {
+                    word: anomaly.word,
+                    count: anomaly.count,
+                    mean: anomaly.mean,
+                    score: anomaly.score,
+                };
+                if tx.send(Ok(anomaly)).await.is_err() {
+                    return;
+                }
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn trending(
+        &self,
+        request: Request<TrendingRequest>,
+    ) -> Result<Response<TrendingReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let window = namespace.counters.window.as_ref().ok_or_else(|| {
+            Status::failed_precondition(
+                "recent words are not counted by this service",
+            )
+        })?;
+        let window = window.read().await;
+        let duration = match data.window {
+            0 => window.duration() / 2,
+            seconds => Duration::from_secs(seconds),
+        };
+        let length = match data.length {
+            0 => DEFAULT_TRENDING,
+            length => length as usize,
+        };
+
+        Ok(Response::new(TrendingReply {
+            words: window
+                .trending(&message_type(data.kind()), duration, length)
+                .into_iter()
+                .map(|(word, count, previous)| TrendingWord {
+                    growth: squid_algorithm::window::growth(count, previous),
+                    word,
+                    count: count as u64,
+                    previous: previous as u64,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn author_top(
+        &self,
+        request: Request<AuthorTopRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let kind = message_type(data.kind());
+        let (ranking, total_words) = namespace
+            .cache
+            .rank(
+                &namespace.counters,
+                Filter::Author(&data.author_id),
+                &kind,
+                namespace.counters.order,
+                0,
+                0,
+                data.length as usize,
+            )
+            .await;
+
+        Ok(Response::new(Ranking {
+            word: ranking
+                .into_iter()
+                .map(|(word, occurence)| Word {
+                    word: word.replace("%20", " "),
+                    occurence: occurence.try_into().unwrap_or_default(),
+                    ..Default::default()
+                })
+                .collect(),
+            total_words: total_words as u64,
+        }))
+    }
+
+    async fn related(
+        &self,
+        request: Request<RelatedRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let length = match data.length {
+            0 => DEFAULT_RELATED,
+            length => length as usize,
+        };
+        // Stop words are never paired, so nothing is related to them.
+        let word = namespace
+            .tokenize_words(&[data.word])
+            .into_iter()
+            .next()
+            .unwrap_or_default();
+        let (ranking, total_words) =
+            database::related(&namespace.counters, &word, length)
+                .await
+                .ok_or_else(|| {
+                    Status::failed_precondition(
+                        "words used together are not counted by this service",
+                    )
+                })?;
+
+        Ok(Response::new(Ranking {
+            word: ranking
+                .into_iter()
+                .map(|(word, occurence)| Word {
+                    word,
+                    occurence: occurence as u64,
+                    ..Default::default()
+                })
+                .collect(),
+            total_words: total_words as u64,
+        }))
+    }
+
+    async fn find_similar(
+        &self,
+        request: Request<FindSimilarRequest>,
+    ) -> Result<Response<FindSimilarReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let threshold = match data.threshold {
+            0.0 => None,
+            threshold if (0.0..=1.0).contains(&threshold) => Some(threshold),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "threshold must be between 0 and 1",
+                ))
+            },
+        };
+        let length = match data.length {
+            0 => DEFAULT_SIMILAR,
+            length => length as usize,
+        };
+
+        let similar = namespace
+            .find_similar(&data.sentence, threshold, length)
+            .ok_or_else(|| {
+                Status::failed_precondition(
+                    "sentences are not indexed by this service",
+                )
+            })?;
+        Ok(Response::new(FindSimilarReply {
+            sentences: similar
+                .into_iter()
+                .map(|similar| SimilarSentence {
+                    id: similar.id,
+                    similarity: similar.similarity,
+                })
+                .collect(),
+        }))
+    }
+
+    async fn preview(
+        &self,
+        request: Request<PreviewRequest>,
+    ) -> Result<Response<PreviewReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let preview = helpers::ingest::preview(
+            namespace,
+            helpers::ingest::Submission {
+                sentence: data.sentence,
+                ..Default::default()
+            },
+        )
+        .await?;
+
+        Ok(Response::new(PreviewReply {
+            tokens: preview.tokens,
+            lang: preview.lang,
+            stopwords: preview.stop_words,
+            counted: preview.counted,
+            excluded: preview.excluded,
+            tags: preview.tags,
+        }))
+    }
+}
@@ -0,0 +1,374 @@
+//! Second version of the Squid service.
+//!
+//! Requests are converted to the first version whenever both behave the
+//! same, so both versions stay consistent.
+
+use crate::{
+    helpers::{
+        self,
+        database::{self, Filter},
+        metrics::METRICS,
+    },
+    models::config::Scope,
+    service::{message_type, rank_order, rank_window, warmed, SuperSquid},
+    squid::{
+        self,
+        v2::{
+            squid_server::Squid, update_ttl_request::Expiration, AddReply,
+            AddRequest, AddStatus, LeaderboardRequest, Ranking, UpdateStatus,
+            UpdateTtlReply, UpdateTtlRequest, Word,
+        },
+        AnomaliesReply, AnomaliesRequest, Anomaly, AuthorTopRequest,
+        ExportChunk, ExportLeaderboardRequest, FindSimilarReply,
+        FindSimilarRequest, GetRequest, HistoryReply, HistoryRequest,
+        ImportProgress, ImportRequest, PreviewReply, PreviewRequest,
+        RankAtReply, RankAtRequest, RankingEvents, RelatedRequest, Sentence,
+        TrendingReply, TrendingRequest, WatchAnomaliesRequest,
+        WatchChangesRequest,
+    },
+};
+use std::time::Instant;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+impl From<AddRequest> for squid::AddRequest {
+    fn from(request: AddRequest) -> Self {
+        squid::AddRequest {
+            sentence: request.sentence,
+            // 0 means the lifetime of the service.
+            lifetime: request.lifetime.unwrap_or_default(),
+            lang: request.lang,
+            namespace: request.namespace,
+            weight: request.weight,
+            store_original: request.store_original,
+            tags: request.tags,
+            metadata: request.metadata,
+            region: request.region.unwrap_or_default(),
+            author_id: request.author_id.unwrap_or_default(),
+        }
+    }
+}
+
+/// Replaces the message of a request, keeping its metadata and the
+/// permissions granted to it.
+fn convert<T, U: From<T>>(request: Request<T>) -> Request<U> {
+    let (metadata, extensions, message) = request.into_parts();
+    Request::from_parts(metadata, extensions, message.into())
+}
+
+#[tonic::async_trait]
+impl Squid for SuperSquid {
+    async fn leaderboard(
+        &self,
+        request: Request<LeaderboardRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+        warmed(self.reject_until_ready)?;
+        let start = Instant::now();
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let kind = message_type(data.kind());
+        let filter = match (&data.lang, &data.tag, &data.metadata, &data.region)
+        {
+            (None, None, None, None) => {
+                namespace.service.lang.as_deref().into()
+            },
+            (Some(lang), None, None, None) => {
+                Some(lang.as_str()).filter(|lang| !lang.is_empty()).into()
+            },
+            (None, Some(tag), None, None) => Filter::Tag(tag),
+            (None, None, Some(entry), None) => Filter::Metadata(entry),
+            (None, None, None, Some(region)) => Filter::Region(region),
+            _ => {
+                return Err(Status::invalid_argument(
+                    "only one of lang, tag, metadata and region can be set",
+                ))
+            },
+        };
+        let exclude = namespace.tokenize_words(&data.exclude);
+        let (offset, length) = database::widened(
+            &exclude,
+            data.offset as usize,
+            data.length as usize,
+        );
+
+        let min_count = data.min_count.try_into().unwrap_or(usize::MAX);
+        let (ranking, total_words) = if data.window > 0 {
+            if data.lang.is_some()
+                || data.tag.is_some()
+                || data.metadata.is_some()
+                || data.region.is_some()
+                || data.order() != squid::RankOrder::Service
+            {
+                return Err(Status::invalid_argument(
+                    "windowed leaderboards cannot be filtered, and are ranked \
+                     by count",
+                ));
+            }
+
+            if data.share {
+                return Err(Status::invalid_argument(
+                    "shares are not returned by windowed leaderboards",
+                ));
+            }
+
+            rank_window(
+                namespace,
+                &kind,
+                data.window,
+                min_count,
+                offset,
+                length,
+            )
+            .await?
+        } else {
+            namespace
+                .cache
+                .rank(
+                    &namespace.counters,
+                    filter,
+                    &kind,
+                    rank_order(data.order(), &namespace.counters),
+                    min_count,
+                    offset,
+                    length,
+                )
+                .await
+        };
+
+        let ranking = database::excluding(
+            ranking,
+            &exclude,
+            data.offset as usize,
+            data.length as usize,
+        );
+        let total = if data.share {
+            database::total(&namespace.counters, filter, &kind).await
+        } else {
+            0
+        };
+
+        let response = Response::new(Ranking {
+            words: squid_algorithm::with_share(ranking, total)
+                .into_iter()
+                .map(|(word, occurrences, share)| Word {
+                    word: word.replace("%20", " "),
+                    occurrences: occurrences as u64,
+                    share,
+                })
+                .collect(),
+            total_words: total_words as u64,
+        });
+        METRICS.leaderboard.observe(start.elapsed());
+
+        Ok(response)
+    }
+
+    async fn add(
+        &self,
+        request: Request<AddRequest>,
+    ) -> Result<Response<AddReply>, Status> {
+        let reply = squid::squid_server::Squid::add(self, convert(request))
+            .await?
+            .into_inner();
+
+        Ok(Response::new(AddReply {
+            id: reply.id,
+            status: if reply.deduplicated {
+                AddStatus::Deduplicated
+            } else {
+                AddStatus::Stored
+            }
+            .into(),
+        }))
+    }
+
+    async fn get(
+        &self,
+        request: Request<GetRequest>,
+    ) -> Result<Response<Sentence>, Status> {
+        squid::squid_server::Squid::get(self, request).await
+    }
+
+    type ExportLeaderboardStream = ReceiverStream<Result<ExportChunk, Status>>;
+
+    async fn export_leaderboard(
+        &self,
+        request: Request<ExportLeaderboardRequest>,
+    ) -> Result<Response<Self::ExportLeaderboardStream>, Status> {
+        squid::squid_server::Squid::export_leaderboard(self, request).await
+    }
+
+    type ImportStream = ReceiverStream<Result<ImportProgress, Status>>;
+
+    async fn import(
+        &self,
+        request: Request<Streaming<ImportRequest>>,
+    ) -> Result<Response<Self::ImportStream>, Status> {
+        squid::squid_server::Squid::import(self, request).await
+    }
+
+    async fn update_ttl(
+        &self,
+        request: Request<UpdateTtlRequest>,
+    ) -> Result<Response<UpdateTtlReply>, Status> {
+        helpers::auth::authorize(&request, Scope::Write)?;
+        // Unknown namespaces are errors, unlike unknown sentences.
+        self.namespaces.get(&request.get_ref().namespace)?;
+
+        let (metadata, extensions, data) = request.into_parts();
+        let (lifetime, expire_now) = match data.expiration {
+            Some(Expiration::Lifetime(0)) | Some(Expiration::ExpireNow(_)) => {
+                (0, true)
+            },
+            Some(Expiration::Lifetime(lifetime)) => (lifetime, false),
+            // 0 means the sentence never expires.
+            Some(Expiration::Permanent(_)) => (0, false),
+            None => {
+                return Err(Status::invalid_argument("expiration must be set"))
+            },
+        };
+        let request = Request::from_parts(
+            metadata,
+            extensions,
+            squid::UpdateTtlRequest {
+                id: data.id,
+                lifetime,
+                expire_now,
+                namespace: data.namespace,
+            },
+        );
+
+        let status = match squid::squid_server::Squid::update_ttl(self, request)
+            .await
+        {
+            Ok(_) => UpdateStatus::Updated,
+            Err(status) if status.code() == Code::NotFound => {
+                UpdateStatus::NotFound
+            },
+            Err(status) => return Err(status),
+        };
+
+        Ok(Response::new(UpdateTtlReply {
+            status: status.into(),
+        }))
+    }
+
+    type WatchChangesStream = ReceiverStream<Result<RankingEvents, Status>>;
+
+    async fn watch_changes(
+        &self,
+        request: Request<WatchChangesRequest>,
+    ) -> Result<Response<Self::WatchChangesStream>, Status> {
+        squid::squid_server::Squid::watch_changes(self, request).await
+    }
+
+    async fn history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<HistoryReply>, Status> {
+        squid::squid_server::Squid::history(self, request).await
+    }
+
+    async fn anomalies(
+        &self,
+        request: Request<AnomaliesRequest>,
+    ) -> Result<Response<AnomaliesReply>, Status> {
+        squid::squid_server::Squid::anomalies(self, request).await
+    }
+
+    type WatchAnomaliesStream = ReceiverStream<Result<Anomaly, Status>>;
+
+    async fn watch_anomalies(
+        &self,
+        request: Request<WatchAnomaliesRequest>,
+    ) -> Result<Response<Self::WatchAnomaliesStream>, Status> {
+        squid::squid_server::Squid::watch_anomalies(self, request).await
+    }
+
+    async fn trending(
+        &self,
+        request: Request<TrendingRequest>,
+    ) -> Result<Response<TrendingReply>, Status> {
+        squid::squid_server::Squid::trending(self, request).await
+    }
+
+    async fn author_top(
+        &self,
+        request: Request<AuthorTopRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        helpers::auth::authorize(&request, Scope::Read)?;
+
+        let data = request.into_inner();
+        let namespace = self.namespaces.get(&data.namespace)?;
+        let kind = message_type(data.kind());
+        let (ranking, total_words) = namespace
+            .cache
+            .rank(
+                &namespace.counters,
+                Filter::Author(&data.author_id),
+                &kind,
+                namespace.counters.order,
+                0,
+                0,
+                data.length as usize,
+            )
+            .await;
+
+        Ok(Response::new(Ranking {
+            words: ranking
+                .into_iter()
+                .map(|(word, occurrences)| Word {
+                    word: word.replace("%20", " "),
+                    occurrences: occurrences as u64,
+                    ..Default::default()
+                })
+                .collect(),
+            total_words: total_words as u64,
+        }))
+    }
+
+    async fn rank_at(
+        &self,
+        request: Request<RankAtRequest>,
+    ) -> Result<Response<RankAtReply>, Status> {
+        squid::squid_server::Squid::rank_at(self, request).await
+    }
+
+    async fn related(
+        &self,
+        request: Request<RelatedRequest>,
+    ) -> Result<Response<Ranking>, Status> {
+        let ranking = squid::squid_server::Squid::related(self, request)
+            .await?
+            .into_inner();
+
+        Ok(Response::new(Ranking {
+            words: ranking
+                .word
+                .into_iter()
+                .map(|word| Word {
+                    word: word.word,
+                    occurrences: word.occurence,
+                    ..Default::default()
+                })
+                .collect(),
+            total_words: ranking.total_words,
+        }))
+    }
+
+    async fn find_similar(
+        &self,
+        request: Request<FindSimilarRequest>,
+    ) -> Result<Response<FindSimilarReply>, Status> {
+        squid::squid_server::Squid::find_similar(self, request).await
+    }
+
+    async fn preview(
+        &self,
+        request: Request<PreviewRequest>,
+    ) -> Result<Response<PreviewReply>, Status> {
+        squid::squid_server::Squid::preview(self, request).await
+    }
+}